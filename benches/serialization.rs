@@ -1,11 +1,46 @@
-use criterion::{black_box, criterion_group, criterion_main, Criterion, BenchmarkId, Throughput};
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, BenchmarkId, Throughput};
 use tx2_link::{
     BinarySerializer, BinaryFormat,
     WorldSnapshot, SerializedEntity, SerializedComponent,
-    protocol::{Message, ComponentData, FieldValue},
+    protocol::{Message, ComponentData, FieldValue, SchemaVersion},
     compression::DeltaCompressor,
 };
+use std::alloc::{GlobalAlloc, Layout, System};
 use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts heap allocations so benches can report them alongside timings.
+struct CountingAllocator;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Average allocations (including reallocations) per call of `f`.
+fn allocations_per_call(calls: usize, mut f: impl FnMut()) -> f64 {
+    let before = ALLOCATIONS.load(Ordering::Relaxed);
+    for _ in 0..calls {
+        f();
+    }
+    (ALLOCATIONS.load(Ordering::Relaxed) - before) as f64 / calls as f64
+}
 
 fn create_test_snapshot(entity_count: usize, components_per_entity: usize) -> WorldSnapshot {
     let mut entities = Vec::with_capacity(entity_count);
@@ -102,10 +137,43 @@ fn benchmark_deserialization_formats(c: &mut Criterion) {
     group.finish();
 }
 
+fn benchmark_binary_deserialization(c: &mut Criterion) {
+    let entities = (0..50)
+        .map(|i| SerializedEntity {
+            id: i,
+            components: vec![SerializedComponent {
+                id: "Mesh".to_string(),
+                data: ComponentData::Binary(bytes::Bytes::from(vec![i as u8; 16 * 1024])),
+            }],
+        })
+        .collect();
+    let message = Message::snapshot(entities, 100.0, SchemaVersion::INITIAL);
+
+    let mut group = c.benchmark_group("binary_deserialization");
+
+    let serializer = BinarySerializer::messagepack();
+    let data = serializer.serialize_message(&message).unwrap();
+    group.throughput(Throughput::Bytes(data.len() as u64));
+
+    group.bench_function("deserialize_message/copy", |b| {
+        b.iter(|| {
+            black_box(serializer.deserialize_message(&data).unwrap());
+        });
+    });
+
+    group.bench_function("deserialize_message_bytes/zero_copy", |b| {
+        b.iter(|| {
+            black_box(serializer.deserialize_message_bytes(&data).unwrap());
+        });
+    });
+
+    group.finish();
+}
+
 fn benchmark_message_sizes(c: &mut Criterion) {
     let snapshot = create_test_snapshot(100, 5);
 
-    let mut group = c.benchmark_group("message_sizes");
+    let group = c.benchmark_group("message_sizes");
 
     for format in &[BinaryFormat::Json, BinaryFormat::MessagePack, BinaryFormat::Bincode] {
         let format_name = match format {
@@ -169,6 +237,93 @@ fn benchmark_delta_compression_field_level(c: &mut Criterion) {
     });
 }
 
+fn benchmark_delta_application(c: &mut Criterion) {
+    let world_a = create_test_snapshot(10_000, 3);
+    let mut world_b = world_a.clone();
+    world_b.timestamp = 101.0;
+    for entity in world_b.entities.iter_mut().step_by(10) {
+        if let ComponentData::Structured(fields) = &mut entity.components[0].data {
+            fields.insert("x".to_string(), FieldValue::F64(-1.0));
+        }
+    }
+    world_b.entities.truncate(9_900);
+
+    let mut compressor = DeltaCompressor::new();
+    compressor.create_delta(world_a.clone());
+    let forward = compressor.create_delta(world_b.clone());
+    let back = compressor.create_delta(world_a.clone());
+
+    let mut group = c.benchmark_group("delta_application_10k");
+
+    // What a client rebuilding each tick from a cloned base pays.
+    group.bench_function("clone_then_apply", |b| {
+        b.iter(|| {
+            let mut world = world_a.clone();
+            world.apply_delta(black_box(&forward)).unwrap();
+            black_box(world);
+        });
+    });
+
+    // Applying to the live snapshot; alternating deltas keeps it repeatable.
+    let mut world = world_a.clone();
+    group.bench_function("apply_in_place", |b| {
+        b.iter(|| {
+            world.apply_delta(black_box(&forward)).unwrap();
+            world.apply_delta(black_box(&back)).unwrap();
+        });
+    });
+
+    group.finish();
+}
+
+fn benchmark_idle_delta(c: &mut Criterion) {
+    let world = create_test_snapshot(10_000, 3);
+    let mut compressor = DeltaCompressor::new();
+    compressor.create_delta(world.clone());
+
+    let mut group = c.benchmark_group("idle_delta_10k");
+
+    // The entity/component map diff an unchanged tick used to go through.
+    group.bench_function("map_diff", |b| {
+        b.iter(|| black_box(world.changed_entities(black_box(&world))));
+    });
+
+    // Entity-by-entity comparison with the recorded baseline.
+    group.bench_function("equality_precheck", |b| {
+        b.iter(|| black_box(compressor.peek_delta(black_box(&world))));
+    });
+
+    group.finish();
+}
+
+fn benchmark_initial_delta(c: &mut Criterion) {
+    let world = create_test_snapshot(1000, 10);
+
+    let mut group = c.benchmark_group("initial_delta_1000");
+
+    // The compressor is returned with the delta, so the baseline it keeps is
+    // part of what is measured rather than dropped for free.
+    group.bench_function("create_delta", |b| {
+        b.iter_batched(
+            || world.clone(),
+            |snapshot| {
+                let mut compressor = DeltaCompressor::new();
+                let delta = compressor.create_delta(snapshot);
+                black_box((compressor, delta))
+            },
+            BatchSize::LargeInput,
+        );
+    });
+
+    // Diffing alone, without keeping a baseline.
+    group.bench_function("peek_delta", |b| {
+        let compressor = DeltaCompressor::new();
+        b.iter(|| black_box(compressor.peek_delta(&world)));
+    });
+
+    group.finish();
+}
+
 fn benchmark_snapshot_sizes(c: &mut Criterion) {
     let mut group = c.benchmark_group("snapshot_size_scaling");
 
@@ -195,7 +350,9 @@ fn benchmark_snapshot_sizes(c: &mut Criterion) {
 fn benchmark_message_serialization(c: &mut Criterion) {
     let mut group = c.benchmark_group("message_serialization");
 
-    let message = Message::ping(1);
+    let message = Message::ping(SchemaVersion::INITIAL);
+    let snapshot = create_test_snapshot(100, 2);
+    let snapshot_message = Message::snapshot(snapshot.entities, snapshot.timestamp, SchemaVersion::INITIAL);
 
     for format in &[BinaryFormat::Json, BinaryFormat::MessagePack, BinaryFormat::Bincode] {
         let format_name = match format {
@@ -214,6 +371,44 @@ fn benchmark_message_serialization(c: &mut Criterion) {
                 });
             },
         );
+
+        // A send loop reusing one buffer versus a fresh allocation per message.
+        let serializer = BinarySerializer::new(*format);
+        let mut buffer = bytes::BytesMut::new();
+        let fresh = allocations_per_call(100, || {
+            black_box(serializer.serialize_message(&snapshot_message).unwrap());
+        });
+        let pooled = allocations_per_call(100, || {
+            buffer.clear();
+            serializer.serialize_message_into(&snapshot_message, &mut buffer).unwrap();
+            black_box(&buffer);
+        });
+        println!("message_serialization/{}: {:.1} allocations per snapshot fresh, {:.1} pooled", format_name, fresh, pooled);
+
+        group.bench_with_input(
+            BenchmarkId::new("serialize_snapshot_message/fresh", format_name),
+            format,
+            |b, format| {
+                let serializer = BinarySerializer::new(*format);
+                b.iter(|| {
+                    black_box(serializer.serialize_message(&snapshot_message).unwrap());
+                });
+            },
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("serialize_snapshot_message/pooled", format_name),
+            format,
+            |b, format| {
+                let serializer = BinarySerializer::new(*format);
+                let mut buffer = bytes::BytesMut::new();
+                b.iter(|| {
+                    buffer.clear();
+                    serializer.serialize_message_into(&snapshot_message, &mut buffer).unwrap();
+                    black_box(&buffer);
+                });
+            },
+        );
     }
 
     group.finish();
@@ -268,16 +463,91 @@ fn benchmark_delta_size_comparison(c: &mut Criterion) {
     });
 }
 
+fn benchmark_payload_tag_sizes(c: &mut Criterion) {
+    // The string-tagged representation binary formats used before payloads
+    // were tagged with their `MessageType` discriminant.
+    #[derive(serde::Serialize)]
+    #[serde(tag = "type", rename_all = "snake_case")]
+    enum StringTagged {
+        Ping,
+        Ack { ack_id: u64 },
+    }
+
+    let version = SchemaVersion::new(1);
+    let messages = [
+        ("ping", Message::ping(version), StringTagged::Ping),
+        ("ack", Message::ack(42, version), StringTagged::Ack { ack_id: 42 }),
+    ];
+
+    println!("\n=== Payload Tag Sizes (MessagePack) ===");
+
+    let serializer = BinarySerializer::messagepack();
+    for (name, message, string_tagged) in &messages {
+        let compact = serializer.serialize_message(message).unwrap().len();
+        let tagged = rmp_serde::to_vec(&(&message.header, string_tagged)).unwrap().len();
+        println!("{}: {} bytes with a numeric tag, {} with a string tag ({:.2}% smaller)",
+                 name, compact, tagged, (tagged - compact) as f64 / tagged as f64 * 100.0);
+    }
+
+    let ping = Message::ping(version);
+    c.bench_function("ping_serialization", |b| {
+        b.iter(|| black_box(serializer.serialize_message(black_box(&ping)).unwrap()));
+    });
+}
+
+/// Sequential encoding of a large snapshot against chunked encoding on the
+/// rayon pool. Run with `--features rayon` to include the parallel cases.
+fn benchmark_parallel_snapshot(c: &mut Criterion) {
+    let mut group = c.benchmark_group("parallel_snapshot");
+    group.sample_size(20);
+
+    for entity_count in [10_000, 100_000] {
+        let snapshot = create_test_snapshot(entity_count, 5);
+        group.throughput(Throughput::Elements(entity_count as u64));
+
+        let sequential = BinarySerializer::messagepack();
+        group.bench_with_input(BenchmarkId::new("serialize_sequential", entity_count), &snapshot, |b, snapshot| {
+            b.iter(|| black_box(sequential.serialize_snapshot(snapshot).unwrap()));
+        });
+
+        #[cfg(feature = "rayon")]
+        {
+            let parallel = BinarySerializer::messagepack().with_parallel_snapshots(1000);
+            group.bench_with_input(BenchmarkId::new("serialize_parallel", entity_count), &snapshot, |b, snapshot| {
+                b.iter(|| black_box(parallel.serialize_snapshot(snapshot).unwrap()));
+            });
+
+            let encoded = parallel.serialize_snapshot(&snapshot).unwrap();
+            group.bench_with_input(BenchmarkId::new("deserialize_parallel", entity_count), &encoded, |b, encoded| {
+                b.iter(|| black_box(parallel.deserialize_snapshot(encoded).unwrap()));
+            });
+        }
+
+        let encoded = sequential.serialize_snapshot(&snapshot).unwrap();
+        group.bench_with_input(BenchmarkId::new("deserialize_sequential", entity_count), &encoded, |b, encoded| {
+            b.iter(|| black_box(sequential.deserialize_snapshot(encoded).unwrap()));
+        });
+    }
+
+    group.finish();
+}
+
 criterion_group!(
     benches,
     benchmark_serialization_formats,
     benchmark_deserialization_formats,
+    benchmark_binary_deserialization,
     benchmark_message_sizes,
     benchmark_delta_compression,
     benchmark_delta_compression_field_level,
+    benchmark_delta_application,
+    benchmark_idle_delta,
+    benchmark_initial_delta,
     benchmark_snapshot_sizes,
     benchmark_message_serialization,
     benchmark_delta_size_comparison,
+    benchmark_payload_tag_sizes,
+    benchmark_parallel_snapshot,
 );
 
 criterion_main!(benches);
@@ -0,0 +1,34 @@
+#![no_main]
+
+//! Arbitrary bytes must decompress or fail without panicking, never produce
+//! more than the requested limit, and compressed data must roundtrip.
+//!
+//! Run with `cargo fuzz run decompress` from the repository root.
+
+use libfuzzer_sys::fuzz_target;
+use tx2_link::compression::{compress, decompress_bounded, decompress_component_bounded};
+use tx2_link::protocol::{ComponentData, CompressionType};
+
+const LIMIT: usize = 1 << 16;
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    compression: u8,
+    data: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let compression = [CompressionType::None, CompressionType::Deflate, CompressionType::Lz4]
+        [usize::from(input.compression) % 3];
+
+    if let Ok(decoded) = decompress_bounded(input.data, compression, LIMIT) {
+        assert!(decoded.len() <= LIMIT);
+    }
+
+    let data = ComponentData::Compressed { compression, data: input.data.to_vec().into() };
+    let _ = decompress_component_bounded(data, LIMIT);
+
+    let compressed = compress(input.data, compression).expect("supported compression");
+    let decoded = decompress_bounded(&compressed, compression, input.data.len()).expect("compressed data decodes");
+    assert_eq!(decoded, input.data);
+});
@@ -0,0 +1,44 @@
+#![no_main]
+
+//! Arbitrary bytes fed to the frame readers, in arbitrary chunks, must yield
+//! messages or errors without panicking or allocating past the frame limit.
+//!
+//! Run with `cargo fuzz run frame` from the repository root.
+
+use libfuzzer_sys::fuzz_target;
+use tx2_link::protocol::CompressionType;
+use tx2_link::serialization::StreamingDeserializer;
+use tx2_link::{BinaryFormat, BinarySerializer, FrameCodec, MessageLimits};
+
+#[derive(Debug, arbitrary::Arbitrary)]
+struct Input<'a> {
+    chunk_len: u8,
+    frame_compression: bool,
+    shared_dictionary: bool,
+    delta_timestamps: bool,
+    data: &'a [u8],
+}
+
+fuzz_target!(|input: Input| {
+    let limits = MessageLimits::new().with_max_message_bytes(1 << 16).with_max_decompressed_bytes(1 << 16);
+
+    let _ = FrameCodec::read_from_bounded(&mut &input.data[..], limits.max_message_bytes);
+
+    for format in [BinaryFormat::Json, BinaryFormat::MessagePack, BinaryFormat::Bincode] {
+        let serializer = BinarySerializer::new(format).with_limits(limits);
+        let compression = if input.frame_compression { CompressionType::Lz4 } else { CompressionType::None };
+        let mut messages = StreamingDeserializer::with_serializer(serializer.clone())
+            .with_frame_compression(compression)
+            .with_shared_dictionary(input.shared_dictionary);
+        let mut deltas = StreamingDeserializer::with_serializer(serializer)
+            .with_frame_compression(compression)
+            .with_delta_timestamps(input.delta_timestamps);
+
+        for chunk in input.data.chunks(usize::from(input.chunk_len).max(1)) {
+            messages.feed(chunk);
+            while let Ok(Some(_)) = messages.try_read_message() {}
+            deltas.feed(chunk);
+            while let Ok(Some(_)) = deltas.try_read_delta() {}
+        }
+    }
+});
@@ -0,0 +1,306 @@
+use crate::compression::FieldCompressor;
+use crate::component::LinkComponent;
+use crate::error::{LinkError, Result};
+use crate::protocol::{ComponentData, ComponentId, DeltaChange, EntityId, FieldDelta, SerializedComponent, SerializedEntity};
+use crate::serialization::{Delta, WorldSnapshot};
+use ahash::AHashMap;
+use bevy_ecs::component::Component;
+use bevy_ecs::entity::Entity;
+use bevy_ecs::world::{EntityRef, EntityWorldMut, World};
+use std::marker::PhantomData;
+
+trait ComponentReplicator: Send + Sync {
+    fn component_id(&self) -> ComponentId;
+    fn extract(&self, entity: &EntityRef) -> Option<ComponentData>;
+    fn insert(&self, entity: &mut EntityWorldMut, data: &ComponentData) -> Result<()>;
+    fn remove(&self, entity: &mut EntityWorldMut);
+    fn apply_fields(&self, entity: &mut EntityWorldMut, fields: &[FieldDelta]) -> Result<()>;
+}
+
+struct TypedReplicator<T>(PhantomData<fn() -> T>);
+
+impl<T: Component + LinkComponent> ComponentReplicator for TypedReplicator<T> {
+    fn component_id(&self) -> ComponentId {
+        T::component_id()
+    }
+
+    fn extract(&self, entity: &EntityRef) -> Option<ComponentData> {
+        entity.get::<T>().map(|c| c.to_component_data())
+    }
+
+    fn insert(&self, entity: &mut EntityWorldMut, data: &ComponentData) -> Result<()> {
+        entity.insert(T::from_component_data(data)?);
+        Ok(())
+    }
+
+    fn remove(&self, entity: &mut EntityWorldMut) {
+        entity.remove::<T>();
+    }
+
+    fn apply_fields(&self, entity: &mut EntityWorldMut, fields: &[FieldDelta]) -> Result<()> {
+        let data = match entity.get::<T>().map(|c| c.to_component_data()) {
+            Some(data @ ComponentData::Structured(_)) => data,
+            _ => ComponentData::Structured(Default::default()),
+        };
+
+        self.insert(entity, &FieldCompressor::new().apply_field_deltas(&data, fields)?)
+    }
+}
+
+/// Replicates a fixed set of component types between a Bevy `World` and tx2-link snapshots.
+///
+/// Sender side: `extract_snapshot` assigns every replicated Bevy `Entity` a stable
+/// `EntityId` that is never reused, even after the Bevy entity is despawned.
+/// Receiver side: `apply_snapshot`/`apply_delta` spawn a local entity per remote
+/// `EntityId` and keep the mapping for later updates.
+pub struct BevyReplicator {
+    replicators: Vec<Box<dyn ComponentReplicator>>,
+    local_ids: AHashMap<Entity, EntityId>,
+    remote_entities: AHashMap<EntityId, Entity>,
+    next_id: EntityId,
+}
+
+impl BevyReplicator {
+    pub fn new() -> Self {
+        Self {
+            replicators: Vec::new(),
+            local_ids: AHashMap::new(),
+            remote_entities: AHashMap::new(),
+            next_id: 1,
+        }
+    }
+
+    pub fn with_component<T: Component + LinkComponent>(mut self) -> Self {
+        self.register::<T>();
+        self
+    }
+
+    pub fn register<T: Component + LinkComponent>(&mut self) {
+        self.replicators.push(Box::new(TypedReplicator::<T>(PhantomData)));
+    }
+
+    pub fn entity_id(&self, entity: Entity) -> Option<EntityId> {
+        self.local_ids.get(&entity).copied()
+    }
+
+    pub fn local_entity(&self, entity_id: EntityId) -> Option<Entity> {
+        self.remote_entities.get(&entity_id).copied()
+    }
+
+    pub fn extract_snapshot(&mut self, world: &World, timestamp: f64) -> WorldSnapshot {
+        let mut entities = Vec::new();
+
+        for entity in world.iter_entities() {
+            let components: Vec<_> = self.replicators.iter()
+                .filter_map(|r| r.extract(&entity).map(|data| SerializedComponent {
+                    id: r.component_id(),
+                    data,
+                }))
+                .collect();
+
+            if components.is_empty() {
+                continue;
+            }
+
+            let next_id = &mut self.next_id;
+            let id = *self.local_ids.entry(entity.id()).or_insert_with(|| {
+                let id = *next_id;
+                *next_id += 1;
+                id
+            });
+
+            entities.push(SerializedEntity { id, components });
+        }
+
+        self.local_ids.retain(|entity, _| world.get_entity(*entity).is_some());
+
+        WorldSnapshot {
+            entities,
+            timestamp,
+            version: "1.0.0".to_string(),
+        }
+    }
+
+    pub fn apply_snapshot(&mut self, world: &mut World, snapshot: &WorldSnapshot) -> Result<()> {
+        let mut seen = AHashMap::new();
+
+        for remote in &snapshot.entities {
+            let entity = self.spawn_or_get(world, remote.id);
+            seen.insert(remote.id, entity);
+
+            let mut entity_mut = world.entity_mut(entity);
+            for replicator in &self.replicators {
+                let id = replicator.component_id();
+                match remote.components.iter().find(|c| c.id == id) {
+                    Some(component) => replicator.insert(&mut entity_mut, &component.data)?,
+                    None => replicator.remove(&mut entity_mut),
+                }
+            }
+        }
+
+        for (entity_id, entity) in std::mem::take(&mut self.remote_entities) {
+            if !seen.contains_key(&entity_id) {
+                world.despawn(entity);
+            }
+        }
+        self.remote_entities = seen;
+
+        Ok(())
+    }
+
+    pub fn apply_delta(&mut self, world: &mut World, delta: &Delta) -> Result<()> {
+        for change in &delta.changes {
+            match change {
+                DeltaChange::EntityAdded { entity_id } => {
+                    self.spawn_or_get(world, *entity_id);
+                }
+                DeltaChange::EntityRemoved { entity_id } => {
+                    if let Some(entity) = self.remote_entities.remove(entity_id) {
+                        world.despawn(entity);
+                    }
+                }
+                DeltaChange::ComponentAdded { entity_id, component_id, data }
+                | DeltaChange::ComponentUpdated { entity_id, component_id, data } => {
+                    let entity = self.spawn_or_get(world, *entity_id);
+                    if let Some(replicator) = self.replicator(component_id) {
+                        replicator.insert(&mut world.entity_mut(entity), data)?;
+                    }
+                }
+                DeltaChange::ComponentRemoved { entity_id, component_id } => {
+                    if let (Some(entity), Some(replicator)) = (self.local_entity(*entity_id), self.replicator(component_id)) {
+                        if let Some(mut entity_mut) = world.get_entity_mut(entity) {
+                            replicator.remove(&mut entity_mut);
+                        }
+                    }
+                }
+                DeltaChange::FieldsUpdated { entity_id, component_id, fields } => {
+                    let entity = self.spawn_or_get(world, *entity_id);
+                    if let Some(replicator) = self.replicator(component_id) {
+                        replicator.apply_fields(&mut world.entity_mut(entity), fields)?;
+                    }
+                }
+                DeltaChange::EntityComponentsReplaced { entity_id, components } => {
+                    let entity = self.spawn_or_get(world, *entity_id);
+                    let mut entity_mut = world.entity_mut(entity);
+                    for replicator in &self.replicators {
+                        match components.iter().find(|c| c.id == replicator.component_id()) {
+                            Some(component) => replicator.insert(&mut entity_mut, &component.data)?,
+                            None => replicator.remove(&mut entity_mut),
+                        }
+                    }
+                }
+                DeltaChange::IndexedFieldsUpdated { component_id, .. } => {
+                    return Err(LinkError::InvalidMessage(format!(
+                        "Indexed field update for {} needs SchemaRegistry::expand_changes first",
+                        component_id
+                    )));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn replicator(&self, component_id: &str) -> Option<&dyn ComponentReplicator> {
+        self.replicators.iter()
+            .find(|r| r.component_id() == component_id)
+            .map(|r| r.as_ref())
+    }
+
+    fn spawn_or_get(&mut self, world: &mut World, entity_id: EntityId) -> Entity {
+        if let Some(entity) = self.remote_entities.get(&entity_id) {
+            if world.get_entity(*entity).is_some() {
+                return *entity;
+            }
+        }
+
+        let entity = world.spawn_empty().id();
+        self.remote_entities.insert(entity_id, entity);
+        entity
+    }
+}
+
+impl Default for BevyReplicator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::DeltaCompressor;
+
+    #[derive(Component, LinkComponent, Debug, Clone, PartialEq)]
+    struct Position {
+        x: f64,
+        y: f64,
+    }
+
+    #[derive(Component, LinkComponent, Debug, Clone, PartialEq)]
+    struct Health {
+        current: u32,
+    }
+
+    #[derive(Component)]
+    struct NotReplicated;
+
+    fn replicator() -> BevyReplicator {
+        BevyReplicator::new()
+            .with_component::<Position>()
+            .with_component::<Health>()
+    }
+
+    #[test]
+    fn test_bevy_snapshot_roundtrip() {
+        let mut server = World::new();
+        let player = server.spawn((Position { x: 1.0, y: 2.0 }, Health { current: 100 })).id();
+        server.spawn(NotReplicated);
+
+        let mut sender = replicator();
+        let snapshot = sender.extract_snapshot(&server, 1.0);
+        assert_eq!(snapshot.entities.len(), 1);
+        assert_eq!(snapshot.entities[0].components.len(), 2);
+
+        let mut client = World::new();
+        let mut receiver = replicator();
+        receiver.apply_snapshot(&mut client, &snapshot).unwrap();
+
+        let remote_id = sender.entity_id(player).unwrap();
+        let local = receiver.local_entity(remote_id).unwrap();
+        assert_eq!(client.get::<Position>(local), Some(&Position { x: 1.0, y: 2.0 }));
+        assert_eq!(client.get::<Health>(local), Some(&Health { current: 100 }));
+    }
+
+    #[test]
+    fn test_bevy_delta_application() {
+        let mut server = World::new();
+        let player = server.spawn((Position { x: 0.0, y: 0.0 }, Health { current: 100 })).id();
+
+        let mut sender = replicator();
+        let mut receiver = replicator();
+        let mut compressor = DeltaCompressor::new();
+        let mut client = World::new();
+
+        let initial = compressor.create_delta(sender.extract_snapshot(&server, 1.0));
+        receiver.apply_delta(&mut client, &initial).unwrap();
+
+        server.get_mut::<Position>(player).unwrap().x = 5.0;
+        server.entity_mut(player).remove::<Health>();
+        let enemy = server.spawn(Health { current: 30 }).id();
+
+        let delta = compressor.create_delta(sender.extract_snapshot(&server, 2.0));
+        receiver.apply_delta(&mut client, &delta).unwrap();
+
+        let local_player = receiver.local_entity(sender.entity_id(player).unwrap()).unwrap();
+        let local_enemy = receiver.local_entity(sender.entity_id(enemy).unwrap()).unwrap();
+        assert_eq!(client.get::<Position>(local_player), Some(&Position { x: 5.0, y: 0.0 }));
+        assert!(client.get::<Health>(local_player).is_none());
+        assert_eq!(client.get::<Health>(local_enemy), Some(&Health { current: 30 }));
+
+        server.despawn(enemy);
+        let delta = compressor.create_delta(sender.extract_snapshot(&server, 3.0));
+        receiver.apply_delta(&mut client, &delta).unwrap();
+        assert!(client.get_entity(local_enemy).is_none());
+    }
+}
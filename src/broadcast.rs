@@ -0,0 +1,511 @@
+use crate::compression::DeltaCompressor;
+use crate::error::{LinkError, Result};
+use crate::protocol::{Message, MessagePayload};
+use crate::schema::SchemaVersion;
+use crate::serialization::{BinarySerializer, WorldSnapshot};
+use crate::sync::wire_timestamp;
+use crate::transport::Transport;
+use bytes::Bytes;
+use std::collections::{BTreeMap, VecDeque};
+
+pub type PeerId = u64;
+
+struct Peer<T> {
+    transport: T,
+    /// Timestamp of the last state sent to the peer; `None` until it has had a
+    /// snapshot.
+    sent: Option<f64>,
+    /// Latest timestamp the peer acknowledged, when using ack baselines.
+    acked: Option<f64>,
+}
+
+#[derive(Debug)]
+pub enum BroadcastEvent {
+    Ack { peer: PeerId, ack_id: u64 },
+    /// `answered` is false when nothing has been broadcast yet.
+    SnapshotRequested { peer: PeerId, since: Option<u64>, answered: bool },
+    /// The peer's transport closed or failed to send; it has been removed.
+    Disconnected { peer: PeerId },
+    /// Receiving from the peer failed, e.g. on a malformed message, or
+    /// sending to it did, in which case `Disconnected` follows.
+    Error { peer: PeerId, error: LinkError },
+    /// Anything else the peer sent, passed along unprocessed.
+    Message { peer: PeerId, message: Message },
+}
+
+/// Sends one world to many peers, diffing each snapshot once against a single
+/// shared `DeltaCompressor`.
+///
+/// Peers that received the previous broadcast get a delta; new peers, and
+/// peers whose baseline has left the history, get a full snapshot. Each
+/// distinct message is encoded once per distinct serializer and the bytes go
+/// to every peer through [`Transport::send_encoded`].
+pub struct BroadcastManager<T: Transport> {
+    peers: BTreeMap<PeerId, Peer<T>>,
+    next_peer_id: PeerId,
+    next_poll: PeerId,
+    delta_compressor: DeltaCompressor,
+    ack_baselines: bool,
+    schema_version: SchemaVersion,
+    pending_events: VecDeque<BroadcastEvent>,
+}
+
+impl<T: Transport> BroadcastManager<T> {
+    pub fn new() -> Self {
+        Self {
+            peers: BTreeMap::new(),
+            next_peer_id: 0,
+            next_poll: 0,
+            delta_compressor: DeltaCompressor::new(),
+            ack_baselines: false,
+            schema_version: SchemaVersion::INITIAL,
+            pending_events: VecDeque::new(),
+        }
+    }
+
+    /// Number of broadcast snapshots kept as baselines for lagging peers.
+    pub fn with_history_size(mut self, size: usize) -> Self {
+        self.delta_compressor = self.delta_compressor.with_history_size(size);
+        self
+    }
+
+    /// Diff against each peer's last acknowledged state (see
+    /// [`Message::ack_baseline`]) instead of assuming every broadcast arrived.
+    /// Peers that haven't acked anything still in the history get full
+    /// snapshots, so pair this with `with_history_size`.
+    pub fn with_ack_baselines(mut self, enabled: bool) -> Self {
+        self.ack_baselines = enabled;
+        self
+    }
+
+    pub fn add_peer(&mut self, transport: T) -> PeerId {
+        let id = self.next_peer_id;
+        self.next_peer_id += 1;
+        self.peers.insert(id, Peer { transport, sent: None, acked: None });
+        id
+    }
+
+    pub fn remove_peer(&mut self, peer: PeerId) -> Option<T> {
+        self.peers.remove(&peer).map(|p| p.transport)
+    }
+
+    pub fn peer_ids(&self) -> impl Iterator<Item = PeerId> + '_ {
+        self.peers.keys().copied()
+    }
+
+    pub fn peer_count(&self) -> usize {
+        self.peers.len()
+    }
+
+    pub fn get_peer(&self, peer: PeerId) -> Option<&T> {
+        self.peers.get(&peer).map(|p| &p.transport)
+    }
+
+    pub fn get_peer_mut(&mut self, peer: PeerId) -> Option<&mut T> {
+        self.peers.get_mut(&peer).map(|p| &mut p.transport)
+    }
+
+    pub fn set_schema_version(&mut self, version: SchemaVersion) {
+        self.schema_version = version;
+    }
+
+    pub fn get_schema_version(&self) -> SchemaVersion {
+        self.schema_version
+    }
+
+    /// Send `snapshot` to every connected peer and return how many were sent
+    /// to. Peers already holding identical state are skipped. Every message is
+    /// encoded before anything is sent, so an encoding error fails the whole
+    /// broadcast and leaves every peer as it was. A peer whose transport fails
+    /// to send is reported through [`Self::receive`] with the error and
+    /// removed, as are disconnected peers.
+    pub fn send(&mut self, snapshot: WorldSnapshot) -> Result<usize> {
+        self.drop_disconnected();
+
+        // Peers grouped by the baseline their message is built against.
+        let mut groups: Vec<(Option<f64>, Vec<PeerId>)> = Vec::new();
+        for (&id, peer) in &self.peers {
+            let base = if self.ack_baselines { peer.acked } else { peer.sent }
+                .filter(|&ts| self.delta_compressor.history().any(|s| s.timestamp == ts));
+
+            match groups.iter_mut().find(|(b, _)| *b == base) {
+                Some((_, ids)) => ids.push(id),
+                None => groups.push((base, vec![id])),
+            }
+        }
+
+        // Each group's message, `None` where the peers already have this state,
+        // and its encodings, one per distinct serializer among the peers.
+        let mut messages: Vec<(Option<Message>, Vec<PeerId>)> = Vec::with_capacity(groups.len());
+        let mut encoded: Vec<(usize, BinarySerializer, Bytes)> = Vec::new();
+        for (base, ids) in groups {
+            let message = match base {
+                Some(base) => {
+                    let delta = self.delta_compressor.peek_delta_from(base, &snapshot);
+                    (!delta.changes.is_empty()).then(|| {
+                        Message::delta_at(delta.changes, wire_timestamp(base), snapshot.timestamp, self.schema_version)
+                    })
+                }
+                None => Some(Message::snapshot(snapshot.entities.clone(), snapshot.timestamp, self.schema_version)),
+            };
+
+            if let Some(message) = &message {
+                let index = messages.len();
+                for serializer in ids.iter().filter_map(|id| self.peers[id].transport.serializer()) {
+                    if !encoded.iter().any(|(i, s, _)| *i == index && s.same_encoding(serializer)) {
+                        encoded.push((index, serializer.clone(), serializer.serialize_message(message)?));
+                    }
+                }
+            }
+            messages.push((message, ids));
+        }
+
+        let mut sent = 0;
+        let mut failed = Vec::new();
+
+        for (index, (message, ids)) in messages.iter().enumerate() {
+            for &id in ids {
+                let Some(peer) = self.peers.get_mut(&id) else { continue };
+                let Some(message) = message else {
+                    peer.sent = Some(snapshot.timestamp);
+                    continue;
+                };
+
+                let data = peer.transport.serializer().and_then(|serializer| {
+                    encoded.iter().find(|(i, s, _)| *i == index && s.same_encoding(serializer))
+                });
+                let result = match data {
+                    Some((_, _, data)) => peer.transport.send_encoded(message, data),
+                    None => peer.transport.send(message),
+                };
+
+                match result {
+                    Ok(()) => {
+                        peer.sent = Some(snapshot.timestamp);
+                        sent += 1;
+                    }
+                    // The peer keeps its baseline and catches up later.
+                    Err(LinkError::Backpressure(_)) => {}
+                    Err(error) => failed.push((id, error)),
+                }
+            }
+        }
+
+        // Peers now hold this state, so it must be in the history to diff against.
+        self.delta_compressor.record(snapshot);
+        for (id, error) in failed {
+            self.fail(id, error);
+        }
+
+        Ok(sent)
+    }
+
+    /// Poll the peers in turn for the next event. Acks, snapshot requests and
+    /// pings are handled here; queued disconnects are reported first.
+    pub fn receive(&mut self) -> Result<Option<BroadcastEvent>> {
+        if let Some(event) = self.pending_events.pop_front() {
+            return Ok(Some(event));
+        }
+
+        let ids: Vec<PeerId> = self.peers.range(self.next_poll..)
+            .chain(self.peers.range(..self.next_poll))
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in ids {
+            let Some(peer) = self.peers.get_mut(&id) else { continue };
+            let received = peer.transport.receive();
+            self.next_poll = id + 1;
+
+            match received {
+                Ok(Some(message)) => {
+                    if let Some(event) = self.process_message(id, message) {
+                        return Ok(Some(event));
+                    }
+                    // Replying may have failed the peer.
+                    if let Some(event) = self.pending_events.pop_front() {
+                        return Ok(Some(event));
+                    }
+                }
+                Ok(None) => {}
+                Err(LinkError::ConnectionClosed) => {
+                    self.peers.remove(&id);
+                    return Ok(Some(BroadcastEvent::Disconnected { peer: id }));
+                }
+                Err(error) => return Ok(Some(BroadcastEvent::Error { peer: id, error })),
+            }
+        }
+
+        Ok(None)
+    }
+
+    fn process_message(&mut self, id: PeerId, message: Message) -> Option<BroadcastEvent> {
+        match message.payload {
+            MessagePayload::Ack { ack_id, timestamp } => {
+                let acked = timestamp.and_then(|timestamp| {
+                    self.delta_compressor.history()
+                        .map(|s| s.timestamp)
+                        .find(|&ts| wire_timestamp(ts) == timestamp)
+                });
+
+                if let (Some(ts), Some(peer)) = (acked, self.peers.get_mut(&id)) {
+                    if peer.acked.is_none_or(|current| ts > current) {
+                        peer.acked = Some(ts);
+                    }
+                }
+                Some(BroadcastEvent::Ack { peer: id, ack_id })
+            }
+            MessagePayload::RequestSnapshot { since } => {
+                let answered = self.answer_snapshot_request(id, since);
+                Some(BroadcastEvent::SnapshotRequested { peer: id, since, answered })
+            }
+            MessagePayload::Ping => {
+                let pong = Message::pong_for(message.header.id, self.schema_version);
+                let result = self.peers.get_mut(&id).map_or(Ok(()), |peer| peer.transport.send(&pong));
+                self.check_reply(id, result);
+                None
+            }
+            _ => Some(BroadcastEvent::Message { peer: id, message }),
+        }
+    }
+
+    /// Reply to one peer with a delta from `since` if it is still in the
+    /// history, otherwise with the latest snapshot. Whether the reply went out.
+    fn answer_snapshot_request(&mut self, id: PeerId, since: Option<u64>) -> bool {
+        let Some(latest) = self.delta_compressor.history().last() else {
+            return false;
+        };
+        let Some(peer) = self.peers.get_mut(&id) else {
+            return false;
+        };
+
+        let base = since.and_then(|since| {
+            self.delta_compressor.history()
+                .find(|s| wire_timestamp(s.timestamp) == since)
+        });
+
+        let message = match base {
+            Some(base) => {
+                let delta = self.delta_compressor.peek_delta_from(base.timestamp, latest);
+                Message::delta_at(delta.changes, wire_timestamp(base.timestamp), latest.timestamp, self.schema_version)
+            }
+            None => Message::snapshot(latest.entities.clone(), latest.timestamp, self.schema_version),
+        };
+
+        let timestamp = latest.timestamp;
+        let result = peer.transport.send(&message);
+        if result.is_ok() {
+            peer.sent = Some(timestamp);
+        }
+        self.check_reply(id, result)
+    }
+
+    /// Fail the peer if a reply to it couldn't be sent, other than for
+    /// backpressure. Whether it was sent.
+    fn check_reply(&mut self, id: PeerId, result: Result<()>) -> bool {
+        match result {
+            Ok(()) => true,
+            Err(LinkError::Backpressure(_)) => false,
+            Err(error) => {
+                self.fail(id, error);
+                false
+            }
+        }
+    }
+
+    fn drop_disconnected(&mut self) {
+        let gone: Vec<PeerId> = self.peers.iter()
+            .filter(|(_, peer)| !peer.transport.is_connected())
+            .map(|(&id, _)| id)
+            .collect();
+
+        for id in gone {
+            self.peers.remove(&id);
+            self.pending_events.push_back(BroadcastEvent::Disconnected { peer: id });
+        }
+    }
+
+    /// Report `error` for the peer, then drop it.
+    fn fail(&mut self, id: PeerId, error: LinkError) {
+        if self.peers.contains_key(&id) {
+            self.pending_events.push_back(BroadcastEvent::Error { peer: id, error });
+            self.disconnect(id);
+        }
+    }
+
+    fn disconnect(&mut self, id: PeerId) {
+        if let Some(mut peer) = self.peers.remove(&id) {
+            let _ = peer.transport.close();
+            self.pending_events.push_back(BroadcastEvent::Disconnected { peer: id });
+        }
+    }
+
+    /// Close every peer's transport and forget the peers.
+    pub fn close(&mut self) -> Result<()> {
+        for (_, mut peer) in std::mem::take(&mut self.peers) {
+            peer.transport.close()?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> Default for BroadcastManager<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{ComponentData, MessageType, SerializedComponent, SerializedEntity};
+    use crate::serialization::{BinaryFormat, Delta, MessageCodec};
+    use crate::transport::MemoryTransport;
+
+    /// MessagePack, except that messages of one type fail to encode.
+    struct FailOn(MessageType);
+
+    impl MessageCodec for FailOn {
+        fn name(&self) -> &str {
+            "FailOn"
+        }
+
+        fn encode(&self, message: &Message) -> Result<Bytes> {
+            if message.header.msg_type == self.0 {
+                return Err(LinkError::Serialization(format!("{:?} refused", self.0)));
+            }
+            BinaryFormat::MessagePack.encode(message)
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<Message> {
+            BinaryFormat::MessagePack.decode(data)
+        }
+
+        fn encode_snapshot(&self, snapshot: &WorldSnapshot) -> Result<Bytes> {
+            BinaryFormat::MessagePack.encode_snapshot(snapshot)
+        }
+
+        fn decode_snapshot(&self, data: &[u8]) -> Result<WorldSnapshot> {
+            BinaryFormat::MessagePack.decode_snapshot(data)
+        }
+
+        fn encode_delta(&self, delta: &Delta) -> Result<Bytes> {
+            BinaryFormat::MessagePack.encode_delta(delta)
+        }
+
+        fn decode_delta(&self, data: &[u8]) -> Result<Delta> {
+            BinaryFormat::MessagePack.decode_delta(data)
+        }
+    }
+
+    fn failing_on(msg_type: MessageType) -> MemoryTransport {
+        MemoryTransport::with_serializer(BinarySerializer::with_codec(FailOn(msg_type)))
+    }
+
+    fn snapshot(timestamp: f64, value: &str) -> WorldSnapshot {
+        WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 1,
+                components: vec![SerializedComponent {
+                    id: "Name".to_string(),
+                    data: ComponentData::Json(value.to_string()),
+                }],
+            }],
+            timestamp,
+            version: "1.0.0".to_string(),
+        }
+    }
+
+    fn received(manager: &mut BroadcastManager<MemoryTransport>, peer: PeerId) -> Vec<MessageType> {
+        let mut client = MemoryTransport::new(BinaryFormat::MessagePack);
+        manager.get_peer_mut(peer).unwrap().connect_to(&mut client);
+        std::iter::from_fn(|| client.receive().unwrap())
+            .map(|m| m.header.msg_type)
+            .collect()
+    }
+
+    #[test]
+    fn test_broadcast_fan_out() {
+        let mut manager = BroadcastManager::new();
+        let a = manager.add_peer(MemoryTransport::new(BinaryFormat::MessagePack));
+        let b = manager.add_peer(MemoryTransport::new(BinaryFormat::MessagePack));
+
+        assert_eq!(manager.send(snapshot(1.0, "\"a\"")).unwrap(), 2);
+        assert_eq!(manager.get_peer(a).unwrap().get_send_buffer(), manager.get_peer(b).unwrap().get_send_buffer());
+
+        // A late joiner gets a snapshot while the others get the delta.
+        let c = manager.add_peer(MemoryTransport::new(BinaryFormat::MessagePack));
+        assert_eq!(manager.send(snapshot(2.0, "\"b\"")).unwrap(), 3);
+        assert_eq!(manager.get_peer(a).unwrap().get_send_buffer()[1], manager.get_peer(b).unwrap().get_send_buffer()[1]);
+        assert_eq!(received(&mut manager, a), vec![MessageType::Snapshot, MessageType::Delta]);
+        assert_eq!(received(&mut manager, c), vec![MessageType::Snapshot]);
+
+        // Nothing changed: nobody is sent anything.
+        assert_eq!(manager.send(snapshot(3.0, "\"b\"")).unwrap(), 0);
+
+        manager.get_peer_mut(b).unwrap().close().unwrap();
+        assert_eq!(manager.send(snapshot(4.0, "\"c\"")).unwrap(), 2);
+        assert!(matches!(manager.receive().unwrap(), Some(BroadcastEvent::Disconnected { peer }) if peer == b));
+        assert_eq!(manager.peer_count(), 2);
+    }
+
+    #[test]
+    fn test_broadcast_ack_baselines() {
+        let mut manager = BroadcastManager::new()
+            .with_ack_baselines(true)
+            .with_history_size(4);
+        let peer = manager.add_peer(MemoryTransport::new(BinaryFormat::MessagePack));
+        let mut client = MemoryTransport::new(BinaryFormat::MessagePack);
+
+        manager.send(snapshot(1.0, "\"a\"")).unwrap();
+        // Unacknowledged, so the next broadcast is a full snapshot again.
+        manager.send(snapshot(2.0, "\"b\"")).unwrap();
+
+        client.send(&Message::ack(wire_timestamp(2.0), SchemaVersion::new(1))).unwrap();
+        client.send(&Message::ack_baseline(wire_timestamp(2.0), SchemaVersion::new(1))).unwrap();
+        manager.get_peer_mut(peer).unwrap().connect_to(&mut client);
+        assert!(matches!(manager.receive().unwrap(), Some(BroadcastEvent::Ack { ack_id: 2000, .. })));
+        assert_eq!(manager.peers[&peer].acked, None);
+        assert!(matches!(manager.receive().unwrap(), Some(BroadcastEvent::Ack { ack_id: 0, .. })));
+
+        manager.send(snapshot(3.0, "\"c\"")).unwrap();
+        assert_eq!(received(&mut manager, peer), vec![MessageType::Delta]);
+    }
+
+    #[test]
+    fn test_broadcast_encodes_before_sending() {
+        let mut manager = BroadcastManager::new();
+        let good = manager.add_peer(MemoryTransport::new(BinaryFormat::MessagePack));
+        manager.add_peer(failing_on(MessageType::Snapshot));
+
+        assert!(manager.send(snapshot(1.0, "\"a\"")).is_err());
+        assert!(manager.get_peer(good).unwrap().get_send_buffer().is_empty());
+        assert!(manager.receive().unwrap().is_none());
+
+        // The failed broadcast left no trace: the good peer still needs a snapshot.
+        let bad = manager.peer_ids().find(|&id| id != good).unwrap();
+        manager.remove_peer(bad);
+        assert_eq!(manager.send(snapshot(2.0, "\"b\"")).unwrap(), 1);
+        assert_eq!(received(&mut manager, good), vec![MessageType::Snapshot]);
+    }
+
+    #[test]
+    fn test_broadcast_reports_failed_peers() {
+        let mut manager = BroadcastManager::new();
+        let good = manager.add_peer(MemoryTransport::new(BinaryFormat::MessagePack));
+        let bad = manager.add_peer(failing_on(MessageType::Pong));
+        manager.send(snapshot(1.0, "\"a\"")).unwrap();
+
+        // A failed pong drops that peer alone, without failing `receive`.
+        let mut client = MemoryTransport::new(BinaryFormat::MessagePack);
+        client.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        manager.get_peer_mut(bad).unwrap().connect_to(&mut client);
+        assert!(matches!(manager.receive().unwrap(), Some(BroadcastEvent::Error { peer, .. }) if peer == bad));
+        assert!(matches!(manager.receive().unwrap(), Some(BroadcastEvent::Disconnected { peer }) if peer == bad));
+        assert_eq!(manager.peer_ids().collect::<Vec<_>>(), vec![good]);
+
+        // The snapshot was recorded, so the remaining peer gets a delta.
+        assert_eq!(manager.send(snapshot(2.0, "\"b\"")).unwrap(), 1);
+        assert_eq!(received(&mut manager, good), vec![MessageType::Snapshot, MessageType::Delta]);
+    }
+}
@@ -0,0 +1,71 @@
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Source of the current time for rate limiting and sync scheduling.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+
+    /// Wall-clock time in milliseconds since the Unix epoch, for timestamps
+    /// reported outside the process. Use `now` for intervals.
+    fn epoch_millis(&self) -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A clock that only moves when told to. Clones share the same time.
+///
+/// Its wall-clock time starts at the system's and advances with it.
+#[derive(Debug, Clone)]
+pub struct MockClock {
+    now: Arc<Mutex<Instant>>,
+    origin: Instant,
+    origin_millis: u64,
+}
+
+impl MockClock {
+    pub fn new() -> Self {
+        let origin = Instant::now();
+        Self {
+            now: Arc::new(Mutex::new(origin)),
+            origin,
+            origin_millis: SystemClock.epoch_millis(),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        let mut now = self.now.lock().unwrap_or_else(|e| e.into_inner());
+        *now += duration;
+    }
+}
+
+impl Default for MockClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for MockClock {
+    fn now(&self) -> Instant {
+        *self.now.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn epoch_millis(&self) -> u64 {
+        self.origin_millis + (self.now() - self.origin).as_millis() as u64
+    }
+}
+
+pub(crate) fn system_clock() -> Arc<dyn Clock> {
+    Arc::new(SystemClock)
+}
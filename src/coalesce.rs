@@ -0,0 +1,283 @@
+use crate::clock::{self, Clock};
+use crate::error::Result;
+use crate::protocol::{CompressionType, Message};
+use crate::serialization::{BinaryFormat, BinarySerializer};
+use crate::transport::Transport;
+use bytes::Bytes;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Wraps a transport and sends messages in batches instead of one write each.
+///
+/// `send` only buffers. The buffer goes out through the inner transport's
+/// `send_batch` once it holds `max_bytes` of encoded messages, or once its
+/// oldest message has waited `max_delay`, so no message is held longer than
+/// that. The delay is measured on the injected clock and checked on every
+/// `send`, `receive` and `poll`; call `poll` regularly when nothing else is
+/// going on. `flush` sends the buffer right away, and a `max_delay` of zero
+/// turns coalescing off.
+///
+/// Messages are encoded once, with the inner transport's serializer, when they
+/// are buffered, and those bytes go out through `send_batch_encoded`. When the
+/// inner transport has no serializer, sizes come from MessagePack and the
+/// messages go out through `send_batch`. A buffer that fails to send is kept
+/// for the next attempt. Buffered messages are sent on `close` and on drop.
+pub struct CoalescingTransport<T: Transport> {
+    inner: T,
+    clock: Arc<dyn Clock>,
+    max_bytes: usize,
+    max_delay: Duration,
+    pending: Vec<Message>,
+    pending_data: Vec<Bytes>,
+    pending_bytes: usize,
+    oldest: Option<Instant>,
+}
+
+impl<T: Transport> CoalescingTransport<T> {
+    pub fn new(inner: T) -> Self {
+        Self::with_clock(inner, clock::system_clock())
+    }
+
+    /// Measure `max_delay` on `clock`.
+    pub fn with_clock(inner: T, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner,
+            clock,
+            max_bytes: 1200,
+            max_delay: Duration::from_millis(5),
+            pending: Vec::new(),
+            pending_data: Vec::new(),
+            pending_bytes: 0,
+            oldest: None,
+        }
+    }
+
+    /// Send once this many encoded bytes are buffered.
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = max_bytes;
+        self
+    }
+
+    /// Longest a message waits in the buffer; zero sends every message at once.
+    pub fn with_max_delay(mut self, max_delay: Duration) -> Self {
+        self.max_delay = max_delay;
+        self
+    }
+
+    pub fn get_inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Messages waiting to be sent.
+    pub fn pending_count(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Encoded size of the messages waiting to be sent.
+    pub fn pending_bytes(&self) -> usize {
+        self.pending_bytes
+    }
+
+    /// When the buffer goes out if nothing else fills it, `None` while empty.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.oldest.map(|oldest| oldest + self.max_delay)
+    }
+
+    /// Send the buffer if it is full or its oldest message has waited long enough.
+    pub fn poll(&mut self) -> Result<()> {
+        let due = self.next_deadline().is_some_and(|deadline| self.clock.now() >= deadline);
+        if due || self.pending_bytes >= self.max_bytes {
+            self.send_pending()?;
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, message: &Message, data: Bytes) -> Result<()> {
+        self.oldest.get_or_insert_with(|| self.clock.now());
+        self.pending_bytes += data.len();
+        self.pending.push(message.clone());
+        self.pending_data.push(data);
+        self.poll()
+    }
+
+    fn encode(&self, message: &Message) -> Result<Bytes> {
+        match self.inner.serializer() {
+            Some(serializer) => serializer.serialize_message(message),
+            None => BinarySerializer::new(BinaryFormat::MessagePack).serialize_message(message),
+        }
+    }
+
+    fn send_pending(&mut self) -> Result<()> {
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let result = if self.inner.serializer().is_some() {
+            self.inner.send_batch_encoded(&self.pending, &self.pending_data)
+        } else {
+            self.inner.send_batch(&self.pending)
+        };
+
+        if result.is_ok() {
+            self.pending.clear();
+            self.pending_data.clear();
+            self.pending_bytes = 0;
+            self.oldest = None;
+        }
+        result
+    }
+}
+
+impl<T: Transport> Transport for CoalescingTransport<T> {
+    fn send(&mut self, message: &Message) -> Result<()> {
+        let data = self.encode(message)?;
+        self.push(message, data)
+    }
+
+    fn serializer(&self) -> Option<&BinarySerializer> {
+        self.inner.serializer()
+    }
+
+    fn send_encoded(&mut self, message: &Message, data: &Bytes) -> Result<()> {
+        self.push(message, data.clone())
+    }
+
+    fn send_batch(&mut self, messages: &[Message]) -> Result<()> {
+        for message in messages {
+            self.send(message)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Message>> {
+        self.poll()?;
+        self.inner.receive()
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        self.poll()?;
+        self.inner.receive_timeout(timeout)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.send_pending()?;
+        self.inner.flush()
+    }
+
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        self.inner.supports_compression(compression)
+    }
+
+    /// Buffered messages are sent first, with the compression they were sent under.
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.send_pending()?;
+        self.inner.set_compression(compression)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        let sent = self.send_pending();
+        let closed = self.inner.close();
+        sent.and(closed)
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+impl<T: Transport> Drop for CoalescingTransport<T> {
+    fn drop(&mut self) {
+        let _ = self.send_pending();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::protocol::SchemaVersion;
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn test_coalescing_delay_and_threshold() {
+        let clock = MockClock::new();
+        let mut transport = CoalescingTransport::with_clock(MemoryTransport::new(BinaryFormat::MessagePack), Arc::new(clock.clone()))
+            .with_max_delay(Duration::from_millis(10));
+
+        for ack_id in 0..3 {
+            transport.send(&Message::ack(ack_id, SchemaVersion::new(1))).unwrap();
+        }
+        assert_eq!(transport.pending_count(), 3);
+        assert!(transport.get_inner().get_send_buffer().is_empty());
+
+        clock.advance(Duration::from_millis(9));
+        transport.poll().unwrap();
+        assert!(transport.get_inner().get_send_buffer().is_empty());
+
+        clock.advance(Duration::from_millis(1));
+        assert!(transport.receive().unwrap().is_none());
+        assert_eq!(transport.get_inner().get_send_buffer().len(), 3);
+        assert_eq!((transport.pending_count(), transport.pending_bytes(), transport.next_deadline()), (0, 0, None));
+
+        let size = transport.encode(&Message::ack(0, SchemaVersion::new(1))).unwrap().len();
+        let mut transport = transport.with_max_bytes(size * 2);
+        transport.send(&Message::ack(0, SchemaVersion::new(1))).unwrap();
+        assert_eq!(transport.pending_count(), 1);
+        transport.send(&Message::ack(1, SchemaVersion::new(1))).unwrap();
+        assert_eq!(transport.pending_count(), 0);
+        assert_eq!(transport.get_inner().get_send_buffer().len(), 5);
+    }
+
+    #[test]
+    fn test_coalescing_disabled_and_close() {
+        let mut transport = CoalescingTransport::new(MemoryTransport::new(BinaryFormat::MessagePack))
+            .with_max_delay(Duration::ZERO);
+        transport.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        assert_eq!(transport.get_inner().get_send_buffer().len(), 1);
+
+        let mut transport = transport.with_max_delay(Duration::from_secs(60));
+        transport.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        transport.flush().unwrap();
+        transport.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        assert_eq!(transport.get_inner().get_send_buffer().len(), 2);
+
+        transport.close().unwrap();
+        assert_eq!(transport.pending_count(), 0);
+        assert!(!transport.is_connected());
+    }
+
+    #[test]
+    fn test_coalescing_sends_encoded_bytes_and_keeps_failed_batches() {
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let mut transport = CoalescingTransport::new(MemoryTransport::new(BinaryFormat::MessagePack))
+            .with_max_delay(Duration::from_secs(60));
+
+        // Pre-encoded bytes go out as they are.
+        let message = Message::ping(SchemaVersion::new(1));
+        let data = serializer.serialize_message(&message).unwrap();
+        transport.send_encoded(&message, &data).unwrap();
+        assert_eq!(transport.pending_bytes(), data.len());
+        transport.flush().unwrap();
+        assert_eq!(transport.get_inner().get_send_buffer(), &[data]);
+
+        transport.send(&Message::pong(SchemaVersion::new(1))).unwrap();
+        transport.get_inner_mut().close().unwrap();
+        assert!(transport.flush().is_err());
+        assert_eq!(transport.pending_count(), 1);
+        assert!(transport.pending_bytes() > 0);
+        assert!(transport.next_deadline().is_some());
+    }
+}
@@ -0,0 +1,339 @@
+use crate::error::Result;
+use crate::protocol::{ComponentData, ComponentId, EntityId, FieldType, FieldValue, SerializedComponent};
+use crate::schema::{ComponentSchema, SchemaRegistry};
+use crate::serialization::WorldSnapshot;
+use std::any::TypeId;
+use std::collections::HashMap;
+
+pub use tx2_link_derive::{LinkComponent, ReplicatedField};
+
+/// A Rust type that replicates as a single component.
+///
+/// Usually implemented with `#[derive(LinkComponent)]`, which maps every named
+/// field to a `ComponentData::Structured` entry through [`ReplicatedField`].
+pub trait LinkComponent: Sized {
+    fn component_id() -> ComponentId;
+    fn schema() -> ComponentSchema;
+    fn to_component_data(&self) -> ComponentData;
+    fn from_component_data(data: &ComponentData) -> Result<Self>;
+
+    fn to_serialized(&self) -> SerializedComponent {
+        SerializedComponent {
+            id: Self::component_id(),
+            data: self.to_component_data(),
+        }
+    }
+}
+
+/// Component types registered by their Rust type.
+///
+/// Each `register::<T>()` adds `T::schema()` to an inner [`SchemaRegistry`],
+/// which stays available for components that are only known by id.
+#[derive(Default)]
+pub struct TypedRegistry {
+    schemas: SchemaRegistry,
+    component_ids: HashMap<TypeId, ComponentId>,
+}
+
+impl TypedRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register `T`, returning its component id. Registering a type twice is a no-op.
+    pub fn register<T: LinkComponent + 'static>(&mut self) -> Result<ComponentId> {
+        if let Some(component_id) = self.component_ids.get(&TypeId::of::<T>()) {
+            return Ok(component_id.clone());
+        }
+
+        self.schemas.register(T::schema())?;
+
+        let component_id = T::component_id();
+        self.component_ids.insert(TypeId::of::<T>(), component_id.clone());
+        Ok(component_id)
+    }
+
+    pub fn is_registered<T: 'static>(&self) -> bool {
+        self.component_ids.contains_key(&TypeId::of::<T>())
+    }
+
+    pub fn get_component_id<T: 'static>(&self) -> Option<&str> {
+        self.component_ids.get(&TypeId::of::<T>()).map(String::as_str)
+    }
+
+    pub fn get_schema_registry(&self) -> &SchemaRegistry {
+        &self.schemas
+    }
+}
+
+impl WorldSnapshot {
+    /// Decode entity `entity_id`'s `T` component. `None` if the entity or the
+    /// component is missing, or its data doesn't decode as `T`.
+    pub fn get_component<T: LinkComponent>(&self, entity_id: EntityId) -> Option<T> {
+        let component_id = T::component_id();
+
+        self.entities.iter()
+            .find(|e| e.id == entity_id)?
+            .components.iter()
+            .find(|c| c.id == component_id)
+            .and_then(|c| T::from_component_data(&c.data).ok())
+    }
+}
+
+/// A field type that can be stored in a `FieldValue`.
+///
+/// Enums implement it with `#[derive(ReplicatedField)]`, which stores them as
+/// `FieldValue::Enum`.
+pub trait ReplicatedField: Sized {
+    const FIELD_TYPE: FieldType;
+    const OPTIONAL: bool = false;
+    /// For enums, every discriminant a valid value may carry.
+    const DISCRIMINANTS: Option<&'static [u32]> = None;
+
+    fn to_field_value(&self) -> FieldValue;
+    fn from_field_value(value: &FieldValue) -> Option<Self>;
+}
+
+macro_rules! impl_replicated_field {
+    ($ty:ty, $variant:ident) => {
+        impl ReplicatedField for $ty {
+            const FIELD_TYPE: FieldType = FieldType::$variant;
+
+            fn to_field_value(&self) -> FieldValue {
+                FieldValue::$variant(*self)
+            }
+
+            fn from_field_value(value: &FieldValue) -> Option<Self> {
+                match value {
+                    FieldValue::$variant(v) => Some(*v),
+                    _ => None,
+                }
+            }
+        }
+    };
+}
+
+impl_replicated_field!(bool, Bool);
+impl_replicated_field!(u8, U8);
+impl_replicated_field!(u16, U16);
+impl_replicated_field!(u32, U32);
+impl_replicated_field!(u64, U64);
+impl_replicated_field!(i8, I8);
+impl_replicated_field!(i16, I16);
+impl_replicated_field!(i32, I32);
+impl_replicated_field!(i64, I64);
+impl_replicated_field!(f32, F32);
+impl_replicated_field!(f64, F64);
+
+impl ReplicatedField for String {
+    const FIELD_TYPE: FieldType = FieldType::String;
+
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::String(self.clone())
+    }
+
+    fn from_field_value(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::String(s) => Some(s.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ReplicatedField> ReplicatedField for Option<T> {
+    const FIELD_TYPE: FieldType = T::FIELD_TYPE;
+    const OPTIONAL: bool = true;
+    const DISCRIMINANTS: Option<&'static [u32]> = T::DISCRIMINANTS;
+
+    fn to_field_value(&self) -> FieldValue {
+        match self {
+            Some(v) => v.to_field_value(),
+            None => FieldValue::Null,
+        }
+    }
+
+    fn from_field_value(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Null => Some(None),
+            v => T::from_field_value(v).map(Some),
+        }
+    }
+}
+
+impl<T: ReplicatedField> ReplicatedField for Vec<T> {
+    const FIELD_TYPE: FieldType = FieldType::Array;
+
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Array(self.iter().map(ReplicatedField::to_field_value).collect())
+    }
+
+    fn from_field_value(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Array(items) => items.iter().map(T::from_field_value).collect(),
+            _ => None,
+        }
+    }
+}
+
+impl<T: ReplicatedField> ReplicatedField for HashMap<String, T> {
+    const FIELD_TYPE: FieldType = FieldType::Map;
+
+    fn to_field_value(&self) -> FieldValue {
+        FieldValue::Map(self.iter().map(|(k, v)| (k.clone(), v.to_field_value())).collect())
+    }
+
+    fn from_field_value(value: &FieldValue) -> Option<Self> {
+        match value {
+            FieldValue::Map(map) => map.iter()
+                .map(|(k, v)| T::from_field_value(v).map(|v| (k.clone(), v)))
+                .collect(),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::LinkError;
+    use crate::protocol::SchemaVersion;
+
+    #[derive(Debug, Clone, PartialEq, LinkComponent)]
+    struct Position {
+        x: f64,
+        y: f64,
+    }
+
+    #[derive(Debug, Clone, PartialEq, LinkComponent)]
+    #[link_component(id = "player.Health", version = 2)]
+    struct Health {
+        current: u32,
+        shield: Option<u32>,
+        tags: Vec<String>,
+        #[link_component(skip)]
+        cached_ratio: f32,
+    }
+
+    #[test]
+    fn test_derive_roundtrip() {
+        let position = Position { x: 1.5, y: -2.0 };
+        let data = position.to_component_data();
+
+        assert!(matches!(data, ComponentData::Structured(ref fields) if fields.len() == 2));
+        assert_eq!(Position::from_component_data(&data).unwrap(), position);
+        assert_eq!(position.to_serialized().id, "Position");
+    }
+
+    #[test]
+    fn test_derive_attributes() {
+        let health = Health {
+            current: 80,
+            shield: None,
+            tags: vec!["boss".to_string()],
+            cached_ratio: 0.8,
+        };
+
+        let decoded = Health::from_component_data(&health.to_component_data()).unwrap();
+        assert_eq!(decoded.current, 80);
+        assert_eq!(decoded.shield, None);
+        assert_eq!(decoded.tags, vec!["boss".to_string()]);
+        assert_eq!(decoded.cached_ratio, 0.0);
+
+        let schema = Health::schema();
+        assert_eq!(schema.component_id, "player.Health");
+        assert_eq!(schema.version, SchemaVersion::new(2));
+        assert_eq!(schema.fields.len(), 3);
+        assert!(schema.get_field("shield").unwrap().optional);
+        assert_eq!(schema.get_field("tags").unwrap().field_type, FieldType::Array);
+    }
+
+    #[derive(Debug, Default, PartialEq, LinkComponent)]
+    struct Player {}
+
+    #[test]
+    fn test_derive_marker_component() {
+        assert_eq!(Player {}.to_component_data(), ComponentData::Empty);
+        assert_eq!(Player::from_component_data(&ComponentData::Empty).unwrap(), Player {});
+        assert!(Player::schema().fields.is_empty());
+    }
+
+    #[test]
+    fn test_typed_registry() {
+        use crate::protocol::SerializedEntity;
+
+        let mut registry = TypedRegistry::new();
+        assert_eq!(registry.register::<Health>().unwrap(), "player.Health");
+        assert_eq!(registry.register::<Health>().unwrap(), "player.Health");
+        assert!(registry.is_registered::<Health>());
+        assert!(!registry.is_registered::<Position>());
+        assert_eq!(registry.get_schema_registry().get("player.Health").unwrap().version, SchemaVersion::new(2));
+
+        let snapshot = WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 7,
+                components: vec![
+                    Position { x: 1.0, y: 2.0 }.to_serialized(),
+                    SerializedComponent {
+                        id: "player.Health".to_string(),
+                        data: ComponentData::from_json_value(serde_json::json!({"current": 1})),
+                    },
+                ],
+            }],
+            timestamp: 0.0,
+            version: "1.0.0".to_string(),
+        };
+
+        assert_eq!(snapshot.get_component::<Position>(7), Some(Position { x: 1.0, y: 2.0 }));
+        assert_eq!(snapshot.get_component::<Position>(8), None);
+        assert_eq!(snapshot.get_component::<Health>(7), None);
+        assert_eq!(snapshot.get_component::<Player>(7), None);
+    }
+
+    #[derive(Debug, Clone, PartialEq, ReplicatedField)]
+    #[repr(u8)]
+    enum Stance {
+        Idle,
+        Walking(f32),
+        Stunned = 5,
+    }
+
+    #[derive(Debug, Clone, PartialEq, LinkComponent)]
+    struct Actor {
+        stance: Stance,
+        previous: Option<Stance>,
+    }
+
+    #[test]
+    fn test_derive_enum() {
+        assert_eq!(Stance::Idle.to_field_value(), FieldValue::Enum { discriminant: 0, payload: None });
+        assert_eq!(
+            Stance::Walking(1.5).to_field_value(),
+            FieldValue::Enum { discriminant: 1, payload: Some(Box::new(FieldValue::F32(1.5))) }
+        );
+
+        let actor = Actor { stance: Stance::Stunned, previous: Some(Stance::Walking(0.5)) };
+        assert_eq!(Actor::from_component_data(&actor.to_component_data()).unwrap(), actor);
+
+        let schema = Actor::schema();
+        let stance = schema.get_field("stance").unwrap();
+        assert_eq!(stance.field_type, FieldType::Enum);
+        assert_eq!(stance.constraints.allowed_discriminants, Some(vec![0, 1, 5]));
+        assert!(schema.get_field("previous").unwrap().constraints.allowed_discriminants.is_some());
+
+        assert_eq!(Stance::from_field_value(&FieldValue::Enum { discriminant: 2, payload: None }), None);
+        assert_eq!(Stance::from_field_value(&FieldValue::Enum { discriminant: 1, payload: None }), None);
+    }
+
+    #[test]
+    fn test_derive_rejects_wrong_types() {
+        let mut fields = HashMap::new();
+        fields.insert("x".to_string(), FieldValue::String("oops".to_string()));
+        fields.insert("y".to_string(), FieldValue::F64(0.0));
+
+        let result = Position::from_component_data(&ComponentData::Structured(fields));
+        assert!(matches!(result, Err(LinkError::Deserialization(_))));
+
+        let json = ComponentData::from_json_value(serde_json::json!({"x": 1.0, "y": 2.0}));
+        assert!(Position::from_component_data(&json).is_err());
+    }
+}
@@ -1,66 +1,365 @@
+use crate::error::{LinkError, Result};
 use crate::protocol::*;
-use crate::serialization::{WorldSnapshot, Delta};
+use crate::serialization::{
+    diff_components, diff_entities, encode_value, BinaryFormat, ComponentDiff, Delta, EntityDiff, WorldSnapshot,
+};
 use crate::debug;
-use ahash::AHashMap;
+use crate::hierarchy;
+use ahash::{AHashMap, AHashSet};
+use std::borrow::Cow;
+use std::collections::{HashMap, VecDeque};
+use std::io::{Read, Write};
+use std::sync::Mutex;
 use std::time::Instant;
 
+/// Compress an encoded payload with the given algorithm.
+pub fn compress(data: &[u8], compression: CompressionType) -> Result<Vec<u8>> {
+    match compression {
+        CompressionType::None => Ok(data.to_vec()),
+        CompressionType::Deflate => {
+            let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        CompressionType::Lz4 => Ok(lz4_flex::block::compress_prepend_size(data)),
+        CompressionType::Zstd => Err(LinkError::Serialization(
+            format!("{:?} compression is not supported", compression)
+        )),
+    }
+}
+
+/// Reverse [`compress`], refusing to inflate past the default
+/// [`MessageLimits::max_decompressed_bytes`].
+pub fn decompress(data: &[u8], compression: CompressionType) -> Result<Vec<u8>> {
+    decompress_bounded(data, compression, MessageLimits::default().max_decompressed_bytes)
+}
+
+/// Reverse [`compress`], failing rather than producing more than `max_len`
+/// bytes. The data comes from the peer, so a small stream must not be able to
+/// claim an arbitrarily large output.
+pub fn decompress_bounded(data: &[u8], compression: CompressionType, max_len: usize) -> Result<Vec<u8>> {
+    match compression {
+        CompressionType::None => check_decompressed_len(data.len(), max_len).map(|()| data.to_vec()),
+        CompressionType::Deflate => {
+            let mut decoded = Vec::new();
+            flate2::read::DeflateDecoder::new(data)
+                .take(max_len as u64 + 1)
+                .read_to_end(&mut decoded)
+                .map_err(|e| LinkError::Deserialization(format!("Invalid deflate stream: {}", e)))?;
+            check_decompressed_len(decoded.len(), max_len)?;
+            Ok(decoded)
+        }
+        CompressionType::Lz4 => {
+            // The size prefix comes from the peer; check it before allocating.
+            let Some((prefix, block)) = data.split_first_chunk::<4>() else {
+                return Err(LinkError::Deserialization("Truncated LZ4 size prefix".to_string()));
+            };
+            let len = u32::from_le_bytes(*prefix) as usize;
+            check_decompressed_len(len, max_len)?;
+
+            let mut decoded = vec![0u8; len];
+            let written = lz4_flex::block::decompress_into(block, &mut decoded)
+                .map_err(|e| LinkError::Deserialization(format!("Invalid LZ4 block: {}", e)))?;
+            if written != len {
+                return Err(LinkError::Deserialization(format!(
+                    "LZ4 block decoded to {} bytes, expected {}", written, len
+                )));
+            }
+            Ok(decoded)
+        }
+        CompressionType::Zstd => Err(LinkError::Deserialization(
+            format!("{:?} compression is not supported", compression)
+        )),
+    }
+}
+
+fn check_decompressed_len(len: usize, max_len: usize) -> Result<()> {
+    if len > max_len {
+        return Err(LinkError::Deserialization(format!(
+            "Decompressed payload exceeds the limit of {} bytes", max_len
+        )));
+    }
+    Ok(())
+}
+
+/// Wrap `data` in [`ComponentData::Compressed`] if that makes it smaller;
+/// otherwise, or with `CompressionType::None`, return it unchanged.
+pub fn compress_component(data: ComponentData, compression: CompressionType) -> Result<ComponentData> {
+    if compression == CompressionType::None || matches!(data, ComponentData::Compressed { .. }) {
+        return Ok(data);
+    }
+
+    let encoded = bincode::serde::encode_to_vec(&data, bincode::config::legacy())?;
+    let compressed = compress(&encoded, compression)?;
+    if compressed.len() >= encoded.len() {
+        return Ok(data);
+    }
+
+    Ok(ComponentData::Compressed { compression, data: compressed.into() })
+}
+
+/// Reverse [`compress_component`]. Other data is returned unchanged.
+pub fn decompress_component(data: ComponentData) -> Result<ComponentData> {
+    decompress_component_bounded(data, MessageLimits::default().max_decompressed_bytes)
+}
+
+/// Like [`decompress_component`], refusing to inflate past `max_len` bytes.
+pub fn decompress_component_bounded(data: ComponentData, max_len: usize) -> Result<ComponentData> {
+    decompress_within(data, &mut { max_len })
+}
+
+/// Decompress `data`, taking its inflated size out of `budget`, so one budget
+/// bounds every component of a message together.
+fn decompress_within(data: ComponentData, budget: &mut usize) -> Result<ComponentData> {
+    let ComponentData::Compressed { compression, data } = data else {
+        return Ok(data);
+    };
+
+    let encoded = decompress_bounded(&data, compression, *budget)?;
+    *budget -= encoded.len();
+    let (decoded, _) = bincode::serde::decode_from_slice(&encoded, bincode::config::legacy())?;
+    Ok(decoded)
+}
+
+/// [`decompress_component`] every component of `entities`, failing once they
+/// inflate to more than `max_len` bytes in total.
+pub fn decompress_entities(entities: &mut [SerializedEntity], max_len: usize) -> Result<()> {
+    let mut budget = max_len;
+    for component in entities.iter_mut().flat_map(|e| e.components.iter_mut()) {
+        if matches!(component.data, ComponentData::Compressed { .. }) {
+            component.data = decompress_within(std::mem::replace(&mut component.data, ComponentData::Empty), &mut budget)?;
+        }
+    }
+    Ok(())
+}
+
+/// [`decompress_entities`] for the data carried by `changes`.
+pub fn decompress_changes(changes: &mut [DeltaChange], max_len: usize) -> Result<()> {
+    let mut budget = max_len;
+    for change in changes {
+        if let DeltaChange::ComponentAdded { data, .. } | DeltaChange::ComponentUpdated { data, .. } = change {
+            if matches!(data, ComponentData::Compressed { .. }) {
+                *data = decompress_within(std::mem::replace(data, ComponentData::Empty), &mut budget)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether [`compress`] and [`decompress`] implement `compression`.
+pub fn is_supported(compression: CompressionType) -> bool {
+    !matches!(compression, CompressionType::Zstd)
+}
+
+/// Pick the compression both peers support. Both sides rank candidates in the
+/// same fixed order, so they agree without another round trip.
+pub fn negotiate(ours: &[CompressionType], theirs: &[CompressionType]) -> CompressionType {
+    const PREFERENCE: [CompressionType; 3] = [CompressionType::Lz4, CompressionType::Zstd, CompressionType::Deflate];
+
+    PREFERENCE.into_iter()
+        .find(|c| ours.contains(c) && theirs.contains(c))
+        .unwrap_or(CompressionType::None)
+}
+
 pub struct DeltaCompressor {
-    previous_snapshot: Option<WorldSnapshot>,
+    history: VecDeque<WorldSnapshot>,
+    history_size: usize,
     field_compressor: FieldCompressor,
+    /// Entities the receiver of the last delta knows about; `None` is the whole world.
+    scope: Option<AHashSet<EntityId>>,
+    bandwidth: Option<BandwidthStats>,
+    canonical_json: bool,
 }
 
 impl DeltaCompressor {
     pub fn new() -> Self {
         Self {
-            previous_snapshot: None,
+            history: VecDeque::new(),
+            history_size: 1,
             field_compressor: FieldCompressor::new(),
+            scope: None,
+            bandwidth: None,
+            canonical_json: false,
         }
     }
 
     pub fn with_field_compression(enable: bool) -> Self {
         Self {
-            previous_snapshot: None,
+            history: VecDeque::new(),
+            history_size: 1,
             field_compressor: FieldCompressor::with_enabled(enable),
+            scope: None,
+            bandwidth: None,
+            canonical_json: false,
+        }
+    }
+
+    /// Diff components with `field_compressor`, e.g. one made with
+    /// [`FieldCompressor::with_stats`].
+    pub fn with_field_compressor(mut self, field_compressor: FieldCompressor) -> Self {
+        self.field_compressor = field_compressor;
+        self
+    }
+
+    /// Compare JSON components by content (see [`ComponentData::content_eq`]),
+    /// so JSON that only differs in key order or whitespace is unchanged. A
+    /// pair that differs as text is parsed once, for both the comparison and
+    /// the field diff.
+    pub fn with_canonical_json(mut self, enabled: bool) -> Self {
+        self.canonical_json = enabled;
+        self
+    }
+
+    pub fn get_field_compressor(&self) -> &FieldCompressor {
+        &self.field_compressor
+    }
+
+    /// Tally the encoded size of every committed delta per component, as
+    /// `format` would encode it; see [`Self::bandwidth_by_component`].
+    pub fn with_bandwidth_stats(mut self, format: BinaryFormat) -> Self {
+        self.bandwidth = Some(BandwidthStats::new(format));
+        self
+    }
+
+    /// Bytes of delta changes per component since tracking started or was
+    /// last reset. Empty unless created with [`Self::with_bandwidth_stats`].
+    pub fn bandwidth_by_component(&self) -> HashMap<ComponentId, u64> {
+        self.bandwidth.as_ref()
+            .map(BandwidthStats::bandwidth_by_component)
+            .unwrap_or_default()
+    }
+
+    pub fn get_bandwidth_stats(&self) -> Option<&BandwidthStats> {
+        self.bandwidth.as_ref()
+    }
+
+    pub fn reset_bandwidth_stats(&mut self) {
+        if let Some(bandwidth) = &mut self.bandwidth {
+            bandwidth.reset();
         }
     }
 
+    /// Keep the last `size` snapshots as candidate baselines for [`Self::create_delta_from`].
+    pub fn with_history_size(mut self, size: usize) -> Self {
+        self.history_size = size.max(1);
+        self
+    }
+
+    /// Diff `current_snapshot` against the previous one and record it as the
+    /// new baseline. If both hold the same entities in the same order, the
+    /// result is an empty delta without building the diff's maps.
     pub fn create_delta(&mut self, current_snapshot: WorldSnapshot) -> Delta {
-        let start = Instant::now();
+        let delta = self.build_delta(self.history.back(), &current_snapshot);
+        self.record(current_snapshot);
+        self.scope = None;
+        self.account(&delta);
+        delta
+    }
 
-        let timestamp = current_snapshot.timestamp;
-        let base_timestamp = self.previous_snapshot.as_ref()
-            .map(|s| s.timestamp)
-            .unwrap_or(0.0);
+    /// Like [`Self::create_delta`], but the delta only covers `entity_ids`.
+    ///
+    /// The full snapshot is still recorded as the baseline. Entities that enter
+    /// the set since the previous call arrive as `EntityAdded` with all their
+    /// components, and entities that leave it as `EntityRemoved`. The set is
+    /// tracked per compressor, so use one compressor per subscriber.
+    pub fn create_delta_for(&mut self, entity_ids: &[EntityId], current_snapshot: WorldSnapshot) -> Delta {
+        let scope: AHashSet<EntityId> = entity_ids.iter().copied().collect();
+
+        let previous = self.history.back().map(|base| match &self.scope {
+            Some(previous_scope) => Cow::Owned(scoped_snapshot(base, previous_scope)),
+            None => Cow::Borrowed(base),
+        });
+        let delta = self.build_delta(previous.as_deref(), &scoped_snapshot(&current_snapshot, &scope));
+
+        self.record(current_snapshot);
+        self.scope = Some(scope);
+        self.account(&delta);
+        delta
+    }
 
-        let changes = if let Some(prev) = &self.previous_snapshot {
-            self.compute_changes(prev, &current_snapshot)
-        } else {
-            self.create_initial_delta(&current_snapshot)
-        };
+    /// Compute the delta `create_delta` would produce without recording
+    /// `snapshot` as the new baseline.
+    pub fn peek_delta(&self, snapshot: &WorldSnapshot) -> Delta {
+        self.build_delta(self.history.back(), snapshot)
+    }
 
-        let delta = Delta {
-            changes,
-            timestamp,
-            base_timestamp,
-        };
+    /// Diff against the retained snapshot with `base_timestamp` (e.g. the last one
+    /// the peer acknowledged) instead of the latest one.
+    ///
+    /// If that baseline has already been evicted from the history, the result is a
+    /// full initial delta with a `base_timestamp` of `0.0`, exactly as if there
+    /// were no baseline at all.
+    pub fn create_delta_from(&mut self, base_timestamp: f64, current_snapshot: WorldSnapshot) -> Delta {
+        let delta = self.build_delta(self.baseline(base_timestamp), &current_snapshot);
+        self.record(current_snapshot);
+        self.scope = None;
+        self.account(&delta);
+        delta
+    }
+
+    /// Like [`Self::create_delta_from`], but without recording `snapshot`.
+    pub fn peek_delta_from(&self, base_timestamp: f64, snapshot: &WorldSnapshot) -> Delta {
+        self.build_delta(self.baseline(base_timestamp), snapshot)
+    }
+
+    /// Delta that re-adds everything in `snapshot`, for a receiver without a baseline.
+    pub fn peek_initial_delta(&self, snapshot: &WorldSnapshot) -> Delta {
+        self.build_delta(None, snapshot)
+    }
+
+    /// Retained snapshots, oldest first.
+    pub fn history(&self) -> impl Iterator<Item = &WorldSnapshot> {
+        self.history.iter()
+    }
+
+    /// Retain `snapshot` as the latest baseline without computing a delta.
+    pub fn record(&mut self, snapshot: WorldSnapshot) {
+        self.history.push_back(snapshot);
+        while self.history.len() > self.history_size {
+            self.history.pop_front();
+        }
+    }
+
+    fn account(&mut self, delta: &Delta) {
+        self.account_changes(&delta.changes);
+    }
 
-        // Debug logging
-        if debug::is_debug_enabled() {
-            debug::log_delta("Created", &delta);
+    /// Count `changes` towards [`Self::bandwidth_by_component`], for callers
+    /// that build deltas with the `peek_*` methods and [`Self::record`] the
+    /// baseline once the delta is sent.
+    pub fn account_changes(&mut self, changes: &[DeltaChange]) {
+        if let Some(bandwidth) = &mut self.bandwidth {
+            bandwidth.record_changes(changes);
         }
+    }
+
+    fn baseline(&self, timestamp: f64) -> Option<&WorldSnapshot> {
+        self.history.iter().find(|s| s.timestamp == timestamp)
+    }
+
+    pub fn has_baseline(&self, timestamp: f64) -> bool {
+        self.baseline(timestamp).is_some()
+    }
+
+    fn build_delta(&self, base: Option<&WorldSnapshot>, current_snapshot: &WorldSnapshot) -> Delta {
+        let start = Instant::now();
+
+        let changes = match base {
+            Some(prev) if self.same_entities(prev, current_snapshot) => Vec::new(),
+            Some(prev) => self.compute_changes(prev, current_snapshot),
+            None => self.create_initial_delta(current_snapshot),
+        };
+        let delta = finish_delta(changes, base, current_snapshot.timestamp);
 
         if debug::is_trace_enabled() {
-            debug::trace_delta(&delta);
             let duration = start.elapsed().as_micros();
 
             // Estimate sizes for compression ratio
-            let original_size = bincode::serialize(&current_snapshot).unwrap_or_default().len();
-            let delta_size = bincode::serialize(&delta).unwrap_or_default().len();
+            let original_size = bincode::serde::encode_to_vec(current_snapshot, bincode::config::legacy()).unwrap_or_default().len();
+            let delta_size = bincode::serde::encode_to_vec(&delta, bincode::config::legacy()).unwrap_or_default().len();
             debug::trace_compression(original_size, delta_size, duration);
         }
 
-        self.previous_snapshot = Some(current_snapshot);
-
         delta
     }
 
@@ -87,38 +386,29 @@ impl DeltaCompressor {
     fn compute_changes(&self, prev: &WorldSnapshot, curr: &WorldSnapshot) -> Vec<DeltaChange> {
         let mut changes = Vec::new();
 
-        let prev_entities: AHashMap<EntityId, &SerializedEntity> = prev.entities.iter()
-            .map(|e| (e.id, e))
-            .collect();
-        let curr_entities: AHashMap<EntityId, &SerializedEntity> = curr.entities.iter()
-            .map(|e| (e.id, e))
-            .collect();
-
-        for (entity_id, curr_entity) in &curr_entities {
-            if let Some(prev_entity) = prev_entities.get(entity_id) {
-                self.compute_component_changes(*entity_id, prev_entity, curr_entity, &mut changes);
-            } else {
+        diff_entities(&prev.entities, &curr.entities, |diff| match diff {
+            EntityDiff::Added(entity) => {
                 changes.push(DeltaChange::EntityAdded {
-                    entity_id: *entity_id,
+                    entity_id: entity.id,
                 });
 
-                for component in &curr_entity.components {
+                for component in &entity.components {
                     changes.push(DeltaChange::ComponentAdded {
-                        entity_id: *entity_id,
+                        entity_id: entity.id,
                         component_id: component.id.clone(),
                         data: component.data.clone(),
                     });
                 }
             }
-        }
-
-        for entity_id in prev_entities.keys() {
-            if !curr_entities.contains_key(entity_id) {
+            EntityDiff::Removed(entity) => {
                 changes.push(DeltaChange::EntityRemoved {
-                    entity_id: *entity_id,
+                    entity_id: entity.id,
                 });
             }
-        }
+            EntityDiff::Common(prev_entity, curr_entity) => {
+                self.compute_component_changes(curr_entity.id, prev_entity, curr_entity, &mut changes);
+            }
+        });
 
         changes
     }
@@ -130,55 +420,80 @@ impl DeltaCompressor {
         curr_entity: &SerializedEntity,
         changes: &mut Vec<DeltaChange>,
     ) {
-        let prev_components: AHashMap<&str, &SerializedComponent> = prev_entity.components.iter()
-            .map(|c| (c.id.as_str(), c))
-            .collect();
-        let curr_components: AHashMap<&str, &SerializedComponent> = curr_entity.components.iter()
-            .map(|c| (c.id.as_str(), c))
-            .collect();
+        // Removals go first and together, so they can be applied as one step.
+        let mut removals = Vec::new();
+        let mut updates = Vec::new();
+        let mut kept_any = false;
+
+        diff_components(prev_entity, curr_entity, |diff| match diff {
+            ComponentDiff::Added(component) => {
+                updates.push(DeltaChange::ComponentAdded {
+                    entity_id,
+                    component_id: component.id.clone(),
+                    data: component.data.clone(),
+                });
+            }
+            ComponentDiff::Removed(component) => {
+                removals.push(DeltaChange::ComponentRemoved {
+                    entity_id,
+                    component_id: component.id.clone(),
+                });
+            }
+            ComponentDiff::Common(prev_component, curr_component) => {
+                kept_any = true;
+                let json = self.parse_json_pair(prev_component, curr_component);
+                let equal = match &json {
+                    Some((prev, curr)) => prev == curr,
+                    None => self.components_equal(prev_component, curr_component),
+                };
+                if equal {
+                    return;
+                }
 
-        for (component_id, curr_component) in &curr_components {
-            if let Some(prev_component) = prev_components.get(component_id) {
-                if !self.components_equal(prev_component, curr_component) {
-                    if self.field_compressor.is_enabled() {
-                        if let Some(field_deltas) = self.field_compressor.compute_field_deltas(
-                            prev_component,
-                            curr_component,
-                        ) {
-                            if !field_deltas.is_empty() {
-                                changes.push(DeltaChange::FieldsUpdated {
-                                    entity_id,
-                                    component_id: component_id.to_string(),
-                                    fields: field_deltas,
-                                });
-                                continue;
-                            }
-                        }
-                    }
+                // The data differs, so something must be sent. If field diffing
+                // comes back empty anyway, send the whole component.
+                let field_deltas = match &json {
+                    Some((prev, curr)) => self.field_compressor.compute_json_field_deltas(&curr_component.id, prev, curr),
+                    None => self.field_compressor.compute_field_deltas(prev_component, curr_component),
+                }.filter(|fields| !fields.is_empty());
 
-                    changes.push(DeltaChange::ComponentUpdated {
+                updates.push(match field_deltas {
+                    Some(fields) => DeltaChange::FieldsUpdated {
                         entity_id,
-                        component_id: component_id.to_string(),
+                        component_id: curr_component.id.clone(),
+                        fields,
+                    },
+                    None => DeltaChange::ComponentUpdated {
+                        entity_id,
+                        component_id: curr_component.id.clone(),
                         data: curr_component.data.clone(),
-                    });
-                }
-            } else {
-                changes.push(DeltaChange::ComponentAdded {
-                    entity_id,
-                    component_id: component_id.to_string(),
-                    data: curr_component.data.clone(),
+                    },
                 });
             }
-        }
+        });
 
-        for component_id in prev_components.keys() {
-            if !curr_components.contains_key(component_id) {
-                changes.push(DeltaChange::ComponentRemoved {
-                    entity_id,
-                    component_id: component_id.to_string(),
-                });
-            }
+        if !removals.is_empty() && !kept_any {
+            changes.push(DeltaChange::EntityComponentsReplaced {
+                entity_id,
+                components: curr_entity.components.clone(),
+            });
+            return;
         }
+
+        changes.append(&mut removals);
+        changes.append(&mut updates);
+    }
+
+    /// Whether `prev` and `curr` hold equal entities in the same order, which
+    /// is how an idle world usually comes back. Stops at the first difference;
+    /// a reordered but equal world is left to the full diff.
+    fn same_entities(&self, prev: &WorldSnapshot, curr: &WorldSnapshot) -> bool {
+        prev.entities.len() == curr.entities.len()
+            && prev.entities.iter().zip(&curr.entities).all(|(a, b)| {
+                a.id == b.id
+                    && a.components.len() == b.components.len()
+                    && a.components.iter().zip(&b.components).all(|(a, b)| self.components_equal(a, b))
+            })
     }
 
     fn components_equal(&self, a: &SerializedComponent, b: &SerializedComponent) -> bool {
@@ -186,20 +501,80 @@ impl DeltaCompressor {
             return false;
         }
 
+        a.data.same_as(&b.data)
+    }
+
+    /// Both sides of a JSON component that differs as text, parsed, when
+    /// comparing JSON by content.
+    fn parse_json_pair(
+        &self,
+        a: &SerializedComponent,
+        b: &SerializedComponent,
+    ) -> Option<(serde_json::Value, serde_json::Value)> {
         match (&a.data, &b.data) {
-            (ComponentData::Binary(a_data), ComponentData::Binary(b_data)) => a_data == b_data,
-            (ComponentData::Json(a_json), ComponentData::Json(b_json)) => a_json == b_json,
-            (ComponentData::Structured(a_map), ComponentData::Structured(b_map)) => a_map == b_map,
-            _ => false,
+            (ComponentData::Json(a_json), ComponentData::Json(b_json)) if self.canonical_json && a_json != b_json => {
+                Some((serde_json::from_str(a_json).ok()?, serde_json::from_str(b_json).ok()?))
+            }
+            _ => None,
         }
     }
 
     pub fn reset(&mut self) {
-        self.previous_snapshot = None;
+        self.history.clear();
+        self.scope = None;
     }
 
     pub fn get_previous_snapshot(&self) -> Option<&WorldSnapshot> {
-        self.previous_snapshot.as_ref()
+        self.history.back()
+    }
+
+    /// A copy of the latest baseline, to checkpoint (e.g. with
+    /// [`BinarySerializer::serialize_snapshot`](crate::BinarySerializer::serialize_snapshot))
+    /// and hand to [`Self::import_baseline`] after a restart.
+    pub fn export_baseline(&self) -> Option<WorldSnapshot> {
+        self.get_previous_snapshot().cloned()
+    }
+
+    /// Replace the history with `snapshot`, so the next delta is diffed against
+    /// it instead of being a full one.
+    ///
+    /// Deltas are only correct for receivers whose state matches the restored
+    /// baseline. A checkpoint older than the last delta a client applied, or
+    /// one from before the client connected, makes deltas that silently
+    /// diverge from its world. Resume with acked baselines (see
+    /// [`Self::create_delta_from`]) or keyframes so a mismatched client falls
+    /// back to a full snapshot.
+    pub fn import_baseline(&mut self, snapshot: WorldSnapshot) {
+        self.reset();
+        self.record(snapshot);
+    }
+}
+
+fn finish_delta(changes: Vec<DeltaChange>, base: Option<&WorldSnapshot>, timestamp: f64) -> Delta {
+    let delta = Delta {
+        changes: hierarchy::order_changes(changes, base),
+        timestamp,
+        base_timestamp: base.map(|s| s.timestamp).unwrap_or(0.0),
+    };
+
+    // Debug logging
+    if debug::is_debug_enabled() {
+        debug::log_delta("Created", &delta);
+    }
+
+    debug::trace_delta(&delta);
+
+    delta
+}
+
+fn scoped_snapshot(snapshot: &WorldSnapshot, scope: &AHashSet<EntityId>) -> WorldSnapshot {
+    WorldSnapshot {
+        entities: snapshot.entities.iter()
+            .filter(|e| scope.contains(&e.id))
+            .cloned()
+            .collect(),
+        timestamp: snapshot.timestamp,
+        version: snapshot.version.clone(),
     }
 }
 
@@ -209,117 +584,921 @@ impl Default for DeltaCompressor {
     }
 }
 
+impl WorldSnapshot {
+    /// Apply `delta`'s changes in order and take its timestamp.
+    ///
+    /// Works in place: entities are located through an index built once per
+    /// call, and removed entities are compacted at the end, so the cost is
+    /// linear in the snapshot and the delta and nothing is cloned.
+    ///
+    /// Fails on the first change that doesn't fit this snapshot (adding an
+    /// entity or component that already exists, or touching one that doesn't);
+    /// the changes before it stay applied. Consecutive component removals from
+    /// one entity are applied as a single step: if one doesn't fit, none are.
+    pub fn apply_delta(&mut self, delta: &Delta) -> Result<()> {
+        let field_compressor = FieldCompressor::new();
+        let mut index = EntityIndex::new(&self.entities);
+
+        let result = delta.changes.chunk_by(same_removal_run)
+            .try_for_each(|group| match group {
+                [change] => self.apply_change(change, &mut index, &field_compressor),
+                removals => self.remove_components(removals, &mut index),
+            });
+        index.compact(&mut self.entities);
+        result?;
+
+        self.timestamp = delta.timestamp;
+        Ok(())
+    }
+
+    /// Best-effort [`Self::apply_delta`] for observers that may have missed
+    /// part of the stream. Changes that don't fit the current state are
+    /// reconciled where possible instead of failing: component adds and
+    /// updates create whatever entity or component is missing, and changes
+    /// whose intent already holds are ignored. Everything else is skipped.
+    /// Each such change is recorded in the returned report.
+    pub fn apply_delta_lenient(&mut self, delta: &Delta) -> ApplyReport {
+        let field_compressor = FieldCompressor::new();
+        let mut index = EntityIndex::new(&self.entities);
+        let mut report = ApplyReport::default();
+        let mut change_index = 0;
+
+        for group in delta.changes.chunk_by(same_removal_run) {
+            if group.len() > 1 && self.remove_components(group, &mut index).is_ok() {
+                change_index += group.len();
+                continue;
+            }
+
+            for change in group {
+                if let Err(error) = self.apply_change(change, &mut index, &field_compressor) {
+                    let issue = ApplyIssue {
+                        change_index,
+                        message: error.to_string(),
+                        resolution: self.reconcile_change(change, &mut index),
+                    };
+                    debug::trace_apply_issue(&issue);
+                    report.issues.push(issue);
+                }
+                change_index += 1;
+            }
+        }
+
+        index.compact(&mut self.entities);
+        self.timestamp = delta.timestamp;
+        report
+    }
+
+    /// [`Self::apply_delta`], but `FieldsUpdated` changes are applied field by
+    /// field: a field `check` rejects (given the component id and the field),
+    /// or whose value doesn't fit the component, is left out and reported with
+    /// its error, and the rest of the update still applies. Every other change
+    /// fails the call as in `apply_delta`.
+    ///
+    /// `IndexedFieldsUpdated` changes aren't split up: as with `apply_delta`,
+    /// run them through [`SchemaRegistry::expand_changes`](crate::schema::SchemaRegistry::expand_changes)
+    /// first, or they fail the call. [`SchemaValidator::check_field`](crate::schema::SchemaValidator::check_field)
+    /// makes a `check` out of a validator.
+    pub fn apply_delta_partial<F>(&mut self, delta: &Delta, mut check: F) -> Result<Vec<(FieldId, LinkError)>>
+    where
+        F: FnMut(&str, &FieldDelta) -> Result<()>,
+    {
+        let field_compressor = FieldCompressor::new();
+        let mut index = EntityIndex::new(&self.entities);
+        let mut rejected = Vec::new();
+
+        let result = delta.changes.chunk_by(same_removal_run)
+            .try_for_each(|group| match group {
+                [DeltaChange::FieldsUpdated { entity_id, component_id, fields }] => {
+                    let entity = &mut self.entities[index.get(*entity_id)?];
+                    let position = component_index(entity, component_id)?;
+                    let component = &mut entity.components[position];
+                    let valid: Vec<FieldDelta> = fields.iter()
+                        .filter(|field| {
+                            check(component_id, field)
+                                .map_err(|error| rejected.push((field.field_id.clone(), error)))
+                                .is_ok()
+                        })
+                        .cloned()
+                        .collect();
+
+                    match field_compressor.apply_field_deltas(&component.data, &valid) {
+                        Ok(data) => component.data = data,
+                        // Find the fields that don't fit by applying them one at a time.
+                        Err(_) => for field in &valid {
+                            match field_compressor.apply_field_deltas(&component.data, std::slice::from_ref(field)) {
+                                Ok(data) => component.data = data,
+                                Err(error) => rejected.push((field.field_id.clone(), error)),
+                            }
+                        },
+                    }
+                    Ok(())
+                }
+                [change] => self.apply_change(change, &mut index, &field_compressor),
+                removals => self.remove_components(removals, &mut index),
+            });
+        index.compact(&mut self.entities);
+        result?;
+
+        self.timestamp = delta.timestamp;
+        Ok(rejected)
+    }
+
+    /// [`Self::apply_delta`] for deltas from a peer: fails, before any change
+    /// is applied, if the delta or the world it would leave exceeds `limits`.
+    /// Deltas that each pass [`MessageLimits::check_changes`] can otherwise
+    /// grow the world without bound.
+    pub fn apply_delta_within(&mut self, delta: &Delta, limits: &MessageLimits) -> Result<()> {
+        self.check_growth(delta, limits)?;
+        self.apply_delta(delta)
+    }
+
+    /// [`Self::apply_delta_lenient`] under the checks of
+    /// [`Self::apply_delta_within`]. Entities and components that reconciling
+    /// would create count towards the limits.
+    pub fn apply_delta_lenient_within(&mut self, delta: &Delta, limits: &MessageLimits) -> Result<ApplyReport> {
+        self.check_growth(delta, limits)?;
+        Ok(self.apply_delta_lenient(delta))
+    }
+
+    /// Walk `delta`'s changes tracking the entity count, and the components
+    /// of the entities they touch, as applying them would leave the world.
+    fn check_growth(&self, delta: &Delta, limits: &MessageLimits) -> Result<()> {
+        limits.check_changes(&delta.changes)?;
+
+        let index = EntityIndex::new(&self.entities);
+        let mut entity_count = self.entities.len();
+        // Component ids of touched entities, `None` while they don't exist.
+        let mut touched: AHashMap<EntityId, Option<AHashSet<&ComponentId>>> = AHashMap::new();
+
+        for change in &delta.changes {
+            let entity_id = change.entity_id();
+            let components = touched.entry(entity_id).or_insert_with(|| {
+                index.get(entity_id).ok().map(|position| self.entities[position].components.iter().map(|c| &c.id).collect())
+            });
+
+            match change {
+                DeltaChange::EntityRemoved { .. } => {
+                    if components.take().is_some() {
+                        entity_count -= 1;
+                    }
+                    continue;
+                }
+                DeltaChange::ComponentRemoved { component_id, .. } => {
+                    if let Some(components) = components {
+                        components.remove(component_id);
+                    }
+                    continue;
+                }
+                DeltaChange::FieldsUpdated { .. } | DeltaChange::IndexedFieldsUpdated { .. } => continue,
+                _ => {}
+            }
+
+            // Everything else creates the entity if it is missing.
+            let components = components.get_or_insert_with(|| {
+                entity_count += 1;
+                AHashSet::new()
+            });
+            if entity_count > limits.max_entities {
+                return Err(LinkError::InvalidMessage(format!(
+                    "{} entities exceeds the limit of {}", entity_count, limits.max_entities
+                )));
+            }
+
+            match change {
+                DeltaChange::ComponentAdded { component_id, .. } | DeltaChange::ComponentUpdated { component_id, .. } => {
+                    components.insert(component_id);
+                }
+                DeltaChange::EntityComponentsReplaced { components: replaced, .. } => {
+                    *components = replaced.iter().map(|c| &c.id).collect();
+                }
+                _ => {}
+            }
+            if components.len() > limits.max_components_per_entity {
+                return Err(limits.too_many_components(entity_id, components.len()));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn reconcile_change(&mut self, change: &DeltaChange, index: &mut EntityIndex) -> Resolution {
+        match change {
+            DeltaChange::EntityAdded { .. }
+            | DeltaChange::EntityRemoved { .. }
+            | DeltaChange::ComponentRemoved { .. } => Resolution::AlreadySatisfied,
+            DeltaChange::ComponentAdded { entity_id, component_id, data }
+            | DeltaChange::ComponentUpdated { entity_id, component_id, data } => {
+                let position = match index.get(*entity_id) {
+                    Ok(position) => position,
+                    Err(_) => index.push(&mut self.entities, *entity_id),
+                };
+                let entity = &mut self.entities[position];
+
+                match entity.components.iter_mut().find(|c| c.id == *component_id) {
+                    Some(component) => component.data = data.clone(),
+                    None => entity.components.push(SerializedComponent {
+                        id: component_id.clone(),
+                        data: data.clone(),
+                    }),
+                }
+                Resolution::Created
+            }
+            DeltaChange::EntityComponentsReplaced { entity_id, components } => {
+                let position = index.push(&mut self.entities, *entity_id);
+                self.entities[position].components = components.clone();
+                Resolution::Created
+            }
+            DeltaChange::FieldsUpdated { .. } | DeltaChange::IndexedFieldsUpdated { .. } => Resolution::Skipped,
+        }
+    }
+
+    /// Apply a run of `ComponentRemoved` for one entity, or none of it if any
+    /// component is missing.
+    fn remove_components(&mut self, removals: &[DeltaChange], index: &mut EntityIndex) -> Result<()> {
+        let entity = &mut self.entities[index.get(removals[0].entity_id())?];
+        let component_ids: Vec<&ComponentId> = removals.iter().filter_map(DeltaChange::component_id).collect();
+        for component_id in &component_ids {
+            component_index(entity, component_id)?;
+        }
+
+        entity.components.retain(|c| !component_ids.contains(&&c.id));
+        Ok(())
+    }
+
+    fn apply_change(
+        &mut self,
+        change: &DeltaChange,
+        index: &mut EntityIndex,
+        field_compressor: &FieldCompressor,
+    ) -> Result<()> {
+        match change {
+            DeltaChange::EntityAdded { entity_id } => {
+                if index.get(*entity_id).is_ok() {
+                    return Err(LinkError::InvalidMessage(format!("Entity {} already exists", entity_id)));
+                }
+                index.push(&mut self.entities, *entity_id);
+            }
+            DeltaChange::EntityRemoved { entity_id } => {
+                index.remove(*entity_id)?;
+            }
+            DeltaChange::ComponentAdded { entity_id, component_id, data } => {
+                let entity = &mut self.entities[index.get(*entity_id)?];
+                if entity.components.iter().any(|c| c.id == *component_id) {
+                    return Err(LinkError::InvalidMessage(
+                        format!("Component {} already exists on entity {}", component_id, entity_id)
+                    ));
+                }
+                entity.components.push(SerializedComponent {
+                    id: component_id.clone(),
+                    data: data.clone(),
+                });
+            }
+            DeltaChange::ComponentRemoved { entity_id, component_id } => {
+                let entity = &mut self.entities[index.get(*entity_id)?];
+                let position = component_index(entity, component_id)?;
+                entity.components.remove(position);
+            }
+            DeltaChange::ComponentUpdated { entity_id, component_id, data } => {
+                let entity = &mut self.entities[index.get(*entity_id)?];
+                let position = component_index(entity, component_id)?;
+                entity.components[position].data = data.clone();
+            }
+            DeltaChange::FieldsUpdated { entity_id, component_id, fields } => {
+                let entity = &mut self.entities[index.get(*entity_id)?];
+                let position = component_index(entity, component_id)?;
+                let component = &mut entity.components[position];
+                component.data = field_compressor.apply_field_deltas(&component.data, fields)?;
+            }
+            DeltaChange::EntityComponentsReplaced { entity_id, components } => {
+                self.entities[index.get(*entity_id)?].components = components.clone();
+            }
+            DeltaChange::IndexedFieldsUpdated { component_id, .. } => {
+                return Err(LinkError::InvalidMessage(format!(
+                    "Indexed field update for {} needs SchemaRegistry::expand_changes first",
+                    component_id
+                )));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether `a` and `b` remove components from the same entity.
+fn same_removal_run(a: &DeltaChange, b: &DeltaChange) -> bool {
+    matches!(
+        (a, b),
+        (DeltaChange::ComponentRemoved { entity_id: a, .. }, DeltaChange::ComponentRemoved { entity_id: b, .. }) if a == b
+    )
+}
+
+impl Delta {
+    /// Merge repeated updates to the same component, e.g. after concatenating
+    /// deltas: a `FieldsUpdated` is folded into an earlier `FieldsUpdated`,
+    /// keeping the latest value of each field, or into the data of an earlier
+    /// `ComponentAdded`/`ComponentUpdated`. Merging stops at any other change
+    /// to that component or its entity, so applying the result has the same
+    /// effect as applying the original. Field updates that don't apply to the
+    /// earlier data, such as binary components, are left as they are.
+    pub fn normalize(&mut self) {
+        let field_compressor = FieldCompressor::new();
+        let mut merged: Vec<DeltaChange> = Vec::with_capacity(self.changes.len());
+        // Where the latest mergeable change to each component sits in `merged`.
+        let mut open: AHashMap<(EntityId, ComponentId), usize> = AHashMap::new();
+
+        for change in std::mem::take(&mut self.changes) {
+            match change {
+                DeltaChange::FieldsUpdated { entity_id, component_id, fields } => {
+                    let key = (entity_id, component_id);
+                    if let Some(&index) = open.get(&key) {
+                        match &mut merged[index] {
+                            DeltaChange::FieldsUpdated { fields: earlier, .. } => {
+                                merge_fields(earlier, fields);
+                                continue;
+                            }
+                            DeltaChange::ComponentAdded { data, .. } | DeltaChange::ComponentUpdated { data, .. } => {
+                                if let Ok(updated) = field_compressor.apply_field_deltas(data, &fields) {
+                                    *data = updated;
+                                    continue;
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+
+                    open.insert(key.clone(), merged.len());
+                    merged.push(DeltaChange::FieldsUpdated { entity_id, component_id: key.1, fields });
+                }
+                DeltaChange::ComponentAdded { entity_id, ref component_id, .. }
+                | DeltaChange::ComponentUpdated { entity_id, ref component_id, .. } => {
+                    open.insert((entity_id, component_id.clone()), merged.len());
+                    merged.push(change);
+                }
+                DeltaChange::ComponentRemoved { entity_id, ref component_id }
+                | DeltaChange::IndexedFieldsUpdated { entity_id, ref component_id, .. } => {
+                    open.remove(&(entity_id, component_id.clone()));
+                    merged.push(change);
+                }
+                DeltaChange::EntityAdded { entity_id }
+                | DeltaChange::EntityRemoved { entity_id }
+                | DeltaChange::EntityComponentsReplaced { entity_id, .. } => {
+                    open.retain(|(id, _), _| *id != entity_id);
+                    merged.push(change);
+                }
+            }
+        }
+
+        self.changes = merged;
+    }
+}
+
+/// Fold `later` field updates into `earlier`. A field set twice keeps its
+/// first `old_value` and moves to the end, so path updates still apply in order.
+fn merge_fields(earlier: &mut Vec<FieldDelta>, later: Vec<FieldDelta>) {
+    for mut field in later {
+        if let Some(position) = earlier.iter().position(|f| f.field_id == field.field_id) {
+            let previous = earlier.remove(position);
+            if !field.removed {
+                field.old_value = previous.old_value;
+            }
+        }
+        earlier.push(field);
+    }
+}
+
+/// Positions of a snapshot's entities while a delta is applied to it.
+/// Removed entities stay in the vector until [`Self::compact`], so positions
+/// don't shift mid-delta and the survivors keep their order.
+struct EntityIndex {
+    positions: AHashMap<EntityId, usize>,
+    removed: Vec<usize>,
+}
+
+impl EntityIndex {
+    fn new(entities: &[SerializedEntity]) -> Self {
+        Self {
+            positions: entities.iter().enumerate().map(|(i, e)| (e.id, i)).collect(),
+            removed: Vec::new(),
+        }
+    }
+
+    fn get(&self, entity_id: EntityId) -> Result<usize> {
+        self.positions.get(&entity_id)
+            .copied()
+            .ok_or_else(|| LinkError::InvalidMessage(format!("Entity {} does not exist", entity_id)))
+    }
+
+    fn push(&mut self, entities: &mut Vec<SerializedEntity>, entity_id: EntityId) -> usize {
+        entities.push(SerializedEntity {
+            id: entity_id,
+            components: Vec::new(),
+        });
+        self.positions.insert(entity_id, entities.len() - 1);
+        entities.len() - 1
+    }
+
+    fn remove(&mut self, entity_id: EntityId) -> Result<()> {
+        let position = self.get(entity_id)?;
+        self.positions.remove(&entity_id);
+        self.removed.push(position);
+        Ok(())
+    }
+
+    fn compact(self, entities: &mut Vec<SerializedEntity>) {
+        if self.removed.is_empty() {
+            return;
+        }
+
+        let mut removed = vec![false; entities.len()];
+        for position in self.removed {
+            removed[position] = true;
+        }
+
+        let mut position = 0;
+        entities.retain(|_| {
+            position += 1;
+            !removed[position - 1]
+        });
+    }
+}
+
+/// Changes [`WorldSnapshot::apply_delta_lenient`] couldn't apply as written.
+#[derive(Debug, Clone, Default)]
+pub struct ApplyReport {
+    pub issues: Vec<ApplyIssue>,
+}
+
+impl ApplyReport {
+    /// Whether every change applied cleanly.
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApplyIssue {
+    /// Position of the change in `Delta::changes`.
+    pub change_index: usize,
+    /// Why strict application rejected it.
+    pub message: String,
+    pub resolution: Resolution,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resolution {
+    /// The state already matched what the change asked for, e.g. removing an
+    /// entity that doesn't exist.
+    AlreadySatisfied,
+    /// The missing entity or component was created from the change's data.
+    Created,
+    /// The change needs state that isn't there, such as field updates to a
+    /// missing component, and was dropped.
+    Skipped,
+}
+
+fn component_index(entity: &SerializedEntity, component_id: &str) -> Result<usize> {
+    entity.components.iter()
+        .position(|c| c.id == component_id)
+        .ok_or_else(|| LinkError::InvalidMessage(
+            format!("Component {} does not exist on entity {}", component_id, entity.id)
+        ))
+}
+
 pub struct FieldCompressor {
     enabled: bool,
+    /// Changes seen per component field, when tracking is on.
+    stats: Option<Mutex<AHashMap<(ComponentId, FieldId), u64>>>,
 }
 
 impl FieldCompressor {
     pub fn new() -> Self {
-        Self { enabled: true }
+        Self { enabled: true, stats: None }
     }
 
     pub fn with_enabled(enabled: bool) -> Self {
-        Self { enabled }
+        Self { enabled, stats: None }
+    }
+
+    /// A field compressor that counts how often each component field changes;
+    /// see [`Self::get_field_stats`]. Counting takes a lock per diffed component,
+    /// so it's off by default.
+    pub fn with_stats(enabled: bool) -> Self {
+        Self {
+            enabled: true,
+            stats: enabled.then(|| Mutex::new(AHashMap::new())),
+        }
+    }
+
+    /// Change counts per field, most frequently changed first. Empty unless
+    /// created with [`Self::with_stats`].
+    pub fn get_field_stats(&self) -> Result<Vec<FieldStats>> {
+        let Some(stats) = &self.stats else {
+            return Ok(Vec::new());
+        };
+        let stats = stats.lock()
+            .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
+
+        let mut fields: Vec<FieldStats> = stats.iter()
+            .map(|((component_id, field_id), changes)| FieldStats {
+                component_id: component_id.clone(),
+                field_id: field_id.clone(),
+                changes: *changes,
+            })
+            .collect();
+        fields.sort_by(|a, b| b.changes.cmp(&a.changes)
+            .then_with(|| a.component_id.cmp(&b.component_id))
+            .then_with(|| a.field_id.cmp(&b.field_id)));
+
+        Ok(fields)
+    }
+
+    pub fn reset_field_stats(&self) -> Result<()> {
+        if let Some(stats) = &self.stats {
+            stats.lock()
+                .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?
+                .clear();
+        }
+        Ok(())
     }
 
     pub fn is_enabled(&self) -> bool {
         self.enabled
     }
 
-    pub fn set_enabled(&mut self, enabled: bool) {
-        self.enabled = enabled;
-    }
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    pub fn compute_field_deltas(
+        &self,
+        prev: &SerializedComponent,
+        curr: &SerializedComponent,
+    ) -> Option<Vec<FieldDelta>> {
+        if !self.enabled {
+            return None;
+        }
+
+        let deltas = self.diff_fields(prev, curr);
+        self.count_changes(&curr.id, &deltas);
+        deltas
+    }
+
+    /// [`Self::compute_field_deltas`] for JSON components already parsed.
+    pub(crate) fn compute_json_field_deltas(
+        &self,
+        component_id: &ComponentId,
+        prev: &serde_json::Value,
+        curr: &serde_json::Value,
+    ) -> Option<Vec<FieldDelta>> {
+        if !self.enabled {
+            return None;
+        }
+
+        let deltas = diff_json(prev, curr);
+        self.count_changes(component_id, &deltas);
+        deltas
+    }
+
+    fn count_changes(&self, component_id: &ComponentId, deltas: &Option<Vec<FieldDelta>>) {
+        if let (Some(stats), Some(deltas)) = (&self.stats, deltas) {
+            // Stats are best-effort; a poisoned lock still holds usable counts.
+            let mut stats = stats.lock().unwrap_or_else(|e| e.into_inner());
+            for delta in deltas {
+                *stats.entry((component_id.clone(), delta.field_id.clone())).or_insert(0) += 1;
+            }
+        }
+    }
+
+    fn diff_fields(&self, prev: &SerializedComponent, curr: &SerializedComponent) -> Option<Vec<FieldDelta>> {
+        match (&prev.data, &curr.data) {
+            (ComponentData::Structured(prev_fields), ComponentData::Structured(curr_fields)) => {
+                let mut deltas = Vec::new();
+
+                for (field_id, curr_value) in curr_fields {
+                    if let Some(prev_value) = prev_fields.get(field_id) {
+                        if let (FieldValue::Map(prev_map), FieldValue::Map(curr_map)) = (prev_value, curr_value) {
+                            diff_maps(&escape_json_key(field_id), prev_map, curr_map, &mut deltas);
+                        } else if !prev_value.same_as(curr_value) {
+                            deltas.push(FieldDelta::new(field_id.clone(), Some(prev_value.clone()), curr_value.clone()));
+                        }
+                    } else {
+                        deltas.push(FieldDelta::new(field_id.clone(), None, curr_value.clone()));
+                    }
+                }
+
+                for field_id in prev_fields.keys() {
+                    if !curr_fields.contains_key(field_id) {
+                        deltas.push(FieldDelta::removal(field_id.clone(), prev_fields.get(field_id).cloned()));
+                    }
+                }
+
+                Some(deltas)
+            }
+            (ComponentData::Json(prev_json_str), ComponentData::Json(curr_json_str)) => {
+                if let (Ok(prev_json), Ok(curr_json)) = (
+                    serde_json::from_str::<serde_json::Value>(prev_json_str),
+                    serde_json::from_str::<serde_json::Value>(curr_json_str)
+                ) {
+                    diff_json(&prev_json, &curr_json)
+                } else {
+                    None
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Apply field deltas produced by [`Self::compute_field_deltas`] to `data`.
+    ///
+    /// A delta marked `removed` (see [`FieldDelta::removal`]) removes the
+    /// field; a `Null` new value sets it to null like any other value. For JSON
+    /// components, field ids are dotted paths into nested objects and arrays;
+    /// for structured ones, a dotted path addresses a key inside a `Map` field
+    /// (a field whose own name contains a '.' is matched whole first).
+    pub fn apply_field_deltas(&self, data: &ComponentData, fields: &[FieldDelta]) -> Result<ComponentData> {
+        match data {
+            ComponentData::Structured(values) => {
+                let mut values = values.clone();
+                for field in fields {
+                    let path: Vec<String> = field.field_id.split('.').map(unescape_json_key).collect();
+                    if path.len() > 1 && !values.contains_key(&field.field_id) {
+                        if let Some(FieldValue::Map(map)) = values.get_mut(&path[0]) {
+                            apply_map_path(map, &path[1..], field)?;
+                            continue;
+                        }
+                    }
+
+                    if field.removed {
+                        values.remove(&field.field_id);
+                    } else {
+                        values.insert(field.field_id.clone(), field.new_value.clone());
+                    }
+                }
+                Ok(ComponentData::Structured(values))
+            }
+            ComponentData::Json(json) => {
+                let mut value: serde_json::Value = serde_json::from_str(json)?;
+                for field in fields {
+                    let path: Vec<String> = field.field_id.split('.').map(unescape_json_key).collect();
+                    apply_json_path(&mut value, &path, field)?;
+                }
+                Ok(ComponentData::Json(value.to_string()))
+            }
+            ComponentData::Binary(_) => Err(LinkError::InvalidMessage(
+                "Field deltas cannot be applied to binary component data".to_string()
+            )),
+            ComponentData::Empty => Err(LinkError::InvalidMessage(
+                "Field deltas cannot be applied to an empty component".to_string()
+            )),
+            ComponentData::Compressed { .. } => Err(LinkError::InvalidMessage(
+                "Field deltas cannot be applied to compressed component data".to_string()
+            )),
+        }
+    }
+}
+
+// Path segments are joined with '.', so escape '~' and '.' inside keys.
+fn escape_json_key(key: &str) -> String {
+    key.replace('~', "~0").replace('.', "~1")
+}
+
+pub(crate) fn unescape_json_key(key: &str) -> String {
+    key.replace("~1", ".").replace("~0", "~")
+}
+
+fn json_path(prefix: &str, key: &str) -> String {
+    if prefix.is_empty() {
+        escape_json_key(key)
+    } else {
+        format!("{}.{}", prefix, escape_json_key(key))
+    }
+}
+
+/// Field deltas between two JSON objects, or `None` if either isn't one.
+fn diff_json(prev: &serde_json::Value, curr: &serde_json::Value) -> Option<Vec<FieldDelta>> {
+    let (prev_obj, curr_obj) = (prev.as_object()?, curr.as_object()?);
+    let mut deltas = Vec::new();
+    diff_json_objects("", prev_obj, curr_obj, &mut deltas);
+    Some(deltas)
+}
+
+fn diff_json_objects(
+    prefix: &str,
+    prev: &serde_json::Map<String, serde_json::Value>,
+    curr: &serde_json::Map<String, serde_json::Value>,
+    deltas: &mut Vec<FieldDelta>,
+) {
+    for (key, curr_value) in curr {
+        let path = json_path(prefix, key);
+        if let Some(prev_value) = prev.get(key) {
+            diff_json_values(path, prev_value, curr_value, deltas);
+        } else {
+            deltas.push(FieldDelta::new(path, None, json_to_field_value(curr_value)));
+        }
+    }
+
+    for (key, prev_value) in prev {
+        if !curr.contains_key(key) {
+            deltas.push(FieldDelta::removal(json_path(prefix, key), Some(json_to_field_value(prev_value))));
+        }
+    }
+}
+
+fn diff_json_values(path: String, prev: &serde_json::Value, curr: &serde_json::Value, deltas: &mut Vec<FieldDelta>) {
+    if prev == curr {
+        return;
+    }
+
+    match (prev, curr) {
+        (serde_json::Value::Object(prev_obj), serde_json::Value::Object(curr_obj)) => {
+            diff_json_objects(&path, prev_obj, curr_obj, deltas);
+        }
+        (serde_json::Value::Array(prev_arr), serde_json::Value::Array(curr_arr)) if prev_arr.len() == curr_arr.len() => {
+            for (index, (prev_item, curr_item)) in prev_arr.iter().zip(curr_arr).enumerate() {
+                diff_json_values(format!("{}.{}", path, index), prev_item, curr_item, deltas);
+            }
+        }
+        _ => {
+            deltas.push(FieldDelta::new(path, Some(json_to_field_value(prev)), json_to_field_value(curr)));
+        }
+    }
+}
+
+/// Per-key deltas between two `Map` values, descending into maps nested in
+/// maps. A key whose value changes type is replaced whole.
+fn diff_maps(
+    prefix: &str,
+    prev: &HashMap<String, FieldValue>,
+    curr: &HashMap<String, FieldValue>,
+    deltas: &mut Vec<FieldDelta>,
+) {
+    for (key, curr_value) in curr {
+        let path = json_path(prefix, key);
+        match (prev.get(key), curr_value) {
+            (Some(FieldValue::Map(prev_map)), FieldValue::Map(curr_map)) => {
+                diff_maps(&path, prev_map, curr_map, deltas);
+            }
+            (Some(prev_value), _) if prev_value.same_as(curr_value) => {}
+            (prev_value, _) => deltas.push(FieldDelta::new(path, prev_value.cloned(), curr_value.clone())),
+        }
+    }
+
+    for (key, prev_value) in prev {
+        if !curr.contains_key(key) {
+            deltas.push(FieldDelta::removal(json_path(prefix, key), Some(prev_value.clone())));
+        }
+    }
+}
+
+fn apply_map_path(map: &mut HashMap<String, FieldValue>, path: &[String], field: &FieldDelta) -> Result<()> {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+
+    if rest.is_empty() {
+        if field.removed {
+            map.remove(key);
+        } else {
+            map.insert(key.clone(), field.new_value.clone());
+        }
+        return Ok(());
+    }
+
+    match map.entry(key.clone()).or_insert_with(|| FieldValue::Map(HashMap::new())) {
+        FieldValue::Map(child) => apply_map_path(child, rest, field),
+        _ => Err(LinkError::InvalidMessage(format!("Field path '{}' does not match the map structure", field.field_id))),
+    }
+}
+
+fn apply_json_path(target: &mut serde_json::Value, path: &[String], field: &FieldDelta) -> Result<()> {
+    let (key, rest) = match path.split_first() {
+        Some(split) => split,
+        None => return Ok(()),
+    };
+
+    match target {
+        serde_json::Value::Object(obj) => {
+            if rest.is_empty() {
+                if field.removed {
+                    obj.remove(key);
+                } else {
+                    obj.insert(key.clone(), field_value_to_json(&field.new_value));
+                }
+                Ok(())
+            } else {
+                let child = obj.entry(key.clone())
+                    .or_insert_with(|| serde_json::Value::Object(serde_json::Map::new()));
+                apply_json_path(child, rest, field)
+            }
+        }
+        serde_json::Value::Array(arr) => {
+            let index: usize = key.parse()
+                .map_err(|_| LinkError::InvalidMessage(format!("Invalid array index '{}' in field path '{}'", key, field.field_id)))?;
+            let item = arr.get_mut(index)
+                .ok_or_else(|| LinkError::InvalidMessage(format!("Array index {} out of bounds in field path '{}'", index, field.field_id)))?;
+
+            if rest.is_empty() {
+                *item = field_value_to_json(&field.new_value);
+                Ok(())
+            } else {
+                apply_json_path(item, rest, field)
+            }
+        }
+        _ => Err(LinkError::InvalidMessage(format!("Field path '{}' does not match the JSON structure", field.field_id))),
+    }
+}
+
+pub(crate) fn field_value_to_json(value: &FieldValue) -> serde_json::Value {
+    match value {
+        FieldValue::Null => serde_json::Value::Null,
+        FieldValue::Bool(b) => serde_json::Value::Bool(*b),
+        FieldValue::U8(v) => serde_json::json!(v),
+        FieldValue::U16(v) => serde_json::json!(v),
+        FieldValue::U32(v) => serde_json::json!(v),
+        FieldValue::U64(v) => serde_json::json!(v),
+        FieldValue::I8(v) => serde_json::json!(v),
+        FieldValue::I16(v) => serde_json::json!(v),
+        FieldValue::I32(v) => serde_json::json!(v),
+        FieldValue::I64(v) => serde_json::json!(v),
+        FieldValue::F32(v) => serde_json::json!(v),
+        FieldValue::F64(v) => serde_json::json!(v),
+        FieldValue::String(s) => serde_json::Value::String(s.clone()),
+        FieldValue::Bytes(bytes) => serde_json::json!(bytes),
+        FieldValue::Array(items) => serde_json::Value::Array(items.iter().map(field_value_to_json).collect()),
+        FieldValue::Map(map) => serde_json::Value::Object(
+            map.iter().map(|(k, v)| (k.clone(), field_value_to_json(v))).collect()
+        ),
+        FieldValue::Enum { discriminant, payload } => serde_json::json!({
+            "discriminant": discriminant,
+            "payload": payload.as_deref().map(field_value_to_json),
+        }),
+    }
+}
+
+/// How often one component field changed, from [`FieldCompressor::get_field_stats`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FieldStats {
+    pub component_id: ComponentId,
+    pub field_id: FieldId,
+    pub changes: u64,
+}
 
-    pub fn compute_field_deltas(
-        &self,
-        prev: &SerializedComponent,
-        curr: &SerializedComponent,
-    ) -> Option<Vec<FieldDelta>> {
-        if !self.enabled {
-            return None;
-        }
+/// Encoded bytes of delta changes per component, to find which components
+/// dominate delta bandwidth.
+///
+/// Each change is encoded on its own, so message framing and entity-level
+/// changes aren't counted and the totals slightly overstate what the change
+/// list costs encoded as a whole. Changes the format can't encode count as zero.
+#[derive(Debug, Clone)]
+pub struct BandwidthStats {
+    format: BinaryFormat,
+    bytes: AHashMap<ComponentId, u64>,
+}
 
-        match (&prev.data, &curr.data) {
-            (ComponentData::Structured(prev_fields), ComponentData::Structured(curr_fields)) => {
-                let mut deltas = Vec::new();
+impl BandwidthStats {
+    pub fn new(format: BinaryFormat) -> Self {
+        Self { format, bytes: AHashMap::new() }
+    }
 
-                for (field_id, curr_value) in curr_fields {
-                    if let Some(prev_value) = prev_fields.get(field_id) {
-                        if prev_value != curr_value {
-                            deltas.push(FieldDelta {
-                                field_id: field_id.clone(),
-                                old_value: Some(prev_value.clone()),
-                                new_value: curr_value.clone(),
-                            });
-                        }
-                    } else {
-                        deltas.push(FieldDelta {
-                            field_id: field_id.clone(),
-                            old_value: None,
-                            new_value: curr_value.clone(),
-                        });
-                    }
-                }
+    pub fn record(&mut self, delta: &Delta) {
+        self.record_changes(&delta.changes);
+    }
 
-                for field_id in prev_fields.keys() {
-                    if !curr_fields.contains_key(field_id) {
-                        deltas.push(FieldDelta {
-                            field_id: field_id.clone(),
-                            old_value: prev_fields.get(field_id).cloned(),
-                            new_value: FieldValue::Null,
-                        });
-                    }
+    pub fn record_changes(&mut self, changes: &[DeltaChange]) {
+        for change in changes {
+            let Some(component_id) = change.component_id() else {
+                continue;
+            };
+            let size = encode_value(self.format, change).map_or(0, |bytes| bytes.len() as u64);
+            match self.bytes.get_mut(component_id) {
+                Some(total) => *total += size,
+                None => {
+                    self.bytes.insert(component_id.clone(), size);
                 }
-
-                Some(deltas)
             }
-            (ComponentData::Json(prev_json_str), ComponentData::Json(curr_json_str)) => {
-                if let (Ok(prev_json), Ok(curr_json)) = (
-                    serde_json::from_str::<serde_json::Value>(prev_json_str),
-                    serde_json::from_str::<serde_json::Value>(curr_json_str)
-                ) {
-                    if let (Some(prev_obj), Some(curr_obj)) = (prev_json.as_object(), curr_json.as_object()) {
-                        let mut deltas = Vec::new();
-
-                        for (key, curr_value) in curr_obj {
-                            if let Some(prev_value) = prev_obj.get(key) {
-                                if prev_value != curr_value {
-                                    deltas.push(FieldDelta {
-                                        field_id: key.clone(),
-                                        old_value: Some(json_to_field_value(prev_value)),
-                                        new_value: json_to_field_value(curr_value),
-                                    });
-                                }
-                            } else {
-                                deltas.push(FieldDelta {
-                                    field_id: key.clone(),
-                                    old_value: None,
-                                    new_value: json_to_field_value(curr_value),
-                                });
-                            }
-                        }
+        }
+    }
 
-                        for key in prev_obj.keys() {
-                            if !curr_obj.contains_key(key) {
-                                deltas.push(FieldDelta {
-                                    field_id: key.clone(),
-                                    old_value: prev_obj.get(key).map(json_to_field_value),
-                                    new_value: FieldValue::Null,
-                                });
-                            }
-                        }
+    pub fn bandwidth_by_component(&self) -> HashMap<ComponentId, u64> {
+        self.bytes.iter().map(|(id, bytes)| (id.clone(), *bytes)).collect()
+    }
 
-                        Some(deltas)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                }
-            }
-            _ => None,
+    pub fn total_bytes(&self) -> u64 {
+        self.bytes.values().sum()
+    }
+
+    /// Fraction of [`Self::total_bytes`] spent on `component_id`.
+    pub fn share(&self, component_id: &str) -> f64 {
+        match self.total_bytes() {
+            0 => 0.0,
+            total => self.bytes.get(component_id).copied().unwrap_or(0) as f64 / total as f64,
         }
     }
+
+    pub fn reset(&mut self) {
+        self.bytes.clear();
+    }
 }
 
 impl Default for FieldCompressor {
@@ -328,7 +1507,7 @@ impl Default for FieldCompressor {
     }
 }
 
-fn json_to_field_value(value: &serde_json::Value) -> FieldValue {
+pub(crate) fn json_to_field_value(value: &serde_json::Value) -> FieldValue {
     match value {
         serde_json::Value::Null => FieldValue::Null,
         serde_json::Value::Bool(b) => FieldValue::Bool(*b),
@@ -431,6 +1610,801 @@ mod tests {
         assert!(delta.changes.iter().any(|c| matches!(c, DeltaChange::ComponentUpdated { .. } | DeltaChange::FieldsUpdated { .. })));
     }
 
+    #[test]
+    fn test_peek_delta_does_not_commit() {
+        let mut compressor = DeltaCompressor::new();
+
+        let snapshot = WorldSnapshot {
+            entities: vec![
+                SerializedEntity {
+                    id: 1,
+                    components: vec![],
+                }
+            ],
+            timestamp: 100.0,
+            version: "1.0.0".to_string(),
+        };
+
+        let peeked = compressor.peek_delta(&snapshot);
+        assert_eq!(peeked.changes.len(), 1);
+        assert!(compressor.get_previous_snapshot().is_none());
+
+        let created = compressor.create_delta(snapshot.clone());
+        assert_eq!(created.changes.len(), peeked.changes.len());
+        assert!(compressor.peek_delta(&snapshot).changes.is_empty());
+    }
+
+    #[test]
+    fn test_apply_delta_within_limits() {
+        let limits = MessageLimits::new().with_max_entities(3).with_max_components_per_entity(2);
+        let component = |entity_id, id: &str| DeltaChange::ComponentAdded {
+            entity_id,
+            component_id: id.to_string(),
+            data: ComponentData::Empty,
+        };
+        let delta = |changes, timestamp| Delta { changes, timestamp, base_timestamp: timestamp - 1.0 };
+
+        // Each delta is within the limits on its own; the world stops at three entities.
+        let mut world = WorldSnapshot::for_test(0.0, vec![]);
+        for id in 1..=3 {
+            let adds = delta(vec![DeltaChange::EntityAdded { entity_id: id }], id as f64);
+            world.apply_delta_within(&adds, &limits).unwrap();
+        }
+        let fourth = delta(vec![DeltaChange::EntityAdded { entity_id: 4 }], 4.0);
+        assert!(world.apply_delta_within(&fourth, &limits).is_err());
+        assert!(world.apply_delta_lenient_within(&fourth, &limits).is_err());
+        assert_eq!(world.entities.len(), 3);
+
+        // Removals make room, and reconciling counts the entities it creates.
+        let churn = delta(vec![DeltaChange::EntityRemoved { entity_id: 1 }, DeltaChange::EntityAdded { entity_id: 4 }], 5.0);
+        world.apply_delta_within(&churn, &limits).unwrap();
+        assert!(world.apply_delta_lenient_within(&delta(vec![component(5, "Player")], 6.0), &limits).is_err());
+
+        // Updating a component doesn't count as another one.
+        let two = delta(vec![component(2, "Player"), component(2, "Health")], 7.0);
+        world.apply_delta_within(&two, &limits).unwrap();
+        let update = DeltaChange::ComponentUpdated { entity_id: 2, component_id: "Health".to_string(), data: ComponentData::Empty };
+        world.apply_delta_within(&delta(vec![update], 8.0), &limits).unwrap();
+        let error = world.apply_delta_within(&delta(vec![component(2, "Dead")], 9.0), &limits).unwrap_err();
+        assert!(error.to_string().contains("exceeding the limit of 2"), "{}", error);
+    }
+
+    #[test]
+    fn test_baseline_survives_restart() {
+        let snapshot = |health: f64| WorldSnapshot::for_test(health, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Health".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({"current": health})),
+            }],
+        }]);
+
+        let mut compressor = DeltaCompressor::new();
+        compressor.create_delta(snapshot(1.0));
+        let serializer = crate::BinarySerializer::messagepack();
+        let checkpoint = serializer.serialize_snapshot(&compressor.export_baseline().unwrap()).unwrap();
+        let expected = compressor.create_delta(snapshot(2.0));
+
+        let mut restarted = DeltaCompressor::new();
+        assert!(restarted.export_baseline().is_none());
+        restarted.import_baseline(serializer.deserialize_snapshot(&checkpoint).unwrap());
+        let delta = restarted.create_delta(snapshot(2.0));
+
+        assert_eq!(delta.base_timestamp, 1.0);
+        assert_eq!(delta.changes.len(), expected.changes.len());
+        assert!(matches!(delta.changes[0], DeltaChange::FieldsUpdated { .. }));
+    }
+
+    #[test]
+    fn test_bandwidth_by_component() {
+        let snapshot = |tick: f64| WorldSnapshot::for_test(tick, (0..10)
+            .map(|id| SerializedEntity {
+                id,
+                components: vec![
+                    SerializedComponent {
+                        id: "Position".to_string(),
+                        data: ComponentData::from_json_value(serde_json::json!({"x": tick, "y": tick})),
+                    },
+                    SerializedComponent {
+                        id: "Name".to_string(),
+                        data: ComponentData::Json(format!("\"entity {}\"", id)),
+                    },
+                ],
+            })
+            .collect());
+
+        let mut compressor = DeltaCompressor::new().with_bandwidth_stats(BinaryFormat::MessagePack);
+        compressor.create_delta(snapshot(1.0));
+        let initial = compressor.bandwidth_by_component();
+        assert!(initial["Position"] > 0 && initial["Name"] > 0);
+
+        compressor.reset_bandwidth_stats();
+        compressor.create_delta(snapshot(2.0));
+        compressor.peek_delta(&snapshot(3.0));
+        let stats = compressor.get_bandwidth_stats().unwrap();
+        assert_eq!(stats.bandwidth_by_component().keys().collect::<Vec<_>>(), vec!["Position"]);
+        assert_eq!(stats.share("Position"), 1.0);
+        assert!(stats.total_bytes() > 0);
+
+        assert!(DeltaCompressor::new().bandwidth_by_component().is_empty());
+    }
+
+    #[test]
+    fn test_unchanged_snapshot_gives_empty_delta() {
+        let mut compressor = DeltaCompressor::new();
+
+        let entity = |id| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Health".to_string(),
+                data: ComponentData::Structured([("hp".to_string(), FieldValue::U32(id))].into_iter().collect()),
+            }],
+        };
+        let snapshot = WorldSnapshot {
+            entities: vec![entity(1), entity(2)],
+            timestamp: 100.0,
+            version: "1.0.0".to_string(),
+        };
+        compressor.create_delta(snapshot.clone());
+
+        // Same content in a different order, at a later tick.
+        let mut idle = snapshot.clone();
+        idle.entities.reverse();
+        idle.timestamp = 200.0;
+        let delta = compressor.create_delta(idle.clone());
+        assert!(delta.changes.is_empty());
+        assert_eq!(delta.timestamp, 200.0);
+        assert_eq!(delta.base_timestamp, 100.0);
+
+        let mut changed = idle.clone();
+        changed.timestamp = 300.0;
+        changed.entities[0] = entity(3);
+        assert!(!compressor.create_delta(changed).changes.is_empty());
+
+        // In the same order, only the last entity differing still counts.
+        let mut compressor = DeltaCompressor::new();
+        compressor.create_delta(snapshot.clone());
+        assert!(compressor.peek_delta(&snapshot).changes.is_empty());
+        let mut last_changed = snapshot;
+        last_changed.entities[1] = entity(3);
+        assert!(!compressor.peek_delta(&last_changed).changes.is_empty());
+    }
+
+    #[test]
+    fn test_nan_fields_are_unchanged() {
+        let snapshot = |hp: f64| WorldSnapshot::for_test(hp, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Health".to_string(),
+                data: ComponentData::Structured([
+                    ("hp".to_string(), FieldValue::F64(hp)),
+                    ("regen".to_string(), FieldValue::F64(f64::NAN)),
+                ].into_iter().collect()),
+            }],
+        }]);
+
+        let mut compressor = DeltaCompressor::new();
+        compressor.create_delta(snapshot(1.0));
+
+        // Changes elsewhere in the component don't drag the NaN field along.
+        let delta = compressor.create_delta(snapshot(2.0));
+        match &delta.changes[..] {
+            [DeltaChange::FieldsUpdated { fields, .. }] => {
+                assert_eq!(fields.len(), 1);
+                assert_eq!(fields[0].field_id, "hp");
+            }
+            other => panic!("expected one field update, got {:?}", other),
+        }
+
+        let previous = compressor.get_previous_snapshot().unwrap().clone();
+        assert!(previous.changed_entities(&snapshot(2.0)).modified.is_empty());
+    }
+
+    #[test]
+    fn test_entity_scoped_delta() {
+        let entity = |id: u32, x: f64| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({"x": x})),
+            }],
+        };
+        let snapshot = |x: f64, timestamp: f64| WorldSnapshot::for_test(timestamp, vec![entity(1, x), entity(2, x), entity(3, x)]);
+        let touched = |delta: &Delta| {
+            let mut ids: Vec<_> = delta.changes.iter().map(|change| match change {
+                DeltaChange::EntityAdded { entity_id } => (*entity_id, "added"),
+                DeltaChange::EntityRemoved { entity_id } => (*entity_id, "removed"),
+                DeltaChange::ComponentAdded { entity_id, .. } => (*entity_id, "component"),
+                DeltaChange::ComponentUpdated { entity_id, .. }
+                | DeltaChange::FieldsUpdated { entity_id, .. }
+                | DeltaChange::IndexedFieldsUpdated { entity_id, .. }
+                | DeltaChange::EntityComponentsReplaced { entity_id, .. } => (*entity_id, "updated"),
+                DeltaChange::ComponentRemoved { entity_id, .. } => (*entity_id, "component removed"),
+            }).collect();
+            ids.sort();
+            ids
+        };
+
+        let mut compressor = DeltaCompressor::new();
+
+        let delta = compressor.create_delta_for(&[1], snapshot(0.0, 1.0));
+        assert_eq!(touched(&delta), vec![(1, "added"), (1, "component")]);
+
+        let delta = compressor.create_delta_for(&[1], snapshot(1.0, 2.0));
+        assert_eq!(touched(&delta), vec![(1, "updated")]);
+
+        let delta = compressor.create_delta_for(&[2], snapshot(1.0, 3.0));
+        assert_eq!(touched(&delta), vec![(1, "removed"), (2, "added"), (2, "component")]);
+        assert_eq!(compressor.get_previous_snapshot().unwrap().entities.len(), 3);
+
+        // After a full delta the receiver knows every entity.
+        compressor.create_delta(snapshot(1.0, 4.0));
+        let delta = compressor.create_delta_for(&[3], snapshot(2.0, 5.0));
+        assert_eq!(touched(&delta), vec![(1, "removed"), (2, "removed"), (3, "updated")]);
+    }
+
+    #[test]
+    fn test_delta_from_acked_baseline() {
+        let mut compressor = DeltaCompressor::new().with_history_size(3);
+
+        let snapshot = |timestamp: f64, x: f64| WorldSnapshot::for_test(timestamp, vec![
+            SerializedEntity {
+                id: 1,
+                components: vec![
+                    SerializedComponent {
+                        id: "Position".to_string(),
+                        data: ComponentData::from_json_value(serde_json::json!({"x": x})),
+                    }
+                ],
+            }
+        ]);
+
+        compressor.create_delta(snapshot(1.0, 0.0));
+        compressor.create_delta(snapshot(2.0, 1.0));
+        compressor.create_delta(snapshot(3.0, 2.0));
+        assert!(compressor.has_baseline(1.0));
+
+        let delta = compressor.create_delta_from(1.0, snapshot(4.0, 0.0));
+        assert_eq!(delta.base_timestamp, 1.0);
+        assert!(delta.changes.is_empty());
+
+        // 1.0 was evicted when 4.0 was recorded, so this falls back to a full delta.
+        assert!(!compressor.has_baseline(1.0));
+        let delta = compressor.create_delta_from(1.0, snapshot(5.0, 0.0));
+        assert_eq!(delta.base_timestamp, 0.0);
+        assert!(matches!(delta.changes[0], DeltaChange::EntityAdded { .. }));
+    }
+
+    #[test]
+    fn test_deflate_roundtrip() {
+        use crate::serialization::BinarySerializer;
+
+        let entities = (0..200)
+            .map(|id| SerializedEntity {
+                id,
+                components: vec![
+                    SerializedComponent {
+                        id: "Position".to_string(),
+                        data: ComponentData::from_json_value(serde_json::json!({"x": 1.0, "y": 2.0, "z": 3.0})),
+                    }
+                ],
+            })
+            .collect();
+        let snapshot = WorldSnapshot {
+            entities,
+            timestamp: 100.0,
+            version: "1.0.0".to_string(),
+        };
+
+        let plain = BinarySerializer::messagepack();
+        let deflate = BinarySerializer::messagepack().with_compression(CompressionType::Deflate);
+
+        let plain_bytes = plain.serialize_snapshot(&snapshot).unwrap();
+        let deflate_bytes = deflate.serialize_snapshot(&snapshot).unwrap();
+        assert!(deflate_bytes.len() < plain_bytes.len());
+
+        let decoded = deflate.deserialize_snapshot(&deflate_bytes).unwrap();
+        assert_eq!(decoded.entities.len(), 200);
+        assert_eq!(decompress(&deflate_bytes, CompressionType::Deflate).unwrap(), plain_bytes.to_vec());
+
+        assert!(decompress(b"not deflate", CompressionType::Deflate).is_err());
+    }
+
+    #[test]
+    fn test_deflate_output_is_bounded() {
+        let bomb = compress(&vec![0u8; 1 << 20], CompressionType::Deflate).unwrap();
+        assert!(bomb.len() < 4096);
+
+        assert_eq!(decompress_bounded(&bomb, CompressionType::Deflate, 1 << 20).unwrap().len(), 1 << 20);
+        assert!(matches!(
+            decompress_bounded(&bomb, CompressionType::Deflate, (1 << 20) - 1),
+            Err(LinkError::Deserialization(_))
+        ));
+    }
+
+    #[test]
+    fn test_lz4_size_prefix_is_bounded() {
+        let data = compress(&[7u8; 1000], CompressionType::Lz4).unwrap();
+        assert_eq!(decompress_bounded(&data, CompressionType::Lz4, 1000).unwrap(), vec![7u8; 1000]);
+        assert!(decompress_bounded(&data, CompressionType::Lz4, 999).is_err());
+
+        // A claimed 4 GiB output is rejected without allocating it.
+        let mut forged = u32::MAX.to_le_bytes().to_vec();
+        forged.extend_from_slice(&data[4..]);
+        assert!(decompress(&forged, CompressionType::Lz4).is_err());
+        assert!(decompress(&[1, 0], CompressionType::Lz4).is_err());
+    }
+
+    #[test]
+    fn test_nested_json_field_deltas() {
+        let compressor = FieldCompressor::new();
+
+        let prev = SerializedComponent {
+            id: "Profile".to_string(),
+            data: ComponentData::from_json_value(serde_json::json!({
+                "name": "Ada",
+                "address": {"city": "London", "zip": "N1", "geo": {"lat": 51.5}},
+                "tags": ["a", "b"],
+                "dotted.key": 1,
+                "legacy": true,
+            })),
+        };
+        let curr = SerializedComponent {
+            id: "Profile".to_string(),
+            data: ComponentData::from_json_value(serde_json::json!({
+                "name": "Ada",
+                "address": {"city": "Paris", "zip": "N1", "geo": {"lat": 48.9}},
+                "tags": ["a", "c"],
+                "dotted.key": 2,
+            })),
+        };
+
+        let deltas = compressor.compute_field_deltas(&prev, &curr).unwrap();
+        let mut ids: Vec<&str> = deltas.iter().map(|d| d.field_id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids, vec!["address.city", "address.geo.lat", "dotted~1key", "legacy", "tags.1"]);
+
+        let applied = compressor.apply_field_deltas(&prev.data, &deltas).unwrap();
+        assert_eq!(applied.to_json_value(), curr.data.to_json_value());
+    }
+
+    #[test]
+    fn test_field_set_to_null_is_not_removed() {
+        let compressor = FieldCompressor::new();
+        let json = |value: serde_json::Value| SerializedComponent {
+            id: "Profile".to_string(),
+            data: ComponentData::from_json_value(value),
+        };
+
+        let prev = json(serde_json::json!({"nick": "ada", "guild": "x", "level": 3}));
+        let curr = json(serde_json::json!({"nick": null, "level": 3}));
+
+        let mut deltas = compressor.compute_field_deltas(&prev, &curr).unwrap();
+        deltas.sort_by(|a, b| a.field_id.cmp(&b.field_id));
+        assert_eq!((deltas[0].field_id.as_str(), deltas[0].removed), ("guild", true));
+        assert_eq!((deltas[1].field_id.as_str(), deltas[1].removed), ("nick", false));
+
+        let applied = compressor.apply_field_deltas(&prev.data, &deltas).unwrap();
+        assert_eq!(applied.to_json_value(), curr.data.to_json_value());
+
+        let structured = |value: FieldValue| SerializedComponent {
+            id: "Profile".to_string(),
+            data: ComponentData::Structured([("nick".to_string(), value)].into_iter().collect()),
+        };
+        let prev = structured(FieldValue::String("ada".to_string()));
+        let curr = structured(FieldValue::Null);
+        let deltas = compressor.compute_field_deltas(&prev, &curr).unwrap();
+        assert_eq!(compressor.apply_field_deltas(&prev.data, &deltas).unwrap(), curr.data);
+    }
+
+    #[test]
+    fn test_reencoded_json_still_sends_update() {
+        let snapshot = |json: &str, timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::Json(json.to_string()),
+            }],
+        }]);
+        let prev = snapshot(r#"{"x":1.0,"y":2.0}"#, 1.0);
+        let curr = snapshot(r#"{"y": 2.0, "x": 1.0}"#, 2.0);
+
+        // Same fields, different bytes: field diffing finds nothing to send.
+        let fields = FieldCompressor::new()
+            .compute_field_deltas(&prev.entities[0].components[0], &curr.entities[0].components[0]);
+        assert_eq!(fields.map(|f| f.len()), Some(0));
+
+        let mut compressor = DeltaCompressor::new();
+        compressor.create_delta(prev.clone());
+        let delta = compressor.create_delta(curr.clone());
+        assert!(matches!(
+            delta.changes.as_slice(),
+            [DeltaChange::ComponentUpdated { data: ComponentData::Json(json), .. }] if json == r#"{"y": 2.0, "x": 1.0}"#
+        ));
+
+        let mut rebuilt = prev;
+        rebuilt.apply_delta(&delta).unwrap();
+        assert_eq!(rebuilt.content_hash(), curr.content_hash());
+    }
+
+    #[test]
+    fn test_canonical_json_is_unchanged() {
+        let snapshot = |json: &str, timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::Json(json.to_string()),
+            }],
+        }]);
+
+        let mut compressor = DeltaCompressor::new()
+            .with_field_compressor(FieldCompressor::with_stats(true))
+            .with_canonical_json(true);
+        compressor.create_delta(snapshot(r#"{"x":1.0,"y":2.0}"#, 1.0));
+        assert!(compressor.create_delta(snapshot(r#"{"y": 2.0, "x": 1.0}"#, 2.0)).changes.is_empty());
+
+        let delta = compressor.create_delta(snapshot(r#"{"y": 2.0, "x": 1.5}"#, 3.0));
+        match delta.changes.as_slice() {
+            [DeltaChange::FieldsUpdated { fields, .. }] => {
+                assert_eq!(fields.len(), 1);
+                assert_eq!(fields[0].field_id, "x");
+            }
+            other => panic!("expected one field update, got {:?}", other),
+        }
+        assert_eq!(compressor.get_field_compressor().get_field_stats().unwrap()[0].changes, 1);
+
+        // Invalid JSON still compares as text.
+        compressor.create_delta(snapshot("{", 4.0));
+        assert!(compressor.create_delta(snapshot("{", 5.0)).changes.is_empty());
+        assert!(matches!(
+            compressor.create_delta(snapshot("{ ", 6.0)).changes.as_slice(),
+            [DeltaChange::ComponentUpdated { .. }]
+        ));
+
+        let mut data = ComponentData::Json(r#"{ "y": 2.0, "x": {"b": 1, "a": null} }"#.to_string());
+        data.canonicalize();
+        assert_eq!(data.as_json_str(), Some(r#"{"x":{"a":null,"b":1},"y":2.0}"#));
+        assert!(data.content_eq(&ComponentData::Json(r#"{"y":2.0,"x":{"a":null,"b":1}}"#.to_string())));
+        assert!(!data.content_eq(&ComponentData::Json(r#"{"y":2,"x":{"a":null,"b":1}}"#.to_string())));
+    }
+
+    #[test]
+    fn test_component_removals_are_grouped() {
+        let entity = |id: EntityId, markers: &[&str]| SerializedEntity {
+            id,
+            components: markers.iter()
+                .map(|id| SerializedComponent { id: id.to_string(), data: ComponentData::Empty })
+                .collect(),
+        };
+        let snapshot = |timestamp: f64, entities: Vec<SerializedEntity>| WorldSnapshot::for_test(timestamp, entities);
+
+        let before = snapshot(1.0, vec![entity(1, &["A", "B", "C", "D"]), entity(2, &["A", "B"])]);
+        let after = snapshot(2.0, vec![entity(1, &["B", "E"]), entity(2, &["E"])]);
+        let mut compressor = DeltaCompressor::new();
+        compressor.create_delta(before.clone());
+        let delta = compressor.create_delta(after.clone());
+
+        let kinds: Vec<_> = delta.changes_for_entity(1)
+            .map(|change| matches!(change, DeltaChange::ComponentRemoved { .. }))
+            .collect();
+        assert_eq!(kinds, vec![true, true, true, false]);
+        assert!(matches!(
+            delta.changes_for_entity(2).collect::<Vec<_>>()[..],
+            [DeltaChange::EntityComponentsReplaced { components, .. }] if components.len() == 1
+        ));
+
+        let mut applied = before.clone();
+        applied.apply_delta(&delta).unwrap();
+        assert_eq!(applied, after);
+
+        let mut partial = before.clone();
+        let removals = Delta {
+            changes: vec![
+                DeltaChange::ComponentRemoved { entity_id: 1, component_id: "A".to_string() },
+                DeltaChange::ComponentRemoved { entity_id: 1, component_id: "E".to_string() },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+        assert!(partial.apply_delta(&removals).is_err());
+        assert_eq!(partial.entities, before.entities);
+
+        let report = partial.apply_delta_lenient(&removals);
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].change_index, 1);
+        assert_eq!(partial.entities[0].components.len(), 3);
+    }
+
+    #[test]
+    fn test_normalize_delta() {
+        let field = |id: &str, old: Option<f64>, new: f64| FieldDelta::new(id.to_string(), old.map(FieldValue::F64), FieldValue::F64(new));
+        let fields = |entity_id, fields: Vec<FieldDelta>| DeltaChange::FieldsUpdated {
+            entity_id,
+            component_id: "Position".to_string(),
+            fields,
+        };
+        let position = |x: f64, y: f64| ComponentData::Structured(
+            [("x", x), ("y", y)].iter().map(|(k, v)| (k.to_string(), FieldValue::F64(*v))).collect()
+        );
+
+        let base = WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 1,
+                components: vec![SerializedComponent { id: "Position".to_string(), data: position(0.0, 0.0) }],
+            }],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        let original = Delta {
+            changes: vec![
+                fields(1, vec![field("x", Some(0.0), 1.0), field("y", Some(0.0), 1.0)]),
+                DeltaChange::EntityAdded { entity_id: 2 },
+                DeltaChange::ComponentAdded { entity_id: 2, component_id: "Position".to_string(), data: position(5.0, 5.0) },
+                fields(1, vec![field("x", Some(1.0), 2.0)]),
+                fields(2, vec![field("y", Some(5.0), 6.0)]),
+                DeltaChange::ComponentRemoved { entity_id: 2, component_id: "Position".to_string() },
+                DeltaChange::ComponentAdded { entity_id: 2, component_id: "Position".to_string(), data: position(7.0, 7.0) },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+
+        let mut normalized = original.clone();
+        normalized.normalize();
+        assert_eq!(normalized.changes, vec![
+            fields(1, vec![field("y", Some(0.0), 1.0), field("x", Some(0.0), 2.0)]),
+            DeltaChange::EntityAdded { entity_id: 2 },
+            DeltaChange::ComponentAdded { entity_id: 2, component_id: "Position".to_string(), data: position(5.0, 6.0) },
+            DeltaChange::ComponentRemoved { entity_id: 2, component_id: "Position".to_string() },
+            DeltaChange::ComponentAdded { entity_id: 2, component_id: "Position".to_string(), data: position(7.0, 7.0) },
+        ]);
+
+        let (mut expected, mut applied) = (base.clone(), base);
+        expected.apply_delta(&original).unwrap();
+        applied.apply_delta(&normalized).unwrap();
+        assert_eq!(applied, expected);
+
+        let mut binary = Delta {
+            changes: vec![
+                DeltaChange::ComponentUpdated { entity_id: 1, component_id: "Position".to_string(), data: ComponentData::Binary(bytes::Bytes::from_static(&[1])) },
+                fields(1, vec![field("x", None, 1.0)]),
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+        binary.normalize();
+        assert_eq!(binary.changes.len(), 2);
+    }
+
+    #[test]
+    fn test_empty_marker_components() {
+        let mut compressor = DeltaCompressor::new();
+
+        let snapshot = |timestamp: f64, markers: &[&str]| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: markers.iter()
+                .map(|id| SerializedComponent { id: id.to_string(), data: ComponentData::Empty })
+                .collect(),
+        }]);
+
+        compressor.create_delta(snapshot(1.0, &["Player"]));
+
+        let delta = compressor.create_delta(snapshot(2.0, &["Player"]));
+        assert!(delta.changes.is_empty());
+
+        let delta = compressor.create_delta(snapshot(3.0, &["Player", "Dead"]));
+        assert_eq!(delta.changes.len(), 1);
+        assert!(matches!(
+            &delta.changes[0],
+            DeltaChange::ComponentAdded { component_id, data: ComponentData::Empty, .. } if component_id == "Dead"
+        ));
+
+        let serializer = crate::serialization::BinarySerializer::messagepack();
+        let encoded = serializer.serialize_delta(&delta).unwrap();
+        let decoded = serializer.deserialize_delta(&encoded).unwrap();
+        assert!(matches!(&decoded.changes[0], DeltaChange::ComponentAdded { data: ComponentData::Empty, .. }));
+    }
+
+    #[test]
+    fn test_apply_delta_in_place_keeps_order() {
+        let mut world = WorldSnapshot {
+            entities: (1..=5).map(|id| SerializedEntity { id, components: vec![] }).collect(),
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        let delta = Delta {
+            changes: vec![
+                DeltaChange::EntityRemoved { entity_id: 2 },
+                DeltaChange::EntityRemoved { entity_id: 4 },
+                DeltaChange::EntityAdded { entity_id: 2 },
+                DeltaChange::ComponentAdded {
+                    entity_id: 2,
+                    component_id: "Player".to_string(),
+                    data: ComponentData::Empty,
+                },
+                DeltaChange::EntityAdded { entity_id: 6 },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+
+        world.apply_delta(&delta).unwrap();
+        assert_eq!(world.entities.iter().map(|e| e.id).collect::<Vec<_>>(), vec![1, 3, 5, 2, 6]);
+        assert_eq!(world.entities[3].components.len(), 1);
+
+        // A failing change still leaves the earlier removals compacted.
+        let bad = Delta {
+            changes: vec![
+                DeltaChange::EntityRemoved { entity_id: 1 },
+                DeltaChange::EntityRemoved { entity_id: 1 },
+            ],
+            timestamp: 3.0,
+            base_timestamp: 2.0,
+        };
+        assert!(world.apply_delta(&bad).is_err());
+        assert_eq!(world.entities.iter().map(|e| e.id).collect::<Vec<_>>(), vec![3, 5, 2, 6]);
+        assert_eq!(world.timestamp, 2.0);
+    }
+
+    #[test]
+    fn test_lenient_apply_reconciles_partial_state() {
+        let position = |x: f64| ComponentData::from_json_value(serde_json::json!({"x": x}));
+        let delta = Delta {
+            changes: vec![
+                DeltaChange::ComponentUpdated {
+                    entity_id: 7,
+                    component_id: "Position".to_string(),
+                    data: position(3.0),
+                },
+                DeltaChange::EntityRemoved { entity_id: 9 },
+                DeltaChange::FieldsUpdated {
+                    entity_id: 8,
+                    component_id: "Velocity".to_string(),
+                    fields: vec![],
+                },
+                DeltaChange::EntityAdded { entity_id: 10 },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+
+        let mut world = WorldSnapshot {
+            entities: vec![],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        assert!(world.clone().apply_delta(&delta).is_err());
+
+        let report = world.apply_delta_lenient(&delta);
+        let resolutions: Vec<_> = report.issues.iter()
+            .map(|issue| (issue.change_index, issue.resolution))
+            .collect();
+        assert_eq!(resolutions, vec![
+            (0, Resolution::Created),
+            (1, Resolution::AlreadySatisfied),
+            (2, Resolution::Skipped),
+        ]);
+
+        assert_eq!(world.timestamp, 2.0);
+        assert_eq!(world.entities.iter().map(|e| e.id).collect::<Vec<_>>(), vec![7, 10]);
+        assert_eq!(world.entities[0].components[0].data, position(3.0));
+        assert!(world.apply_delta_lenient(&Delta { changes: vec![], timestamp: 3.0, base_timestamp: 2.0 }).is_clean());
+    }
+
+    #[test]
+    fn test_partial_field_apply() {
+        use crate::schema::{ComponentSchema, FieldSchema, SchemaRegistry, SchemaValidator, SchemaVersion};
+
+        let registry = SchemaRegistry::new();
+        registry.register(
+            ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
+                .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
+                .with_field(FieldSchema::new("y".to_string(), FieldType::F64))
+        ).unwrap();
+        let validator = SchemaValidator::new(registry).with_strict(true);
+
+        let field = |field_id: &str, value: FieldValue| FieldDelta::new(field_id.to_string(), None, value);
+        let position = |x: f64| ComponentData::Structured(
+            [("x".to_string(), FieldValue::F64(x)), ("y".to_string(), FieldValue::F64(0.0))].into_iter().collect()
+        );
+        let mut world = WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 1,
+                components: vec![
+                    SerializedComponent { id: "Position".to_string(), data: position(0.0) },
+                    SerializedComponent { id: "Tags".to_string(), data: ComponentData::from_json_value(serde_json::json!({"list": [1]})) },
+                ],
+            }],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        let delta = Delta {
+            changes: vec![
+                DeltaChange::FieldsUpdated {
+                    entity_id: 1,
+                    component_id: "Position".to_string(),
+                    fields: vec![
+                        field("x", FieldValue::F64(2.0)),
+                        field("y", FieldValue::String("up".to_string())),
+                        field("z", FieldValue::F64(1.0)),
+                    ],
+                },
+                DeltaChange::FieldsUpdated {
+                    entity_id: 1,
+                    component_id: "Tags".to_string(),
+                    fields: vec![field("list.first", FieldValue::U32(2)), field("name", FieldValue::String("a".to_string()))],
+                },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+
+        let rejected = world.apply_delta_partial(&delta, |component_id, field| validator.check_field(component_id, field)).unwrap();
+        assert_eq!(rejected.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>(), vec!["y", "z", "list.first"]);
+        assert_eq!(world.entities[0].components[0].data, position(2.0));
+        assert_eq!(
+            world.entities[0].components[1].data.to_json_value().unwrap(),
+            serde_json::json!({"list": [1], "name": "a"})
+        );
+        assert_eq!(world.timestamp, 2.0);
+
+        let missing = Delta { changes: vec![DeltaChange::EntityRemoved { entity_id: 5 }], timestamp: 3.0, base_timestamp: 2.0 };
+        assert!(world.apply_delta_partial(&missing, |_, _| Ok(())).is_err());
+
+        // Indexed updates have to be expanded through the registry first.
+        let indexed = Delta {
+            changes: vec![DeltaChange::IndexedFieldsUpdated {
+                entity_id: 1,
+                component_id: "Position".to_string(),
+                schema_version: SchemaVersion::new(1),
+                fields: vec![],
+            }],
+            timestamp: 3.0,
+            base_timestamp: 2.0,
+        };
+        assert!(world.apply_delta_partial(&indexed, |_, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_field_change_stats() {
+        let snapshot = |tick: u32| WorldSnapshot::for_test(tick as f64, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Transform".to_string(),
+                data: ComponentData::Structured([
+                    ("x".to_string(), FieldValue::U32(tick)),
+                    ("layer".to_string(), FieldValue::U32(tick / 3)),
+                    ("name".to_string(), FieldValue::String("crate".to_string())),
+                ].into_iter().collect()),
+            }],
+        }]);
+
+        let mut compressor = DeltaCompressor::new()
+            .with_field_compressor(FieldCompressor::with_stats(true));
+        for tick in 0..6 {
+            compressor.create_delta(snapshot(tick));
+        }
+
+        let stats = compressor.get_field_compressor().get_field_stats().unwrap();
+        let counts: Vec<(&str, u64)> = stats.iter().map(|s| (s.field_id.as_str(), s.changes)).collect();
+        assert_eq!(counts, vec![("x", 5), ("layer", 1)]);
+
+        compressor.get_field_compressor().reset_field_stats().unwrap();
+        assert!(compressor.get_field_compressor().get_field_stats().unwrap().is_empty());
+        assert!(FieldCompressor::new().get_field_stats().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_negotiate_compression() {
+        use CompressionType::*;
+
+        assert_eq!(negotiate(&[Deflate, Lz4, None], &[Lz4, Deflate]), Lz4);
+        assert_eq!(negotiate(&[Lz4, Deflate], &[Deflate, Lz4]), negotiate(&[Deflate, Lz4], &[Lz4, Deflate]));
+        assert_eq!(negotiate(&[Deflate], &[Zstd, Deflate]), Deflate);
+        assert_eq!(negotiate(&[Lz4], &[Deflate]), None);
+        assert_eq!(negotiate(&[], &[Lz4]), None);
+    }
+
     #[test]
     fn test_field_level_delta() {
         let compressor = FieldCompressor::new();
@@ -458,4 +2432,63 @@ mod tests {
         assert_eq!(deltas.len(), 1);
         assert_eq!(deltas[0].field_id, "x");
     }
+
+    #[test]
+    fn test_map_field_keyed_deltas() {
+        let compressor = FieldCompressor::new();
+        let component = |effects: Vec<(&str, FieldValue)>| {
+            let mut fields = HashMap::new();
+            fields.insert("hp".to_string(), FieldValue::U32(10));
+            fields.insert(
+                "effects".to_string(),
+                FieldValue::Map(effects.into_iter().map(|(k, v)| (k.to_string(), v)).collect()),
+            );
+            SerializedComponent { id: "Status".to_string(), data: ComponentData::Structured(fields) }
+        };
+
+        let prev = component(vec![
+            ("poison", FieldValue::U32(3)),
+            ("haste", FieldValue::F32(1.5)),
+            ("stun", FieldValue::Bool(true)),
+            ("a.b", FieldValue::U8(1)),
+        ]);
+        let curr = component(vec![
+            ("poison", FieldValue::U32(2)),
+            ("haste", FieldValue::String("max".to_string())),
+            ("burn", FieldValue::U32(1)),
+            ("a.b", FieldValue::U8(2)),
+        ]);
+
+        let mut deltas = compressor.compute_field_deltas(&prev, &curr).unwrap();
+        deltas.sort_by(|a, b| a.field_id.cmp(&b.field_id));
+        let ids: Vec<_> = deltas.iter().map(|d| d.field_id.as_str()).collect();
+        assert_eq!(ids, vec!["effects.a~1b", "effects.burn", "effects.haste", "effects.poison", "effects.stun"]);
+        assert_eq!(deltas[2].new_value, FieldValue::String("max".to_string()));
+
+        let rebuilt = compressor.apply_field_deltas(&prev.data, &deltas).unwrap();
+        assert_eq!(rebuilt, curr.data);
+    }
+
+    #[test]
+    fn test_map_value_set_to_null_keeps_key() {
+        let compressor = FieldCompressor::new();
+        let component = |effects: Vec<(&str, FieldValue)>| SerializedComponent {
+            id: "Status".to_string(),
+            data: ComponentData::Structured([(
+                "effects".to_string(),
+                FieldValue::Map(effects.into_iter().map(|(k, v)| (k.to_string(), v)).collect()),
+            )].into_iter().collect()),
+        };
+
+        let prev = component(vec![("poison", FieldValue::U32(3)), ("stun", FieldValue::Bool(true))]);
+        let curr = component(vec![("poison", FieldValue::Null)]);
+
+        let mut deltas = compressor.compute_field_deltas(&prev, &curr).unwrap();
+        deltas.sort_by(|a, b| a.field_id.cmp(&b.field_id));
+        assert_eq!((deltas[0].field_id.as_str(), deltas[0].removed), ("effects.poison", false));
+        assert_eq!((deltas[1].field_id.as_str(), deltas[1].removed), ("effects.stun", true));
+
+        let rebuilt = compressor.apply_field_deltas(&prev.data, &deltas).unwrap();
+        assert_eq!(rebuilt, curr.data);
+    }
 }
@@ -1,5 +1,10 @@
-use crate::protocol::{Message, MessageType, DeltaChange};
+use crate::protocol::{ComponentData, Message, MessageType, DeltaChange};
 use crate::serialization::{WorldSnapshot, Delta};
+use crate::compression::{field_value_to_json, ApplyIssue};
+use crate::error::Result;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::env;
 
@@ -35,7 +40,41 @@ pub fn is_debug_enabled() -> bool {
 
 /// Check if trace mode is enabled
 pub fn is_trace_enabled() -> bool {
-    TRACE_MODE.load(Ordering::Relaxed)
+    TRACE_MODE.load(Ordering::Relaxed) || capturing()
+}
+
+#[cfg(test)]
+thread_local! {
+    static CAPTURED: std::cell::RefCell<Option<Vec<String>>> = const { std::cell::RefCell::new(None) };
+}
+
+#[cfg(test)]
+fn capturing() -> bool {
+    CAPTURED.with(|captured| captured.borrow().is_some())
+}
+
+#[cfg(not(test))]
+fn capturing() -> bool {
+    false
+}
+
+/// Run `f` with trace mode on for the current thread only, returning the
+/// one-line traces it made instead of printing them.
+#[cfg(test)]
+pub(crate) fn capture_traces(f: impl FnOnce()) -> Vec<String> {
+    CAPTURED.with(|captured| *captured.borrow_mut() = Some(Vec::new()));
+    f();
+    CAPTURED.with(|captured| captured.borrow_mut().take().unwrap_or_default())
+}
+
+/// Print a one-line trace.
+fn trace_line(line: std::fmt::Arguments<'_>) {
+    #[cfg(test)]
+    if CAPTURED.with(|captured| captured.borrow_mut().as_mut().map(|lines| lines.push(line.to_string()))).is_some() {
+        return;
+    }
+
+    eprintln!("{}", line);
 }
 
 /// Log a message in JSON format if debug mode is enabled
@@ -114,6 +153,8 @@ pub fn trace_delta(delta: &Delta) {
             DeltaChange::ComponentRemoved { .. } => components_removed += 1,
             DeltaChange::ComponentUpdated { .. } => components_modified += 1,
             DeltaChange::FieldsUpdated { .. } => components_modified += 1,
+            DeltaChange::IndexedFieldsUpdated { .. } => components_modified += 1,
+            DeltaChange::EntityComponentsReplaced { .. } => components_modified += 1,
         }
     }
 
@@ -142,8 +183,8 @@ pub fn trace_serialization(format: &str, size_bytes: usize, duration_micros: u12
         return;
     }
 
-    eprintln!("[TX2-LINK] Serialized {} bytes using {} in {}µs",
-        size_bytes, format, duration_micros);
+    trace_line(format_args!("[TX2-LINK] Serialized {} bytes using {} in {}µs",
+        size_bytes, format, duration_micros));
 }
 
 /// Trace a deserialization operation
@@ -152,8 +193,8 @@ pub fn trace_deserialization(format: &str, size_bytes: usize, duration_micros: u
         return;
     }
 
-    eprintln!("[TX2-LINK] Deserialized {} bytes using {} in {}µs",
-        size_bytes, format, duration_micros);
+    trace_line(format_args!("[TX2-LINK] Deserialized {} bytes using {} in {}µs",
+        size_bytes, format, duration_micros));
 }
 
 /// Trace a delta compression operation
@@ -168,8 +209,18 @@ pub fn trace_compression(original_size: usize, delta_size: usize, duration_micro
         0.0
     };
 
-    eprintln!("[TX2-LINK] Delta compression: {} bytes → {} bytes ({:.2}× reduction) in {}µs",
-        original_size, delta_size, ratio, duration_micros);
+    trace_line(format_args!("[TX2-LINK] Delta compression: {} bytes → {} bytes ({:.2}× reduction) in {}µs",
+        original_size, delta_size, ratio, duration_micros));
+}
+
+/// Trace a change that lenient delta application had to reconcile
+pub fn trace_apply_issue(issue: &ApplyIssue) {
+    if !is_trace_enabled() {
+        return;
+    }
+
+    trace_line(format_args!("[TX2-LINK] Lenient apply: change #{} {:?} ({})",
+        issue.change_index, issue.resolution, issue.message));
 }
 
 /// Trace a rate limit check
@@ -179,8 +230,8 @@ pub fn trace_rate_limit(allowed: bool, current_rate: f64, limit: f64) {
     }
 
     let status = if allowed { "ALLOWED" } else { "BLOCKED" };
-    eprintln!("[TX2-LINK] Rate limit check: {} (current: {:.1}/s, limit: {:.1}/s)",
-        status, current_rate, limit);
+    trace_line(format_args!("[TX2-LINK] Rate limit check: {} (current: {:.1}/s, limit: {:.1}/s)",
+        status, current_rate, limit));
 }
 
 /// Trace a transport operation
@@ -189,7 +240,7 @@ pub fn trace_transport_send(bytes: usize, destination: &str) {
         return;
     }
 
-    eprintln!("[TX2-LINK] → Sent {} bytes to {}", bytes, destination);
+    trace_line(format_args!("[TX2-LINK] → Sent {} bytes to {}", bytes, destination));
 }
 
 /// Trace a transport receive
@@ -198,7 +249,16 @@ pub fn trace_transport_receive(bytes: usize, source: &str) {
         return;
     }
 
-    eprintln!("[TX2-LINK] ← Received {} bytes from {}", bytes, source);
+    trace_line(format_args!("[TX2-LINK] ← Received {} bytes from {}", bytes, source));
+}
+
+/// Trace a failure to close a transport while dropping it
+pub fn trace_transport_drop_error(transport: &str, error: &crate::error::LinkError) {
+    if !is_trace_enabled() {
+        return;
+    }
+
+    trace_line(format_args!("[TX2-LINK] Closing {} transport on drop failed: {}", transport, error));
 }
 
 /// Format bytes in human-readable format (KB, MB, etc.)
@@ -245,7 +305,211 @@ pub fn message_summary(message: &Message) -> String {
         MessageType::Error => {
             format!("Error (seq: {})", message.header.sequence)
         }
+        MessageType::Encrypted => {
+            format!("Encrypted (seq: {})", message.header.sequence)
+        }
+        MessageType::Channel => {
+            format!("Channel (seq: {})", message.header.sequence)
+        }
+        MessageType::Unknown => {
+            format!("Unknown (seq: {})", message.header.sequence)
+        }
+    }
+}
+
+const DOT_ADDED: &str = "palegreen";
+const DOT_REMOVED: &str = "lightcoral";
+const DOT_MODIFIED: &str = "khaki";
+
+/// Export the entity/component graph of a snapshot as Graphviz DOT
+pub fn snapshot_to_dot(snapshot: &WorldSnapshot) -> String {
+    let mut dot = String::new();
+    let _ = writeln!(dot, "digraph snapshot {{");
+    let _ = writeln!(dot, "  label={};", dot_quote(&format!("Snapshot @ {}", snapshot.timestamp)));
+    let _ = writeln!(dot, "  node [shape=box];");
+
+    for entity in &snapshot.entities {
+        let entity_node = format!("e{}", entity.id);
+        let _ = writeln!(dot, "  {} [label={}];", entity_node, dot_quote(&format!("Entity {}", entity.id)));
+
+        for component in &entity.components {
+            let component_node = dot_quote(&format!("e{}.{}", entity.id, component.id));
+            let _ = writeln!(dot, "  {} [label={}, shape=ellipse];", component_node, dot_quote(&component.id));
+            let _ = writeln!(dot, "  {} -> {};", entity_node, component_node);
+        }
     }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Export the changes in a delta as Graphviz DOT (added green, removed red, modified yellow)
+pub fn delta_to_dot(delta: &Delta) -> String {
+    let mut entities: BTreeMap<_, Option<&str>> = BTreeMap::new();
+    let mut components: BTreeMap<_, (&str, String)> = BTreeMap::new();
+
+    for change in &delta.changes {
+        match change {
+            DeltaChange::EntityAdded { entity_id } => {
+                entities.insert(*entity_id, Some(DOT_ADDED));
+            }
+            DeltaChange::EntityRemoved { entity_id } => {
+                entities.insert(*entity_id, Some(DOT_REMOVED));
+            }
+            DeltaChange::ComponentAdded { entity_id, component_id, .. } => {
+                entities.entry(*entity_id).or_insert(None);
+                components.insert((*entity_id, component_id.as_str()), (DOT_ADDED, component_id.clone()));
+            }
+            DeltaChange::ComponentRemoved { entity_id, component_id } => {
+                entities.entry(*entity_id).or_insert(None);
+                components.insert((*entity_id, component_id.as_str()), (DOT_REMOVED, component_id.clone()));
+            }
+            DeltaChange::ComponentUpdated { entity_id, component_id, .. } => {
+                entities.entry(*entity_id).or_insert(None);
+                components.insert((*entity_id, component_id.as_str()), (DOT_MODIFIED, component_id.clone()));
+            }
+            DeltaChange::FieldsUpdated { entity_id, component_id, fields } => {
+                entities.entry(*entity_id).or_insert(None);
+                let field_ids: Vec<&str> = fields.iter().map(|f| f.field_id.as_str()).collect();
+                let label = format!("{}\n{}", component_id, field_ids.join(", "));
+                components.insert((*entity_id, component_id.as_str()), (DOT_MODIFIED, label));
+            }
+            DeltaChange::IndexedFieldsUpdated { entity_id, component_id, fields, .. } => {
+                entities.entry(*entity_id).or_insert(None);
+                let indices: Vec<String> = fields.iter().map(|f| format!("#{}", f.index)).collect();
+                let label = format!("{}\n{}", component_id, indices.join(", "));
+                components.insert((*entity_id, component_id.as_str()), (DOT_MODIFIED, label));
+            }
+            DeltaChange::EntityComponentsReplaced { entity_id, components: replaced } => {
+                entities.insert(*entity_id, Some(DOT_MODIFIED));
+                for component in replaced {
+                    components.insert((*entity_id, component.id.as_str()), (DOT_ADDED, component.id.clone()));
+                }
+            }
+        }
+    }
+
+    let mut dot = String::new();
+    let _ = writeln!(dot, "digraph delta {{");
+    let _ = writeln!(
+        dot,
+        "  label={};",
+        dot_quote(&format!("Delta {} -> {}", delta.base_timestamp, delta.timestamp))
+    );
+    let _ = writeln!(dot, "  node [shape=box, style=filled, fillcolor=white];");
+
+    for (entity_id, color) in &entities {
+        let label = dot_quote(&format!("Entity {}", entity_id));
+        match color {
+            Some(color) => {
+                let _ = writeln!(dot, "  e{} [label={}, fillcolor={}];", entity_id, label, color);
+            }
+            None => {
+                let _ = writeln!(dot, "  e{} [label={}];", entity_id, label);
+            }
+        }
+    }
+
+    for ((entity_id, component_id), (color, label)) in &components {
+        let component_node = dot_quote(&format!("e{}.{}", entity_id, component_id));
+        let _ = writeln!(
+            dot,
+            "  {} [label={}, shape=ellipse, fillcolor={}];",
+            component_node,
+            dot_quote(label),
+            color
+        );
+        let _ = writeln!(dot, "  e{} -> {};", entity_id, component_node);
+    }
+
+    dot.push_str("}\n");
+    dot
+}
+
+/// Write `delta` as JSON Lines for analysis tools such as pandas, one object
+/// per change and one per field of a field update. Every line has the same
+/// keys: `timestamp`, `base_timestamp`, `kind`, `entity_id`, `component_id`,
+/// `field_id` and `value`, with `null` where they don't apply. Returns the
+/// number of lines written.
+///
+/// Values are component data or field values as JSON; binary and compressed
+/// data is `null`. Indexed field updates use `#index` as their field id, and
+/// removed fields are written as `field_removed` lines.
+pub fn write_delta_jsonl<W: std::io::Write>(mut writer: W, delta: &Delta) -> Result<usize> {
+    let mut lines = 0;
+    let mut record = |kind: &str, entity_id, component_id: Option<&str>, field_id: Option<&str>, value: Value| {
+        let line = serde_json::json!({
+            "timestamp": delta.timestamp,
+            "base_timestamp": delta.base_timestamp,
+            "kind": kind,
+            "entity_id": entity_id,
+            "component_id": component_id,
+            "field_id": field_id,
+            "value": value,
+        });
+        serde_json::to_writer(&mut writer, &line)?;
+        writer.write_all(b"\n")?;
+        lines += 1;
+        Result::Ok(())
+    };
+
+    for change in &delta.changes {
+        match change {
+            DeltaChange::EntityAdded { entity_id } => record("entity_added", *entity_id, None, None, Value::Null)?,
+            DeltaChange::EntityRemoved { entity_id } => record("entity_removed", *entity_id, None, None, Value::Null)?,
+            DeltaChange::ComponentAdded { entity_id, component_id, data } => {
+                record("component_added", *entity_id, Some(component_id), None, component_json(data))?
+            }
+            DeltaChange::ComponentRemoved { entity_id, component_id } => {
+                record("component_removed", *entity_id, Some(component_id), None, Value::Null)?
+            }
+            DeltaChange::ComponentUpdated { entity_id, component_id, data } => {
+                record("component_updated", *entity_id, Some(component_id), None, component_json(data))?
+            }
+            DeltaChange::FieldsUpdated { entity_id, component_id, fields } => {
+                for field in fields {
+                    if field.removed {
+                        record("field_removed", *entity_id, Some(component_id), Some(&field.field_id), Value::Null)?;
+                    } else {
+                        let value = field_value_to_json(&field.new_value);
+                        record("field_updated", *entity_id, Some(component_id), Some(&field.field_id), value)?;
+                    }
+                }
+            }
+            DeltaChange::IndexedFieldsUpdated { entity_id, component_id, fields, .. } => {
+                for field in fields {
+                    let field_id = format!("#{}", field.index);
+                    let value = field_value_to_json(&field.value);
+                    record("field_updated", *entity_id, Some(component_id), Some(&field_id), value)?;
+                }
+            }
+            DeltaChange::EntityComponentsReplaced { entity_id, components } => {
+                if components.is_empty() {
+                    record("components_replaced", *entity_id, None, None, Value::Null)?;
+                }
+                for component in components {
+                    record("components_replaced", *entity_id, Some(&component.id), None, component_json(&component.data))?;
+                }
+            }
+        }
+    }
+
+    writer.flush()?;
+    Ok(lines)
+}
+
+fn component_json(data: &ComponentData) -> Value {
+    match data {
+        ComponentData::Json(_) => data.to_json_value().unwrap_or(Value::Null),
+        ComponentData::Structured(fields) => Value::Object(
+            fields.iter().map(|(id, value)| (id.clone(), field_value_to_json(value))).collect()
+        ),
+        _ => Value::Null,
+    }
+}
+
+fn dot_quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n"))
 }
 
 #[cfg(test)]
@@ -261,6 +525,85 @@ mod tests {
         assert_eq!(format_bytes(1024 * 1024 * 1024), "1.00 GB");
     }
 
+    #[test]
+    fn test_dot_export() {
+        use crate::protocol::{ComponentData, FieldDelta, FieldValue, SerializedComponent, SerializedEntity};
+
+        let snapshot = WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 1,
+                components: vec![SerializedComponent {
+                    id: "Position".to_string(),
+                    data: ComponentData::from_json_value(serde_json::json!({"x": 1.0})),
+                }],
+            }],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+
+        let dot = snapshot_to_dot(&snapshot);
+        assert!(dot.starts_with("digraph snapshot {"));
+        assert!(dot.contains("e1 -> \"e1.Position\";"));
+
+        let delta = Delta {
+            changes: vec![
+                DeltaChange::EntityAdded { entity_id: 2 },
+                DeltaChange::EntityRemoved { entity_id: 3 },
+                DeltaChange::FieldsUpdated {
+                    entity_id: 1,
+                    component_id: "Position".to_string(),
+                    fields: vec![FieldDelta::new("x".to_string(), Some(FieldValue::F64(1.0)), FieldValue::F64(2.0))],
+                },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+
+        let dot = delta_to_dot(&delta);
+        assert!(dot.contains("e2 [label=\"Entity 2\", fillcolor=palegreen];"));
+        assert!(dot.contains("e3 [label=\"Entity 3\", fillcolor=lightcoral];"));
+        assert!(dot.contains("e1 [label=\"Entity 1\"];"));
+        assert!(dot.contains("[label=\"Position\\nx\", shape=ellipse, fillcolor=khaki];"));
+    }
+
+    #[test]
+    fn test_delta_jsonl() {
+        use crate::protocol::{FieldDelta, FieldValue};
+
+        let delta = Delta {
+            changes: vec![
+                DeltaChange::EntityAdded { entity_id: 2 },
+                DeltaChange::ComponentAdded {
+                    entity_id: 2,
+                    component_id: "Health".to_string(),
+                    data: ComponentData::from_json_value(serde_json::json!({"current": 10})),
+                },
+                DeltaChange::FieldsUpdated {
+                    entity_id: 1,
+                    component_id: "Position".to_string(),
+                    fields: ["x", "y"].iter().map(|id| FieldDelta::new(id.to_string(), None, FieldValue::F64(2.5))).collect(),
+                },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+
+        let mut out = Vec::new();
+        assert_eq!(write_delta_jsonl(&mut out, &delta).unwrap(), 4);
+
+        let lines: Vec<Value> = String::from_utf8(out).unwrap().lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines.iter().all(|line| line.as_object().unwrap().len() == 7 && line["timestamp"] == 2.0));
+        assert_eq!(lines[0]["kind"], "entity_added");
+        assert_eq!(lines[0]["component_id"], Value::Null);
+        assert_eq!(lines[1]["value"]["current"], 10);
+        assert_eq!(lines[3]["kind"], "field_updated");
+        assert_eq!(lines[3]["field_id"], "y");
+        assert_eq!(lines[3]["value"], 2.5);
+    }
+
     #[test]
     fn test_debug_mode_initialization() {
         // Should not crash without env vars
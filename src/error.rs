@@ -1,3 +1,8 @@
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use alloc::format;
+use alloc::string::String;
+use core::fmt;
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -8,35 +13,75 @@ pub enum LinkError {
     #[error("Deserialization error: {0}")]
     Deserialization(String),
 
+    /// A [`BinarySerializer`](crate::BinarySerializer) decode failure, with
+    /// where it happened.
+    ///
+    /// Decode errors that used to surface as [`Self::Json`],
+    /// [`Self::MsgPackDecode`], [`Self::BincodeDecode`] or
+    /// [`Self::Deserialization`] now arrive wrapped in this variant; match on
+    /// [`Self::without_context`] to get at the underlying one.
+    #[error("Failed to decode {context}: {source}")]
+    Decode {
+        context: DeserializationContext,
+        source: Box<LinkError>,
+    },
+
     #[error("Transport error: {0}")]
     Transport(String),
 
     #[error("Schema mismatch: expected version {expected}, got {actual}")]
     SchemaMismatch { expected: String, actual: String },
 
+    #[error("Protocol version mismatch: we speak {local}, peer speaks {remote}")]
+    ProtocolMismatch { local: u32, remote: u32 },
+
     #[error("Component schema not found: {0}")]
     SchemaNotFound(String),
 
     #[error("Rate limit exceeded: {0}")]
     RateLimitExceeded(String),
 
+    #[error("Backpressure: send buffer full ({0} messages pending)")]
+    Backpressure(usize),
+
     #[error("Invalid message format: {0}")]
     InvalidMessage(String),
 
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
+
+    #[cfg(feature = "std")]
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
     #[error("JSON error: {0}")]
     Json(#[from] serde_json::Error),
 
+    #[cfg(feature = "std")]
     #[error("MessagePack encode error: {0}")]
     MsgPackEncode(#[from] rmp_serde::encode::Error),
 
+    #[cfg(feature = "std")]
     #[error("MessagePack decode error: {0}")]
     MsgPackDecode(#[from] rmp_serde::decode::Error),
 
-    #[error("Bincode error: {0}")]
-    Bincode(#[from] bincode::Error),
+    #[cfg(feature = "std")]
+    #[error("Bincode encode error: {0}")]
+    BincodeEncode(#[from] bincode::error::EncodeError),
+
+    #[cfg(feature = "std")]
+    #[error("Bincode decode error: {0}")]
+    BincodeDecode(#[from] bincode::error::DecodeError),
+
+    // bincode's error types only implement `Display` and `Error` with its
+    // `std` feature, so without it keep their `Debug` output instead.
+    #[cfg(not(feature = "std"))]
+    #[error("Bincode encode error: {0}")]
+    BincodeEncode(String),
+
+    #[cfg(not(feature = "std"))]
+    #[error("Bincode decode error: {0}")]
+    BincodeDecode(String),
 
     #[error("Connection closed")]
     ConnectionClosed,
@@ -48,4 +93,66 @@ pub enum LinkError {
     Unknown(String),
 }
 
-pub type Result<T> = std::result::Result<T, LinkError>;
+#[cfg(not(feature = "std"))]
+impl From<bincode::error::EncodeError> for LinkError {
+    fn from(e: bincode::error::EncodeError) -> Self {
+        LinkError::BincodeEncode(format!("{:?}", e))
+    }
+}
+
+#[cfg(not(feature = "std"))]
+impl From<bincode::error::DecodeError> for LinkError {
+    fn from(e: bincode::error::DecodeError) -> Self {
+        LinkError::BincodeDecode(format!("{:?}", e))
+    }
+}
+
+impl LinkError {
+    /// Where the decode that produced this error failed, if it was one.
+    pub fn deserialization_context(&self) -> Option<&DeserializationContext> {
+        match self {
+            LinkError::Decode { context, .. } => Some(context),
+            _ => None,
+        }
+    }
+
+    /// The error with any [`DeserializationContext`] stripped off, for
+    /// matching on the variant the decoder actually raised.
+    pub fn without_context(&self) -> &LinkError {
+        match self {
+            LinkError::Decode { source, .. } => source.without_context(),
+            _ => self,
+        }
+    }
+}
+
+/// What a failed decode was reading.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeserializationContext {
+    /// Name of the format or custom codec, e.g. `"JSON"`.
+    pub format: String,
+    /// What was being decoded: `"message"`, `"snapshot"`, `"delta"` or `"component"`.
+    pub target: &'static str,
+    /// Length of the buffer as received, before decompression.
+    pub length: usize,
+    /// 1-based line and column of the failure, for JSON.
+    pub position: Option<(usize, usize)>,
+    /// Byte offset of the failure in the decompressed buffer, where the
+    /// format reports one.
+    pub offset: Option<usize>,
+}
+
+impl fmt::Display for DeserializationContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-byte {} {}", self.length, self.format, self.target)?;
+        if let Some((line, column)) = self.position {
+            write!(f, " at line {}, column {}", line, column)?;
+        }
+        if let Some(offset) = self.offset {
+            write!(f, " (byte {})", offset)?;
+        }
+        Ok(())
+    }
+}
+
+pub type Result<T> = core::result::Result<T, LinkError>;
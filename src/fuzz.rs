@@ -0,0 +1,256 @@
+//! [`Arbitrary`] impls for fuzzing, behind the `arbitrary` feature.
+//!
+//! Values are structurally valid-ish rather than uniformly random: entity,
+//! component and field ids come from small pools so that deltas mostly touch
+//! things a snapshot has, and collections and nesting are capped so one input
+//! can't ask for unbounded memory. Byte payloads and strings are left fully
+//! arbitrary. Floats are arbitrary but finite: JSON writes NaN and infinities
+//! as `null`, which can't decode back into a float, so they would fail the
+//! roundtrip target on their own. See `fuzz/fuzz_targets/`.
+
+use crate::protocol::*;
+use crate::serialization::{Delta, WorldSnapshot};
+use arbitrary::{Arbitrary, Result, Unstructured};
+use bytes::Bytes;
+
+/// Items in any one generated collection.
+const MAX_ITEMS: usize = 16;
+/// Nesting of `FieldValue::Array`, `FieldValue::Map` and `FieldValue::Enum`.
+const MAX_DEPTH: usize = 3;
+const COMPONENT_IDS: [&str; 4] = ["Position", "Velocity", "Health", "Player"];
+const FIELD_IDS: [&str; 4] = ["x", "y", "z", "current"];
+
+fn items<'a, T>(
+    u: &mut Unstructured<'a>,
+    mut item: impl FnMut(&mut Unstructured<'a>) -> Result<T>,
+) -> Result<Vec<T>> {
+    let len = u.int_in_range(0..=MAX_ITEMS)?;
+    (0..len).map(|_| item(u)).collect()
+}
+
+/// Mostly one of `pool`, occasionally anything.
+fn pooled_id(u: &mut Unstructured, pool: &[&str]) -> Result<String> {
+    if u.ratio(1, 8)? {
+        u.arbitrary()
+    } else {
+        Ok(u.choose(pool)?.to_string())
+    }
+}
+
+fn entity_id(u: &mut Unstructured) -> Result<EntityId> {
+    u.int_in_range(0..=31)
+}
+
+fn bytes(u: &mut Unstructured) -> Result<Bytes> {
+    Ok(Bytes::copy_from_slice(u.arbitrary()?))
+}
+
+fn finite_f64(u: &mut Unstructured) -> Result<f64> {
+    let value: f64 = u.arbitrary()?;
+    Ok(if value.is_finite() { value } else { 0.0 })
+}
+
+fn finite_f32(u: &mut Unstructured) -> Result<f32> {
+    let value: f32 = u.arbitrary()?;
+    Ok(if value.is_finite() { value } else { 0.0 })
+}
+
+fn field_value(u: &mut Unstructured, depth: usize) -> Result<FieldValue> {
+    let variants = if depth < MAX_DEPTH { 17 } else { 14 };
+    Ok(match u.choose_index(variants)? {
+        0 => FieldValue::Null,
+        1 => FieldValue::Bool(u.arbitrary()?),
+        2 => FieldValue::U8(u.arbitrary()?),
+        3 => FieldValue::U16(u.arbitrary()?),
+        4 => FieldValue::U32(u.arbitrary()?),
+        5 => FieldValue::U64(u.arbitrary()?),
+        6 => FieldValue::I8(u.arbitrary()?),
+        7 => FieldValue::I16(u.arbitrary()?),
+        8 => FieldValue::I32(u.arbitrary()?),
+        9 => FieldValue::I64(u.arbitrary()?),
+        10 => FieldValue::F32(finite_f32(u)?),
+        11 => FieldValue::F64(finite_f64(u)?),
+        12 => FieldValue::String(u.arbitrary()?),
+        13 => FieldValue::Bytes(u.arbitrary()?),
+        14 => FieldValue::Array(items(u, |u| field_value(u, depth + 1))?),
+        15 => FieldValue::Map(
+            items(u, |u| Ok((pooled_id(u, &FIELD_IDS)?, field_value(u, depth + 1)?)))?
+                .into_iter()
+                .collect(),
+        ),
+        _ => FieldValue::Enum {
+            discriminant: u.int_in_range(0..=7)?,
+            payload: if u.arbitrary()? { Some(Box::new(field_value(u, depth + 1)?)) } else { None },
+        },
+    })
+}
+
+fn compression_type(u: &mut Unstructured) -> Result<CompressionType> {
+    u.choose(&[CompressionType::None, CompressionType::Deflate, CompressionType::Lz4, CompressionType::Zstd])
+        .copied()
+}
+
+fn components(u: &mut Unstructured) -> Result<Vec<SerializedComponent>> {
+    items(u, |u| Ok(SerializedComponent { id: pooled_id(u, &COMPONENT_IDS)?, data: u.arbitrary()? }))
+}
+
+fn entities(u: &mut Unstructured) -> Result<Vec<SerializedEntity>> {
+    items(u, |u| Ok(SerializedEntity { id: entity_id(u)?, components: components(u)? }))
+}
+
+fn delta_change(u: &mut Unstructured) -> Result<DeltaChange> {
+    let entity_id = entity_id(u)?;
+    Ok(match u.choose_index(8)? {
+        0 => DeltaChange::EntityAdded { entity_id },
+        1 => DeltaChange::EntityRemoved { entity_id },
+        2 => DeltaChange::ComponentAdded {
+            entity_id,
+            component_id: pooled_id(u, &COMPONENT_IDS)?,
+            data: u.arbitrary()?,
+        },
+        3 => DeltaChange::ComponentRemoved { entity_id, component_id: pooled_id(u, &COMPONENT_IDS)? },
+        4 => DeltaChange::ComponentUpdated {
+            entity_id,
+            component_id: pooled_id(u, &COMPONENT_IDS)?,
+            data: u.arbitrary()?,
+        },
+        5 => DeltaChange::FieldsUpdated {
+            entity_id,
+            component_id: pooled_id(u, &COMPONENT_IDS)?,
+            fields: items(u, |u| {
+                let field_id = pooled_id(u, &FIELD_IDS)?;
+                let old_value = if u.arbitrary()? { Some(u.arbitrary()?) } else { None };
+                Ok(if u.ratio(1, 4)? {
+                    FieldDelta::removal(field_id, old_value)
+                } else {
+                    FieldDelta::new(field_id, old_value, u.arbitrary()?)
+                })
+            })?,
+        },
+        6 => DeltaChange::EntityComponentsReplaced { entity_id, components: components(u)? },
+        _ => DeltaChange::IndexedFieldsUpdated {
+            entity_id,
+            component_id: pooled_id(u, &COMPONENT_IDS)?,
+            schema_version: SchemaVersion::new(u.int_in_range(1..=3)?),
+            fields: items(u, |u| Ok(IndexedFieldDelta { index: u.int_in_range(0..=7)?, value: u.arbitrary()? }))?,
+        },
+    })
+}
+
+impl<'a> Arbitrary<'a> for FieldValue {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        field_value(u, 0)
+    }
+}
+
+impl<'a> Arbitrary<'a> for ComponentData {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(match u.choose_index(6)? {
+            0 => ComponentData::Binary(bytes(u)?),
+            1 => ComponentData::Json(u.arbitrary()?),
+            2 => ComponentData::from_json_value(serde_json::Value::Object(
+                items(u, |u| Ok((pooled_id(u, &FIELD_IDS)?, serde_json::Value::from(finite_f64(u)?))))?
+                    .into_iter()
+                    .collect(),
+            )),
+            3 => ComponentData::Structured(
+                items(u, |u| Ok((pooled_id(u, &FIELD_IDS)?, u.arbitrary()?)))?.into_iter().collect(),
+            ),
+            4 => ComponentData::Empty,
+            _ => ComponentData::Compressed { compression: compression_type(u)?, data: bytes(u)? },
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for WorldSnapshot {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(WorldSnapshot {
+            entities: entities(u)?,
+            timestamp: finite_f64(u)?,
+            version: u.arbitrary()?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Delta {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        Ok(Delta {
+            changes: items(u, delta_change)?,
+            timestamp: finite_f64(u)?,
+            base_timestamp: finite_f64(u)?,
+        })
+    }
+}
+
+impl<'a> Arbitrary<'a> for Message {
+    fn arbitrary(u: &mut Unstructured<'a>) -> Result<Self> {
+        let schema_version = SchemaVersion::new(u.int_in_range(1..=3)?);
+        let mut message = match u.choose_index(10)? {
+            0 => Message::snapshot(entities(u)?, finite_f64(u)?, schema_version),
+            1 => Message::delta_at(items(u, delta_change)?, u.arbitrary()?, finite_f64(u)?, schema_version),
+            2 => Message::request_snapshot(schema_version),
+            3 => Message::request_snapshot_since(u.arbitrary()?, schema_version),
+            4 => Message::ack(u.arbitrary()?, schema_version),
+            5 => Message::ping(schema_version),
+            6 => Message::pong(schema_version),
+            7 => Message::new(
+                MessageType::Error,
+                schema_version,
+                MessagePayload::Error { code: u.arbitrary()?, message: u.arbitrary()? },
+            ),
+            8 => Message::encrypted(bytes(u)?, schema_version),
+            _ => Message::channel(u.arbitrary()?, Message::arbitrary(u)?),
+        };
+
+        message.header = MessageHeader::with_timestamp(
+            message.header.msg_type,
+            schema_version,
+            u.arbitrary()?,
+            u.arbitrary()?,
+        );
+        Ok(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::serialization::{BinaryFormat, BinarySerializer};
+
+    #[test]
+    fn test_generated_messages_roundtrip() {
+        let mut seed = 0x2545_f491_4f6c_dd1du64;
+        let data: Vec<u8> = (0..64 * 1024)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                seed as u8
+            })
+            .collect();
+
+        let mut u = Unstructured::new(&data);
+        for _ in 0..50 {
+            let message = Message::arbitrary(&mut u).unwrap();
+            let mut world = WorldSnapshot::arbitrary(&mut u).unwrap();
+            world.apply_delta_lenient(&Delta::arbitrary(&mut u).unwrap());
+
+            for format in [BinaryFormat::Json, BinaryFormat::MessagePack, BinaryFormat::Bincode] {
+                let serializer = BinarySerializer::new(format).with_interned_ids(u.arbitrary().unwrap());
+                let decoded = serializer.deserialize_message(&serializer.serialize_message(&message).unwrap()).unwrap();
+                assert_eq!(decoded.header.msg_type, message.header.msg_type);
+            }
+        }
+    }
+
+    #[test]
+    fn test_generated_floats_are_finite() {
+        let mut u = Unstructured::new(&[0xff; 12]);
+        assert_eq!(finite_f64(&mut u).unwrap(), 0.0);
+        assert_eq!(finite_f32(&mut u).unwrap(), 0.0);
+
+        let message = Message::snapshot(vec![], finite_f64(&mut Unstructured::new(&[0xff; 8])).unwrap(), SchemaVersion::new(1));
+        let serializer = BinarySerializer::new(BinaryFormat::Json);
+        assert!(serializer.deserialize_message(&serializer.serialize_message(&message).unwrap()).is_ok());
+    }
+}
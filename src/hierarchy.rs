@@ -0,0 +1,206 @@
+use crate::protocol::*;
+use crate::serialization::WorldSnapshot;
+use ahash::{AHashMap, AHashSet};
+use std::collections::HashMap;
+
+/// Reserved component carrying an entity's parent, as `{"entity": <EntityId>}`.
+/// It replicates like any other component; the only special treatment is the
+/// ordering applied by [`order_changes`].
+pub const PARENT_COMPONENT: &str = "tx2_link::Parent";
+
+/// A [`PARENT_COMPONENT`] pointing at `parent`.
+pub fn parent_component(parent: EntityId) -> SerializedComponent {
+    let mut fields = HashMap::new();
+    fields.insert("entity".to_string(), FieldValue::U32(parent));
+
+    SerializedComponent {
+        id: PARENT_COMPONENT.to_string(),
+        data: ComponentData::Structured(fields),
+    }
+}
+
+/// The parent stored in a [`PARENT_COMPONENT`]'s data, structured or JSON.
+pub fn parent_of(data: &ComponentData) -> Option<EntityId> {
+    match data {
+        ComponentData::Structured(fields) => match fields.get("entity")? {
+            FieldValue::U32(id) => Some(*id),
+            FieldValue::U64(id) => EntityId::try_from(*id).ok(),
+            _ => None,
+        },
+        ComponentData::Json(_) => data.to_json_value()?
+            .get("entity")?
+            .as_u64()
+            .and_then(|id| EntityId::try_from(id).ok()),
+        _ => None,
+    }
+}
+
+impl SerializedEntity {
+    pub fn parent(&self) -> Option<EntityId> {
+        self.components.iter()
+            .find(|c| c.id == PARENT_COMPONENT)
+            .and_then(|c| parent_of(&c.data))
+    }
+
+    /// Set or clear (`None`) the entity's [`PARENT_COMPONENT`].
+    pub fn set_parent(&mut self, parent: Option<EntityId>) {
+        self.components.retain(|c| c.id != PARENT_COMPONENT);
+        if let Some(parent) = parent {
+            self.components.push(parent_component(parent));
+        }
+    }
+}
+
+/// Reorder a delta's changes so a hierarchy can be rebuilt while applying
+/// them in order: added entities come first, parents before their children,
+/// then changes to existing entities, then removals, children before their
+/// parents.
+///
+/// Parents of added entities are read from the delta itself, parents of
+/// removed entities from `base`, the state the delta applies to. Each entity's
+/// own changes keep their relative order, as does everything that isn't
+/// constrained by a parent link. Without any parent links, `changes` is
+/// returned untouched.
+pub fn order_changes(changes: Vec<DeltaChange>, base: Option<&WorldSnapshot>) -> Vec<DeltaChange> {
+    let mut added = AHashSet::new();
+    let mut removed = AHashSet::new();
+    for change in &changes {
+        match change {
+            DeltaChange::EntityAdded { entity_id } => { added.insert(*entity_id); }
+            DeltaChange::EntityRemoved { entity_id } => { removed.insert(*entity_id); }
+            _ => {}
+        }
+    }
+
+    let mut parents = AHashMap::new();
+    if let Some(base) = base.filter(|_| !removed.is_empty()) {
+        for entity in base.entities.iter().filter(|e| removed.contains(&e.id)) {
+            if let Some(parent) = entity.parent() {
+                parents.insert(entity.id, parent);
+            }
+        }
+    }
+    for change in &changes {
+        if let DeltaChange::ComponentAdded { entity_id, component_id, data }
+        | DeltaChange::ComponentUpdated { entity_id, component_id, data } = change {
+            if component_id == PARENT_COMPONENT && added.contains(entity_id) {
+                if let Some(parent) = parent_of(data) {
+                    parents.insert(*entity_id, parent);
+                }
+            }
+        }
+    }
+
+    if parents.is_empty() {
+        return changes;
+    }
+
+    // Group changes per entity, in order of first appearance.
+    let mut groups: Vec<(EntityId, Vec<DeltaChange>)> = Vec::new();
+    let mut group_of = AHashMap::new();
+    for change in changes {
+        let entity_id = change.entity_id();
+        let group = *group_of.entry(entity_id).or_insert_with(|| {
+            groups.push((entity_id, Vec::new()));
+            groups.len() - 1
+        });
+        groups[group].1.push(change);
+    }
+
+    let mut keyed: Vec<_> = groups.into_iter()
+        .map(|(entity_id, group)| {
+            let key = if added.contains(&entity_id) {
+                (0, depth(entity_id, &parents, &added))
+            } else if removed.contains(&entity_id) {
+                (2, usize::MAX - depth(entity_id, &parents, &removed))
+            } else {
+                (1, 0)
+            };
+            (key, group)
+        })
+        .collect();
+    keyed.sort_by_key(|(key, _)| *key);
+
+    keyed.into_iter().flat_map(|(_, group)| group).collect()
+}
+
+/// Number of ancestors of `entity_id` within `set`. Cycles stop after
+/// `set.len()` steps.
+fn depth(entity_id: EntityId, parents: &AHashMap<EntityId, EntityId>, set: &AHashSet<EntityId>) -> usize {
+    let mut depth = 0;
+    let mut current = entity_id;
+    while let Some(&parent) = parents.get(&current) {
+        if !set.contains(&parent) || depth >= set.len() {
+            break;
+        }
+        depth += 1;
+        current = parent;
+    }
+    depth
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::DeltaCompressor;
+
+    fn entity(id: EntityId, parent: Option<EntityId>) -> SerializedEntity {
+        let mut entity = SerializedEntity { id, components: Vec::new() };
+        entity.set_parent(parent);
+        entity
+    }
+
+    fn snapshot(entities: Vec<SerializedEntity>) -> WorldSnapshot {
+        WorldSnapshot {
+            entities,
+            timestamp: 0.0,
+            version: "1.0.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_hierarchy_ordering() {
+        // Children listed before their parents: 3 -> 2 -> 1.
+        let world = snapshot(vec![entity(3, Some(2)), entity(2, Some(1)), entity(1, None), entity(4, None)]);
+        assert_eq!(world.entities[0].parent(), Some(2));
+
+        let mut compressor = DeltaCompressor::new();
+        let delta = compressor.create_delta(world);
+        let added: Vec<_> = delta.changes.iter()
+            .filter_map(|c| match c {
+                DeltaChange::EntityAdded { entity_id } => Some(*entity_id),
+                _ => None,
+            })
+            .collect();
+        assert!(added.iter().position(|&id| id == 1) < added.iter().position(|&id| id == 2));
+        assert!(added.iter().position(|&id| id == 2) < added.iter().position(|&id| id == 3));
+
+        // Removing the whole chain removes children first.
+        let delta = compressor.create_delta(snapshot(vec![entity(4, None)]));
+        let removals: Vec<_> = delta.changes.iter()
+            .filter_map(|c| match c {
+                DeltaChange::EntityRemoved { entity_id } => Some(*entity_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(removals, vec![3, 2, 1]);
+
+        // Re-parenting onto a new entity comes after that entity is added.
+        let changes = vec![
+            DeltaChange::ComponentUpdated {
+                entity_id: 4,
+                component_id: PARENT_COMPONENT.to_string(),
+                data: parent_component(5).data,
+            },
+            DeltaChange::EntityAdded { entity_id: 6 },
+            DeltaChange::ComponentAdded {
+                entity_id: 6,
+                component_id: PARENT_COMPONENT.to_string(),
+                data: ComponentData::Json(r#"{"entity":5}"#.to_string()),
+            },
+            DeltaChange::EntityAdded { entity_id: 5 },
+        ];
+        let ordered: Vec<_> = order_changes(changes, None).iter().map(DeltaChange::entity_id).collect();
+        assert_eq!(ordered, vec![5, 6, 6, 4]);
+    }
+}
@@ -0,0 +1,196 @@
+use crate::error::{LinkError, Result};
+use crate::protocol::MessageLimits;
+use crate::serialization::{Delta, WorldSnapshot};
+use crate::sync::SyncEvent;
+use std::collections::VecDeque;
+
+/// Received world states, oldest first, for looking up the world at a past time.
+///
+/// This is the receiver's counterpart to the sender's keyframe history: full
+/// snapshots are stored as they arrive, and deltas are applied to the snapshot
+/// they were computed against. Beyond `capacity` the oldest state is evicted.
+#[derive(Debug, Clone)]
+pub struct SnapshotHistory {
+    snapshots: VecDeque<WorldSnapshot>,
+    capacity: usize,
+    limits: MessageLimits,
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            snapshots: VecDeque::new(),
+            capacity: capacity.max(1),
+            limits: MessageLimits::default(),
+        }
+    }
+
+    /// Reject ingested snapshots, and states rebuilt from deltas, that exceed `limits`.
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Store `snapshot`, replacing any state with the same timestamp.
+    pub fn push(&mut self, snapshot: WorldSnapshot) {
+        let index = self.snapshots.partition_point(|s| s.timestamp < snapshot.timestamp);
+
+        if self.snapshots.get(index).is_some_and(|s| s.timestamp == snapshot.timestamp) {
+            self.snapshots[index] = snapshot;
+        } else {
+            self.snapshots.insert(index, snapshot);
+        }
+
+        while self.snapshots.len() > self.capacity {
+            self.snapshots.pop_front();
+        }
+    }
+
+    /// Rebuild the state `delta` produces from its baseline and store it.
+    ///
+    /// A `base_timestamp` of `0.0` means the delta starts from an empty world.
+    /// Wire deltas carry their baseline in whole milliseconds, so the baseline
+    /// is matched to within one millisecond.
+    pub fn apply_delta(&mut self, delta: &Delta) -> Result<()> {
+        let mut snapshot = if delta.base_timestamp == 0.0 {
+            WorldSnapshot {
+                entities: Vec::new(),
+                timestamp: 0.0,
+                version: "1.0.0".to_string(),
+            }
+        } else {
+            self.snapshots.iter()
+                .rev()
+                .find(|s| (s.timestamp - delta.base_timestamp).abs() < 0.001)
+                .cloned()
+                .ok_or_else(|| LinkError::InvalidMessage(
+                    format!("No snapshot at delta base timestamp {}", delta.base_timestamp)
+                ))?
+        };
+
+        snapshot.apply_delta_within(delta, &self.limits)?;
+        self.push(snapshot);
+
+        Ok(())
+    }
+
+    /// Record the world state carried by `event`. Returns whether it was a
+    /// `Snapshot` or `Delta` event.
+    pub fn ingest(&mut self, event: &SyncEvent) -> Result<bool> {
+        match event {
+            SyncEvent::Snapshot(snapshot) => {
+                self.limits.check_entities(&snapshot.entities)?;
+                self.push(snapshot.clone());
+                Ok(true)
+            }
+            SyncEvent::Delta(delta) => {
+                self.apply_delta(delta)?;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// The latest state at or before `timestamp`.
+    pub fn at(&self, timestamp: f64) -> Option<&WorldSnapshot> {
+        self.snapshots.iter().rev().find(|s| s.timestamp <= timestamp)
+    }
+
+    pub fn latest(&self) -> Option<&WorldSnapshot> {
+        self.snapshots.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::DeltaCompressor;
+    use crate::protocol::{ComponentData, SerializedComponent, SerializedEntity};
+
+    fn snapshot(x: f64, timestamp: f64) -> WorldSnapshot {
+        WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 1,
+                components: vec![SerializedComponent {
+                    id: "Position".to_string(),
+                    data: ComponentData::from_json_value(serde_json::json!({"x": x, "y": 0.0})),
+                }],
+            }],
+            timestamp,
+            version: "1.0.0".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_history_rebuilds_deltas() {
+        let mut compressor = DeltaCompressor::new();
+        let mut history = SnapshotHistory::new(3);
+
+        for step in 1..=4 {
+            let delta = compressor.create_delta(snapshot(step as f64, step as f64));
+            history.ingest(&SyncEvent::Delta(delta)).unwrap();
+        }
+
+        assert_eq!(history.len(), 3);
+        assert_eq!(history.latest().unwrap().timestamp, 4.0);
+        assert_eq!(history.latest().unwrap().content_hash(), snapshot(4.0, 4.0).content_hash());
+
+        assert_eq!(history.at(3.5).unwrap().timestamp, 3.0);
+        assert_eq!(history.at(2.0).unwrap().content_hash(), snapshot(2.0, 2.0).content_hash());
+        assert!(history.at(1.5).is_none());
+
+        history.push(snapshot(0.0, 2.5));
+        assert_eq!(history.at(2.9).unwrap().timestamp, 2.5);
+        assert_eq!(history.len(), 3);
+
+        let orphan = Delta {
+            changes: vec![],
+            timestamp: 9.0,
+            base_timestamp: 8.0,
+        };
+        assert!(history.apply_delta(&orphan).is_err());
+    }
+
+    #[test]
+    fn test_history_enforces_limits() {
+        let mut compressor = DeltaCompressor::new();
+        let mut history = SnapshotHistory::new(4)
+            .with_limits(MessageLimits::new().with_max_entities(2));
+
+        let mut world = snapshot(1.0, 1.0);
+        history.ingest(&SyncEvent::Delta(compressor.create_delta(world.clone()))).unwrap();
+
+        // Each delta adds one entity, so the third breaks the limit once applied.
+        for id in 2..=3 {
+            let mut entity = world.entities[0].clone();
+            entity.id = id;
+            world.entities.push(entity);
+            world.timestamp = id as f64;
+
+            let result = history.ingest(&SyncEvent::Delta(compressor.create_delta(world.clone())));
+            assert_eq!(result.is_ok(), id == 2);
+        }
+
+        assert_eq!(history.len(), 2);
+        assert!(matches!(
+            history.ingest(&SyncEvent::Snapshot(world)),
+            Err(LinkError::InvalidMessage(_))
+        ));
+    }
+}
@@ -0,0 +1,498 @@
+//! Wire forms for [`BinarySerializer::with_interned_ids`](crate::BinarySerializer::with_interned_ids).
+//!
+//! Component and field ids are written once into a per-message dictionary and
+//! referenced everywhere else by their index in it. On a stream with a
+//! [`SharedDictionary`], each message's dictionary only holds the ids the
+//! stream hasn't seen yet, and indices continue after the shared ones.
+
+use crate::error::{LinkError, Result};
+use crate::protocol::*;
+use crate::serialization::{encode_value, decode_value, BinaryFormat, Delta, WorldSnapshot};
+use alloc::borrow::Cow;
+use alloc::collections::BTreeMap;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+use bytes::Bytes;
+use serde::{Deserialize, Serialize};
+
+/// Values with an interned encoding. With `shared`, ids it already holds are
+/// referenced instead of repeated, and the new ones are added to it.
+pub(crate) trait Interned: Sized {
+    fn encode_interned(&self, format: BinaryFormat, shared: Option<&mut SharedDictionary>) -> Result<Bytes>;
+    fn decode_interned(format: BinaryFormat, data: &[u8], shared: Option<&mut SharedDictionary>) -> Result<Self>;
+}
+
+type Dictionary<'a> = Vec<Cow<'a, str>>;
+
+/// The ids sent so far on a stream, kept in step on both ends.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SharedDictionary {
+    indices: BTreeMap<String, u32>,
+    strings: Vec<String>,
+}
+
+impl SharedDictionary {
+    pub(crate) fn len(&self) -> usize {
+        self.strings.len()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.indices.clear();
+        self.strings.clear();
+    }
+
+    /// Forget the ids added after the first `len`.
+    pub(crate) fn truncate(&mut self, len: usize) {
+        for id in self.strings.drain(len.min(self.strings.len())..) {
+            self.indices.remove(&id);
+        }
+    }
+
+    fn record(&mut self, dictionary: &[Cow<'_, str>]) {
+        for id in dictionary {
+            self.indices.insert(String::from(id.as_ref()), self.strings.len() as u32);
+            self.strings.push(String::from(id.as_ref()));
+        }
+    }
+}
+
+fn record(shared: Option<&mut SharedDictionary>, dictionary: &[Cow<'_, str>]) {
+    if let Some(shared) = shared {
+        shared.record(dictionary);
+    }
+}
+
+struct Interner<'a, 'k> {
+    shared: Option<&'k SharedDictionary>,
+    indices: BTreeMap<&'a str, u32>,
+    strings: Dictionary<'a>,
+}
+
+impl<'a, 'k> Interner<'a, 'k> {
+    fn new(shared: Option<&'k SharedDictionary>) -> Self {
+        Self { shared, indices: BTreeMap::new(), strings: Vec::new() }
+    }
+
+    fn intern(&mut self, id: &'a str) -> u32 {
+        if let Some(&index) = self.shared.and_then(|shared| shared.indices.get(id)) {
+            return index;
+        }
+
+        let offset = self.shared.map_or(0, SharedDictionary::len);
+        *self.indices.entry(id).or_insert_with(|| {
+            self.strings.push(Cow::Borrowed(id));
+            (offset + self.strings.len() - 1) as u32
+        })
+    }
+
+    fn entities(&mut self, entities: &'a [SerializedEntity]) -> Vec<InternedEntity<'a>> {
+        entities
+            .iter()
+            .map(|entity| InternedEntity { id: entity.id, components: self.components(&entity.components) })
+            .collect()
+    }
+
+    fn components(&mut self, components: &'a [SerializedComponent]) -> Vec<(u32, InternedData<'a>)> {
+        components
+            .iter()
+            .map(|component| (self.intern(&component.id), self.data(&component.data)))
+            .collect()
+    }
+
+    /// Structured fields are interned in id order so equal components always
+    /// encode the same.
+    fn data(&mut self, data: &'a ComponentData) -> InternedData<'a> {
+        match data {
+            ComponentData::Structured(fields) => {
+                let mut sorted: Vec<_> = fields.iter().collect();
+                sorted.sort_unstable_by(|a, b| a.0.cmp(b.0));
+                InternedData::Structured(
+                    sorted.into_iter()
+                        .map(|(field_id, value)| (self.intern(field_id), Cow::Borrowed(value)))
+                        .collect(),
+                )
+            }
+            other => InternedData::Other(Cow::Borrowed(other)),
+        }
+    }
+
+    fn changes(&mut self, changes: &'a [DeltaChange]) -> Vec<InternedChange<'a>> {
+        changes.iter().map(|change| self.change(change)).collect()
+    }
+
+    fn change(&mut self, change: &'a DeltaChange) -> InternedChange<'a> {
+        match change {
+            DeltaChange::EntityAdded { entity_id } => InternedChange::EntityAdded(*entity_id),
+            DeltaChange::EntityRemoved { entity_id } => InternedChange::EntityRemoved(*entity_id),
+            DeltaChange::ComponentAdded { entity_id, component_id, data } => {
+                InternedChange::ComponentAdded(*entity_id, self.intern(component_id), self.data(data))
+            }
+            DeltaChange::ComponentRemoved { entity_id, component_id } => {
+                InternedChange::ComponentRemoved(*entity_id, self.intern(component_id))
+            }
+            DeltaChange::ComponentUpdated { entity_id, component_id, data } => {
+                InternedChange::ComponentUpdated(*entity_id, self.intern(component_id), self.data(data))
+            }
+            DeltaChange::FieldsUpdated { entity_id, component_id, fields } => InternedChange::FieldsUpdated(
+                *entity_id,
+                self.intern(component_id),
+                fields.iter()
+                    .map(|field| InternedFieldDelta {
+                        field: self.intern(&field.field_id),
+                        old_value: field.old_value.as_ref().map(Cow::Borrowed),
+                        new_value: Cow::Borrowed(&field.new_value),
+                        removed: field.removed,
+                    })
+                    .collect(),
+            ),
+            DeltaChange::IndexedFieldsUpdated { entity_id, component_id, schema_version, fields } => {
+                InternedChange::IndexedFieldsUpdated(
+                    *entity_id,
+                    self.intern(component_id),
+                    *schema_version,
+                    Cow::Borrowed(fields.as_slice()),
+                )
+            }
+            DeltaChange::EntityComponentsReplaced { entity_id, components } => {
+                InternedChange::EntityComponentsReplaced(*entity_id, self.components(components))
+            }
+        }
+    }
+
+    fn finish(self) -> Dictionary<'a> {
+        self.strings
+    }
+}
+
+/// Turns indices back into ids while decoding.
+struct Rehydrator<'a, 'k> {
+    shared: &'k [String],
+    dictionary: Dictionary<'a>,
+}
+
+impl<'a, 'k> Rehydrator<'a, 'k> {
+    fn new(shared: Option<&'k SharedDictionary>, dictionary: Dictionary<'a>) -> Self {
+        Self { shared: shared.map_or(&[], |shared| shared.strings.as_slice()), dictionary }
+    }
+
+    fn id(&self, index: u32) -> Result<String> {
+        let index = index as usize;
+        let id = match index.checked_sub(self.shared.len()) {
+            None => Some(self.shared[index].as_str()),
+            Some(local) => self.dictionary.get(local).map(|id| id.as_ref()),
+        };
+
+        id.map(String::from).ok_or_else(|| LinkError::Deserialization(format!(
+            "Interned id {} is outside the {}-entry dictionary",
+            index,
+            self.shared.len() + self.dictionary.len()
+        )))
+    }
+
+    fn entities(&self, entities: Vec<InternedEntity<'_>>) -> Result<Vec<SerializedEntity>> {
+        entities
+            .into_iter()
+            .map(|entity| Ok(SerializedEntity { id: entity.id, components: self.components(entity.components)? }))
+            .collect()
+    }
+
+    fn components(&self, components: Vec<(u32, InternedData<'_>)>) -> Result<Vec<SerializedComponent>> {
+        components
+            .into_iter()
+            .map(|(id, data)| Ok(SerializedComponent { id: self.id(id)?, data: self.data(data)? }))
+            .collect()
+    }
+
+    fn data(&self, data: InternedData<'_>) -> Result<ComponentData> {
+        match data {
+            InternedData::Structured(fields) => Ok(ComponentData::Structured(
+                fields.into_iter()
+                    .map(|(field, value)| Ok((self.id(field)?, value.into_owned())))
+                    .collect::<Result<_>>()?,
+            )),
+            InternedData::Other(data) => Ok(data.into_owned()),
+        }
+    }
+
+    fn changes(&self, changes: Vec<InternedChange<'_>>) -> Result<Vec<DeltaChange>> {
+        changes.into_iter().map(|change| self.change(change)).collect()
+    }
+
+    fn change(&self, change: InternedChange<'_>) -> Result<DeltaChange> {
+        Ok(match change {
+            InternedChange::EntityAdded(entity_id) => DeltaChange::EntityAdded { entity_id },
+            InternedChange::EntityRemoved(entity_id) => DeltaChange::EntityRemoved { entity_id },
+            InternedChange::ComponentAdded(entity_id, component, data) => DeltaChange::ComponentAdded {
+                entity_id,
+                component_id: self.id(component)?,
+                data: self.data(data)?,
+            },
+            InternedChange::ComponentRemoved(entity_id, component) => DeltaChange::ComponentRemoved {
+                entity_id,
+                component_id: self.id(component)?,
+            },
+            InternedChange::ComponentUpdated(entity_id, component, data) => DeltaChange::ComponentUpdated {
+                entity_id,
+                component_id: self.id(component)?,
+                data: self.data(data)?,
+            },
+            InternedChange::FieldsUpdated(entity_id, component, fields) => DeltaChange::FieldsUpdated {
+                entity_id,
+                component_id: self.id(component)?,
+                fields: fields.into_iter()
+                    .map(|field| {
+                        Ok(FieldDelta {
+                            field_id: self.id(field.field)?,
+                            old_value: field.old_value.map(Cow::into_owned),
+                            new_value: field.new_value.into_owned(),
+                            removed: field.removed,
+                        })
+                    })
+                    .collect::<Result<_>>()?,
+            },
+            InternedChange::IndexedFieldsUpdated(entity_id, component, schema_version, fields) => {
+                DeltaChange::IndexedFieldsUpdated {
+                    entity_id,
+                    component_id: self.id(component)?,
+                    schema_version,
+                    fields: fields.into_owned(),
+                }
+            }
+            InternedChange::EntityComponentsReplaced(entity_id, components) => {
+                DeltaChange::EntityComponentsReplaced { entity_id, components: self.components(components)? }
+            }
+        })
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct InternedEntity<'a> {
+    id: EntityId,
+    components: Vec<(u32, InternedData<'a>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+enum InternedData<'a> {
+    Structured(Vec<(u32, Cow<'a, FieldValue>)>),
+    Other(Cow<'a, ComponentData>),
+}
+
+#[derive(Serialize, Deserialize)]
+enum InternedChange<'a> {
+    EntityAdded(EntityId),
+    EntityRemoved(EntityId),
+    ComponentAdded(EntityId, u32, InternedData<'a>),
+    ComponentRemoved(EntityId, u32),
+    ComponentUpdated(EntityId, u32, InternedData<'a>),
+    FieldsUpdated(EntityId, u32, Vec<InternedFieldDelta<'a>>),
+    IndexedFieldsUpdated(EntityId, u32, SchemaVersion, Cow<'a, [IndexedFieldDelta]>),
+    EntityComponentsReplaced(EntityId, Vec<(u32, InternedData<'a>)>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct InternedFieldDelta<'a> {
+    field: u32,
+    old_value: Option<Cow<'a, FieldValue>>,
+    new_value: Cow<'a, FieldValue>,
+    removed: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct InternedMessage<'a> {
+    header: Cow<'a, MessageHeader>,
+    payload: InternedPayload<'a>,
+}
+
+/// Payloads without ids are carried as they are.
+#[derive(Serialize, Deserialize)]
+enum InternedPayload<'a> {
+    Snapshot {
+        dictionary: Dictionary<'a>,
+        entities: Vec<InternedEntity<'a>>,
+        metadata: Cow<'a, SnapshotMetadata>,
+    },
+    Delta {
+        dictionary: Dictionary<'a>,
+        changes: Vec<InternedChange<'a>>,
+        base_timestamp: u64,
+        metadata: Cow<'a, DeltaMetadata>,
+    },
+    Other(Cow<'a, MessagePayload>),
+}
+
+#[derive(Serialize, Deserialize)]
+struct InternedSnapshot<'a> {
+    dictionary: Dictionary<'a>,
+    entities: Vec<InternedEntity<'a>>,
+    timestamp: f64,
+    version: Cow<'a, str>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct InternedDelta<'a> {
+    dictionary: Dictionary<'a>,
+    changes: Vec<InternedChange<'a>>,
+    timestamp: f64,
+    base_timestamp: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct InternedChanges<'a> {
+    dictionary: Dictionary<'a>,
+    changes: Vec<InternedChange<'a>>,
+}
+
+impl InternedPayload<'_> {
+    fn dictionary(&self) -> &[Cow<'_, str>] {
+        match self {
+            InternedPayload::Snapshot { dictionary, .. } | InternedPayload::Delta { dictionary, .. } => dictionary,
+            InternedPayload::Other(_) => &[],
+        }
+    }
+}
+
+impl Interned for Message {
+    fn encode_interned(&self, format: BinaryFormat, shared: Option<&mut SharedDictionary>) -> Result<Bytes> {
+        let mut interner = Interner::new(shared.as_deref());
+        let payload = match &self.payload {
+            MessagePayload::Snapshot(payload) => {
+                let entities = interner.entities(&payload.entities);
+                InternedPayload::Snapshot {
+                    dictionary: interner.finish(),
+                    entities,
+                    metadata: Cow::Borrowed(&payload.metadata),
+                }
+            }
+            MessagePayload::Delta(payload) => {
+                let changes = interner.changes(&payload.changes);
+                InternedPayload::Delta {
+                    dictionary: interner.finish(),
+                    changes,
+                    base_timestamp: payload.base_timestamp,
+                    metadata: Cow::Borrowed(&payload.metadata),
+                }
+            }
+            other => InternedPayload::Other(Cow::Borrowed(other)),
+        };
+
+        let message = InternedMessage { header: Cow::Borrowed(&self.header), payload };
+        let encoded = encode_value(format, &message)?;
+        record(shared, message.payload.dictionary());
+        Ok(encoded)
+    }
+
+    fn decode_interned(format: BinaryFormat, data: &[u8], shared: Option<&mut SharedDictionary>) -> Result<Self> {
+        let message: InternedMessage = decode_value(format, data)?;
+        let (payload, dictionary) = match message.payload {
+            InternedPayload::Snapshot { dictionary, entities, metadata } => {
+                let rehydrator = Rehydrator::new(shared.as_deref(), dictionary);
+                let payload = MessagePayload::Snapshot(SnapshotPayload {
+                    entities: rehydrator.entities(entities)?,
+                    metadata: metadata.into_owned(),
+                });
+                (payload, rehydrator.dictionary)
+            }
+            InternedPayload::Delta { dictionary, changes, base_timestamp, metadata } => {
+                let rehydrator = Rehydrator::new(shared.as_deref(), dictionary);
+                let payload = MessagePayload::Delta(DeltaPayload {
+                    changes: rehydrator.changes(changes)?,
+                    base_timestamp,
+                    metadata: metadata.into_owned(),
+                });
+                (payload, rehydrator.dictionary)
+            }
+            InternedPayload::Other(payload) => (payload.into_owned(), Vec::new()),
+        };
+
+        record(shared, &dictionary);
+        Ok(Message { header: message.header.into_owned(), payload })
+    }
+}
+
+/// The interned encoding of a snapshot made of the given parts.
+pub(crate) fn encode_snapshot(
+    format: BinaryFormat,
+    entities: &[SerializedEntity],
+    timestamp: f64,
+    version: &str,
+    shared: Option<&mut SharedDictionary>,
+) -> Result<Bytes> {
+    let mut interner = Interner::new(shared.as_deref());
+    let entities = interner.entities(entities);
+    let snapshot = InternedSnapshot {
+        dictionary: interner.finish(),
+        entities,
+        timestamp,
+        version: Cow::Borrowed(version),
+    };
+
+    let encoded = encode_value(format, &snapshot)?;
+    record(shared, &snapshot.dictionary);
+    Ok(encoded)
+}
+
+impl Interned for WorldSnapshot {
+    fn encode_interned(&self, format: BinaryFormat, shared: Option<&mut SharedDictionary>) -> Result<Bytes> {
+        encode_snapshot(format, &self.entities, self.timestamp, &self.version, shared)
+    }
+
+    fn decode_interned(format: BinaryFormat, data: &[u8], shared: Option<&mut SharedDictionary>) -> Result<Self> {
+        let snapshot: InternedSnapshot = decode_value(format, data)?;
+        let rehydrator = Rehydrator::new(shared.as_deref(), snapshot.dictionary);
+        let entities = rehydrator.entities(snapshot.entities)?;
+
+        let dictionary = rehydrator.dictionary;
+        record(shared, &dictionary);
+        Ok(WorldSnapshot { entities, timestamp: snapshot.timestamp, version: snapshot.version.into_owned() })
+    }
+}
+
+impl Interned for Delta {
+    fn encode_interned(&self, format: BinaryFormat, shared: Option<&mut SharedDictionary>) -> Result<Bytes> {
+        let mut interner = Interner::new(shared.as_deref());
+        let changes = interner.changes(&self.changes);
+        let delta = InternedDelta {
+            dictionary: interner.finish(),
+            changes,
+            timestamp: self.timestamp,
+            base_timestamp: self.base_timestamp,
+        };
+
+        let encoded = encode_value(format, &delta)?;
+        record(shared, &delta.dictionary);
+        Ok(encoded)
+    }
+
+    fn decode_interned(format: BinaryFormat, data: &[u8], shared: Option<&mut SharedDictionary>) -> Result<Self> {
+        let delta: InternedDelta = decode_value(format, data)?;
+        let rehydrator = Rehydrator::new(shared.as_deref(), delta.dictionary);
+        let changes = rehydrator.changes(delta.changes)?;
+
+        let dictionary = rehydrator.dictionary;
+        record(shared, &dictionary);
+        Ok(Delta { changes, timestamp: delta.timestamp, base_timestamp: delta.base_timestamp })
+    }
+}
+
+impl Interned for Vec<DeltaChange> {
+    fn encode_interned(&self, format: BinaryFormat, shared: Option<&mut SharedDictionary>) -> Result<Bytes> {
+        let mut interner = Interner::new(shared.as_deref());
+        let changes = interner.changes(self);
+        let changes = InternedChanges { dictionary: interner.finish(), changes };
+
+        let encoded = encode_value(format, &changes)?;
+        record(shared, &changes.dictionary);
+        Ok(encoded)
+    }
+
+    fn decode_interned(format: BinaryFormat, data: &[u8], shared: Option<&mut SharedDictionary>) -> Result<Self> {
+        let changes: InternedChanges = decode_value(format, data)?;
+        let rehydrator = Rehydrator::new(shared.as_deref(), changes.dictionary);
+        let decoded = rehydrator.changes(changes.changes)?;
+
+        let dictionary = rehydrator.dictionary;
+        record(shared, &dictionary);
+        Ok(decoded)
+    }
+}
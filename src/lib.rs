@@ -1,48 +1,144 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+//! Without the default `std` feature only `protocol`, `serialization` and `error`
+//! are available, built on `core` + `alloc`. JSON and Bincode work in that mode;
+//! MessagePack still needs `std`.
+
+extern crate alloc;
+extern crate self as tx2_link;
+
 pub mod protocol;
-pub mod transport;
 pub mod serialization;
+pub mod error;
+mod intern;
+
+#[cfg(feature = "std")]
+pub mod transport;
+#[cfg(feature = "std")]
 pub mod compression;
+#[cfg(feature = "std")]
 pub mod rate_limit;
+#[cfg(feature = "std")]
 pub mod schema;
-pub mod error;
+#[cfg(feature = "std")]
 pub mod sync;
+#[cfg(feature = "std")]
 pub mod debug;
+#[cfg(feature = "std")]
+pub mod component;
+#[cfg(feature = "std")]
+pub mod clock;
+#[cfg(feature = "std")]
+pub mod replay;
+#[cfg(feature = "std")]
+pub mod sim;
+#[cfg(feature = "std")]
+pub mod ordered;
+#[cfg(feature = "std")]
+pub mod coalesce;
+#[cfg(feature = "std")]
+pub mod multiplex;
+#[cfg(feature = "std")]
+pub mod history;
+#[cfg(feature = "std")]
+pub mod hierarchy;
+#[cfg(feature = "std")]
+pub mod broadcast;
+
+#[cfg(feature = "bevy")]
+pub mod bevy;
+#[cfg(feature = "encryption")]
+pub mod secure;
+#[cfg(feature = "arbitrary")]
+mod fuzz;
 
 pub use protocol::{
     EntityId, ComponentId, FieldId,
-    Message, MessageType, MessageHeader,
-    DeltaChange, FieldDelta,
+    Message, MessageType, MessageHeader, SchemaVersion,
+    DeltaChange, FieldDelta, ErrorCode, MessageLimits, PROTOCOL_VERSION,
 };
 
 pub use serialization::{
     SerializedComponent, SerializedEntity, WorldSnapshot, Delta,
-    BinarySerializer, BinaryFormat,
+    BinarySerializer, BinaryFormat, ChangedEntities, FrameCodec, MessageCodec,
 };
 
+#[cfg(feature = "std")]
 pub use transport::{
     Transport, TransportError,
 };
 
+#[cfg(feature = "std")]
+pub use replay::{
+    Recorder, ReplayTransport, RecordedMessage, Direction,
+};
+
+#[cfg(feature = "std")]
+pub use history::SnapshotHistory;
+
+#[cfg(feature = "std")]
+pub use hierarchy::PARENT_COMPONENT;
+
+#[cfg(feature = "std")]
+pub use sim::{
+    SimTransport, SimConfig,
+};
+
+#[cfg(feature = "std")]
+pub use ordered::OrderedTransport;
+
+#[cfg(feature = "std")]
+pub use coalesce::CoalescingTransport;
+
+#[cfg(feature = "std")]
+pub use multiplex::MultiplexTransport;
+
+#[cfg(feature = "std")]
 pub use compression::{
-    DeltaCompressor, FieldCompressor,
+    DeltaCompressor, FieldCompressor, FieldStats, BandwidthStats, ApplyReport, ApplyIssue, Resolution,
+};
+
+#[cfg(feature = "std")]
+pub use clock::{
+    Clock, SystemClock, MockClock,
 };
 
+#[cfg(feature = "std")]
 pub use rate_limit::{
-    RateLimiter, RateLimitConfig,
+    RateLimiter, RateLimitConfig, RateLimitPolicy,
 };
 
+#[cfg(feature = "std")]
+pub use component::{
+    LinkComponent, ReplicatedField, TypedRegistry,
+};
+
+#[cfg(feature = "bevy")]
+pub use bevy::BevyReplicator;
+
+#[cfg(feature = "encryption")]
+pub use secure::SecureTransport;
+
+#[cfg(feature = "std")]
 pub use schema::{
-    ComponentSchema, FieldSchema, SchemaRegistry, SchemaVersion,
+    ComponentSchema, FieldSchema, FieldConstraints, SchemaRegistry, Compatibility,
 };
 
 pub use error::{
-    LinkError, Result,
+    LinkError, DeserializationContext, Result,
 };
 
+#[cfg(feature = "std")]
 pub use sync::{
-    SyncManager, SyncConfig, SyncMode,
+    SyncManager, SyncConfig, SyncConfigBuilder, SyncMode, ConnectionState,
+};
+
+#[cfg(feature = "std")]
+pub use broadcast::{
+    BroadcastManager, BroadcastEvent, PeerId,
 };
 
+#[cfg(feature = "std")]
 pub use debug::{
     init_debug_mode, is_debug_enabled, is_trace_enabled,
     log_message, log_snapshot, log_delta,
@@ -50,4 +146,5 @@ pub use debug::{
     trace_compression, trace_rate_limit,
     trace_transport_send, trace_transport_receive,
     format_bytes, message_summary,
+    snapshot_to_dot, delta_to_dot,
 };
@@ -0,0 +1,294 @@
+use crate::error::Result;
+use crate::protocol::{CompressionType, Message, MessagePayload, MessageType};
+use crate::transport::Transport;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+/// Channel for messages that must arrive: snapshots, schemas, acks, snapshot
+/// requests and errors.
+pub const RELIABLE_CHANNEL: u8 = 0;
+/// Channel for messages a later one makes up for: deltas, pings and pongs.
+pub const FAST_CHANNEL: u8 = 1;
+
+/// Default for [`MultiplexTransport::with_max_pending`].
+pub const DEFAULT_MAX_PENDING: usize = 1024;
+
+/// Wraps a transport to carry several logical channels over one connection.
+///
+/// Each message's payload is wrapped in a `MessagePayload::Channel` with the
+/// channel id and handed to the inner transport under the message's own
+/// header, so a channel costs a couple of bytes per message. The wrapped
+/// message stays as reliable as its payload; see [`Message::is_reliable`].
+/// Received messages are sorted into a queue per channel, so reading one
+/// channel never waits behind another's backlog.
+///
+/// As a plain `Transport`, `send` picks the channel by message type:
+/// [`RELIABLE_CHANNEL`] for types that
+/// [require reliable delivery](MessageType::requires_reliable_delivery),
+/// [`FAST_CHANNEL`] for the rest, unless [`Self::with_route`] says otherwise.
+/// That way a `SyncManager` sends snapshots and deltas apart without knowing
+/// about channels. `receive` serves the lowest channel id with messages
+/// waiting first. Messages from a peer without the wrapper arrive on
+/// [`RELIABLE_CHANNEL`].
+///
+/// Each channel queues at most [`Self::with_max_pending`] messages. While
+/// one is full, nothing more is read from the inner transport, so a channel
+/// nobody reads holds the others up rather than growing without bound.
+///
+/// Both peers need the wrapper.
+pub struct MultiplexTransport<T: Transport> {
+    inner: T,
+    routes: HashMap<MessageType, u8>,
+    queues: BTreeMap<u8, VecDeque<Message>>,
+    max_pending: usize,
+}
+
+impl<T: Transport> MultiplexTransport<T> {
+    pub fn new(inner: T) -> Self {
+        Self {
+            inner,
+            routes: HashMap::new(),
+            queues: BTreeMap::new(),
+            max_pending: DEFAULT_MAX_PENDING,
+        }
+    }
+
+    /// Queue at most `max` received messages per channel.
+    pub fn with_max_pending(mut self, max: usize) -> Self {
+        self.max_pending = max.max(1);
+        self
+    }
+
+    /// Send messages of `msg_type` on `channel` when sent through `send`.
+    pub fn with_route(mut self, msg_type: MessageType, channel: u8) -> Self {
+        self.routes.insert(msg_type, channel);
+        self
+    }
+
+    pub fn get_inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// The channel `send` uses for `msg_type`.
+    pub fn channel_for(&self, msg_type: MessageType) -> u8 {
+        match self.routes.get(&msg_type) {
+            Some(&channel) => channel,
+            None if msg_type.requires_reliable_delivery() => RELIABLE_CHANNEL,
+            None => FAST_CHANNEL,
+        }
+    }
+
+    pub fn send_on(&mut self, channel: u8, message: &Message) -> Result<()> {
+        self.inner.send(&Message::channel(channel, message.clone()))
+    }
+
+    /// The next message on `channel`, after reading whatever the inner
+    /// transport has available.
+    pub fn receive_on(&mut self, channel: u8) -> Result<Option<Message>> {
+        self.pull()?;
+        Ok(self.queues.get_mut(&channel).and_then(VecDeque::pop_front))
+    }
+
+    /// Messages received on `channel` and not yet read.
+    pub fn pending_on(&self, channel: u8) -> usize {
+        self.queues.get(&channel).map_or(0, VecDeque::len)
+    }
+
+    fn demux(&mut self, message: Message) {
+        let (channel, message) = match message.payload {
+            MessagePayload::Channel { channel, payload } => {
+                let mut header = message.header;
+                header.msg_type = payload.message_type();
+                (channel, Message { header, payload: *payload })
+            }
+            _ => (RELIABLE_CHANNEL, message),
+        };
+
+        self.queues.entry(channel).or_default().push_back(message);
+    }
+
+    /// Whether any channel's queue is full, so nothing more may be read.
+    fn is_full(&self) -> bool {
+        self.queues.values().any(|queue| queue.len() >= self.max_pending)
+    }
+
+    fn pull(&mut self) -> Result<()> {
+        while !self.is_full() {
+            let Some(message) = self.inner.receive()? else {
+                break;
+            };
+            self.demux(message);
+        }
+        Ok(())
+    }
+
+    fn pop_any(&mut self) -> Option<Message> {
+        self.queues.values_mut().find_map(VecDeque::pop_front)
+    }
+}
+
+impl<T: Transport> Transport for MultiplexTransport<T> {
+    fn send(&mut self, message: &Message) -> Result<()> {
+        self.send_on(self.channel_for(message.header.msg_type), message)
+    }
+
+    fn receive(&mut self) -> Result<Option<Message>> {
+        self.pull()?;
+        Ok(self.pop_any())
+    }
+
+    fn send_batch(&mut self, messages: &[Message]) -> Result<()> {
+        let framed: Vec<_> = messages.iter()
+            .map(|message| Message::channel(self.channel_for(message.header.msg_type), message.clone()))
+            .collect();
+        self.inner.send_batch(&framed)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        self.inner.supports_compression(compression)
+    }
+
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.inner.set_compression(compression)
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        if let Some(message) = self.pop_any() {
+            return Ok(Some(message));
+        }
+
+        if let Some(message) = self.inner.receive_timeout(timeout)? {
+            self.demux(message);
+        }
+        Ok(self.pop_any())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.queues.clear();
+        self.inner.close()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::SchemaVersion;
+    use crate::serialization::{BinaryFormat, BinarySerializer};
+    use crate::transport::MemoryTransport;
+
+    #[test]
+    fn test_interleaved_channels_demux() {
+        let version = SchemaVersion::new(1);
+        let delta = |timestamp: u64| Message::delta_at(Vec::new(), 0, timestamp as f64, version);
+
+        let mut sender = MultiplexTransport::new(MemoryTransport::new(BinaryFormat::MessagePack));
+        sender.send(&Message::snapshot(Vec::new(), 1.0, version)).unwrap();
+        sender.send(&delta(2)).unwrap();
+        sender.send_on(7, &Message::ack(1, version)).unwrap();
+        sender.send_batch(&[delta(3), Message::ack(2, version), delta(4)]).unwrap();
+        sender.send(&Message::ping(version)).unwrap();
+
+        let mut inner = MemoryTransport::new(BinaryFormat::MessagePack);
+        sender.get_inner_mut().connect_to(&mut inner);
+        assert_eq!(inner.get_receive_buffer().len(), 7);
+        let mut receiver = MultiplexTransport::new(inner);
+
+        let fast: Vec<MessageType> = std::iter::from_fn(|| receiver.receive_on(FAST_CHANNEL).unwrap())
+            .map(|m| m.header.msg_type)
+            .collect();
+        assert_eq!(fast, vec![MessageType::Delta, MessageType::Delta, MessageType::Delta, MessageType::Ping]);
+        assert_eq!((receiver.pending_on(RELIABLE_CHANNEL), receiver.pending_on(7)), (2, 1));
+
+        let reliable = receiver.receive().unwrap().unwrap();
+        let MessagePayload::Snapshot(payload) = reliable.payload else { panic!("expected the snapshot first") };
+        assert_eq!(payload.metadata.world_time, 1.0);
+        assert!(matches!(receiver.receive().unwrap().unwrap().payload, MessagePayload::Ack { ack_id: 2, .. }));
+        assert!(matches!(receiver.receive_on(7).unwrap().unwrap().payload, MessagePayload::Ack { ack_id: 1, .. }));
+        assert!(receiver.receive().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_routes_and_plain_messages() {
+        let version = SchemaVersion::new(1);
+        let mut sender = MemoryTransport::new(BinaryFormat::MessagePack);
+        sender.send(&Message::pong(version)).unwrap();
+
+        let mut inner = MemoryTransport::new(BinaryFormat::MessagePack);
+        sender.connect_to(&mut inner);
+        let mut receiver = MultiplexTransport::new(inner).with_route(MessageType::Ping, RELIABLE_CHANNEL);
+        assert_eq!(receiver.channel_for(MessageType::Ping), RELIABLE_CHANNEL);
+        assert_eq!(receiver.channel_for(MessageType::Pong), FAST_CHANNEL);
+        assert!(matches!(receiver.receive_on(RELIABLE_CHANNEL).unwrap().unwrap().payload, MessagePayload::Pong { .. }));
+
+    }
+
+    #[test]
+    fn test_channel_messages_keep_reliability_and_header() {
+        let version = SchemaVersion::new(1);
+        let delta = Message::delta_at(Vec::new(), 0, 1.0, version);
+        let wrapped = Message::channel(FAST_CHANNEL, delta.clone());
+        assert!(!wrapped.is_reliable());
+        assert!(Message::channel(FAST_CHANNEL, Message::snapshot(Vec::new(), 1.0, version)).is_reliable());
+
+        // The envelope adds a few bytes, not a second header.
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let plain = serializer.serialize_message(&delta).unwrap().len();
+        assert!(serializer.serialize_message(&wrapped).unwrap().len() <= plain + 8);
+
+        let mut sender = MultiplexTransport::new(MemoryTransport::new(BinaryFormat::MessagePack));
+        sender.send(&delta).unwrap();
+        let mut inner = MemoryTransport::new(BinaryFormat::MessagePack);
+        sender.get_inner_mut().connect_to(&mut inner);
+        let received = MultiplexTransport::new(inner).receive().unwrap().unwrap();
+        assert_eq!(received.header.msg_type, MessageType::Delta);
+        assert_eq!(received.header.id, delta.header.id);
+    }
+
+    #[test]
+    fn test_pending_queues_are_bounded() {
+        let version = SchemaVersion::new(1);
+        let mut sender = MultiplexTransport::new(MemoryTransport::new(BinaryFormat::MessagePack));
+        for _ in 0..3 {
+            sender.send(&Message::ping(version)).unwrap();
+        }
+        sender.send(&Message::ack(1, version)).unwrap();
+
+        let mut inner = MemoryTransport::new(BinaryFormat::MessagePack);
+        sender.get_inner_mut().connect_to(&mut inner);
+        let mut receiver = MultiplexTransport::new(inner).with_max_pending(2);
+
+        // The fast channel fills up before the ack is read.
+        assert!(receiver.receive_on(RELIABLE_CHANNEL).unwrap().is_none());
+        assert_eq!(receiver.pending_on(FAST_CHANNEL), 2);
+        assert_eq!(receiver.get_inner().get_receive_buffer().len(), 2);
+
+        assert!(receiver.receive_on(FAST_CHANNEL).unwrap().is_some());
+        assert!(receiver.receive_on(FAST_CHANNEL).unwrap().is_some());
+        assert!(matches!(receiver.receive_on(RELIABLE_CHANNEL).unwrap().unwrap().payload, MessagePayload::Ack { ack_id: 1, .. }));
+    }
+}
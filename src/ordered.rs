@@ -0,0 +1,324 @@
+use crate::clock::{self, Clock};
+use crate::error::Result;
+use crate::protocol::{CompressionType, Message, SchemaVersion};
+use crate::transport::Transport;
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Wraps a transport that may reorder messages and delivers them in the order
+/// they were sent.
+///
+/// Outgoing messages get a per-stream `header.sequence`, starting at 1. This
+/// overwrites the process-wide counter [`MessageHeader::new`] put there, so
+/// don't rely on `sequence` on either side of the wrapper; `header.id` is left
+/// alone, and duplicate suppression keys on that.
+///
+/// Incoming messages that arrive early are held until the ones before them
+/// come in; duplicates and messages behind the stream are discarded. A gap is
+/// given up on once it has been open for `gap_timeout`, or once more than
+/// `window` messages are waiting behind it. A message more than `window`
+/// ahead of the stream gives up on the oldest missing ones straight away, so
+/// at most `window + 1` messages are ever held. Giving up sends a
+/// `RequestSnapshot`, since deltas after the gap build on what was lost.
+///
+/// [`MessageHeader::new`]: crate::protocol::MessageHeader::new
+///
+/// Both peers need the wrapper. Closing it restarts the stream.
+pub struct OrderedTransport<T: Transport> {
+    inner: T,
+    clock: Arc<dyn Clock>,
+    window: usize,
+    gap_timeout: Duration,
+    request_snapshots: bool,
+    next_send: u64,
+    next_receive: u64,
+    held: BTreeMap<u64, Message>,
+    gap_since: Option<Instant>,
+    skipped: u64,
+    discarded: u64,
+}
+
+impl<T: Transport> OrderedTransport<T> {
+    pub fn new(inner: T) -> Self {
+        Self::with_clock(inner, clock::system_clock())
+    }
+
+    /// Measure gap timeouts on `clock`.
+    pub fn with_clock(inner: T, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner,
+            clock,
+            window: 64,
+            gap_timeout: Duration::from_millis(250),
+            request_snapshots: true,
+            next_send: 1,
+            next_receive: 1,
+            held: BTreeMap::new(),
+            gap_since: None,
+            skipped: 0,
+            discarded: 0,
+        }
+    }
+
+    /// Hold at most `window` messages behind a gap.
+    pub fn with_window(mut self, window: usize) -> Self {
+        self.window = window;
+        self
+    }
+
+    pub fn with_gap_timeout(mut self, timeout: Duration) -> Self {
+        self.gap_timeout = timeout;
+        self
+    }
+
+    /// Whether giving up on a gap sends a `RequestSnapshot`. On by default.
+    pub fn with_snapshot_requests(mut self, enabled: bool) -> Self {
+        self.request_snapshots = enabled;
+        self
+    }
+
+    pub fn get_inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    /// Messages received early, waiting for a gap to fill.
+    pub fn held_count(&self) -> usize {
+        self.held.len()
+    }
+
+    /// Sequence numbers given up on.
+    pub fn skipped_count(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Duplicate and late messages thrown away.
+    pub fn discarded_count(&self) -> u64 {
+        self.discarded
+    }
+
+    fn tag(&mut self, message: &Message) -> Message {
+        let mut tagged = message.clone();
+        tagged.header.sequence = self.next_send;
+        self.next_send += 1;
+        tagged
+    }
+
+    fn pull(&mut self) -> Result<()> {
+        while let Some(message) = self.inner.receive()? {
+            let sequence = message.header.sequence;
+            // `u64::MAX` can't be followed by anything, so no real stream sends it.
+            if sequence < self.next_receive || sequence == u64::MAX || self.held.contains_key(&sequence) {
+                self.discarded += 1;
+                continue;
+            }
+
+            let window = self.window as u64;
+            if sequence - self.next_receive > window {
+                self.give_up_before(sequence - window, message.header.schema_version)?;
+            }
+            self.held.insert(sequence, message);
+        }
+
+        Ok(())
+    }
+
+    /// Stop waiting for anything before `sequence`, dropping what is held
+    /// there, and ask for a snapshot to cover it.
+    fn give_up_before(&mut self, sequence: u64, schema_version: SchemaVersion) -> Result<()> {
+        let kept = self.held.split_off(&sequence);
+        let dropped = std::mem::replace(&mut self.held, kept).len() as u64;
+
+        self.discarded += dropped;
+        self.skipped += (sequence - self.next_receive).saturating_sub(dropped);
+        self.next_receive = sequence;
+        self.gap_since = None;
+
+        if self.request_snapshots {
+            self.send(&Message::request_snapshot(schema_version))?;
+        }
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for OrderedTransport<T> {
+    fn send(&mut self, message: &Message) -> Result<()> {
+        let tagged = self.tag(message);
+        self.inner.send(&tagged)
+    }
+
+    fn receive(&mut self) -> Result<Option<Message>> {
+        self.pull()?;
+
+        let Some((&sequence, first)) = self.held.first_key_value() else {
+            return Ok(None);
+        };
+
+        if sequence != self.next_receive {
+            let schema_version = first.header.schema_version;
+            let now = self.clock.now();
+            let since = *self.gap_since.get_or_insert(now);
+            if self.held.len() <= self.window && now.duration_since(since) < self.gap_timeout {
+                return Ok(None);
+            }
+
+            self.skipped += sequence - self.next_receive;
+            if self.request_snapshots {
+                self.send(&Message::request_snapshot(schema_version))?;
+            }
+        }
+
+        self.gap_since = None;
+        // `pull` never holds `u64::MAX`, so this can't overflow.
+        self.next_receive = sequence + 1;
+        Ok(self.held.pop_first().map(|(_, message)| message))
+    }
+
+    fn send_batch(&mut self, messages: &[Message]) -> Result<()> {
+        let tagged: Vec<Message> = messages.iter()
+            .map(|message| self.tag(message))
+            .collect();
+        self.inner.send_batch(&tagged)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        self.inner.supports_compression(compression)
+    }
+
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.inner.set_compression(compression)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.next_send = 1;
+        self.next_receive = 1;
+        self.held.clear();
+        self.gap_since = None;
+        self.inner.close()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::protocol::MessagePayload;
+    use crate::serialization::{BinaryFormat, BinarySerializer};
+    use crate::sim::{SimConfig, SimTransport};
+    use crate::transport::MemoryTransport;
+
+    fn ack_ids(messages: impl Iterator<Item = Message>) -> Vec<u64> {
+        messages
+            .filter_map(|m| match m.payload {
+                MessagePayload::Ack { ack_id, .. } => Some(ack_id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_reordered_messages_arrive_in_order() {
+        let format = BinaryFormat::MessagePack;
+        let clock = MockClock::new();
+        let mut sender = OrderedTransport::new(MemoryTransport::new(format));
+        for ack_id in 0..50 {
+            sender.send(&Message::ack(ack_id, SchemaVersion::new(1))).unwrap();
+        }
+
+        let mut inner = MemoryTransport::new(format);
+        sender.get_inner_mut().connect_to(&mut inner);
+        let config = SimConfig::new()
+            .with_latency(Duration::from_millis(20))
+            .with_jitter(Duration::from_millis(40))
+            .with_reordering(0.3)
+            .with_seed(3);
+        let sim = SimTransport::with_clock(inner, config, Arc::new(clock.clone()));
+        let mut receiver = OrderedTransport::with_clock(sim, Arc::new(clock.clone()))
+            .with_gap_timeout(Duration::from_secs(1));
+
+        let mut received = Vec::new();
+        for _ in 0..20 {
+            clock.advance(Duration::from_millis(10));
+            received.extend(ack_ids(std::iter::from_fn(|| receiver.receive().unwrap())));
+        }
+
+        assert_eq!(received, (0..50).collect::<Vec<_>>());
+        assert_eq!(receiver.skipped_count(), 0);
+    }
+
+    #[test]
+    fn test_gap_timeout_skips_and_requests_snapshot() {
+        let format = BinaryFormat::MessagePack;
+        let clock = MockClock::new();
+        let mut sender = MemoryTransport::new(format);
+        for sequence in [1, 3, 4, 3] {
+            let mut message = Message::ack(sequence, SchemaVersion::new(1));
+            message.header.sequence = sequence;
+            sender.send(&message).unwrap();
+        }
+
+        let mut inner = MemoryTransport::new(format);
+        sender.connect_to(&mut inner);
+        let mut receiver = OrderedTransport::with_clock(inner, Arc::new(clock.clone()))
+            .with_gap_timeout(Duration::from_millis(100));
+
+        assert_eq!(ack_ids(std::iter::from_fn(|| receiver.receive().unwrap())), vec![1]);
+        assert_eq!((receiver.held_count(), receiver.discarded_count()), (2, 1));
+
+        clock.advance(Duration::from_millis(100));
+        assert_eq!(ack_ids(std::iter::from_fn(|| receiver.receive().unwrap())), vec![3, 4]);
+        assert_eq!(receiver.skipped_count(), 1);
+
+        let requests = receiver.get_inner().get_send_buffer();
+        assert_eq!(requests.len(), 1);
+        let request = BinarySerializer::new(format).deserialize_message(&requests[0]).unwrap();
+        assert!(matches!(request.payload, MessagePayload::RequestSnapshot { since: None }));
+    }
+
+    #[test]
+    fn test_far_ahead_and_absurd_sequences_are_bounded() {
+        let format = BinaryFormat::MessagePack;
+        let mut sender = MemoryTransport::new(format);
+        for sequence in [u64::MAX, 2, 3, 100, 1] {
+            let mut message = Message::ack(sequence, SchemaVersion::new(1));
+            message.header.sequence = sequence;
+            sender.send(&message).unwrap();
+        }
+
+        let mut inner = MemoryTransport::new(format);
+        sender.connect_to(&mut inner);
+        let mut receiver = OrderedTransport::new(inner).with_window(4);
+
+        // 100 is far past the window: 2 and 3 are dropped and 1..96 given up on.
+        assert!(receiver.receive().unwrap().is_none());
+        assert_eq!(receiver.held_count(), 1);
+        assert_eq!(receiver.discarded_count(), 4);
+        assert_eq!(receiver.skipped_count(), 93);
+        assert_eq!(receiver.get_inner().get_send_buffer().len(), 1);
+    }
+}
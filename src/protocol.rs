@@ -1,10 +1,76 @@
-use serde::{Deserialize, Serialize};
+use crate::error::{LinkError, Result};
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use bytes::Bytes;
+use serde::{Deserialize, Deserializer, Serialize};
+
+#[cfg(feature = "std")]
 use std::collections::HashMap;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
 
 pub type EntityId = u32;
 pub type ComponentId = String;
 pub type FieldId = String;
 
+/// Version of the wire format itself, as opposed to component schemas. Bumped
+/// whenever messages from one build stop being readable by another; peers
+/// exchange it in `SchemaSync` and refuse to talk on a mismatch.
+pub const PROTOCOL_VERSION: u32 = 6;
+
+/// Version of a component schema, and of the schema set a message was built
+/// against. Encoded as a bare `u32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct SchemaVersion(u32);
+
+impl SchemaVersion {
+    pub const INITIAL: Self = Self(1);
+
+    pub const fn new(version: u32) -> Self {
+        Self(version)
+    }
+
+    pub const fn get(self) -> u32 {
+        self.0
+    }
+
+    /// The following version. Panics on overflow.
+    pub fn next(self) -> Self {
+        Self(self.0.checked_add(1).expect("schema version overflow"))
+    }
+
+    pub fn increment(&mut self) {
+        *self = self.next();
+    }
+}
+
+impl Default for SchemaVersion {
+    fn default() -> Self {
+        Self::INITIAL
+    }
+}
+
+impl core::fmt::Display for SchemaVersion {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<u32> for SchemaVersion {
+    fn from(version: u32) -> Self {
+        Self(version)
+    }
+}
+
+impl From<SchemaVersion> for u32 {
+    fn from(version: SchemaVersion) -> Self {
+        version.0
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum MessageType {
@@ -16,6 +82,95 @@ pub enum MessageType {
     Pong = 5,
     SchemaSync = 6,
     Error = 7,
+    Encrypted = 8,
+    Channel = 9,
+    /// A type added by a newer peer.
+    #[serde(other)]
+    Unknown = 255,
+}
+
+impl MessageType {
+    /// The type with discriminant `tag`, `Unknown` for ones this build lacks.
+    pub(crate) fn from_tag(tag: u8) -> Self {
+        match tag {
+            0 => MessageType::Snapshot,
+            1 => MessageType::Delta,
+            2 => MessageType::RequestSnapshot,
+            3 => MessageType::Ack,
+            4 => MessageType::Ping,
+            5 => MessageType::Pong,
+            6 => MessageType::SchemaSync,
+            7 => MessageType::Error,
+            8 => MessageType::Encrypted,
+            9 => MessageType::Channel,
+            _ => MessageType::Unknown,
+        }
+    }
+
+    /// Whether losing a message of this type breaks the session, so it has to
+    /// be delivered reliably.
+    ///
+    /// Snapshots, schema exchange, acks, snapshot requests and errors do.
+    /// Deltas don't: a lost one is repaired by acked baselines or a later
+    /// snapshot. Neither do pings and pongs, nor unknown types. Encrypted and
+    /// channel messages hide their type, so they are treated as reliable;
+    /// [`Message::is_reliable`] looks inside channel messages.
+    pub fn requires_reliable_delivery(&self) -> bool {
+        match self {
+            MessageType::Snapshot
+            | MessageType::RequestSnapshot
+            | MessageType::Ack
+            | MessageType::SchemaSync
+            | MessageType::Error
+            | MessageType::Encrypted
+            | MessageType::Channel => true,
+            MessageType::Delta
+            | MessageType::Ping
+            | MessageType::Pong
+            | MessageType::Unknown => false,
+        }
+    }
+}
+
+/// Well-known values for `MessagePayload::Error::code`.
+///
+/// The wire format keeps the raw `u32`, so codes added by newer peers survive a
+/// roundtrip; `ErrorCode::try_from` hands back unknown codes as the `Err` value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u32)]
+pub enum ErrorCode {
+    Internal = 0,
+    SchemaMismatch = 1,
+    RateLimited = 2,
+    MalformedMessage = 3,
+    Unauthorized = 4,
+    Backpressure = 5,
+    Timeout = 6,
+    ProtocolMismatch = 7,
+}
+
+impl From<ErrorCode> for u32 {
+    fn from(code: ErrorCode) -> Self {
+        code as u32
+    }
+}
+
+impl TryFrom<u32> for ErrorCode {
+    type Error = u32;
+
+    fn try_from(code: u32) -> core::result::Result<Self, u32> {
+        match code {
+            0 => Ok(ErrorCode::Internal),
+            1 => Ok(ErrorCode::SchemaMismatch),
+            2 => Ok(ErrorCode::RateLimited),
+            3 => Ok(ErrorCode::MalformedMessage),
+            4 => Ok(ErrorCode::Unauthorized),
+            5 => Ok(ErrorCode::Backpressure),
+            6 => Ok(ErrorCode::Timeout),
+            7 => Ok(ErrorCode::ProtocolMismatch),
+            other => Err(other),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,11 +179,12 @@ pub struct MessageHeader {
     pub timestamp: u64,
     pub id: u64,
     pub sequence: u64,
-    pub schema_version: u32,
+    pub schema_version: SchemaVersion,
 }
 
 impl MessageHeader {
-    pub fn new(msg_type: MessageType, schema_version: u32) -> Self {
+    #[cfg(feature = "std")]
+    pub fn new(msg_type: MessageType, schema_version: SchemaVersion) -> Self {
         use std::time::{SystemTime, UNIX_EPOCH};
 
         static mut SEQUENCE_COUNTER: u64 = 0;
@@ -42,6 +198,12 @@ impl MessageHeader {
             .unwrap()
             .as_millis() as u64;
 
+        Self::with_timestamp(msg_type, schema_version, timestamp, sequence)
+    }
+
+    /// Build a header from a caller-supplied millisecond timestamp and sequence
+    /// number, for targets without a system clock.
+    pub fn with_timestamp(msg_type: MessageType, schema_version: SchemaVersion, timestamp: u64, sequence: u64) -> Self {
         let id = (timestamp << 20) | (sequence & 0xFFFFF);
 
         Self {
@@ -60,17 +222,203 @@ pub struct Message {
     pub payload: MessagePayload,
 }
 
+/// Human-readable formats tag payloads with a `"type"` field. Binary formats
+/// encode a `(MessageType as u8, body)` pair instead, so the tag costs a
+/// byte rather than its name on every message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
-#[serde(tag = "type", rename_all = "snake_case")]
+#[serde(remote = "Self", tag = "type", rename_all = "snake_case")]
 pub enum MessagePayload {
     Snapshot(SnapshotPayload),
     Delta(DeltaPayload),
-    RequestSnapshot,
-    Ack { ack_id: u64 },
+    RequestSnapshot { since: Option<u64> },
+    /// `timestamp` is set when acknowledging a world state (milliseconds, as
+    /// in `DeltaPayload::base_timestamp`) rather than a message; see
+    /// [`Message::ack_baseline`].
+    Ack {
+        ack_id: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        timestamp: Option<u64>,
+    },
     Ping,
-    Pong,
+    /// `ping_id` is the header id of the ping being answered, so overlapping
+    /// pings can be told apart; see [`Message::pong_for`].
+    Pong {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        ping_id: Option<u64>,
+    },
     SchemaSync(SchemaSyncPayload),
     Error { code: u32, message: String },
+    /// Another message, encoded and sealed by `SecureTransport`: the nonce
+    /// followed by the ciphertext and its authentication tag.
+    Encrypted {
+        #[serde(serialize_with = "serialize_binary", deserialize_with = "deserialize_binary")]
+        sealed: Bytes,
+    },
+    /// Another message's payload, sent on a logical channel by
+    /// `MultiplexTransport`. The message's header carries over as is, except
+    /// for its type. The payload can't be another channel payload.
+    Channel {
+        channel: u8,
+        #[serde(deserialize_with = "deserialize_channel_payload")]
+        payload: Box<MessagePayload>,
+    },
+    /// A payload added by a newer peer. Its fields are discarded, so it can be
+    /// skipped but not forwarded.
+    #[serde(other)]
+    Unknown,
+}
+
+impl MessagePayload {
+    /// The `MessageType` matching this payload.
+    pub fn message_type(&self) -> MessageType {
+        match self {
+            MessagePayload::Snapshot(_) => MessageType::Snapshot,
+            MessagePayload::Delta(_) => MessageType::Delta,
+            MessagePayload::RequestSnapshot { .. } => MessageType::RequestSnapshot,
+            MessagePayload::Ack { .. } => MessageType::Ack,
+            MessagePayload::Ping => MessageType::Ping,
+            MessagePayload::Pong { .. } => MessageType::Pong,
+            MessagePayload::SchemaSync(_) => MessageType::SchemaSync,
+            MessagePayload::Error { .. } => MessageType::Error,
+            MessagePayload::Encrypted { .. } => MessageType::Encrypted,
+            MessagePayload::Channel { .. } => MessageType::Channel,
+            MessagePayload::Unknown => MessageType::Unknown,
+        }
+    }
+}
+
+impl Serialize for MessagePayload {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        use serde::ser::SerializeTuple;
+
+        if serializer.is_human_readable() {
+            return MessagePayload::serialize(self, serializer);
+        }
+
+        let mut tuple = serializer.serialize_tuple(2)?;
+        tuple.serialize_element(&(self.message_type() as u8))?;
+        match self {
+            MessagePayload::Snapshot(payload) => tuple.serialize_element(payload)?,
+            MessagePayload::Delta(payload) => tuple.serialize_element(payload)?,
+            MessagePayload::RequestSnapshot { since } => tuple.serialize_element(since)?,
+            MessagePayload::Ack { ack_id, timestamp } => tuple.serialize_element(&(ack_id, timestamp))?,
+            MessagePayload::Ping | MessagePayload::Unknown => tuple.serialize_element(&())?,
+            MessagePayload::Pong { ping_id } => tuple.serialize_element(ping_id)?,
+            MessagePayload::SchemaSync(payload) => tuple.serialize_element(payload)?,
+            MessagePayload::Error { code, message } => tuple.serialize_element(&(code, message))?,
+            MessagePayload::Encrypted { sealed } => tuple.serialize_element(&Sealed(sealed.clone()))?,
+            MessagePayload::Channel { channel, payload } => tuple.serialize_element(&(channel, payload))?,
+        }
+        tuple.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for MessagePayload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        if deserializer.is_human_readable() {
+            return MessagePayload::deserialize(deserializer);
+        }
+
+        deserializer.deserialize_tuple(2, CompactPayloadVisitor { in_channel: false })
+    }
+}
+
+/// A channel's payload, rejected if it is a channel payload itself. Nesting
+/// has no use, and without a depth limit a small message could recurse until
+/// the stack overflows.
+fn deserialize_channel_payload<'de, D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Box<MessagePayload>, D::Error> {
+    use serde::de::Error;
+
+    if !deserializer.is_human_readable() {
+        return deserializer.deserialize_tuple(2, CompactPayloadVisitor { in_channel: true }).map(Box::new);
+    }
+
+    match MessagePayload::deserialize(deserializer)? {
+        MessagePayload::Channel { .. } => Err(D::Error::custom("nested channel payload")),
+        payload => Ok(Box::new(payload)),
+    }
+}
+
+/// The compact encoding of `MessagePayload::Channel`'s payload.
+struct ChannelPayload(Box<MessagePayload>);
+
+impl<'de> Deserialize<'de> for ChannelPayload {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        deserialize_channel_payload(deserializer).map(ChannelPayload)
+    }
+}
+
+/// `in_channel` is set for a channel's payload, which may not be a channel.
+struct CompactPayloadVisitor {
+    in_channel: bool,
+}
+
+impl<'de> serde::de::Visitor<'de> for CompactPayloadVisitor {
+    type Value = MessagePayload;
+
+    fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+        f.write_str("a message type and its payload")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> core::result::Result<MessagePayload, A::Error> {
+        use serde::de::Error;
+
+        let tag: u8 = seq.next_element()?.ok_or_else(|| A::Error::invalid_length(0, &self))?;
+        let missing = || A::Error::invalid_length(1, &"a message type and its payload");
+
+        let payload = match MessageType::from_tag(tag) {
+            MessageType::Snapshot => MessagePayload::Snapshot(seq.next_element()?.ok_or_else(missing)?),
+            MessageType::Delta => MessagePayload::Delta(seq.next_element()?.ok_or_else(missing)?),
+            MessageType::RequestSnapshot => MessagePayload::RequestSnapshot { since: seq.next_element()?.ok_or_else(missing)? },
+            MessageType::Ack => {
+                let (ack_id, timestamp) = seq.next_element()?.ok_or_else(missing)?;
+                MessagePayload::Ack { ack_id, timestamp }
+            }
+            MessageType::Ping => {
+                seq.next_element::<()>()?.ok_or_else(missing)?;
+                MessagePayload::Ping
+            }
+            MessageType::Pong => MessagePayload::Pong { ping_id: seq.next_element()?.ok_or_else(missing)? },
+            MessageType::SchemaSync => MessagePayload::SchemaSync(seq.next_element()?.ok_or_else(missing)?),
+            MessageType::Error => {
+                let (code, message) = seq.next_element()?.ok_or_else(missing)?;
+                MessagePayload::Error { code, message }
+            }
+            MessageType::Encrypted => {
+                let Sealed(sealed) = seq.next_element()?.ok_or_else(missing)?;
+                MessagePayload::Encrypted { sealed }
+            }
+            MessageType::Channel if self.in_channel => return Err(A::Error::custom("nested channel payload")),
+            MessageType::Channel => {
+                let (channel, ChannelPayload(payload)) = seq.next_element()?.ok_or_else(missing)?;
+                MessagePayload::Channel { channel, payload }
+            }
+            // Skipping the body needs a self-describing format; see
+            // `BinaryFormat::is_self_describing`.
+            MessageType::Unknown => {
+                seq.next_element::<serde::de::IgnoredAny>()
+                    .map_err(|e| A::Error::custom(format_args!("can't skip message type {}: {}", tag, e)))?;
+                MessagePayload::Unknown
+            }
+        };
+
+        Ok(payload)
+    }
+}
+
+/// `MessagePayload::Encrypted`'s bytes, as the body of the compact encoding.
+struct Sealed(Bytes);
+
+impl Serialize for Sealed {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+        serialize_binary(&self.0, serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Sealed {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Self, D::Error> {
+        deserialize_binary(deserializer).map(Sealed)
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -96,13 +444,13 @@ pub enum CompressionType {
     Zstd = 3,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SerializedEntity {
     pub id: EntityId,
     pub components: Vec<SerializedComponent>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct SerializedComponent {
     pub id: ComponentId,
     pub data: ComponentData,
@@ -110,9 +458,24 @@ pub struct SerializedComponent {
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum ComponentData {
-    Binary(Vec<u8>),
+    /// Opaque bytes. Decoding MessagePack or Bincode from a `Bytes` buffer (see
+    /// `BinarySerializer::deserialize_message_bytes`) slices into that buffer
+    /// instead of copying.
+    Binary(
+        #[serde(serialize_with = "serialize_binary", deserialize_with = "deserialize_binary")]
+        Bytes
+    ),
     Json(String),
-    Structured(HashMap<FieldId, FieldValue>),
+    Structured(#[serde(serialize_with = "serialize_sorted_map")] HashMap<FieldId, FieldValue>),
+    /// A marker component without fields, e.g. `Player` or `Dead`.
+    Empty,
+    /// Another `ComponentData`, bincode-encoded and then compressed for the
+    /// wire. See `SchemaRegistry::compress_entities`.
+    Compressed {
+        compression: CompressionType,
+        #[serde(serialize_with = "serialize_binary", deserialize_with = "deserialize_binary")]
+        data: Bytes,
+    },
 }
 
 impl ComponentData {
@@ -133,8 +496,51 @@ impl ComponentData {
             _ => None,
         }
     }
+
+    /// Re-emit JSON compactly with object keys sorted, so encodings of the
+    /// same value become byte-identical. Invalid JSON is left as it is, and
+    /// the other variants already compare by content.
+    pub fn canonicalize(&mut self) {
+        if let ComponentData::Json(s) = self {
+            if let Ok(value) = serde_json::from_str::<serde_json::Value>(s) {
+                *s = value.to_string();
+            }
+        }
+    }
+
+    /// Equality after [`Self::canonicalize`], without re-encoding: JSON that
+    /// only differs in key order or whitespace is equal.
+    pub fn content_eq(&self, other: &ComponentData) -> bool {
+        match (self, other) {
+            (ComponentData::Json(a), ComponentData::Json(b)) => a == b || matches!(
+                (serde_json::from_str::<serde_json::Value>(a), serde_json::from_str::<serde_json::Value>(b)),
+                (Ok(a), Ok(b)) if a == b
+            ),
+            _ => self.same_as(other),
+        }
+    }
+
+    /// `==`, but comparing floats in structured data by their bits; see
+    /// [`FieldValue::same_as`].
+    pub fn same_as(&self, other: &ComponentData) -> bool {
+        match (self, other) {
+            (ComponentData::Structured(a), ComponentData::Structured(b)) => same_fields(a, b),
+            (
+                ComponentData::Binary(_) | ComponentData::Json(_) | ComponentData::Structured(_)
+                | ComponentData::Empty | ComponentData::Compressed { .. },
+                _,
+            ) => self == other,
+        }
+    }
 }
 
+fn same_fields(a: &HashMap<FieldId, FieldValue>, b: &HashMap<FieldId, FieldValue>) -> bool {
+    a.len() == b.len() && a.iter().all(|(key, value)| b.get(key).is_some_and(|other| value.same_as(other)))
+}
+
+/// In human-readable formats such as JSON, non-finite floats are written as
+/// the strings `"NaN"`, `"Infinity"` and `"-Infinity"`; `null` also reads
+/// back as NaN.
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum FieldValue {
     Null,
@@ -147,12 +553,293 @@ pub enum FieldValue {
     I16(i16),
     I32(i32),
     I64(i64),
-    F32(f32),
-    F64(f64),
+    F32(#[serde(serialize_with = "serialize_float", deserialize_with = "deserialize_f32")] f32),
+    F64(#[serde(serialize_with = "serialize_float", deserialize_with = "deserialize_f64")] f64),
     String(String),
     Bytes(Vec<u8>),
     Array(Vec<FieldValue>),
-    Map(HashMap<String, FieldValue>),
+    Map(#[serde(serialize_with = "serialize_sorted_map")] HashMap<String, FieldValue>),
+    /// A Rust enum: which variant, and the variant's data if it has any.
+    Enum { discriminant: u32, payload: Option<Box<FieldValue>> },
+}
+
+impl FieldValue {
+    /// Equality that compares floats by their bits, so a NaN field is the
+    /// same as itself and doesn't show up as changed on every diff, while
+    /// `0.0` and `-0.0` differ. `==` keeps IEEE semantics.
+    pub fn same_as(&self, other: &FieldValue) -> bool {
+        use FieldValue::*;
+
+        match (self, other) {
+            (F32(a), F32(b)) => a.to_bits() == b.to_bits(),
+            (F64(a), F64(b)) => a.to_bits() == b.to_bits(),
+            (Array(a), Array(b)) => a.len() == b.len() && a.iter().zip(b).all(|(a, b)| a.same_as(b)),
+            (Map(a), Map(b)) => same_fields(a, b),
+            (Enum { discriminant: a, payload: a_payload }, Enum { discriminant: b, payload: b_payload }) => {
+                a == b && match (a_payload, b_payload) {
+                    (Some(a), Some(b)) => a.same_as(b),
+                    (a, b) => a.is_none() && b.is_none(),
+                }
+            }
+            (Null, _) | (Bool(_), _) | (U8(_), _) | (U16(_), _) | (U32(_), _) | (U64(_), _)
+            | (I8(_), _) | (I16(_), _) | (I32(_), _) | (I64(_), _) | (F32(_), _) | (F64(_), _)
+            | (String(_), _) | (Bytes(_), _) | (Array(_), _) | (Map(_), _) | (Enum { .. }, _) => self == other,
+        }
+    }
+
+    pub fn field_type(&self) -> FieldType {
+        match self {
+            FieldValue::Null => FieldType::Null,
+            FieldValue::Bool(_) => FieldType::Bool,
+            FieldValue::U8(_) => FieldType::U8,
+            FieldValue::U16(_) => FieldType::U16,
+            FieldValue::U32(_) => FieldType::U32,
+            FieldValue::U64(_) => FieldType::U64,
+            FieldValue::I8(_) => FieldType::I8,
+            FieldValue::I16(_) => FieldType::I16,
+            FieldValue::I32(_) => FieldType::I32,
+            FieldValue::I64(_) => FieldType::I64,
+            FieldValue::F32(_) => FieldType::F32,
+            FieldValue::F64(_) => FieldType::F64,
+            FieldValue::String(_) => FieldType::String,
+            FieldValue::Bytes(_) => FieldType::Bytes,
+            FieldValue::Array(_) => FieldType::Array,
+            FieldValue::Map(_) => FieldType::Map,
+            FieldValue::Enum { .. } => FieldType::Enum,
+        }
+    }
+
+    /// Convert a number to the numeric `field_type`, e.g. the `I64(5)` a JSON
+    /// round trip produces back to the schema's `U32(5)`. Integers must fit the
+    /// target, floats only become integers when they are whole, and finite
+    /// `F64`s only become `F32`s within its range (losing precision). Values
+    /// that aren't numbers, or targets that aren't numeric, are returned
+    /// unchanged.
+    pub fn coerce_to(self, field_type: FieldType) -> Result<FieldValue> {
+        if self.field_type() == field_type {
+            return Ok(self);
+        }
+
+        let out_of_range = |value: &dyn core::fmt::Display| LinkError::InvalidMessage(
+            format!("{} does not fit in {:?}", value, field_type)
+        );
+
+        let integer: i128 = match self {
+            FieldValue::U8(v) => v.into(),
+            FieldValue::U16(v) => v.into(),
+            FieldValue::U32(v) => v.into(),
+            FieldValue::U64(v) => v.into(),
+            FieldValue::I8(v) => v.into(),
+            FieldValue::I16(v) => v.into(),
+            FieldValue::I32(v) => v.into(),
+            FieldValue::I64(v) => v.into(),
+            FieldValue::F32(v) => return FieldValue::F64(v.into()).coerce_to(field_type),
+            FieldValue::F64(v) => match field_type {
+                // `as` would quietly turn out-of-range values into infinities.
+                FieldType::F32 if v.is_finite() && v.abs() > f64::from(f32::MAX) => return Err(out_of_range(&v)),
+                FieldType::F32 => return Ok(FieldValue::F32(v as f32)),
+                _ if !is_integer_type(field_type) => return Ok(self),
+                // Whole floats survive the round trip; fractions, NaN and values
+                // beyond i128 (which saturate) don't.
+                _ if (v as i128) as f64 == v => v as i128,
+                _ => return Err(out_of_range(&v)),
+            },
+            _ => return Ok(self),
+        };
+
+        let value = match field_type {
+            FieldType::U8 => u8::try_from(integer).map(FieldValue::U8).ok(),
+            FieldType::U16 => u16::try_from(integer).map(FieldValue::U16).ok(),
+            FieldType::U32 => u32::try_from(integer).map(FieldValue::U32).ok(),
+            FieldType::U64 => u64::try_from(integer).map(FieldValue::U64).ok(),
+            FieldType::I8 => i8::try_from(integer).map(FieldValue::I8).ok(),
+            FieldType::I16 => i16::try_from(integer).map(FieldValue::I16).ok(),
+            FieldType::I32 => i32::try_from(integer).map(FieldValue::I32).ok(),
+            FieldType::I64 => i64::try_from(integer).map(FieldValue::I64).ok(),
+            FieldType::F32 => Some(FieldValue::F32(integer as f32)),
+            FieldType::F64 => Some(FieldValue::F64(integer as f64)),
+            _ => return Ok(self),
+        };
+
+        value.ok_or_else(|| out_of_range(&integer))
+    }
+}
+
+fn is_integer_type(field_type: FieldType) -> bool {
+    matches!(
+        field_type,
+        FieldType::U8 | FieldType::U16 | FieldType::U32 | FieldType::U64
+            | FieldType::I8 | FieldType::I16 | FieldType::I32 | FieldType::I64
+    )
+}
+
+#[cfg(feature = "std")]
+std::thread_local! {
+    static BINARY_SOURCE: core::cell::RefCell<Option<Bytes>> = const { core::cell::RefCell::new(None) };
+}
+
+/// Makes `source` the buffer that borrowed binary data is sliced from on this
+/// thread, until the guard is dropped. Dropping restores the previous source,
+/// also when decoding unwinds, so guards nest.
+#[cfg(feature = "std")]
+pub(crate) struct BinarySourceGuard {
+    previous: Option<Bytes>,
+}
+
+#[cfg(feature = "std")]
+impl BinarySourceGuard {
+    pub(crate) fn new(source: &Bytes) -> Self {
+        Self { previous: BINARY_SOURCE.with(|s| s.replace(Some(source.clone()))) }
+    }
+}
+
+#[cfg(feature = "std")]
+impl Drop for BinarySourceGuard {
+    fn drop(&mut self) {
+        let previous = self.previous.take();
+        BINARY_SOURCE.with(|s| *s.borrow_mut() = previous);
+    }
+}
+
+fn binary_from_borrowed(data: &[u8]) -> Bytes {
+    #[cfg(feature = "std")]
+    {
+        let sliced = BINARY_SOURCE.with(|s| {
+            s.borrow().as_ref().and_then(|source| {
+                let start = source.as_ptr() as usize;
+                let ptr = data.as_ptr() as usize;
+                let within = ptr >= start && ptr + data.len() <= start + source.len();
+                within.then(|| source.slice_ref(data))
+            })
+        });
+
+        if let Some(bytes) = sliced {
+            return bytes;
+        }
+    }
+
+    Bytes::copy_from_slice(data)
+}
+
+fn serialize_binary<S: serde::Serializer>(data: &Bytes, serializer: S) -> core::result::Result<S::Ok, S::Error> {
+    serializer.serialize_bytes(data)
+}
+
+fn deserialize_binary<'de, D: Deserializer<'de>>(deserializer: D) -> core::result::Result<Bytes, D::Error> {
+    struct BinaryVisitor;
+
+    impl<'de> serde::de::Visitor<'de> for BinaryVisitor {
+        type Value = Bytes;
+
+        fn expecting(&self, f: &mut core::fmt::Formatter) -> core::fmt::Result {
+            f.write_str("binary data")
+        }
+
+        fn visit_borrowed_bytes<E>(self, v: &'de [u8]) -> core::result::Result<Bytes, E> {
+            Ok(binary_from_borrowed(v))
+        }
+
+        fn visit_bytes<E>(self, v: &[u8]) -> core::result::Result<Bytes, E> {
+            Ok(Bytes::copy_from_slice(v))
+        }
+
+        fn visit_byte_buf<E>(self, v: Vec<u8>) -> core::result::Result<Bytes, E> {
+            Ok(Bytes::from(v))
+        }
+
+        // Older peers encoded binary data as a sequence of integers.
+        fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> core::result::Result<Bytes, A::Error> {
+            let mut data = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+            while let Some(byte) = seq.next_element::<u8>()? {
+                data.push(byte);
+            }
+            Ok(Bytes::from(data))
+        }
+    }
+
+    deserializer.deserialize_bytes(BinaryVisitor)
+}
+
+/// Serialize a map in key order so identical contents always encode to identical bytes.
+fn serialize_sorted_map<S: serde::Serializer>(
+    map: &HashMap<String, FieldValue>,
+    serializer: S,
+) -> core::result::Result<S::Ok, S::Error> {
+    let sorted: alloc::collections::BTreeMap<&String, &FieldValue> = map.iter().collect();
+    sorted.serialize(serializer)
+}
+
+fn serialize_float<F, S>(value: &F, serializer: S) -> core::result::Result<S::Ok, S::Error>
+where
+    F: Copy + Into<f64> + Serialize,
+    S: serde::Serializer,
+{
+    let wide: f64 = (*value).into();
+    if !serializer.is_human_readable() || wide.is_finite() {
+        value.serialize(serializer)
+    } else if wide.is_nan() {
+        serializer.serialize_str("NaN")
+    } else if wide > 0.0 {
+        serializer.serialize_str("Infinity")
+    } else {
+        serializer.serialize_str("-Infinity")
+    }
+}
+
+fn deserialize_f64<'de, D: Deserializer<'de>>(deserializer: D) -> core::result::Result<f64, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(FloatVisitor)
+    } else {
+        f64::deserialize(deserializer)
+    }
+}
+
+fn deserialize_f32<'de, D: Deserializer<'de>>(deserializer: D) -> core::result::Result<f32, D::Error> {
+    if deserializer.is_human_readable() {
+        deserializer.deserialize_any(FloatVisitor).map(|v| v as f32)
+    } else {
+        f32::deserialize(deserializer)
+    }
+}
+
+/// Reads a float written by `serialize_float`, or a plain integer.
+struct FloatVisitor;
+
+impl<'de> serde::de::Visitor<'de> for FloatVisitor {
+    type Value = f64;
+
+    fn expecting(&self, formatter: &mut core::fmt::Formatter) -> core::fmt::Result {
+        formatter.write_str("a number, null, \"NaN\", \"Infinity\" or \"-Infinity\"")
+    }
+
+    fn visit_f64<E: serde::de::Error>(self, v: f64) -> core::result::Result<f64, E> {
+        Ok(v)
+    }
+
+    fn visit_i64<E: serde::de::Error>(self, v: i64) -> core::result::Result<f64, E> {
+        Ok(v as f64)
+    }
+
+    fn visit_u64<E: serde::de::Error>(self, v: u64) -> core::result::Result<f64, E> {
+        Ok(v as f64)
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> core::result::Result<f64, E> {
+        Ok(f64::NAN)
+    }
+
+    fn visit_none<E: serde::de::Error>(self) -> core::result::Result<f64, E> {
+        Ok(f64::NAN)
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> core::result::Result<f64, E> {
+        match v {
+            "NaN" => Ok(f64::NAN),
+            "Infinity" => Ok(f64::INFINITY),
+            "-Infinity" => Ok(f64::NEG_INFINITY),
+            _ => Err(E::invalid_value(serde::de::Unexpected::Str(v), &self)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -168,9 +855,12 @@ pub struct DeltaMetadata {
     pub entities_added: u32,
     pub entities_removed: u32,
     pub components_updated: u32,
+    /// World time of the snapshot this delta produces, in seconds.
+    #[serde(default)]
+    pub world_time: Option<f64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum DeltaChange {
     EntityAdded {
@@ -198,25 +888,104 @@ pub enum DeltaChange {
         component_id: ComponentId,
         fields: Vec<FieldDelta>,
     },
+    /// Compact `FieldsUpdated` for a component with a registered schema: fields
+    /// are addressed by their position in that schema version and carry only
+    /// the new value. See `SchemaRegistry::compact_changes`.
+    IndexedFieldsUpdated {
+        entity_id: EntityId,
+        component_id: ComponentId,
+        schema_version: SchemaVersion,
+        fields: Vec<IndexedFieldDelta>,
+    },
+    /// An existing entity's components, all at once: those not listed are
+    /// removed. Sent when none of the entity's previous components survive,
+    /// so it never appears half-stripped.
+    EntityComponentsReplaced {
+        entity_id: EntityId,
+        components: Vec<SerializedComponent>,
+    },
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl DeltaChange {
+    pub fn entity_id(&self) -> EntityId {
+        match self {
+            DeltaChange::EntityAdded { entity_id }
+            | DeltaChange::EntityRemoved { entity_id }
+            | DeltaChange::ComponentAdded { entity_id, .. }
+            | DeltaChange::ComponentRemoved { entity_id, .. }
+            | DeltaChange::ComponentUpdated { entity_id, .. }
+            | DeltaChange::FieldsUpdated { entity_id, .. }
+            | DeltaChange::IndexedFieldsUpdated { entity_id, .. }
+            | DeltaChange::EntityComponentsReplaced { entity_id, .. } => *entity_id,
+        }
+    }
+
+    /// The component the change is about, `None` for whole-entity changes.
+    pub fn component_id(&self) -> Option<&ComponentId> {
+        match self {
+            DeltaChange::EntityAdded { .. }
+            | DeltaChange::EntityRemoved { .. }
+            | DeltaChange::EntityComponentsReplaced { .. } => None,
+            DeltaChange::ComponentAdded { component_id, .. }
+            | DeltaChange::ComponentRemoved { component_id, .. }
+            | DeltaChange::ComponentUpdated { component_id, .. }
+            | DeltaChange::FieldsUpdated { component_id, .. }
+            | DeltaChange::IndexedFieldsUpdated { component_id, .. } => Some(component_id),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IndexedFieldDelta {
+    pub index: u16,
+    pub value: FieldValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FieldDelta {
     pub field_id: FieldId,
     pub old_value: Option<FieldValue>,
     pub new_value: FieldValue,
+    /// The field (or map key) was removed; `new_value` is ignored. Setting a
+    /// field to `FieldValue::Null` is an ordinary update.
+    #[serde(default)]
+    pub removed: bool,
+}
+
+impl FieldDelta {
+    /// Set `field_id` to `new_value`.
+    pub fn new(field_id: FieldId, old_value: Option<FieldValue>, new_value: FieldValue) -> Self {
+        Self { field_id, old_value, new_value, removed: false }
+    }
+
+    /// Remove `field_id`, which held `old_value`.
+    pub fn removal(field_id: FieldId, old_value: Option<FieldValue>) -> Self {
+        Self { field_id, old_value, new_value: FieldValue::Null, removed: true }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SchemaSyncPayload {
     pub schemas: Vec<ComponentSchemaInfo>,
+    /// Message compression the sender can handle, used to negotiate a common one.
+    #[serde(default)]
+    pub compression: Vec<CompressionType>,
+    /// Set on the answer to a peer's `SchemaSync`, which must not be answered again.
+    #[serde(default)]
+    pub is_reply: bool,
+    /// The sender's `PROTOCOL_VERSION`; 0 from builds that predate it.
+    #[serde(default)]
+    pub protocol_version: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ComponentSchemaInfo {
     pub component_id: ComponentId,
-    pub version: u32,
+    pub version: SchemaVersion,
     pub fields: Vec<FieldSchemaInfo>,
+    /// The sender sends this component's `F64` fields as `F32`.
+    #[serde(default)]
+    pub low_precision: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -245,17 +1014,30 @@ pub enum FieldType {
     Bytes = 13,
     Array = 14,
     Map = 15,
+    Enum = 16,
+}
+
+impl Message {
+    /// See [`MessageType::requires_reliable_delivery`]. A channel message is
+    /// as reliable as the payload it carries.
+    pub fn is_reliable(&self) -> bool {
+        match &self.payload {
+            MessagePayload::Channel { payload, .. } => payload.message_type().requires_reliable_delivery(),
+            _ => self.header.msg_type.requires_reliable_delivery(),
+        }
+    }
 }
 
+#[cfg(feature = "std")]
 impl Message {
-    pub fn new(msg_type: MessageType, schema_version: u32, payload: MessagePayload) -> Self {
+    pub fn new(msg_type: MessageType, schema_version: SchemaVersion, payload: MessagePayload) -> Self {
         Self {
             header: MessageHeader::new(msg_type, schema_version),
             payload,
         }
     }
 
-    pub fn snapshot(entities: Vec<SerializedEntity>, world_time: f64, schema_version: u32) -> Self {
+    pub fn snapshot(entities: Vec<SerializedEntity>, world_time: f64, schema_version: SchemaVersion) -> Self {
         let entity_count = entities.len() as u32;
         let component_count: u32 = entities.iter()
             .map(|e| e.components.len() as u32)
@@ -276,7 +1058,7 @@ impl Message {
         )
     }
 
-    pub fn delta(changes: Vec<DeltaChange>, base_timestamp: u64, schema_version: u32) -> Self {
+    pub fn delta(changes: Vec<DeltaChange>, base_timestamp: u64, schema_version: SchemaVersion) -> Self {
         let change_count = changes.len() as u32;
         let entities_added = changes.iter()
             .filter(|c| matches!(c, DeltaChange::EntityAdded { .. }))
@@ -285,7 +1067,13 @@ impl Message {
             .filter(|c| matches!(c, DeltaChange::EntityRemoved { .. }))
             .count() as u32;
         let components_updated = changes.iter()
-            .filter(|c| matches!(c, DeltaChange::ComponentUpdated { .. } | DeltaChange::FieldsUpdated { .. }))
+            .filter(|c| matches!(
+                c,
+                DeltaChange::ComponentUpdated { .. }
+                    | DeltaChange::FieldsUpdated { .. }
+                    | DeltaChange::IndexedFieldsUpdated { .. }
+                    | DeltaChange::EntityComponentsReplaced { .. }
+            ))
             .count() as u32;
 
         Self::new(
@@ -299,40 +1087,239 @@ impl Message {
                     entities_added,
                     entities_removed,
                     components_updated,
+                    world_time: None,
                 },
             }),
         )
     }
 
-    pub fn request_snapshot(schema_version: u32) -> Self {
+    /// Like [`Self::delta`], also carrying the world time of the target snapshot
+    /// so the receiver can rebuild it with the right timestamp.
+    pub fn delta_at(changes: Vec<DeltaChange>, base_timestamp: u64, world_time: f64, schema_version: SchemaVersion) -> Self {
+        let mut message = Self::delta(changes, base_timestamp, schema_version);
+        if let MessagePayload::Delta(payload) = &mut message.payload {
+            payload.metadata.world_time = Some(world_time);
+        }
+        message
+    }
+
+    pub fn request_snapshot(schema_version: SchemaVersion) -> Self {
         Self::new(
             MessageType::RequestSnapshot,
             schema_version,
-            MessagePayload::RequestSnapshot,
+            MessagePayload::RequestSnapshot { since: None },
+        )
+    }
+
+    /// Ask for the changes since `since` (milliseconds, as in `DeltaPayload::base_timestamp`).
+    /// The peer falls back to a full snapshot if it no longer has that baseline.
+    pub fn request_snapshot_since(since: u64, schema_version: SchemaVersion) -> Self {
+        Self::new(
+            MessageType::RequestSnapshot,
+            schema_version,
+            MessagePayload::RequestSnapshot { since: Some(since) },
+        )
+    }
+
+    pub fn ack(ack_id: u64, schema_version: SchemaVersion) -> Self {
+        Self::new(
+            MessageType::Ack,
+            schema_version,
+            MessagePayload::Ack { ack_id, timestamp: None },
         )
     }
 
-    pub fn ack(ack_id: u64, schema_version: u32) -> Self {
+    /// Tell a sender using ack baselines that the world state at `timestamp`
+    /// (milliseconds, as in `DeltaPayload::base_timestamp`) is in place. The
+    /// `ack_id` is 0: it acknowledges a state, not a message.
+    pub fn ack_baseline(timestamp: u64, schema_version: SchemaVersion) -> Self {
         Self::new(
             MessageType::Ack,
             schema_version,
-            MessagePayload::Ack { ack_id },
+            MessagePayload::Ack { ack_id: 0, timestamp: Some(timestamp) },
         )
     }
 
-    pub fn ping(schema_version: u32) -> Self {
+    pub fn ping(schema_version: SchemaVersion) -> Self {
         Self::new(MessageType::Ping, schema_version, MessagePayload::Ping)
     }
 
-    pub fn pong(schema_version: u32) -> Self {
-        Self::new(MessageType::Pong, schema_version, MessagePayload::Pong)
+    pub fn pong(schema_version: SchemaVersion) -> Self {
+        Self::new(MessageType::Pong, schema_version, MessagePayload::Pong { ping_id: None })
     }
 
-    pub fn error(code: u32, message: String, schema_version: u32) -> Self {
+    /// Answer the ping whose header id is `ping_id`.
+    pub fn pong_for(ping_id: u64, schema_version: SchemaVersion) -> Self {
+        Self::new(MessageType::Pong, schema_version, MessagePayload::Pong { ping_id: Some(ping_id) })
+    }
+
+    pub fn schema_sync(schemas: Vec<ComponentSchemaInfo>, schema_version: SchemaVersion) -> Self {
+        Self::new(
+            MessageType::SchemaSync,
+            schema_version,
+            MessagePayload::SchemaSync(SchemaSyncPayload {
+                schemas,
+                compression: Vec::new(),
+                is_reply: false,
+                protocol_version: PROTOCOL_VERSION,
+            }),
+        )
+    }
+
+    pub fn error(code: ErrorCode, message: String, schema_version: SchemaVersion) -> Self {
         Self::new(
             MessageType::Error,
             schema_version,
-            MessagePayload::Error { code, message },
+            MessagePayload::Error { code: code.into(), message },
+        )
+    }
+
+    pub fn encrypted(sealed: Bytes, schema_version: SchemaVersion) -> Self {
+        Self::new(
+            MessageType::Encrypted,
+            schema_version,
+            MessagePayload::Encrypted { sealed },
         )
     }
+
+    /// `message` on logical channel `channel`, keeping its header but for the type.
+    pub fn channel(channel: u8, message: Message) -> Self {
+        let mut header = message.header;
+        header.msg_type = MessageType::Channel;
+
+        Self {
+            header,
+            payload: MessagePayload::Channel { channel, payload: Box::new(message.payload) },
+        }
+    }
+}
+
+/// Upper bounds on what a received snapshot or delta may describe.
+///
+/// A message that passes the size checks can still claim millions of
+/// entities or changes; these limits stop it from growing the world without
+/// bound. The defaults are conservative (100 000 entities, 16 MiB messages);
+/// raise them for larger worlds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MessageLimits {
+    pub max_entities: usize,
+    pub max_components_per_entity: usize,
+    pub max_changes_per_delta: usize,
+    /// Largest payload a compressed message or component may inflate to.
+    pub max_decompressed_bytes: usize,
+    /// Largest encoded message or frame body accepted from the peer.
+    pub max_message_bytes: usize,
+}
+
+impl Default for MessageLimits {
+    fn default() -> Self {
+        Self {
+            max_entities: 100_000,
+            max_components_per_entity: 256,
+            max_changes_per_delta: 1_000_000,
+            max_decompressed_bytes: 16 * 1024 * 1024,
+            max_message_bytes: 16 * 1024 * 1024,
+        }
+    }
+}
+
+impl MessageLimits {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_max_entities(mut self, max: usize) -> Self {
+        self.max_entities = max;
+        self
+    }
+
+    pub fn with_max_components_per_entity(mut self, max: usize) -> Self {
+        self.max_components_per_entity = max;
+        self
+    }
+
+    pub fn with_max_changes_per_delta(mut self, max: usize) -> Self {
+        self.max_changes_per_delta = max;
+        self
+    }
+
+    pub fn with_max_decompressed_bytes(mut self, max: usize) -> Self {
+        self.max_decompressed_bytes = max;
+        self
+    }
+
+    pub fn with_max_message_bytes(mut self, max: usize) -> Self {
+        self.max_message_bytes = max;
+        self
+    }
+
+    pub fn check_payload(&self, payload: &MessagePayload) -> Result<()> {
+        match payload {
+            MessagePayload::Snapshot(payload) => self.check_entities(&payload.entities),
+            MessagePayload::Delta(payload) => self.check_changes(&payload.changes),
+            MessagePayload::Channel { payload, .. } => self.check_payload(payload),
+            _ => Ok(()),
+        }
+    }
+
+    pub fn check_entities(&self, entities: &[SerializedEntity]) -> Result<()> {
+        if entities.len() > self.max_entities {
+            return Err(LinkError::InvalidMessage(format!(
+                "{} entities exceeds the limit of {}", entities.len(), self.max_entities
+            )));
+        }
+
+        match entities.iter().find(|e| e.components.len() > self.max_components_per_entity) {
+            Some(entity) => Err(self.too_many_components(entity.id, entity.components.len())),
+            None => Ok(()),
+        }
+    }
+
+    /// Check a delta's own size. Whether applying it keeps the world within
+    /// `max_entities` depends on the base; see `WorldSnapshot::apply_delta_within`.
+    pub fn check_changes(&self, changes: &[DeltaChange]) -> Result<()> {
+        if changes.len() > self.max_changes_per_delta {
+            return Err(LinkError::InvalidMessage(format!(
+                "{} changes exceeds the limit of {}", changes.len(), self.max_changes_per_delta
+            )));
+        }
+
+        let mut added_entities = 0;
+        let mut added_components: HashMap<EntityId, usize> = HashMap::new();
+
+        for change in changes {
+            match change {
+                DeltaChange::EntityAdded { .. } => {
+                    added_entities += 1;
+                    if added_entities > self.max_entities {
+                        return Err(LinkError::InvalidMessage(format!(
+                            "Delta adds more than {} entities", self.max_entities
+                        )));
+                    }
+                }
+                DeltaChange::ComponentAdded { entity_id, .. } => {
+                    let count = added_components.entry(*entity_id).or_insert(0);
+                    *count += 1;
+                    if *count > self.max_components_per_entity {
+                        return Err(self.too_many_components(*entity_id, *count));
+                    }
+                }
+                DeltaChange::EntityComponentsReplaced { entity_id, components }
+                    if components.len() > self.max_components_per_entity =>
+                {
+                    return Err(self.too_many_components(*entity_id, components.len()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn too_many_components(&self, entity_id: EntityId, count: usize) -> LinkError {
+        LinkError::InvalidMessage(format!(
+            "Entity {} has {} components, exceeding the limit of {}",
+            entity_id, count, self.max_components_per_entity
+        ))
+    }
 }
@@ -1,6 +1,23 @@
+use crate::clock::{self, Clock};
 use crate::error::{LinkError, Result};
+use serde::Serialize;
 use std::time::{Duration, Instant};
 use std::collections::VecDeque;
+use std::sync::Arc;
+
+/// What `SyncManager` does with a message the rate limiter turns down.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RateLimitPolicy {
+    /// Fail the send with `LinkError::RateLimitExceeded`.
+    #[default]
+    Reject,
+    /// Hold only the newest rejected message, dropping any older one still
+    /// waiting. A dropped delta turns the held message into a full snapshot.
+    DropOldest,
+    /// Hold up to this many rejected messages and send them in order as the
+    /// window frees up; reject once the backlog is full.
+    Queue(usize),
+}
 
 #[derive(Debug, Clone)]
 pub struct RateLimitConfig {
@@ -8,6 +25,7 @@ pub struct RateLimitConfig {
     pub max_bytes_per_second: u64,
     pub burst_size: u32,
     pub window_duration: Duration,
+    pub policy: RateLimitPolicy,
 }
 
 impl Default for RateLimitConfig {
@@ -17,6 +35,7 @@ impl Default for RateLimitConfig {
             max_bytes_per_second: 10 * 1024 * 1024,
             burst_size: 100,
             window_duration: Duration::from_secs(1),
+            policy: RateLimitPolicy::Reject,
         }
     }
 }
@@ -45,17 +64,64 @@ impl RateLimitConfig {
         self.window_duration = duration;
         self
     }
+
+    pub fn with_policy(mut self, policy: RateLimitPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
 }
 
-struct MessageRecord {
+#[derive(Clone)]
+pub(crate) struct MessageRecord {
     timestamp: Instant,
     size: u64,
 }
 
+/// Timestamped message sizes, oldest first. A record is in a window while it
+/// is no older than the window's duration.
+#[derive(Default)]
+pub(crate) struct SlidingWindow {
+    records: VecDeque<MessageRecord>,
+}
+
+impl SlidingWindow {
+    pub(crate) fn record(&mut self, timestamp: Instant, size: u64) {
+        self.records.push_back(MessageRecord { timestamp, size });
+    }
+
+    /// Drop the records that have left `window`.
+    pub(crate) fn prune(&mut self, now: Instant, window: Duration) {
+        let Some(cutoff) = now.checked_sub(window) else {
+            return;
+        };
+        while self.records.front().is_some_and(|r| r.timestamp < cutoff) {
+            self.records.pop_front();
+        }
+    }
+
+    /// Records still in `window`, oldest first.
+    pub(crate) fn in_window(&self, now: Instant, window: Duration) -> impl Iterator<Item = &MessageRecord> {
+        let cutoff = now.checked_sub(window);
+        self.records.iter().filter(move |r| cutoff.is_none_or(|cutoff| r.timestamp >= cutoff))
+    }
+
+    pub(crate) fn count(&self, now: Instant, window: Duration) -> u64 {
+        self.in_window(now, window).count() as u64
+    }
+
+    pub(crate) fn bytes(&self, now: Instant, window: Duration) -> u64 {
+        self.in_window(now, window).map(|r| r.size).sum()
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.records.clear();
+    }
+}
+
 pub struct RateLimiter {
     config: RateLimitConfig,
-    message_history: VecDeque<MessageRecord>,
-    byte_history: VecDeque<MessageRecord>,
+    clock: Arc<dyn Clock>,
+    history: SlidingWindow,
     total_messages: u64,
     total_bytes: u64,
     total_rejected: u64,
@@ -63,10 +129,14 @@ pub struct RateLimiter {
 
 impl RateLimiter {
     pub fn new(config: RateLimitConfig) -> Self {
+        Self::with_clock(config, clock::system_clock())
+    }
+
+    pub fn with_clock(config: RateLimitConfig, clock: Arc<dyn Clock>) -> Self {
         Self {
             config,
-            message_history: VecDeque::new(),
-            byte_history: VecDeque::new(),
+            clock,
+            history: SlidingWindow::default(),
             total_messages: 0,
             total_bytes: 0,
             total_rejected: 0,
@@ -74,38 +144,91 @@ impl RateLimiter {
     }
 
     pub fn check_and_record(&mut self, message_size: u64) -> Result<()> {
-        let now = Instant::now();
-
-        self.cleanup_old_records(now);
+        let now = self.clock.now();
 
-        let messages_in_window = self.count_messages_in_window(now);
-        let bytes_in_window = self.count_bytes_in_window(now);
+        self.history.prune(now, self.config.window_duration);
 
-        if messages_in_window >= self.config.max_messages_per_second {
+        if let Some(error) = self.limit_error(now, message_size) {
             self.total_rejected += 1;
-            return Err(LinkError::RateLimitExceeded(
+            return Err(error);
+        }
+
+        self.record_message(now, message_size);
+
+        Ok(())
+    }
+
+    /// How long until a message of `message_size` bytes would be accepted,
+    /// zero if it would be now. `None` if it never fits the limits.
+    pub fn time_until_available(&self, message_size: u64) -> Option<Duration> {
+        let now = self.clock.now();
+        if self.limit_error(now, message_size).is_none() {
+            return Some(Duration::ZERO);
+        }
+        if self.config.max_messages_per_second == 0
+            || self.config.burst_size == 0
+            || message_size > self.config.max_bytes_per_second
+        {
+            return None;
+        }
+
+        let window = self.config.window_duration;
+        let in_window: Vec<&MessageRecord> = self.history.in_window(now, window).collect();
+        let mut ready = now;
+        // Records count until they are strictly older than the window.
+        let leaves = |record: &MessageRecord, span: Duration| record.timestamp + span + Duration::from_nanos(1);
+
+        // Enough old messages have to leave the window for one more to fit.
+        let max = self.config.max_messages_per_second as usize;
+        if in_window.len() >= max {
+            ready = ready.max(leaves(in_window[in_window.len() - max], window));
+        }
+
+        let mut bytes: u64 = in_window.iter().map(|r| r.size).sum();
+        for record in &in_window {
+            if bytes + message_size <= self.config.max_bytes_per_second {
+                break;
+            }
+            bytes -= record.size;
+            ready = ready.max(leaves(record, window));
+        }
+
+        let burst_window = Duration::from_millis(100);
+        let burst: Vec<_> = in_window.iter()
+            .filter(|r| r.timestamp >= now - burst_window)
+            .collect();
+        let burst_size = self.config.burst_size as usize;
+        if burst.len() >= burst_size {
+            ready = ready.max(leaves(burst[burst.len() - burst_size], burst_window));
+        }
+
+        Some(ready.duration_since(now))
+    }
+
+    fn limit_error(&self, now: Instant, message_size: u64) -> Option<LinkError> {
+        let messages_in_window = self.history.count(now, self.config.window_duration);
+        let bytes_in_window = self.history.bytes(now, self.config.window_duration);
+
+        if messages_in_window >= self.config.max_messages_per_second as u64 {
+            return Some(LinkError::RateLimitExceeded(
                 format!("Message rate limit exceeded: {} msgs/sec", self.config.max_messages_per_second)
             ));
         }
 
         if bytes_in_window + message_size > self.config.max_bytes_per_second {
-            self.total_rejected += 1;
-            return Err(LinkError::RateLimitExceeded(
+            return Some(LinkError::RateLimitExceeded(
                 format!("Byte rate limit exceeded: {} bytes/sec", self.config.max_bytes_per_second)
             ));
         }
 
-        let burst_count = self.count_recent_burst(now);
-        if burst_count >= self.config.burst_size {
-            self.total_rejected += 1;
-            return Err(LinkError::RateLimitExceeded(
+        let burst_count = self.history.count(now, Duration::from_millis(100));
+        if burst_count >= self.config.burst_size as u64 {
+            return Some(LinkError::RateLimitExceeded(
                 format!("Burst limit exceeded: {} msgs", self.config.burst_size)
             ));
         }
 
-        self.record_message(now, message_size);
-
-        Ok(())
+        None
     }
 
     pub fn check(&mut self, message_size: u64) -> bool {
@@ -113,74 +236,34 @@ impl RateLimiter {
     }
 
     fn record_message(&mut self, timestamp: Instant, size: u64) {
-        let record = MessageRecord {
-            timestamp,
-            size,
-        };
-
-        self.message_history.push_back(record.clone());
-        self.byte_history.push_back(record);
+        self.history.record(timestamp, size);
 
         self.total_messages += 1;
         self.total_bytes += size;
     }
 
-    fn cleanup_old_records(&mut self, now: Instant) {
-        let cutoff = now - self.config.window_duration;
-
-        while let Some(record) = self.message_history.front() {
-            if record.timestamp < cutoff {
-                self.message_history.pop_front();
-            } else {
-                break;
-            }
-        }
-
-        while let Some(record) = self.byte_history.front() {
-            if record.timestamp < cutoff {
-                self.byte_history.pop_front();
-            } else {
-                break;
-            }
-        }
-    }
-
-    fn count_messages_in_window(&self, now: Instant) -> u32 {
-        let cutoff = now - self.config.window_duration;
-        self.message_history.iter()
-            .filter(|r| r.timestamp >= cutoff)
-            .count() as u32
-    }
-
-    fn count_bytes_in_window(&self, now: Instant) -> u64 {
-        let cutoff = now - self.config.window_duration;
-        self.byte_history.iter()
-            .filter(|r| r.timestamp >= cutoff)
-            .map(|r| r.size)
-            .sum()
-    }
-
-    fn count_recent_burst(&self, now: Instant) -> u32 {
-        let burst_window = Duration::from_millis(100);
-        let cutoff = now - burst_window;
-
-        self.message_history.iter()
-            .filter(|r| r.timestamp >= cutoff)
-            .count() as u32
+    pub fn reset(&mut self) {
+        self.history.clear();
     }
 
-    pub fn reset(&mut self) {
-        self.message_history.clear();
-        self.byte_history.clear();
+    /// Zero the lifetime totals in [`RateLimitStats`], keeping the window so
+    /// limits still apply.
+    pub fn reset_stats(&mut self) {
+        self.total_messages = 0;
+        self.total_bytes = 0;
+        self.total_rejected = 0;
     }
 
     pub fn get_stats(&self) -> RateLimitStats {
+        let now = self.clock.now();
+        let window = self.config.window_duration;
+
         RateLimitStats {
             total_messages: self.total_messages,
             total_bytes: self.total_bytes,
             total_rejected: self.total_rejected,
-            messages_in_window: self.message_history.len() as u32,
-            bytes_in_window: self.byte_history.iter().map(|r| r.size).sum(),
+            messages_in_window: self.history.count(now, window) as u32,
+            bytes_in_window: self.history.bytes(now, window),
         }
     }
 
@@ -193,16 +276,7 @@ impl RateLimiter {
     }
 }
 
-impl Clone for MessageRecord {
-    fn clone(&self) -> Self {
-        Self {
-            timestamp: self.timestamp,
-            size: self.size,
-        }
-    }
-}
-
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct RateLimitStats {
     pub total_messages: u64,
     pub total_bytes: u64,
@@ -215,6 +289,7 @@ pub struct TokenBucketRateLimiter {
     capacity: u32,
     tokens: u32,
     refill_rate: u32,
+    clock: Arc<dyn Clock>,
     last_refill: Instant,
     total_messages: u64,
     total_rejected: u64,
@@ -222,11 +297,16 @@ pub struct TokenBucketRateLimiter {
 
 impl TokenBucketRateLimiter {
     pub fn new(capacity: u32, refill_rate: u32) -> Self {
+        Self::with_clock(capacity, refill_rate, clock::system_clock())
+    }
+
+    pub fn with_clock(capacity: u32, refill_rate: u32, clock: Arc<dyn Clock>) -> Self {
         Self {
             capacity,
             tokens: capacity,
             refill_rate,
-            last_refill: Instant::now(),
+            last_refill: clock.now(),
+            clock,
             total_messages: 0,
             total_rejected: 0,
         }
@@ -253,7 +333,7 @@ impl TokenBucketRateLimiter {
     }
 
     fn refill(&mut self) {
-        let now = Instant::now();
+        let now = self.clock.now();
         let elapsed = now.duration_since(self.last_refill);
         let elapsed_secs = elapsed.as_secs_f64();
 
@@ -267,7 +347,7 @@ impl TokenBucketRateLimiter {
 
     pub fn reset(&mut self) {
         self.tokens = self.capacity;
-        self.last_refill = Instant::now();
+        self.last_refill = self.clock.now();
     }
 
     pub fn get_available_tokens(&self) -> u32 {
@@ -282,7 +362,7 @@ impl TokenBucketRateLimiter {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::thread;
+    use crate::clock::MockClock;
 
     #[test]
     fn test_rate_limiter_basic() {
@@ -332,7 +412,8 @@ mod tests {
             .with_max_messages(5)
             .with_window_duration(Duration::from_millis(100));
 
-        let mut limiter = RateLimiter::new(config);
+        let clock = MockClock::new();
+        let mut limiter = RateLimiter::with_clock(config, Arc::new(clock.clone()));
 
         for _ in 0..5 {
             assert!(limiter.check_and_record(100).is_ok());
@@ -340,14 +421,39 @@ mod tests {
 
         assert!(limiter.check_and_record(100).is_err());
 
-        thread::sleep(Duration::from_millis(150));
+        clock.advance(Duration::from_millis(150));
+
+        assert!(limiter.check_and_record(100).is_ok());
+    }
+
+    #[test]
+    fn test_rate_limiter_time_until_available() {
+        let config = RateLimitConfig::new()
+            .with_max_messages(2)
+            .with_max_bytes(1000)
+            .with_window_duration(Duration::from_millis(100));
+
+        let clock = MockClock::new();
+        let mut limiter = RateLimiter::with_clock(config, Arc::new(clock.clone()));
+
+        assert_eq!(limiter.time_until_available(100), Some(Duration::ZERO));
+        limiter.check_and_record(100).unwrap();
+        clock.advance(Duration::from_millis(40));
+        limiter.check_and_record(100).unwrap();
+
+        let wait = limiter.time_until_available(100).unwrap();
+        assert!(wait > Duration::from_millis(59) && wait <= Duration::from_millis(61));
+        assert_eq!(limiter.time_until_available(2000), None);
 
+        clock.advance(wait);
+        assert_eq!(limiter.time_until_available(100), Some(Duration::ZERO));
         assert!(limiter.check_and_record(100).is_ok());
     }
 
     #[test]
     fn test_token_bucket() {
-        let mut limiter = TokenBucketRateLimiter::new(5, 10);
+        let clock = MockClock::new();
+        let mut limiter = TokenBucketRateLimiter::with_clock(5, 10, Arc::new(clock.clone()));
 
         for _ in 0..5 {
             assert!(limiter.check_and_consume().is_ok());
@@ -355,7 +461,7 @@ mod tests {
 
         assert!(limiter.check_and_consume().is_err());
 
-        thread::sleep(Duration::from_millis(100));
+        clock.advance(Duration::from_millis(100));
         limiter.refill();
 
         assert!(limiter.check_and_consume().is_ok());
@@ -0,0 +1,379 @@
+use crate::error::{LinkError, Result};
+use crate::protocol::{CompressionType, Message, MessageType};
+use crate::serialization::{BinaryFormat, BinarySerializer};
+use crate::transport::Transport;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::SocketAddr;
+use std::path::Path;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum Direction {
+    Sent = 0,
+    Received = 1,
+}
+
+/// One entry of a recording: the message, which way it went, and when
+/// (milliseconds since the Unix epoch).
+#[derive(Debug, Clone)]
+pub struct RecordedMessage {
+    pub direction: Direction,
+    pub timestamp: u64,
+    pub message: Message,
+}
+
+/// Wraps a transport and appends every message it sends or receives to a log.
+///
+/// Each record is a `u32` little-endian length followed by the direction byte,
+/// the `u64` little-endian timestamp and the encoded message.
+pub struct Recorder<T: Transport, W: Write = BufWriter<File>> {
+    inner: T,
+    writer: W,
+    serializer: BinarySerializer,
+}
+
+impl<T: Transport> Recorder<T> {
+    pub fn create<P: AsRef<Path>>(inner: T, path: P, format: BinaryFormat) -> Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self::new(inner, BufWriter::new(file), format))
+    }
+}
+
+impl<T: Transport, W: Write> Recorder<T, W> {
+    pub fn new(inner: T, writer: W, format: BinaryFormat) -> Self {
+        Self {
+            inner,
+            writer,
+            serializer: BinarySerializer::new(format),
+        }
+    }
+
+    pub fn get_inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Flush the log and return the wrapped transport and writer.
+    pub fn into_parts(mut self) -> Result<(T, W)> {
+        self.writer.flush()?;
+        Ok((self.inner, self.writer))
+    }
+
+    fn record(&mut self, direction: Direction, message: &Message) -> Result<()> {
+        let data = self.serializer.serialize_message(message)?;
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+
+        let len = (1 + 8 + data.len()) as u32;
+        self.writer.write_all(&len.to_le_bytes())?;
+        self.writer.write_all(&[direction as u8])?;
+        self.writer.write_all(&timestamp.to_le_bytes())?;
+        self.writer.write_all(&data)?;
+
+        Ok(())
+    }
+}
+
+impl<T: Transport, W: Write> Transport for Recorder<T, W> {
+    fn send(&mut self, message: &Message) -> Result<()> {
+        self.inner.send(message)?;
+        self.record(Direction::Sent, message)
+    }
+
+    fn send_batch(&mut self, messages: &[Message]) -> Result<()> {
+        self.inner.send_batch(messages)?;
+        for message in messages {
+            self.record(Direction::Sent, message)?;
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Message>> {
+        let message = self.inner.receive()?;
+        if let Some(message) = &message {
+            self.record(Direction::Received, message)?;
+        }
+        Ok(message)
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        let message = self.inner.receive_timeout(timeout)?;
+        if let Some(message) = &message {
+            self.record(Direction::Received, message)?;
+        }
+        Ok(message)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.inner.flush()
+    }
+
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        self.inner.supports_compression(compression)
+    }
+
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.inner.set_compression(compression)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.inner.close()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+/// Read every record from a log written by [`Recorder`].
+///
+/// A truncated final record (e.g. from a crashed process) ends the recording
+/// instead of failing it.
+pub fn read_recording<R: Read>(mut reader: R, format: BinaryFormat) -> Result<Vec<RecordedMessage>> {
+    let serializer = BinarySerializer::new(format);
+    let mut records = Vec::new();
+
+    loop {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+
+        // The length comes from the file: check it, and read through `take` so
+        // a truncated record never allocates more than is actually there.
+        let len = u32::from_le_bytes(len) as usize;
+        if len > serializer.limits().max_message_bytes + 9 {
+            return Err(LinkError::InvalidMessage(format!("Recording entry of {} bytes is too large", len)));
+        }
+
+        let mut record = Vec::new();
+        reader.by_ref().take(len as u64).read_to_end(&mut record)?;
+        if record.len() < len {
+            break;
+        }
+
+        if record.len() < 9 {
+            return Err(LinkError::InvalidMessage("Recording entry too short".to_string()));
+        }
+
+        let direction = match record[0] {
+            0 => Direction::Sent,
+            1 => Direction::Received,
+            other => return Err(LinkError::InvalidMessage(format!("Unknown recording direction {}", other))),
+        };
+
+        let mut timestamp = [0u8; 8];
+        timestamp.copy_from_slice(&record[1..9]);
+
+        records.push(RecordedMessage {
+            direction,
+            timestamp: u64::from_le_bytes(timestamp),
+            message: serializer.deserialize_message(&record[9..])?,
+        });
+    }
+
+    Ok(records)
+}
+
+/// A transport that plays back a recording.
+///
+/// `receive` yields the recorded messages of one direction (received ones by
+/// default) in their original order, so a `SyncManager` re-processes the
+/// captured stream exactly. Anything sent through it is discarded.
+pub struct ReplayTransport {
+    records: VecDeque<RecordedMessage>,
+    direction: Direction,
+    message_types: Option<Vec<MessageType>>,
+    connected: bool,
+}
+
+impl ReplayTransport {
+    pub fn new(records: Vec<RecordedMessage>) -> Self {
+        Self {
+            records: records.into(),
+            direction: Direction::Received,
+            message_types: None,
+            connected: true,
+        }
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P, format: BinaryFormat) -> Result<Self> {
+        let file = File::open(path)?;
+        Ok(Self::new(read_recording(BufReader::new(file), format)?))
+    }
+
+    /// Replay the messages recorded in `direction` instead of the received ones.
+    pub fn with_direction(mut self, direction: Direction) -> Self {
+        self.direction = direction;
+        self
+    }
+
+    /// Only replay messages of the given types.
+    pub fn with_message_types(mut self, message_types: &[MessageType]) -> Self {
+        self.message_types = Some(message_types.to_vec());
+        self
+    }
+
+    /// Number of recorded messages not yet replayed, before filtering.
+    pub fn remaining(&self) -> usize {
+        self.records.len()
+    }
+
+    fn matches(&self, record: &RecordedMessage) -> bool {
+        record.direction == self.direction
+            && self.message_types.as_ref()
+                .is_none_or(|types| types.contains(&record.message.header.msg_type))
+    }
+}
+
+impl Transport for ReplayTransport {
+    fn send(&mut self, _message: &Message) -> Result<()> {
+        if !self.connected {
+            return Err(LinkError::ConnectionClosed);
+        }
+        Ok(())
+    }
+
+    fn receive(&mut self) -> Result<Option<Message>> {
+        if !self.connected {
+            return Err(LinkError::ConnectionClosed);
+        }
+
+        while let Some(record) = self.records.pop_front() {
+            if self.matches(&record) {
+                return Ok(Some(record.message));
+            }
+        }
+
+        Ok(None)
+    }
+
+    // Recorded messages are already decoded, so any negotiated compression is fine.
+    fn supports_compression(&self, _compression: CompressionType) -> bool {
+        true
+    }
+
+    fn set_compression(&mut self, _compression: CompressionType) -> Result<()> {
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.connected = false;
+        self.records.clear();
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.connected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::SchemaVersion;
+    use crate::serialization::WorldSnapshot;
+    use crate::sync::{SyncConfig, SyncManager};
+    use crate::transport::MemoryTransport;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_record_and_replay() {
+        let format = BinaryFormat::MessagePack;
+        let mut sender = MemoryTransport::new(format);
+        sender.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        sender.send(&Message::snapshot(vec![], 1.0, SchemaVersion::new(1))).unwrap();
+        sender.send(&Message::pong(SchemaVersion::new(1))).unwrap();
+
+        let mut inner = MemoryTransport::new(format);
+        sender.connect_to(&mut inner);
+
+        let mut recorder = Recorder::new(inner, Vec::new(), format);
+        recorder.send(&Message::request_snapshot(SchemaVersion::new(1))).unwrap();
+        while recorder.receive().unwrap().is_some() {}
+        let (_, log) = recorder.into_parts().unwrap();
+
+        let records = read_recording(log.as_slice(), format).unwrap();
+        assert_eq!(records.len(), 4);
+        assert_eq!(records[0].direction, Direction::Sent);
+        assert_eq!(records[0].message.header.msg_type, MessageType::RequestSnapshot);
+
+        let mut replay = ReplayTransport::new(records.clone());
+        let types: Vec<_> = std::iter::from_fn(|| replay.receive().unwrap())
+            .map(|m| m.header.msg_type)
+            .collect();
+        assert_eq!(types, vec![MessageType::Ping, MessageType::Snapshot, MessageType::Pong]);
+
+        let mut filtered = ReplayTransport::new(records).with_message_types(&[MessageType::Snapshot]);
+        assert_eq!(filtered.receive().unwrap().unwrap().header.msg_type, MessageType::Snapshot);
+        assert!(filtered.receive().unwrap().is_none());
+
+        let mut truncated = log.clone();
+        truncated.truncate(log.len() - 3);
+        assert_eq!(read_recording(truncated.as_slice(), format).unwrap().len(), 3);
+    }
+
+    /// A log writer that only counts its flushes.
+    struct FlushCounter(Arc<AtomicUsize>);
+
+    impl Write for FlushCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_recorder_flush() {
+        let format = BinaryFormat::MessagePack;
+        let flushes = Arc::new(AtomicUsize::new(0));
+        let mut recorder = Recorder::new(MemoryTransport::new(format), FlushCounter(flushes.clone()), format);
+
+        recorder.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        assert_eq!(flushes.load(Ordering::Relaxed), 0);
+        recorder.flush().unwrap();
+        assert_eq!(flushes.load(Ordering::Relaxed), 1);
+
+        // Batches are flushed once they've been handed to the transport.
+        let config = SyncConfig::new().with_rate_limiting(false);
+        let mut manager = SyncManager::try_new(recorder, config).unwrap();
+        let snapshot = WorldSnapshot { entities: vec![], timestamp: 1.0, version: "1.0.0".to_string() };
+        manager.send_batch(vec![snapshot]).unwrap();
+        assert_eq!(flushes.load(Ordering::Relaxed), 2);
+        assert_eq!(manager.get_transport().get_inner().get_send_buffer().len(), 2);
+    }
+
+    #[test]
+    fn test_oversized_record_is_rejected() {
+        let forged = u32::MAX.to_le_bytes();
+        assert!(matches!(
+            read_recording(forged.as_slice(), BinaryFormat::MessagePack),
+            Err(LinkError::InvalidMessage(_))
+        ));
+    }
+}
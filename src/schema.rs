@@ -1,17 +1,28 @@
+use crate::compression::{compress_component, unescape_json_key};
 use crate::error::{LinkError, Result};
-use crate::protocol::{ComponentId, FieldId, FieldType};
+use crate::protocol::{
+    ComponentData, ComponentId, CompressionType, ComponentSchemaInfo, DeltaChange, FieldDelta, FieldId, FieldSchemaInfo,
+    FieldType, FieldValue, IndexedFieldDelta, SerializedEntity,
+};
+pub use crate::protocol::SchemaVersion;
 use ahash::AHashMap;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::sync::{Arc, RwLock};
 
-pub type SchemaVersion = u32;
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ComponentSchema {
     pub component_id: ComponentId,
     pub version: SchemaVersion,
     pub fields: Vec<FieldSchema>,
     pub description: Option<String>,
+    /// Compress this component's data on its own when sending; see
+    /// [`SchemaRegistry::compress_entities`].
+    #[serde(default)]
+    pub compression_hint: Option<CompressionType>,
+    /// Send `F64` values as `F32`; see [`SchemaRegistry::reduce_precision_entities`].
+    #[serde(default)]
+    pub low_precision: bool,
 }
 
 impl ComponentSchema {
@@ -21,6 +32,8 @@ impl ComponentSchema {
             version,
             fields: Vec::new(),
             description: None,
+            compression_hint: None,
+            low_precision: false,
         }
     }
 
@@ -34,6 +47,26 @@ impl ComponentSchema {
         self
     }
 
+    /// Mark the component as worth compressing on its own, e.g. mesh data.
+    pub fn with_compression_hint(mut self, compression: CompressionType) -> Self {
+        self.compression_hint = Some(compression);
+        self
+    }
+
+    /// Mark the component as fine with `f32` precision, e.g. positions of
+    /// distant objects. Its `F64` fields then accept `F32` values.
+    pub fn with_low_precision(mut self) -> Self {
+        self.low_precision = true;
+        self
+    }
+
+    /// Whether a value of `field_type` fits `field_schema`, counting `F32`
+    /// for `F64` when the component is low precision.
+    fn accepts(&self, field_schema: &FieldSchema, field_type: FieldType) -> bool {
+        field_type == field_schema.field_type
+            || (self.low_precision && field_type == FieldType::F32 && field_schema.field_type == FieldType::F64)
+    }
+
     pub fn get_field(&self, field_id: &str) -> Option<&FieldSchema> {
         self.fields.iter().find(|f| f.field_id == field_id)
     }
@@ -45,15 +78,156 @@ impl ComponentSchema {
             false
         }
     }
+
+    /// Compare this schema against a newer one field by field.
+    ///
+    /// Adding optional fields, dropping optional fields, or relaxing a required
+    /// field to optional keeps old readers working (`Backward`). Removing a
+    /// required field, adding a required field, making an optional field
+    /// required, or changing a field's type is `Breaking`.
+    pub fn check_compatibility(&self, new: &ComponentSchema) -> Compatibility {
+        let mut breaking = Vec::new();
+        let mut changed = false;
+
+        for old_field in &self.fields {
+            match new.get_field(&old_field.field_id) {
+                Some(new_field) => {
+                    if new_field.field_type != old_field.field_type {
+                        breaking.push(format!(
+                            "Field '{}' changed type from {:?} to {:?}",
+                            old_field.field_id, old_field.field_type, new_field.field_type
+                        ));
+                    } else if old_field.optional && !new_field.optional {
+                        breaking.push(format!("Field '{}' changed from optional to required", old_field.field_id));
+                    } else if old_field.optional != new_field.optional {
+                        changed = true;
+                    }
+                }
+                None if old_field.optional => changed = true,
+                None => {
+                    breaking.push(format!("Required field '{}' was removed", old_field.field_id));
+                }
+            }
+        }
+
+        for new_field in &new.fields {
+            if self.get_field(&new_field.field_id).is_none() {
+                if new_field.optional {
+                    changed = true;
+                } else {
+                    breaking.push(format!("Required field '{}' was added", new_field.field_id));
+                }
+            }
+        }
+
+        if !breaking.is_empty() {
+            Compatibility::Breaking(breaking)
+        } else if changed {
+            Compatibility::Backward
+        } else {
+            Compatibility::Full
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Compatibility {
+    /// The schemas describe the same fields.
+    Full,
+    /// The new schema can still be read by peers using the old one.
+    Backward,
+    /// The new schema breaks old peers; each entry describes one breaking change.
+    Breaking(Vec<String>),
+}
+
+impl Compatibility {
+    pub fn is_compatible(&self) -> bool {
+        !matches!(self, Compatibility::Breaking(_))
+    }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct FieldSchema {
     pub field_id: FieldId,
     pub field_type: FieldType,
     pub optional: bool,
     pub default_value: Option<String>,
     pub description: Option<String>,
+    #[serde(default)]
+    pub constraints: FieldConstraints,
+}
+
+/// Limits on a field's value, checked by [`SchemaValidator`] on top of its
+/// type. Each applies only to values it makes sense for: `min`/`max` to
+/// numbers, `max_len` to strings (in chars), bytes, arrays and maps,
+/// `allowed_values` to strings, `allowed_discriminants` to enums. `Null`
+/// passes them all.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FieldConstraints {
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub max_len: Option<usize>,
+    pub allowed_values: Option<Vec<String>>,
+    #[serde(default)]
+    pub allowed_discriminants: Option<Vec<u32>>,
+}
+
+impl FieldConstraints {
+    /// Describes the first constraint `value` violates.
+    pub fn check(&self, value: &FieldValue) -> std::result::Result<(), String> {
+        if let Some(number) = numeric_value(value) {
+            // NaN is outside any range.
+            if let Some(min) = self.min.filter(|&min| number.is_nan() || number < min) {
+                return Err(format!("min {} (got {})", min, number));
+            }
+            if let Some(max) = self.max.filter(|&max| number.is_nan() || number > max) {
+                return Err(format!("max {} (got {})", max, number));
+            }
+        }
+
+        let len = match value {
+            FieldValue::String(s) => Some(s.chars().count()),
+            FieldValue::Bytes(bytes) => Some(bytes.len()),
+            FieldValue::Array(items) => Some(items.len()),
+            FieldValue::Map(map) => Some(map.len()),
+            _ => None,
+        };
+        if let (Some(max_len), Some(len)) = (self.max_len, len) {
+            if len > max_len {
+                return Err(format!("max_len {} (got {})", max_len, len));
+            }
+        }
+
+        if let (Some(allowed), FieldValue::String(s)) = (&self.allowed_values, value) {
+            if !allowed.contains(s) {
+                return Err(format!("allowed_values (got {:?})", s));
+            }
+        }
+
+        if let (Some(allowed), FieldValue::Enum { discriminant, .. }) = (&self.allowed_discriminants, value) {
+            if !allowed.contains(discriminant) {
+                return Err(format!("allowed_discriminants (got {})", discriminant));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+fn numeric_value(value: &FieldValue) -> Option<f64> {
+    match *value {
+        FieldValue::U8(v) => Some(v.into()),
+        FieldValue::U16(v) => Some(v.into()),
+        FieldValue::U32(v) => Some(v.into()),
+        FieldValue::U64(v) => Some(v as f64),
+        FieldValue::I8(v) => Some(v.into()),
+        FieldValue::I16(v) => Some(v.into()),
+        FieldValue::I32(v) => Some(v.into()),
+        FieldValue::I64(v) => Some(v as f64),
+        FieldValue::F32(v) => Some(v.into()),
+        FieldValue::F64(v) => Some(v),
+        _ => None,
+    }
 }
 
 impl FieldSchema {
@@ -64,6 +238,7 @@ impl FieldSchema {
             optional: false,
             default_value: None,
             description: None,
+            constraints: FieldConstraints::default(),
         }
     }
 
@@ -72,6 +247,38 @@ impl FieldSchema {
         self
     }
 
+    pub fn with_min(mut self, min: f64) -> Self {
+        self.constraints.min = Some(min);
+        self
+    }
+
+    pub fn with_max(mut self, max: f64) -> Self {
+        self.constraints.max = Some(max);
+        self
+    }
+
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.constraints.max_len = Some(max_len);
+        self
+    }
+
+    pub fn with_allowed_values<S: Into<String>>(mut self, values: impl IntoIterator<Item = S>) -> Self {
+        self.constraints.allowed_values = Some(values.into_iter().map(Into::into).collect());
+        self
+    }
+
+    pub fn with_allowed_discriminants(mut self, discriminants: impl IntoIterator<Item = u32>) -> Self {
+        self.constraints.allowed_discriminants = Some(discriminants.into_iter().collect());
+        self
+    }
+
+    /// Check `value` against the field's constraints.
+    fn check_constraints(&self, component_id: &str, value: &FieldValue) -> Result<()> {
+        self.constraints.check(value).map_err(|violation| LinkError::InvalidMessage(
+            format!("Field '{}' in component '{}' violates {}", self.field_id, component_id, violation)
+        ))
+    }
+
     pub fn with_default(mut self, default: String) -> Self {
         self.default_value = Some(default);
         self
@@ -83,9 +290,26 @@ impl FieldSchema {
     }
 }
 
+impl From<&ComponentSchema> for ComponentSchemaInfo {
+    fn from(schema: &ComponentSchema) -> Self {
+        Self {
+            component_id: schema.component_id.clone(),
+            version: schema.version,
+            low_precision: schema.low_precision,
+            fields: schema.fields.iter()
+                .map(|f| FieldSchemaInfo {
+                    field_id: f.field_id.clone(),
+                    field_type: f.field_type,
+                    optional: f.optional,
+                })
+                .collect(),
+        }
+    }
+}
+
 pub struct SchemaRegistry {
     schemas: Arc<RwLock<AHashMap<ComponentId, ComponentSchema>>>,
-    version_history: Arc<RwLock<AHashMap<ComponentId, Vec<SchemaVersion>>>>,
+    versions: Arc<RwLock<AHashMap<(ComponentId, SchemaVersion), ComponentSchema>>>,
     current_version: SchemaVersion,
 }
 
@@ -93,8 +317,8 @@ impl SchemaRegistry {
     pub fn new() -> Self {
         Self {
             schemas: Arc::new(RwLock::new(AHashMap::new())),
-            version_history: Arc::new(RwLock::new(AHashMap::new())),
-            current_version: 1,
+            versions: Arc::new(RwLock::new(AHashMap::new())),
+            current_version: SchemaVersion::INITIAL,
         }
     }
 
@@ -102,7 +326,7 @@ impl SchemaRegistry {
         let mut schemas = self.schemas.write()
             .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
 
-        let mut version_history = self.version_history.write()
+        let mut versions = self.versions.write()
             .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
 
         let component_id = schema.component_id.clone();
@@ -116,10 +340,7 @@ impl SchemaRegistry {
             }
         }
 
-        version_history.entry(component_id.clone())
-            .or_insert_with(Vec::new)
-            .push(version);
-
+        versions.insert((component_id.clone(), version), schema.clone());
         schemas.insert(component_id, schema);
 
         Ok(())
@@ -135,16 +356,19 @@ impl SchemaRegistry {
     }
 
     pub fn get_version(&self, component_id: &str, version: SchemaVersion) -> Result<ComponentSchema> {
-        let schema = self.get(component_id)?;
+        let versions = self.versions.read()
+            .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
 
-        if schema.version == version {
-            Ok(schema)
-        } else {
-            Err(LinkError::SchemaMismatch {
-                expected: version.to_string(),
-                actual: schema.version.to_string(),
-            })
+        if let Some(schema) = versions.get(&(component_id.to_string(), version)) {
+            return Ok(schema.clone());
         }
+
+        let latest = self.get(component_id)?;
+
+        Err(LinkError::SchemaMismatch {
+            expected: version.to_string(),
+            actual: latest.version.to_string(),
+        })
     }
 
     pub fn has(&self, component_id: &str) -> bool {
@@ -161,27 +385,116 @@ impl SchemaRegistry {
     }
 
     pub fn get_version_history(&self, component_id: &str) -> Result<Vec<SchemaVersion>> {
-        let history = self.version_history.read()
+        Ok(self.get_schema_history(component_id)?
+            .into_iter()
+            .map(|schema| schema.version)
+            .collect())
+    }
+
+    /// Every registered version of a component's schema, oldest first.
+    pub fn get_schema_history(&self, component_id: &str) -> Result<Vec<ComponentSchema>> {
+        let versions = self.versions.read()
             .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
 
-        Ok(history.get(component_id)
-            .cloned()
-            .unwrap_or_default())
+        let mut history: Vec<ComponentSchema> = versions.iter()
+            .filter(|((id, _), _)| id == component_id)
+            .map(|(_, schema)| schema.clone())
+            .collect();
+        history.sort_by_key(|schema| schema.version);
+
+        Ok(history)
+    }
+
+    /// Every registered version of every schema, ordered by component id and
+    /// then version, for loading elsewhere with [`Self::import`].
+    pub fn export(&self) -> Result<Vec<ComponentSchema>> {
+        let versions = self.versions.read()
+            .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
+
+        let mut schemas: Vec<ComponentSchema> = versions.values().cloned().collect();
+        schemas.sort_by(|a, b| (&a.component_id, a.version).cmp(&(&b.component_id, b.version)));
+
+        Ok(schemas)
+    }
+
+    /// Add exported schemas, in any order. Versions that are already
+    /// registered with the same definition are skipped; the newest version of
+    /// each component becomes its current schema.
+    ///
+    /// Fails without importing anything if a version is already registered,
+    /// or listed twice, with a different definition.
+    pub fn import(&self, schemas: Vec<ComponentSchema>) -> Result<()> {
+        let mut current = self.schemas.write()
+            .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
+
+        let mut versions = self.versions.write()
+            .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
+
+        let mut incoming: AHashMap<(ComponentId, SchemaVersion), ComponentSchema> = AHashMap::new();
+        for schema in schemas {
+            let key = (schema.component_id.clone(), schema.version);
+            let existing = versions.get(&key).or_else(|| incoming.get(&key));
+            if existing.is_some_and(|existing| *existing != schema) {
+                return Err(LinkError::SchemaMismatch {
+                    expected: format!("{} v{} as registered", key.0, key.1),
+                    actual: "a different definition".to_string(),
+                });
+            }
+            incoming.insert(key, schema);
+        }
+
+        for (key, schema) in incoming {
+            let newer = current.get(&key.0).is_none_or(|latest| latest.version < key.1);
+            if newer {
+                current.insert(key.0.clone(), schema.clone());
+            }
+            versions.insert(key, schema);
+        }
+
+        Ok(())
+    }
+
+    /// [`Self::export`] as pretty-printed JSON, e.g. for a canonical schema file.
+    pub fn to_json(&self) -> Result<String> {
+        Ok(serde_json::to_string_pretty(&self.export()?)?)
     }
 
-    pub fn validate_compatibility(&self, old_version: SchemaVersion, new_version: SchemaVersion) -> bool {
-        new_version >= old_version
+    /// A registry holding the schemas in `json`, as written by [`Self::to_json`].
+    pub fn from_json(json: &str) -> Result<Self> {
+        let registry = Self::new();
+        registry.import(serde_json::from_str(json)?)?;
+        Ok(registry)
+    }
+
+    pub fn validate_compatibility(
+        &self,
+        component_id: &str,
+        old_version: SchemaVersion,
+        new_version: SchemaVersion,
+    ) -> Result<Compatibility> {
+        let versions = self.versions.read()
+            .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
+
+        let lookup = |version: SchemaVersion| {
+            versions.get(&(component_id.to_string(), version))
+                .ok_or_else(|| LinkError::SchemaNotFound(format!("{} v{}", component_id, version)))
+        };
+
+        let old_schema = lookup(old_version)?;
+        let new_schema = lookup(new_version)?;
+
+        Ok(old_schema.check_compatibility(new_schema))
     }
 
     pub fn clear(&self) -> Result<()> {
         let mut schemas = self.schemas.write()
             .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
 
-        let mut version_history = self.version_history.write()
+        let mut versions = self.versions.write()
             .map_err(|e| LinkError::Unknown(format!("Lock poisoned: {}", e)))?;
 
         schemas.clear();
-        version_history.clear();
+        versions.clear();
 
         Ok(())
     }
@@ -193,6 +506,307 @@ impl SchemaRegistry {
     pub fn set_current_version(&mut self, version: SchemaVersion) {
         self.current_version = version;
     }
+
+    /// Rewrite `FieldsUpdated` changes to registered components as
+    /// `IndexedFieldsUpdated`, dropping the field names and old values. Changes
+    /// whose fields aren't all top-level schema fields, or that remove a field,
+    /// are left as they are.
+    pub fn compact_changes(&self, changes: Vec<DeltaChange>) -> Vec<DeltaChange> {
+        let mut schemas: AHashMap<ComponentId, Option<ComponentSchema>> = AHashMap::new();
+
+        changes.into_iter().map(|change| {
+            let DeltaChange::FieldsUpdated { entity_id, component_id, fields } = change else {
+                return change;
+            };
+
+            let schema = schemas.entry(component_id.clone())
+                .or_insert_with(|| self.get(&component_id).ok());
+
+            let indexed: Option<Vec<IndexedFieldDelta>> = schema.as_ref().and_then(|schema| {
+                fields.iter().map(|field| {
+                    if field.removed {
+                        return None;
+                    }
+                    let index = schema.fields.iter().position(|f| f.field_id == field.field_id)?;
+                    Some(IndexedFieldDelta {
+                        index: u16::try_from(index).ok()?,
+                        value: field.new_value.clone(),
+                    })
+                }).collect()
+            });
+
+            match (schema, indexed) {
+                (Some(schema), Some(indexed)) => DeltaChange::IndexedFieldsUpdated {
+                    entity_id,
+                    component_id,
+                    schema_version: schema.version,
+                    fields: indexed,
+                },
+                _ => DeltaChange::FieldsUpdated { entity_id, component_id, fields },
+            }
+        }).collect()
+    }
+
+    /// Turn `IndexedFieldsUpdated` changes back into `FieldsUpdated` (without
+    /// old values) using the schema version they were compacted with.
+    pub fn expand_changes(&self, changes: Vec<DeltaChange>) -> Result<Vec<DeltaChange>> {
+        let mut schemas: AHashMap<(ComponentId, SchemaVersion), ComponentSchema> = AHashMap::new();
+
+        changes.into_iter().map(|change| {
+            let DeltaChange::IndexedFieldsUpdated { entity_id, component_id, schema_version, fields } = change else {
+                return Ok(change);
+            };
+
+            let key = (component_id.clone(), schema_version);
+            if !schemas.contains_key(&key) {
+                schemas.insert(key.clone(), self.get_version(&component_id, schema_version)?);
+            }
+            let schema = &schemas[&key];
+
+            let fields = fields.into_iter().map(|field| {
+                let field_schema = schema.fields.get(field.index as usize).ok_or_else(|| {
+                    LinkError::InvalidMessage(format!(
+                        "Field index {} out of range for {} v{}",
+                        field.index, component_id, schema_version
+                    ))
+                })?;
+
+                Ok(FieldDelta::new(field_schema.field_id.clone(), None, field.value))
+            }).collect::<Result<Vec<_>>>()?;
+
+            Ok(DeltaChange::FieldsUpdated { entity_id, component_id, fields })
+        }).collect()
+    }
+
+    /// Compress the data of components whose schema has a compression hint,
+    /// where that makes it smaller (see [`crate::compression::compress_component`]).
+    /// The result is self-describing: the peer restores it with
+    /// [`crate::compression::decompress_entities`] without needing the hints.
+    pub fn compress_entities(&self, entities: &mut [SerializedEntity]) -> Result<()> {
+        let mut schemas = AHashMap::new();
+
+        for component in entities.iter_mut().flat_map(|e| e.components.iter_mut()) {
+            if let Some(hint) = self.cached(&mut schemas, &component.id).and_then(|s| s.compression_hint) {
+                component.data = compress_component(std::mem::replace(&mut component.data, ComponentData::Empty), hint)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// [`Self::compress_entities`] for the data carried by delta changes. Only
+    /// whole-component data is compressed; field-level updates are left as
+    /// they are, so field deltas keep sending just the changed fields.
+    pub fn compress_changes(&self, changes: &mut [DeltaChange]) -> Result<()> {
+        let mut schemas = AHashMap::new();
+
+        for change in changes {
+            if let DeltaChange::ComponentAdded { component_id, data, .. }
+            | DeltaChange::ComponentUpdated { component_id, data, .. } = change {
+                if let Some(hint) = self.cached(&mut schemas, component_id).and_then(|s| s.compression_hint) {
+                    *data = compress_component(std::mem::replace(data, ComponentData::Empty), hint)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Downcast every `F64` value of components whose schema is marked
+    /// [low precision](ComponentSchema::with_low_precision) to `F32`, including
+    /// values nested in arrays, maps and enums. This is lossy: the peer gets
+    /// `F32` values, and widening them back (e.g. with numeric coercion) does
+    /// not restore the original `f64`. Finite values beyond the `f32` range
+    /// saturate to `f32::MIN`/`f32::MAX` rather than becoming infinite. JSON
+    /// and binary data are left alone.
+    pub fn reduce_precision_entities(&self, entities: &mut [SerializedEntity]) {
+        let mut schemas = AHashMap::new();
+
+        for component in entities.iter_mut().flat_map(|e| e.components.iter_mut()) {
+            if self.cached(&mut schemas, &component.id).is_some_and(|s| s.low_precision) {
+                reduce_data(&mut component.data);
+            }
+        }
+    }
+
+    /// [`Self::reduce_precision_entities`] for the values carried by delta changes.
+    pub fn reduce_precision_changes(&self, changes: &mut [DeltaChange]) {
+        let mut schemas = AHashMap::new();
+
+        for change in changes {
+            match change {
+                DeltaChange::ComponentAdded { component_id, data, .. }
+                | DeltaChange::ComponentUpdated { component_id, data, .. } => {
+                    if self.cached(&mut schemas, component_id).is_some_and(|s| s.low_precision) {
+                        reduce_data(data);
+                    }
+                }
+                DeltaChange::FieldsUpdated { component_id, fields, .. } => {
+                    if self.cached(&mut schemas, component_id).is_some_and(|s| s.low_precision) {
+                        for field in fields {
+                            reduce_value(&mut field.new_value);
+                            if let Some(old_value) = &mut field.old_value {
+                                reduce_value(old_value);
+                            }
+                        }
+                    }
+                }
+                DeltaChange::IndexedFieldsUpdated { component_id, fields, .. } => {
+                    if self.cached(&mut schemas, component_id).is_some_and(|s| s.low_precision) {
+                        for field in fields {
+                            reduce_value(&mut field.value);
+                        }
+                    }
+                }
+                DeltaChange::EntityComponentsReplaced { components, .. } => {
+                    for component in components {
+                        if self.cached(&mut schemas, &component.id).is_some_and(|s| s.low_precision) {
+                            reduce_data(&mut component.data);
+                        }
+                    }
+                }
+                DeltaChange::EntityAdded { .. }
+                | DeltaChange::EntityRemoved { .. }
+                | DeltaChange::ComponentRemoved { .. } => {}
+            }
+        }
+    }
+
+    /// Coerce numeric fields of registered components to their schema types
+    /// with [`FieldValue::coerce_to`], so values that drifted to a wider type
+    /// through JSON compare equal again. Unregistered components, JSON data
+    /// and field paths that aren't schema fields are left alone.
+    pub fn normalize_entities(&self, entities: &mut [SerializedEntity]) -> Result<()> {
+        let mut schemas = AHashMap::new();
+
+        for entity in entities {
+            for component in &mut entity.components {
+                if let Some(schema) = self.cached(&mut schemas, &component.id) {
+                    normalize_data(schema, &mut component.data)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// [`Self::normalize_entities`] for the values carried by delta changes.
+    pub fn normalize_changes(&self, changes: &mut [DeltaChange]) -> Result<()> {
+        let mut schemas = AHashMap::new();
+
+        for change in changes {
+            match change {
+                DeltaChange::ComponentAdded { component_id, data, .. }
+                | DeltaChange::ComponentUpdated { component_id, data, .. } => {
+                    if let Some(schema) = self.cached(&mut schemas, component_id) {
+                        normalize_data(schema, data)?;
+                    }
+                }
+                DeltaChange::FieldsUpdated { component_id, fields, .. } => {
+                    if let Some(schema) = self.cached(&mut schemas, component_id) {
+                        for field in fields {
+                            coerce_field(schema, &field.field_id, &mut field.new_value)?;
+                            if let Some(old_value) = &mut field.old_value {
+                                coerce_field(schema, &field.field_id, old_value)?;
+                            }
+                        }
+                    }
+                }
+                DeltaChange::IndexedFieldsUpdated { component_id, schema_version, fields, .. } => {
+                    let schema = self.get_version(component_id, *schema_version)?;
+                    for field in fields {
+                        if let Some(field_schema) = schema.fields.get(field.index as usize) {
+                            coerce_field(&schema, &field_schema.field_id, &mut field.value)?;
+                        }
+                    }
+                }
+                DeltaChange::EntityComponentsReplaced { components, .. } => {
+                    for component in components {
+                        if let Some(schema) = self.cached(&mut schemas, &component.id) {
+                            normalize_data(schema, &mut component.data)?;
+                        }
+                    }
+                }
+                DeltaChange::EntityAdded { .. }
+                | DeltaChange::EntityRemoved { .. }
+                | DeltaChange::ComponentRemoved { .. } => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn cached<'a>(
+        &self,
+        schemas: &'a mut AHashMap<ComponentId, Option<ComponentSchema>>,
+        component_id: &str,
+    ) -> Option<&'a ComponentSchema> {
+        schemas.entry(component_id.to_string())
+            .or_insert_with(|| self.get(component_id).ok())
+            .as_ref()
+    }
+}
+
+fn normalize_data(schema: &ComponentSchema, data: &mut ComponentData) -> Result<()> {
+    if let ComponentData::Structured(values) = data {
+        for (field_id, value) in values.iter_mut() {
+            coerce_field(schema, field_id, value)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn reduce_data(data: &mut ComponentData) {
+    if let ComponentData::Structured(values) = data {
+        values.values_mut().for_each(reduce_value);
+    }
+}
+
+/// `v` as `f32`, keeping finite values finite.
+fn saturating_f32(v: f64) -> f32 {
+    if v.is_finite() {
+        v.clamp(f32::MIN as f64, f32::MAX as f64) as f32
+    } else {
+        v as f32
+    }
+}
+
+fn reduce_value(value: &mut FieldValue) {
+    match value {
+        FieldValue::F64(v) => *value = FieldValue::F32(saturating_f32(*v)),
+        FieldValue::Array(items) => items.iter_mut().for_each(reduce_value),
+        FieldValue::Map(entries) => entries.values_mut().for_each(reduce_value),
+        FieldValue::Enum { payload: Some(payload), .. } => reduce_value(payload),
+        _ => {}
+    }
+}
+
+/// Whether `field_id` is a path to a key inside one of the schema's `Map`
+/// fields, as produced by keyed map diffing.
+fn is_map_key(schema: &ComponentSchema, field_id: &str) -> bool {
+    let Some((head, _)) = field_id.split_once('.') else {
+        return false;
+    };
+    schema.get_field(&unescape_json_key(head))
+        .is_some_and(|field| field.field_type == FieldType::Map)
+}
+
+fn coerce_field(schema: &ComponentSchema, field_id: &str, value: &mut FieldValue) -> Result<()> {
+    let Some(field_schema) = schema.get_field(field_id) else {
+        return Ok(());
+    };
+
+    *value = std::mem::replace(value, FieldValue::Null)
+        .coerce_to(field_schema.field_type)
+        .map_err(|e| match e {
+            LinkError::InvalidMessage(reason) => LinkError::InvalidMessage(
+                format!("Field '{}' in component '{}': {}", field_id, schema.component_id, reason)
+            ),
+            other => other,
+        })?;
+
+    Ok(())
 }
 
 impl Default for SchemaRegistry {
@@ -205,35 +819,57 @@ impl Clone for SchemaRegistry {
     fn clone(&self) -> Self {
         Self {
             schemas: Arc::clone(&self.schemas),
-            version_history: Arc::clone(&self.version_history),
+            versions: Arc::clone(&self.versions),
             current_version: self.current_version,
         }
     }
 }
 
-pub struct SchemaValidator {
-    registry: SchemaRegistry,
+pub struct SchemaValidator<'a> {
+    registry: Cow<'a, SchemaRegistry>,
+    strict: bool,
 }
 
-impl SchemaValidator {
+impl SchemaValidator<'static> {
     pub fn new(registry: SchemaRegistry) -> Self {
-        Self { registry }
+        Self { registry: Cow::Owned(registry), strict: false }
+    }
+}
+
+impl<'a> SchemaValidator<'a> {
+    /// Validate against `registry` without taking a handle to it.
+    pub fn borrowed(registry: &'a SchemaRegistry) -> Self {
+        Self { registry: Cow::Borrowed(registry), strict: false }
+    }
+
+    /// Also reject fields the schema doesn't declare, including field updates
+    /// addressed by a dotted path into nested data. Off by default, so a peer
+    /// on a newer schema that added fields still validates.
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
     }
 
     pub fn validate_component(&self, component_id: &str, fields: &AHashMap<FieldId, FieldType>) -> Result<()> {
         let schema = self.registry.get(component_id)?;
 
-        for field_schema in &schema.fields {
-            if !field_schema.optional {
-                if !fields.contains_key(&field_schema.field_id) {
-                    return Err(LinkError::InvalidMessage(
-                        format!("Required field '{}' missing in component '{}'", field_schema.field_id, component_id)
-                    ));
+        if self.strict {
+            for field_id in fields.keys() {
+                if schema.get_field(field_id).is_none() {
+                    return Err(unknown_field(field_id, component_id));
                 }
             }
+        }
+
+        for field_schema in &schema.fields {
+            if !field_schema.optional && !fields.contains_key(&field_schema.field_id) {
+                return Err(LinkError::InvalidMessage(
+                    format!("Required field '{}' missing in component '{}'", field_schema.field_id, component_id)
+                ));
+            }
 
             if let Some(field_type) = fields.get(&field_schema.field_id) {
-                if field_type != &field_schema.field_type {
+                if !schema.accepts(field_schema, *field_type) {
                     return Err(LinkError::InvalidMessage(
                         format!("Field '{}' has wrong type in component '{}'", field_schema.field_id, component_id)
                     ));
@@ -244,11 +880,150 @@ impl SchemaValidator {
         Ok(())
     }
 
+    /// Validate component data against its registered schema. `Null` values count
+    /// as absent. JSON objects are checked for missing fields (and unknown ones
+    /// when strict) and field constraints but not types, binary data is opaque
+    /// and always passes.
+    pub fn validate_data(&self, component_id: &str, data: &ComponentData) -> Result<()> {
+        match data {
+            ComponentData::Structured(values) => {
+                let fields = values.iter()
+                    .filter(|(_, value)| **value != FieldValue::Null)
+                    .map(|(id, value)| (id.clone(), value.field_type()))
+                    .collect();
+                self.validate_component(component_id, &fields)?;
+
+                let schema = self.registry.get(component_id)?;
+                for (field_id, value) in values {
+                    if let Some(field_schema) = schema.get_field(field_id) {
+                        field_schema.check_constraints(component_id, value)?;
+                    }
+                }
+
+                Ok(())
+            }
+            ComponentData::Json(_) => {
+                let schema = self.registry.get(component_id)?;
+                let value = data.to_json_value();
+                let object = value.as_ref().and_then(|v| v.as_object()).ok_or_else(|| LinkError::InvalidMessage(
+                    format!("Component '{}' expects a JSON object", component_id)
+                ))?;
+
+                for (key, value) in object {
+                    match schema.get_field(key) {
+                        Some(field_schema) => {
+                            field_schema.check_constraints(component_id, &crate::compression::json_to_field_value(value))?;
+                        }
+                        None if self.strict => return Err(unknown_field(key, component_id)),
+                        None => {}
+                    }
+                }
+
+                for field_schema in &schema.fields {
+                    let present = object.get(&field_schema.field_id).is_some_and(|v| !v.is_null());
+                    if !field_schema.optional && !present {
+                        return Err(LinkError::InvalidMessage(
+                            format!("Required field '{}' missing in component '{}'", field_schema.field_id, component_id)
+                        ));
+                    }
+                }
+
+                Ok(())
+            }
+            ComponentData::Binary(_) => Ok(()),
+            ComponentData::Empty => self.validate_component(component_id, &AHashMap::new()),
+            ComponentData::Compressed { .. } => {
+                self.validate_data(component_id, &crate::compression::decompress_component(data.clone())?)
+            }
+        }
+    }
+
+    /// Validate individual field updates. Setting a required field to `Null` is
+    /// rejected. Updates to fields the schema doesn't declare pass unless strict.
+    pub fn validate_fields(&self, component_id: &str, fields: &[FieldDelta]) -> Result<()> {
+        let schema = self.registry.get(component_id)?;
+
+        for field in fields {
+            let Some(field_schema) = schema.get_field(&field.field_id) else {
+                // Map values aren't typed by the schema.
+                if !self.strict || is_map_key(&schema, &field.field_id) {
+                    continue;
+                }
+                return Err(unknown_field(&field.field_id, component_id));
+            };
+
+            let field_type = field.new_value.field_type();
+            let valid = if field_type == FieldType::Null {
+                field_schema.optional
+            } else {
+                schema.accepts(field_schema, field_type)
+            };
+
+            if !valid {
+                return Err(LinkError::InvalidMessage(
+                    format!("Field '{}' has wrong type in component '{}'", field.field_id, component_id)
+                ));
+            }
+
+            field_schema.check_constraints(component_id, &field.new_value)?;
+        }
+
+        Ok(())
+    }
+
+    /// [`Self::validate_fields`] for one field, passing fields of unregistered
+    /// components; e.g. the check for [`WorldSnapshot::apply_delta_partial`](crate::WorldSnapshot::apply_delta_partial).
+    pub fn check_field(&self, component_id: &str, field: &FieldDelta) -> Result<()> {
+        if !self.registry.has(component_id) {
+            return Ok(());
+        }
+        self.validate_fields(component_id, std::slice::from_ref(field))
+    }
+
+    /// Validate every component with a registered schema; unregistered components pass.
+    pub fn validate_entities(&self, entities: &[SerializedEntity]) -> Result<()> {
+        for entity in entities {
+            for component in &entity.components {
+                if self.registry.has(&component.id) {
+                    self.validate_data(&component.id, &component.data)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Validate the component payloads in a delta; unregistered components pass.
+    pub fn validate_changes(&self, changes: &[DeltaChange]) -> Result<()> {
+        for change in changes {
+            match change {
+                DeltaChange::ComponentAdded { component_id, data, .. }
+                | DeltaChange::ComponentUpdated { component_id, data, .. }
+                    if self.registry.has(component_id) =>
+                {
+                    self.validate_data(component_id, data)?;
+                }
+                DeltaChange::FieldsUpdated { component_id, fields, .. }
+                    if self.registry.has(component_id) =>
+                {
+                    self.validate_fields(component_id, fields)?;
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
     pub fn get_registry(&self) -> &SchemaRegistry {
         &self.registry
     }
 }
 
+fn unknown_field(field_id: &str, component_id: &str) -> LinkError {
+    LinkError::InvalidMessage(format!("Unknown field '{}' in component '{}'", field_id, component_id))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -257,7 +1032,7 @@ mod tests {
     fn test_schema_registry() {
         let registry = SchemaRegistry::new();
 
-        let schema = ComponentSchema::new("Position".to_string(), 1)
+        let schema = ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
             .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
             .with_field(FieldSchema::new("y".to_string(), FieldType::F64))
             .with_description("2D position component".to_string());
@@ -269,17 +1044,70 @@ mod tests {
         assert_eq!(retrieved.fields.len(), 2);
     }
 
+    #[test]
+    fn test_compact_field_deltas() {
+        let registry = SchemaRegistry::new();
+        registry.register(
+            ComponentSchema::new("Transform".to_string(), SchemaVersion::new(3))
+                .with_field(FieldSchema::new("x".to_string(), FieldType::F32))
+                .with_field(FieldSchema::new("y".to_string(), FieldType::F32))
+        ).unwrap();
+
+        let update = |component_id: &str, field_id: &str| DeltaChange::FieldsUpdated {
+            entity_id: 1,
+            component_id: component_id.to_string(),
+            fields: vec![FieldDelta::new(field_id.to_string(), Some(FieldValue::F32(0.0)), FieldValue::F32(1.0))],
+        };
+
+        let changes = vec![
+            update("Transform", "y"),
+            update("Transform", "w"),
+            update("Velocity", "y"),
+            DeltaChange::EntityRemoved { entity_id: 2 },
+        ];
+        let compacted = registry.compact_changes(changes);
+
+        match &compacted[0] {
+            DeltaChange::IndexedFieldsUpdated { schema_version, fields, .. } => {
+                assert_eq!(*schema_version, SchemaVersion::new(3));
+                assert_eq!(fields[0].index, 1);
+            }
+            other => panic!("expected indexed update, got {:?}", other),
+        }
+        assert!(matches!(compacted[1], DeltaChange::FieldsUpdated { .. }));
+        assert!(matches!(compacted[2], DeltaChange::FieldsUpdated { .. }));
+        assert!(matches!(compacted[3], DeltaChange::EntityRemoved { .. }));
+
+        let expanded = registry.expand_changes(compacted).unwrap();
+        match &expanded[0] {
+            DeltaChange::FieldsUpdated { fields, .. } => {
+                assert_eq!(fields[0].field_id, "y");
+                assert_eq!(fields[0].old_value, None);
+                assert_eq!(fields[0].new_value, FieldValue::F32(1.0));
+            }
+            other => panic!("expected field update, got {:?}", other),
+        }
+
+        let unknown_version = vec![DeltaChange::IndexedFieldsUpdated {
+            entity_id: 1,
+            component_id: "Transform".to_string(),
+            schema_version: SchemaVersion::new(4),
+            fields: vec![],
+        }];
+        assert!(registry.expand_changes(unknown_version).is_err());
+    }
+
     #[test]
     fn test_schema_versioning() {
         let registry = SchemaRegistry::new();
 
-        let schema_v1 = ComponentSchema::new("Position".to_string(), 1)
+        let schema_v1 = ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
             .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
             .with_field(FieldSchema::new("y".to_string(), FieldType::F64));
 
         registry.register(schema_v1).unwrap();
 
-        let schema_v2 = ComponentSchema::new("Position".to_string(), 2)
+        let schema_v2 = ComponentSchema::new("Position".to_string(), SchemaVersion::new(2))
             .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
             .with_field(FieldSchema::new("y".to_string(), FieldType::F64))
             .with_field(FieldSchema::new("z".to_string(), FieldType::F64).optional());
@@ -288,15 +1116,93 @@ mod tests {
 
         let history = registry.get_version_history("Position").unwrap();
         assert_eq!(history.len(), 2);
-        assert!(history.contains(&1));
-        assert!(history.contains(&2));
+        assert!(history.contains(&SchemaVersion::new(1)));
+        assert!(history.contains(&SchemaVersion::new(2)));
+
+        let old = registry.get_version("Position", SchemaVersion::new(1)).unwrap();
+        assert_eq!(old.version, SchemaVersion::new(1));
+        assert_eq!(old.fields.len(), 2);
+        assert_eq!(registry.get_version("Position", SchemaVersion::new(2)).unwrap().fields.len(), 3);
+        assert!(matches!(
+            registry.get_version("Position", SchemaVersion::new(3)),
+            Err(LinkError::SchemaMismatch { .. })
+        ));
+    }
+
+    #[test]
+    fn test_schema_export_import() {
+        let registry = SchemaRegistry::new();
+        let v1 = ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
+            .with_field(FieldSchema::new("x".to_string(), FieldType::F64));
+        let v2 = ComponentSchema::new("Position".to_string(), SchemaVersion::new(2))
+            .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
+            .with_field(FieldSchema::new("y".to_string(), FieldType::F64).optional());
+        registry.register(v1.clone()).unwrap();
+        registry.register(v2.clone()).unwrap();
+        registry.register(ComponentSchema::new("Health".to_string(), SchemaVersion::new(1))
+            .with_field(FieldSchema::new("hp".to_string(), FieldType::U32))).unwrap();
+
+        let json = registry.to_json().unwrap();
+        let loaded = SchemaRegistry::from_json(&json).unwrap();
+        assert_eq!(loaded.export().unwrap(), registry.export().unwrap());
+        assert_eq!(loaded.get("Position").unwrap(), v2);
+        assert_eq!(loaded.get_version_history("Position").unwrap(), vec![SchemaVersion::new(1), SchemaVersion::new(2)]);
+
+        // Re-importing the same set is a no-op.
+        loaded.import(registry.export().unwrap()).unwrap();
+
+        // An older version doesn't replace the current one.
+        let other = SchemaRegistry::new();
+        other.import(vec![v2.clone(), v1.clone()]).unwrap();
+        assert_eq!(other.get("Position").unwrap().version, SchemaVersion::new(2));
+
+        // A conflicting definition is rejected and nothing is imported.
+        let conflicting = ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
+            .with_field(FieldSchema::new("x".to_string(), FieldType::F32));
+        let velocity = ComponentSchema::new("Velocity".to_string(), SchemaVersion::new(1));
+        assert!(matches!(
+            loaded.import(vec![velocity, conflicting]),
+            Err(LinkError::SchemaMismatch { .. })
+        ));
+        assert!(!loaded.has("Velocity"));
+    }
+
+    #[test]
+    fn test_schema_compatibility() {
+        let registry = SchemaRegistry::new();
+
+        let v1 = ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
+            .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
+            .with_field(FieldSchema::new("y".to_string(), FieldType::F64));
+        let v2 = ComponentSchema::new("Position".to_string(), SchemaVersion::new(2))
+            .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
+            .with_field(FieldSchema::new("y".to_string(), FieldType::F64))
+            .with_field(FieldSchema::new("z".to_string(), FieldType::F64).optional());
+        let v3 = ComponentSchema::new("Position".to_string(), SchemaVersion::new(3))
+            .with_field(FieldSchema::new("x".to_string(), FieldType::F32))
+            .with_field(FieldSchema::new("z".to_string(), FieldType::F64).optional())
+            .with_field(FieldSchema::new("w".to_string(), FieldType::F64));
+
+        registry.register(v1.clone()).unwrap();
+        registry.register(v2).unwrap();
+        registry.register(v3).unwrap();
+
+        assert_eq!(v1.check_compatibility(&v1), Compatibility::Full);
+        assert_eq!(registry.validate_compatibility("Position", SchemaVersion::new(1), SchemaVersion::new(2)).unwrap(), Compatibility::Backward);
+
+        match registry.validate_compatibility("Position", SchemaVersion::new(2), SchemaVersion::new(3)).unwrap() {
+            Compatibility::Breaking(changes) => assert_eq!(changes.len(), 3),
+            other => panic!("expected breaking changes, got {:?}", other),
+        }
+
+        assert!(registry.validate_compatibility("Position", SchemaVersion::new(1), SchemaVersion::new(4)).is_err());
     }
 
     #[test]
     fn test_schema_validation() {
         let registry = SchemaRegistry::new();
 
-        let schema = ComponentSchema::new("Position".to_string(), 1)
+        let schema = ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
             .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
             .with_field(FieldSchema::new("y".to_string(), FieldType::F64));
 
@@ -315,4 +1221,146 @@ mod tests {
 
         assert!(validator.validate_component("Position", &invalid_fields).is_err());
     }
+
+    #[test]
+    fn test_validate_component_data() {
+        use std::collections::HashMap;
+
+        let registry = SchemaRegistry::new();
+        registry.register(
+            ComponentSchema::new("Health".to_string(), SchemaVersion::new(1))
+                .with_field(FieldSchema::new("current".to_string(), FieldType::U32))
+                .with_field(FieldSchema::new("shield".to_string(), FieldType::U32).optional())
+        ).unwrap();
+        let validator = SchemaValidator::borrowed(&registry);
+        let strict = SchemaValidator::borrowed(&registry).with_strict(true);
+
+        let structured = |fields: &[(&str, FieldValue)]| ComponentData::Structured(
+            fields.iter().map(|(k, v)| (k.to_string(), v.clone())).collect::<HashMap<_, _>>()
+        );
+
+        assert!(validator.validate_data("Health", &structured(&[("current", FieldValue::U32(1)), ("shield", FieldValue::Null)])).is_ok());
+        assert!(validator.validate_data("Health", &structured(&[("current", FieldValue::F32(1.0))])).is_err());
+        assert!(validator.validate_data("Health", &structured(&[("shield", FieldValue::U32(1))])).is_err());
+
+        // Unknown fields only fail strict validation.
+        let extra = structured(&[("current", FieldValue::U32(1)), ("mana", FieldValue::U32(1))]);
+        assert!(validator.validate_data("Health", &extra).is_ok());
+        assert!(strict.validate_data("Health", &extra).is_err());
+
+        let json = ComponentData::from_json_value(serde_json::json!({"current": 5, "armor": 2}));
+        assert!(validator.validate_data("Health", &json).is_ok());
+        assert!(matches!(strict.validate_data("Health", &json), Err(LinkError::InvalidMessage(_))));
+
+        let update = |field: &str, value: FieldValue| vec![FieldDelta::new(field.to_string(), None, value)];
+        assert!(validator.validate_fields("Health", &update("armor.plates", FieldValue::U32(2))).is_ok());
+        assert!(strict.validate_fields("Health", &update("armor.plates", FieldValue::U32(2))).is_err());
+        assert!(validator.validate_fields("Health", &update("shield", FieldValue::Null)).is_ok());
+        assert!(validator.validate_fields("Health", &update("current", FieldValue::Null)).is_err());
+        assert!(validator.validate_fields("Health", &update("current", FieldValue::String("x".to_string()))).is_err());
+    }
+
+    #[test]
+    fn test_field_constraints() {
+        use std::collections::HashMap;
+
+        let registry = SchemaRegistry::new();
+        registry.register(
+            ComponentSchema::new("Player".to_string(), SchemaVersion::new(1))
+                .with_field(FieldSchema::new("health".to_string(), FieldType::I32).with_min(0.0).with_max(100.0))
+                .with_field(FieldSchema::new("name".to_string(), FieldType::String).with_max_len(8).optional())
+                .with_field(FieldSchema::new("team".to_string(), FieldType::String).with_allowed_values(["red", "blue"]).optional())
+        ).unwrap();
+        let validator = SchemaValidator::new(registry);
+
+        let structured = |fields: &[(&str, FieldValue)]| ComponentData::Structured(
+            fields.iter().map(|(k, v)| (k.to_string(), v.clone())).collect::<HashMap<_, _>>()
+        );
+        let violation = |result: Result<()>| match result {
+            Err(LinkError::InvalidMessage(reason)) => reason,
+            other => panic!("expected a constraint violation, got {:?}", other),
+        };
+
+        assert!(validator.validate_data("Player", &structured(&[("health", FieldValue::I32(100)), ("team", FieldValue::String("red".to_string()))])).is_ok());
+        assert!(violation(validator.validate_data("Player", &structured(&[("health", FieldValue::I32(101))]))).contains("max 100"));
+        assert!(violation(validator.validate_data("Player", &structured(&[("health", FieldValue::I32(-1))]))).contains("min 0"));
+        assert!(violation(validator.validate_data("Player", &structured(&[("health", FieldValue::I32(5)), ("name", FieldValue::String("a".repeat(9)))]))).contains("max_len 8"));
+
+        let json = ComponentData::from_json_value(serde_json::json!({"health": 50, "team": "green"}));
+        assert!(violation(validator.validate_data("Player", &json)).contains("allowed_values"));
+
+        let update = vec![FieldDelta::new("health".to_string(), None, FieldValue::I32(250))];
+        assert!(violation(validator.validate_fields("Player", &update)).contains("'health'"));
+
+        let constraints = FieldConstraints { min: Some(0.0), ..Default::default() };
+        assert!(constraints.check(&FieldValue::F64(f64::NAN)).is_err());
+
+        let state = |discriminant| FieldValue::Enum { discriminant, payload: None };
+        let constraints = FieldConstraints { allowed_discriminants: Some(vec![0, 2]), ..Default::default() };
+        assert!(constraints.check(&state(2)).is_ok());
+        assert!(constraints.check(&state(1)).unwrap_err().contains("allowed_discriminants"));
+    }
+
+    #[test]
+    fn test_numeric_coercion() {
+        assert_eq!(FieldValue::I64(5).coerce_to(FieldType::U32).unwrap(), FieldValue::U32(5));
+        assert_eq!(FieldValue::U64(7).coerce_to(FieldType::I8).unwrap(), FieldValue::I8(7));
+        assert_eq!(FieldValue::F64(3.0).coerce_to(FieldType::U16).unwrap(), FieldValue::U16(3));
+        assert_eq!(FieldValue::I64(2).coerce_to(FieldType::F32).unwrap(), FieldValue::F32(2.0));
+        assert_eq!(FieldValue::F64(0.5).coerce_to(FieldType::F32).unwrap(), FieldValue::F32(0.5));
+        assert_eq!(FieldValue::Bool(true).coerce_to(FieldType::U8).unwrap(), FieldValue::Bool(true));
+        assert_eq!(FieldValue::I64(1).coerce_to(FieldType::String).unwrap(), FieldValue::I64(1));
+        assert!(FieldValue::I64(-1).coerce_to(FieldType::U32).is_err());
+        assert!(FieldValue::U64(300).coerce_to(FieldType::U8).is_err());
+        assert!(FieldValue::F64(1.5).coerce_to(FieldType::I32).is_err());
+        assert!(FieldValue::F64(1e30).coerce_to(FieldType::I64).is_err());
+        assert!(FieldValue::F64(1e300).coerce_to(FieldType::F32).is_err());
+        assert!(FieldValue::F64(-1e300).coerce_to(FieldType::F32).is_err());
+        assert_eq!(FieldValue::F64(f64::from(f32::MAX)).coerce_to(FieldType::F32).unwrap(), FieldValue::F32(f32::MAX));
+        assert_eq!(FieldValue::F64(f64::INFINITY).coerce_to(FieldType::F32).unwrap(), FieldValue::F32(f32::INFINITY));
+
+        let registry = SchemaRegistry::new();
+        registry.register(
+            ComponentSchema::new("Health".to_string(), SchemaVersion::new(1))
+                .with_field(FieldSchema::new("current".to_string(), FieldType::U32))
+        ).unwrap();
+
+        let mut changes = vec![
+            DeltaChange::ComponentUpdated {
+                entity_id: 1,
+                component_id: "Health".to_string(),
+                data: ComponentData::Structured([("current".to_string(), FieldValue::I64(5))].into_iter().collect()),
+            },
+            DeltaChange::FieldsUpdated {
+                entity_id: 1,
+                component_id: "Health".to_string(),
+                fields: vec![FieldDelta::new("current".to_string(), Some(FieldValue::U64(5)), FieldValue::I64(4))],
+            },
+            DeltaChange::FieldsUpdated {
+                entity_id: 1,
+                component_id: "Unregistered".to_string(),
+                fields: vec![FieldDelta::new("current".to_string(), None, FieldValue::I64(4))],
+            },
+        ];
+        registry.normalize_changes(&mut changes).unwrap();
+
+        let DeltaChange::ComponentUpdated { data: ComponentData::Structured(values), .. } = &changes[0] else {
+            unreachable!()
+        };
+        assert_eq!(values["current"], FieldValue::U32(5));
+        let DeltaChange::FieldsUpdated { fields, .. } = &changes[1] else { unreachable!() };
+        assert_eq!((&fields[0].old_value, &fields[0].new_value), (&Some(FieldValue::U32(5)), &FieldValue::U32(4)));
+        let DeltaChange::FieldsUpdated { fields, .. } = &changes[2] else { unreachable!() };
+        assert_eq!(fields[0].new_value, FieldValue::I64(4));
+
+        let mut negative = vec![SerializedEntity {
+            id: 1,
+            components: vec![crate::protocol::SerializedComponent {
+                id: "Health".to_string(),
+                data: ComponentData::Structured([("current".to_string(), FieldValue::I64(-3))].into_iter().collect()),
+            }],
+        }];
+        let error = registry.normalize_entities(&mut negative).unwrap_err().to_string();
+        assert!(error.contains("Field 'current' in component 'Health': -3 does not fit in U32"), "{}", error);
+    }
 }
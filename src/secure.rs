@@ -0,0 +1,309 @@
+use crate::error::{LinkError, Result};
+use crate::protocol::{CompressionType, Message, MessageHeader, MessagePayload};
+use crate::serialization::{BinaryFormat, BinarySerializer};
+use crate::transport::Transport;
+use bytes::Bytes;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::net::SocketAddr;
+use std::time::Duration;
+
+const NONCE_LEN: usize = 24;
+const HEADER_LEN: usize = 29;
+const REPLAY_WINDOW: u64 = 64;
+
+/// Wraps a transport so that messages cross it encrypted and authenticated
+/// with XChaCha20-Poly1305 under a pre-shared 256-bit key.
+///
+/// Each message is encoded with the wrapper's own serializer, sealed under a
+/// fresh random nonce and handed to the inner transport as a
+/// `MessagePayload::Encrypted` message. Received messages that aren't sealed,
+/// or fail authentication, are rejected with `LinkError::InvalidMessage`.
+///
+/// The outer header is authenticated as associated data, and its sequence
+/// number is the wrapper's own send counter. Messages whose sequence was
+/// already accepted, or is more than 64 behind the newest one, are rejected
+/// as replays; reordering within that window is tolerated.
+///
+/// Nonces are random, so both peers can use the same key. Compression is
+/// applied before encryption, by the wrapper's serializer.
+pub struct SecureTransport<T: Transport> {
+    inner: T,
+    cipher: XChaCha20Poly1305,
+    serializer: BinarySerializer,
+    send_sequence: u64,
+    replay: ReplayWindow,
+}
+
+/// The sequence numbers recently accepted from the peer: the highest one, and
+/// a bitmap of it and the 63 before it.
+#[derive(Debug)]
+struct ReplayWindow {
+    highest: u64,
+    seen: u64,
+}
+
+impl ReplayWindow {
+    fn new() -> Self {
+        // Sequence 0 is never sent, so it starts out as seen.
+        Self { highest: 0, seen: 1 }
+    }
+
+    /// Record `sequence`, returning false if it was already accepted or has
+    /// fallen out of the window.
+    fn accept(&mut self, sequence: u64) -> bool {
+        if sequence > self.highest {
+            let shift = sequence - self.highest;
+            self.seen = if shift >= REPLAY_WINDOW { 0 } else { self.seen << shift };
+            self.seen |= 1;
+            self.highest = sequence;
+            return true;
+        }
+
+        let age = self.highest - sequence;
+        if age >= REPLAY_WINDOW || self.seen & (1 << age) != 0 {
+            return false;
+        }
+        self.seen |= 1 << age;
+        true
+    }
+}
+
+/// The header fields of a sealed message, as bound into its authentication tag.
+fn associated_data(header: &MessageHeader) -> [u8; HEADER_LEN] {
+    let mut bytes = [0u8; HEADER_LEN];
+    bytes[0] = header.msg_type as u8;
+    bytes[1..9].copy_from_slice(&header.timestamp.to_le_bytes());
+    bytes[9..17].copy_from_slice(&header.id.to_le_bytes());
+    bytes[17..25].copy_from_slice(&header.sequence.to_le_bytes());
+    bytes[25..29].copy_from_slice(&header.schema_version.get().to_le_bytes());
+    bytes
+}
+
+impl<T: Transport> SecureTransport<T> {
+    pub fn new(inner: T, key: [u8; 32]) -> Self {
+        Self::with_serializer(inner, key, BinarySerializer::new(BinaryFormat::MessagePack))
+    }
+
+    /// Encode messages with `serializer` before sealing them. Both peers need
+    /// the same format.
+    pub fn with_serializer(inner: T, key: [u8; 32], serializer: BinarySerializer) -> Self {
+        Self {
+            inner,
+            cipher: XChaCha20Poly1305::new(&key.into()),
+            serializer,
+            send_sequence: 0,
+            replay: ReplayWindow::new(),
+        }
+    }
+
+    pub fn get_inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn seal(&mut self, message: &Message) -> Result<Message> {
+        let plaintext = self.serializer.serialize_message(message)?;
+
+        let mut envelope = Message::encrypted(Bytes::new(), message.header.schema_version);
+        self.send_sequence += 1;
+        envelope.header.sequence = self.send_sequence;
+        let aad = associated_data(&envelope.header);
+
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.cipher.encrypt(&nonce, Payload { msg: plaintext.as_ref(), aad: &aad })
+            .map_err(|_| LinkError::Serialization("Encryption failed".to_string()))?;
+
+        let mut sealed = Vec::with_capacity(NONCE_LEN + ciphertext.len());
+        sealed.extend_from_slice(&nonce);
+        sealed.extend_from_slice(&ciphertext);
+
+        envelope.payload = MessagePayload::Encrypted { sealed: Bytes::from(sealed) };
+        Ok(envelope)
+    }
+
+    fn open(&mut self, message: Message) -> Result<Message> {
+        let aad = associated_data(&message.header);
+        let sealed = match message.payload {
+            MessagePayload::Encrypted { sealed } => sealed,
+            _ => return Err(LinkError::InvalidMessage(format!(
+                "Expected an encrypted message, got {:?}",
+                message.header.msg_type
+            ))),
+        };
+
+        if sealed.len() < NONCE_LEN {
+            return Err(LinkError::InvalidMessage("Encrypted message too short".to_string()));
+        }
+        let (nonce, ciphertext) = sealed.split_at(NONCE_LEN);
+        let plaintext = self.cipher.decrypt(XNonce::from_slice(nonce), Payload { msg: ciphertext, aad: &aad })
+            .map_err(|_| LinkError::InvalidMessage("Message failed authentication".to_string()))?;
+
+        if !self.replay.accept(message.header.sequence) {
+            return Err(LinkError::InvalidMessage(format!(
+                "Replayed message (sequence {})",
+                message.header.sequence
+            )));
+        }
+
+        self.serializer.deserialize_message(&plaintext)
+    }
+}
+
+impl<T: Transport> Transport for SecureTransport<T> {
+    fn send(&mut self, message: &Message) -> Result<()> {
+        let sealed = self.seal(message)?;
+        self.inner.send(&sealed)
+    }
+
+    fn receive(&mut self) -> Result<Option<Message>> {
+        self.inner.receive()?
+            .map(|message| self.open(message))
+            .transpose()
+    }
+
+    fn send_batch(&mut self, messages: &[Message]) -> Result<()> {
+        let sealed = messages.iter()
+            .map(|message| self.seal(message))
+            .collect::<Result<Vec<_>>>()?;
+        self.inner.send_batch(&sealed)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        crate::compression::is_supported(compression)
+    }
+
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.serializer.set_compression(compression);
+        Ok(())
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        self.inner.receive_timeout(timeout)?
+            .map(|message| self.open(message))
+            .transpose()
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.inner.close()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::protocol::{MessageType, SchemaVersion};
+    use crate::transport::MemoryTransport;
+
+    fn pair(key_a: [u8; 32], key_b: [u8; 32]) -> (SecureTransport<MemoryTransport>, SecureTransport<MemoryTransport>) {
+        (
+            SecureTransport::new(MemoryTransport::new(BinaryFormat::MessagePack), key_a),
+            SecureTransport::new(MemoryTransport::new(BinaryFormat::MessagePack), key_b),
+        )
+    }
+
+    fn deliver(a: &mut SecureTransport<MemoryTransport>, b: &mut SecureTransport<MemoryTransport>) {
+        a.get_inner_mut().connect_to(b.get_inner_mut());
+    }
+
+    #[test]
+    fn test_secure_transport_roundtrip() {
+        let (mut a, mut b) = pair([7; 32], [7; 32]);
+        a.set_compression(CompressionType::Lz4).unwrap();
+        b.set_compression(CompressionType::Lz4).unwrap();
+
+        a.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        a.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        let on_wire = a.get_inner().get_send_buffer().to_vec();
+        assert_ne!(on_wire[0], on_wire[1]);
+
+        deliver(&mut a, &mut b);
+        assert_eq!(b.receive().unwrap().unwrap().header.msg_type, MessageType::Ping);
+        assert_eq!(b.receive().unwrap().unwrap().header.msg_type, MessageType::Ping);
+        assert!(b.receive().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_secure_transport_rejects_forgeries() {
+        let (mut a, mut b) = pair([1; 32], [2; 32]);
+        a.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        deliver(&mut a, &mut b);
+        assert!(matches!(b.receive(), Err(LinkError::InvalidMessage(_))));
+
+        let (mut a, mut b) = pair([1; 32], [1; 32]);
+        let mut sealed = match a.seal(&Message::ping(SchemaVersion::new(1))).unwrap().payload {
+            MessagePayload::Encrypted { sealed } => sealed.to_vec(),
+            other => panic!("expected encrypted payload, got {:?}", other),
+        };
+        *sealed.last_mut().unwrap() ^= 1;
+        a.get_inner_mut().send(&Message::encrypted(Bytes::from(sealed), SchemaVersion::new(1))).unwrap();
+        a.get_inner_mut().send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        deliver(&mut a, &mut b);
+        assert!(matches!(b.receive(), Err(LinkError::InvalidMessage(_))));
+        assert!(matches!(b.receive(), Err(LinkError::InvalidMessage(_))));
+    }
+
+    #[test]
+    fn test_secure_transport_authenticates_header() {
+        let (mut a, mut b) = pair([3; 32], [3; 32]);
+        let mut sealed = a.seal(&Message::ping(SchemaVersion::new(1))).unwrap();
+        sealed.header.timestamp += 1;
+        a.get_inner_mut().send(&sealed).unwrap();
+        deliver(&mut a, &mut b);
+        assert!(matches!(b.receive(), Err(LinkError::InvalidMessage(_))));
+    }
+
+    #[test]
+    fn test_secure_transport_rejects_replays() {
+        let (mut a, mut b) = pair([5; 32], [5; 32]);
+        let first = a.seal(&Message::ping(SchemaVersion::new(1))).unwrap();
+        let second = a.seal(&Message::ping(SchemaVersion::new(1))).unwrap();
+        let stale: Vec<Message> = (0..=REPLAY_WINDOW)
+            .map(|_| a.seal(&Message::ping(SchemaVersion::new(1))).unwrap())
+            .collect();
+
+        // Reordered messages are accepted once each.
+        for message in [&second, &first, &first, &second] {
+            a.get_inner_mut().send(message).unwrap();
+        }
+        deliver(&mut a, &mut b);
+        assert!(b.receive().unwrap().is_some());
+        assert!(b.receive().unwrap().is_some());
+        assert!(matches!(b.receive(), Err(LinkError::InvalidMessage(_))));
+        assert!(matches!(b.receive(), Err(LinkError::InvalidMessage(_))));
+
+        // Once the window has moved past a sequence it's rejected, even if
+        // it was never seen.
+        let (last, rest) = stale.split_last().unwrap();
+        a.get_inner_mut().send(last).unwrap();
+        a.get_inner_mut().send(&rest[0]).unwrap();
+        a.get_inner_mut().send(&rest[1]).unwrap();
+        deliver(&mut a, &mut b);
+        assert!(b.receive().unwrap().is_some());
+        assert!(matches!(b.receive(), Err(LinkError::InvalidMessage(_))));
+        assert!(b.receive().unwrap().is_some());
+    }
+}
@@ -1,9 +1,27 @@
-use crate::error::Result;
+use crate::error::{DeserializationContext, LinkError, Result};
 use crate::protocol::*;
+use crate::intern::{Interned, SharedDictionary};
+#[cfg(feature = "std")]
 use crate::debug;
+use alloc::boxed::Box;
+use alloc::format;
+use alloc::string::String;
+#[cfg(not(feature = "std"))]
+use alloc::string::ToString;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::fmt;
+use serde::de::{DeserializeOwned, IgnoredAny};
 use serde::{Deserialize, Serialize};
-use bytes::{Bytes, BytesMut, BufMut};
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+#[cfg(feature = "std")]
+use std::io::{ErrorKind, Read, Write};
+#[cfg(feature = "std")]
 use std::time::Instant;
+#[cfg(feature = "std")]
+use std::collections::HashMap;
+#[cfg(not(feature = "std"))]
+use hashbrown::HashMap;
 
 pub use crate::protocol::{SerializedComponent, SerializedEntity};
 
@@ -14,398 +32,2556 @@ pub struct WorldSnapshot {
     pub version: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct Delta {
-    pub changes: Vec<DeltaChange>,
-    pub timestamp: f64,
-    pub base_timestamp: f64,
-}
-
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub enum BinaryFormat {
-    Json,
-    MessagePack,
-    Bincode,
+/// Entities and components compare in order, so [`WorldSnapshot::sort`]
+/// snapshots built from different iteration orders first. Timestamps compare
+/// bit for bit, like float field values, so a snapshot always equals itself;
+/// see [`WorldSnapshot::approx_eq`] for timestamps that went through arithmetic.
+impl PartialEq for WorldSnapshot {
+    fn eq(&self, other: &Self) -> bool {
+        self.timestamp.to_bits() == other.timestamp.to_bits() && self.same_content(other)
+    }
 }
 
-pub struct BinarySerializer {
-    format: BinaryFormat,
-}
+impl WorldSnapshot {
+    /// Like `==`, but the timestamps only need to be within `epsilon`.
+    pub fn approx_eq(&self, other: &Self, epsilon: f64) -> bool {
+        (self.timestamp - other.timestamp).abs() <= epsilon && self.same_content(other)
+    }
 
-impl BinarySerializer {
-    pub fn new(format: BinaryFormat) -> Self {
-        Self { format }
+    fn same_content(&self, other: &Self) -> bool {
+        self.version == other.version && self.entities == other.entities
     }
 
-    pub fn json() -> Self {
-        Self::new(BinaryFormat::Json)
+    /// Sort entities by id and each entity's components by id.
+    ///
+    /// Snapshots built from ECS iteration order can list the same state in
+    /// different orders; sorting makes their encoded bytes reproducible.
+    pub fn sort(&mut self) {
+        sort_entities(&mut self.entities);
     }
 
-    pub fn messagepack() -> Self {
-        Self::new(BinaryFormat::MessagePack)
+    /// Sort each entity's components by id, leaving the entity order alone.
+    pub fn sort_components(&mut self) {
+        for entity in &mut self.entities {
+            sort_components(entity);
+        }
     }
 
-    pub fn bincode() -> Self {
-        Self::new(BinaryFormat::Bincode)
+    /// A stable 64-bit hash of the entities and components, independent of their
+    /// order. `timestamp` and `version` are not included, so two ticks with the
+    /// same world state hash the same.
+    ///
+    /// Entities, components and map entries are hashed one by one and summed,
+    /// so nothing is sorted or encoded and the cost is a single pass over the
+    /// data. JSON is hashed as text: equal JSON with its keys in a different
+    /// order hashes differently.
+    pub fn content_hash(&self) -> u64 {
+        let entities = self.entities.iter().fold(0u64, |sum, entity| {
+            let components = entity.components.iter().fold(0u64, |sum, component| {
+                let mut hash = ContentHasher::new();
+                hash.str(&component.id);
+                hash.component_data(&component.data);
+                sum.wrapping_add(hash.finish())
+            });
+
+            let mut hash = ContentHasher::new();
+            hash.u64(entity.id as u64);
+            hash.u64(entity.components.len() as u64);
+            hash.u64(components);
+            sum.wrapping_add(hash.finish())
+        });
+
+        let mut hash = ContentHasher::new();
+        hash.u64(self.entities.len() as u64);
+        hash.u64(entities);
+        hash.finish()
     }
 
-    pub fn serialize_message(&self, message: &Message) -> Result<Bytes> {
-        let start = Instant::now();
+    /// Check that entity ids are unique and that no entity has two components
+    /// with the same id. Diffing keys on these ids, so a duplicate would be
+    /// silently dropped from deltas.
+    pub fn validate(&self) -> Result<()> {
+        let mut entity_ids: Vec<EntityId> = self.entities.iter().map(|e| e.id).collect();
+        entity_ids.sort_unstable();
+        if let Some(pair) = entity_ids.windows(2).find(|pair| pair[0] == pair[1]) {
+            return Err(LinkError::InvalidMessage(format!("Duplicate entity {}", pair[0])));
+        }
 
-        let result = match self.format {
-            BinaryFormat::Json => {
-                let json = serde_json::to_vec(message)?;
-                Ok(Bytes::from(json))
+        for entity in &self.entities {
+            let mut component_ids: Vec<&str> = entity.components.iter().map(|c| c.id.as_str()).collect();
+            component_ids.sort_unstable();
+            if let Some(pair) = component_ids.windows(2).find(|pair| pair[0] == pair[1]) {
+                return Err(LinkError::InvalidMessage(
+                    format!("Duplicate component {} on entity {}", pair[0], entity.id)
+                ));
             }
-            BinaryFormat::MessagePack => {
-                let msgpack = rmp_serde::to_vec(message)?;
-                Ok(Bytes::from(msgpack))
-            }
-            BinaryFormat::Bincode => {
-                let bincode_data = bincode::serialize(message)?;
-                Ok(Bytes::from(bincode_data))
-            }
-        };
+        }
 
-        if let Ok(ref bytes) = result {
-            if debug::is_debug_enabled() {
-                debug::log_message("Serialized", message);
-            }
+        Ok(())
+    }
 
-            if debug::is_trace_enabled() {
-                let format_name = match self.format {
-                    BinaryFormat::Json => "JSON",
-                    BinaryFormat::MessagePack => "MessagePack",
-                    BinaryFormat::Bincode => "Bincode",
-                };
-                debug::trace_serialization(format_name, bytes.len(), start.elapsed().as_micros());
+    /// Ids of the entities added, removed or modified going from `self` to `other`,
+    /// without building the `DeltaChange`s.
+    pub fn changed_entities(&self, other: &WorldSnapshot) -> ChangedEntities {
+        let mut changed = ChangedEntities::default();
+
+        diff_entities(&self.entities, &other.entities, |diff| match diff {
+            EntityDiff::Added(entity) => changed.added.push(entity.id),
+            EntityDiff::Removed(entity) => changed.removed.push(entity.id),
+            EntityDiff::Common(prev, curr) => {
+                let mut modified = false;
+                diff_components(prev, curr, |diff| {
+                    modified |= !matches!(diff, ComponentDiff::Common(a, b) if a.data.same_as(&b.data));
+                });
+                if modified {
+                    changed.modified.push(curr.id);
+                }
             }
-        }
+        });
 
-        result
+        changed.added.sort_unstable();
+        changed.removed.sort_unstable();
+        changed.modified.sort_unstable();
+        changed
     }
+}
 
-    pub fn deserialize_message(&self, data: &[u8]) -> Result<Message> {
-        let start = Instant::now();
+#[cfg(test)]
+impl WorldSnapshot {
+    /// Test snapshot at `timestamp` with the default `1.0.0` version.
+    pub(crate) fn for_test(timestamp: f64, entities: Vec<SerializedEntity>) -> Self {
+        WorldSnapshot { entities, timestamp, version: "1.0.0".to_string() }
+    }
+}
 
-        let result = match self.format {
-            BinaryFormat::Json => {
-                let message = serde_json::from_slice(data)?;
-                Ok(message)
-            }
-            BinaryFormat::MessagePack => {
-                let message = rmp_serde::from_slice(data)?;
-                Ok(message)
-            }
-            BinaryFormat::Bincode => {
-                let message = bincode::deserialize(data)?;
-                Ok(message)
-            }
-        };
+/// FNV-1a over the bytes fed to it, finished with a 64-bit mix so that the
+/// sums taken in [`WorldSnapshot::content_hash`] stay well distributed.
+struct ContentHasher(u64);
 
-        if let Ok(ref message) = result {
-            if debug::is_debug_enabled() {
-                debug::log_message("Deserialized", message);
-            }
+impl ContentHasher {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
 
-            if debug::is_trace_enabled() {
-                let format_name = match self.format {
-                    BinaryFormat::Json => "JSON",
-                    BinaryFormat::MessagePack => "MessagePack",
-                    BinaryFormat::Bincode => "Bincode",
-                };
-                debug::trace_deserialization(format_name, data.len(), start.elapsed().as_micros());
-            }
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x0000_0100_0000_01b3);
         }
+    }
 
-        result
+    fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
     }
 
-    pub fn serialize_snapshot(&self, snapshot: &WorldSnapshot) -> Result<Bytes> {
-        match self.format {
-            BinaryFormat::Json => {
-                let json = serde_json::to_vec(snapshot)?;
-                Ok(Bytes::from(json))
-            }
-            BinaryFormat::MessagePack => {
-                let msgpack = rmp_serde::to_vec(snapshot)?;
-                Ok(Bytes::from(msgpack))
-            }
-            BinaryFormat::Bincode => {
-                let bincode_data = bincode::serialize(snapshot)?;
-                Ok(Bytes::from(bincode_data))
-            }
-        }
+    fn str(&mut self, value: &str) {
+        self.u64(value.len() as u64);
+        self.bytes(value.as_bytes());
     }
 
-    pub fn deserialize_snapshot(&self, data: &[u8]) -> Result<WorldSnapshot> {
-        match self.format {
-            BinaryFormat::Json => {
-                let snapshot = serde_json::from_slice(data)?;
-                Ok(snapshot)
+    fn component_data(&mut self, data: &ComponentData) {
+        match data {
+            ComponentData::Binary(bytes) => {
+                self.bytes(&[0]);
+                self.u64(bytes.len() as u64);
+                self.bytes(bytes);
             }
-            BinaryFormat::MessagePack => {
-                let snapshot = rmp_serde::from_slice(data)?;
-                Ok(snapshot)
+            ComponentData::Json(json) => {
+                self.bytes(&[1]);
+                self.str(json);
             }
-            BinaryFormat::Bincode => {
-                let snapshot = bincode::deserialize(data)?;
-                Ok(snapshot)
+            ComponentData::Structured(fields) => {
+                self.bytes(&[2]);
+                self.map(fields);
+            }
+            ComponentData::Empty => self.bytes(&[3]),
+            ComponentData::Compressed { compression, data } => {
+                self.bytes(&[4, *compression as u8]);
+                self.u64(data.len() as u64);
+                self.bytes(data);
             }
         }
     }
 
-    pub fn serialize_delta(&self, delta: &Delta) -> Result<Bytes> {
-        match self.format {
-            BinaryFormat::Json => {
-                let json = serde_json::to_vec(delta)?;
-                Ok(Bytes::from(json))
+    fn map(&mut self, map: &HashMap<String, FieldValue>) {
+        let entries = map.iter().fold(0u64, |sum, (key, value)| {
+            let mut hash = ContentHasher::new();
+            hash.str(key);
+            hash.field_value(value);
+            sum.wrapping_add(hash.finish())
+        });
+        self.u64(map.len() as u64);
+        self.u64(entries);
+    }
+
+    fn field_value(&mut self, value: &FieldValue) {
+        match value {
+            FieldValue::Null => self.bytes(&[0]),
+            FieldValue::Bool(v) => self.bytes(&[1, *v as u8]),
+            FieldValue::U8(v) => self.bytes(&[2, *v]),
+            FieldValue::U16(v) => { self.bytes(&[3]); self.bytes(&v.to_le_bytes()); }
+            FieldValue::U32(v) => { self.bytes(&[4]); self.bytes(&v.to_le_bytes()); }
+            FieldValue::U64(v) => { self.bytes(&[5]); self.bytes(&v.to_le_bytes()); }
+            FieldValue::I8(v) => self.bytes(&[6, *v as u8]),
+            FieldValue::I16(v) => { self.bytes(&[7]); self.bytes(&v.to_le_bytes()); }
+            FieldValue::I32(v) => { self.bytes(&[8]); self.bytes(&v.to_le_bytes()); }
+            FieldValue::I64(v) => { self.bytes(&[9]); self.bytes(&v.to_le_bytes()); }
+            FieldValue::F32(v) => { self.bytes(&[10]); self.bytes(&v.to_le_bytes()); }
+            FieldValue::F64(v) => { self.bytes(&[11]); self.bytes(&v.to_le_bytes()); }
+            FieldValue::String(v) => { self.bytes(&[12]); self.str(v); }
+            FieldValue::Bytes(v) => {
+                self.bytes(&[13]);
+                self.u64(v.len() as u64);
+                self.bytes(v);
+            }
+            FieldValue::Array(values) => {
+                self.bytes(&[14]);
+                self.u64(values.len() as u64);
+                for value in values {
+                    self.field_value(value);
+                }
             }
-            BinaryFormat::MessagePack => {
-                let msgpack = rmp_serde::to_vec(delta)?;
-                Ok(Bytes::from(msgpack))
+            FieldValue::Map(map) => {
+                self.bytes(&[15]);
+                self.map(map);
             }
-            BinaryFormat::Bincode => {
-                let bincode_data = bincode::serialize(delta)?;
-                Ok(Bytes::from(bincode_data))
+            FieldValue::Enum { discriminant, payload } => {
+                self.bytes(&[16]);
+                self.bytes(&discriminant.to_le_bytes());
+                match payload {
+                    Some(payload) => { self.bytes(&[1]); self.field_value(payload); }
+                    None => self.bytes(&[0]),
+                }
             }
         }
     }
 
-    pub fn deserialize_delta(&self, data: &[u8]) -> Result<Delta> {
-        match self.format {
-            BinaryFormat::Json => {
-                let delta = serde_json::from_slice(data)?;
-                Ok(delta)
-            }
-            BinaryFormat::MessagePack => {
-                let delta = rmp_serde::from_slice(data)?;
-                Ok(delta)
-            }
-            BinaryFormat::Bincode => {
-                let delta = bincode::deserialize(data)?;
-                Ok(delta)
-            }
+    fn finish(&self) -> u64 {
+        let mut h = self.0;
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xff51_afd7_ed55_8ccd);
+        h ^= h >> 33;
+        h = h.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+        h ^ (h >> 33)
+    }
+}
+
+/// Result of [`WorldSnapshot::changed_entities`]; each list is sorted by id.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangedEntities {
+    pub added: Vec<EntityId>,
+    pub removed: Vec<EntityId>,
+    pub modified: Vec<EntityId>,
+}
+
+impl ChangedEntities {
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.modified.is_empty()
+    }
+}
+
+pub(crate) enum EntityDiff<'a> {
+    Added(&'a SerializedEntity),
+    Removed(&'a SerializedEntity),
+    Common(&'a SerializedEntity, &'a SerializedEntity),
+}
+
+// Only the delta compressor reads the components; no_std builds just test for changes.
+#[cfg_attr(not(feature = "std"), allow(dead_code))]
+pub(crate) enum ComponentDiff<'a> {
+    Added(&'a SerializedComponent),
+    Removed(&'a SerializedComponent),
+    Common(&'a SerializedComponent, &'a SerializedComponent),
+}
+
+/// Match entities by id: current ones first (added or common), then removed ones.
+pub(crate) fn diff_entities<'a>(
+    prev: &'a [SerializedEntity],
+    curr: &'a [SerializedEntity],
+    mut visit: impl FnMut(EntityDiff<'a>),
+) {
+    let prev_by_id: HashMap<EntityId, &SerializedEntity> = prev.iter().map(|e| (e.id, e)).collect();
+    let curr_by_id: HashMap<EntityId, &SerializedEntity> = curr.iter().map(|e| (e.id, e)).collect();
+
+    for (entity_id, curr_entity) in &curr_by_id {
+        match prev_by_id.get(entity_id) {
+            Some(prev_entity) => visit(EntityDiff::Common(prev_entity, curr_entity)),
+            None => visit(EntityDiff::Added(curr_entity)),
         }
     }
 
-    pub fn serialize_component(&self, component: &SerializedComponent) -> Result<Bytes> {
-        match self.format {
-            BinaryFormat::Json => {
-                let json = serde_json::to_vec(component)?;
-                Ok(Bytes::from(json))
-            }
-            BinaryFormat::MessagePack => {
-                let msgpack = rmp_serde::to_vec(component)?;
-                Ok(Bytes::from(msgpack))
-            }
-            BinaryFormat::Bincode => {
-                let bincode_data = bincode::serialize(component)?;
-                Ok(Bytes::from(bincode_data))
-            }
+    for (entity_id, prev_entity) in &prev_by_id {
+        if !curr_by_id.contains_key(entity_id) {
+            visit(EntityDiff::Removed(prev_entity));
         }
     }
+}
 
-    pub fn deserialize_component(&self, data: &[u8]) -> Result<SerializedComponent> {
-        match self.format {
-            BinaryFormat::Json => {
-                let component = serde_json::from_slice(data)?;
-                Ok(component)
-            }
-            BinaryFormat::MessagePack => {
-                let component = rmp_serde::from_slice(data)?;
-                Ok(component)
-            }
-            BinaryFormat::Bincode => {
-                let component = bincode::deserialize(data)?;
-                Ok(component)
-            }
+/// Match two entities' components by id, in the same order as [`diff_entities`].
+pub(crate) fn diff_components<'a>(
+    prev: &'a SerializedEntity,
+    curr: &'a SerializedEntity,
+    mut visit: impl FnMut(ComponentDiff<'a>),
+) {
+    let prev_by_id: HashMap<&str, &SerializedComponent> = prev.components.iter().map(|c| (c.id.as_str(), c)).collect();
+    let curr_by_id: HashMap<&str, &SerializedComponent> = curr.components.iter().map(|c| (c.id.as_str(), c)).collect();
+
+    for (component_id, curr_component) in &curr_by_id {
+        match prev_by_id.get(component_id) {
+            Some(prev_component) => visit(ComponentDiff::Common(prev_component, curr_component)),
+            None => visit(ComponentDiff::Added(curr_component)),
         }
     }
 
-    pub fn get_format(&self) -> BinaryFormat {
-        self.format
+    for (component_id, prev_component) in &prev_by_id {
+        if !curr_by_id.contains_key(component_id) {
+            visit(ComponentDiff::Removed(prev_component));
+        }
     }
 }
 
-pub struct StreamingSerializer {
-    format: BinaryFormat,
-    buffer: BytesMut,
+fn sort_entities(entities: &mut [SerializedEntity]) {
+    entities.sort_by_key(|e| e.id);
+    for entity in entities.iter_mut() {
+        sort_components(entity);
+    }
 }
 
-impl StreamingSerializer {
-    pub fn new(format: BinaryFormat) -> Self {
-        Self {
-            format,
-            buffer: BytesMut::with_capacity(8192),
-        }
-    }
+fn sort_components(entity: &mut SerializedEntity) {
+    entity.components.sort_by(|a, b| a.id.cmp(&b.id));
+}
 
-    pub fn write_message(&mut self, message: &Message) -> Result<()> {
-        let serializer = BinarySerializer::new(self.format);
-        let data = serializer.serialize_message(message)?;
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delta {
+    pub changes: Vec<DeltaChange>,
+    pub timestamp: f64,
+    pub base_timestamp: f64,
+}
 
-        let len = data.len() as u32;
-        self.buffer.put_u32_le(len);
-        self.buffer.put(data);
+/// Changes compare in order; timestamps bit for bit, as for [`WorldSnapshot`].
+impl PartialEq for Delta {
+    fn eq(&self, other: &Self) -> bool {
+        self.timestamp.to_bits() == other.timestamp.to_bits()
+            && self.base_timestamp.to_bits() == other.base_timestamp.to_bits()
+            && self.changes == other.changes
+    }
+}
 
-        Ok(())
+impl Delta {
+    /// Like `==`, but each timestamp only needs to be within `epsilon` of the other's.
+    pub fn approx_eq(&self, other: &Self, epsilon: f64) -> bool {
+        (self.timestamp - other.timestamp).abs() <= epsilon
+            && (self.base_timestamp - other.base_timestamp).abs() <= epsilon
+            && self.changes == other.changes
     }
 
-    pub fn flush(&mut self) -> Bytes {
-        self.buffer.split().freeze()
+    /// The changes to `entity_id`, in order.
+    pub fn changes_for_entity(&self, entity_id: EntityId) -> impl Iterator<Item = &DeltaChange> {
+        self.changes.iter().filter(move |change| change.entity_id() == entity_id)
     }
 
-    pub fn clear(&mut self) {
-        self.buffer.clear();
+    /// Components of `entity_id` that were added, removed or updated, each
+    /// once, in the order they first appear.
+    pub fn changed_components(&self, entity_id: EntityId) -> Vec<&ComponentId> {
+        let mut components: Vec<&ComponentId> = Vec::new();
+        for component_id in self.changes_for_entity(entity_id).filter_map(DeltaChange::component_id) {
+            if !components.contains(&component_id) {
+                components.push(component_id);
+            }
+        }
+        components
     }
 }
 
-pub struct StreamingDeserializer {
-    format: BinaryFormat,
-    buffer: BytesMut,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryFormat {
+    Json,
+    MessagePack,
+    Bincode,
 }
 
-impl StreamingDeserializer {
-    pub fn new(format: BinaryFormat) -> Self {
-        Self {
-            format,
-            buffer: BytesMut::with_capacity(8192),
+impl BinaryFormat {
+    /// Whether values can be skipped without knowing their type, which is what
+    /// lets messages of a type this build lacks be decoded as `Unknown`.
+    /// Bincode encodes positionally, so it can't.
+    pub fn is_self_describing(self) -> bool {
+        match self {
+            BinaryFormat::Json | BinaryFormat::MessagePack => true,
+            BinaryFormat::Bincode => false,
         }
     }
+}
 
-    pub fn feed(&mut self, data: &[u8]) {
-        self.buffer.extend_from_slice(data);
-    }
+/// A wire format for messages, snapshots and deltas.
+///
+/// Implement this for formats the crate doesn't ship and pass it to
+/// [`BinarySerializer::with_codec`]; compression, framing and snapshot sorting
+/// still happen around it. The built-in formats are the [`BinaryFormat`] impl.
+pub trait MessageCodec: Send + Sync {
+    /// Name shown in debug traces.
+    fn name(&self) -> &str;
+    fn encode(&self, message: &Message) -> Result<Bytes>;
+    fn decode(&self, data: &[u8]) -> Result<Message>;
+    fn encode_snapshot(&self, snapshot: &WorldSnapshot) -> Result<Bytes>;
+    fn decode_snapshot(&self, data: &[u8]) -> Result<WorldSnapshot>;
+    fn encode_delta(&self, delta: &Delta) -> Result<Bytes>;
+    fn decode_delta(&self, data: &[u8]) -> Result<Delta>;
+}
 
-    pub fn try_read_message(&mut self) -> Result<Option<Message>> {
-        if self.buffer.len() < 4 {
-            return Ok(None);
+impl MessageCodec for BinaryFormat {
+    fn name(&self) -> &str {
+        match self {
+            BinaryFormat::Json => "JSON",
+            BinaryFormat::MessagePack => "MessagePack",
+            BinaryFormat::Bincode => "Bincode",
         }
+    }
 
-        let len = u32::from_le_bytes([
-            self.buffer[0],
-            self.buffer[1],
-            self.buffer[2],
-            self.buffer[3],
-        ]) as usize;
-
-        if self.buffer.len() < 4 + len {
-            return Ok(None);
-        }
+    fn encode(&self, message: &Message) -> Result<Bytes> {
+        encode_value(*self, message)
+    }
 
-        self.buffer.advance(4);
+    fn decode(&self, data: &[u8]) -> Result<Message> {
+        decode_value(*self, data)
+    }
 
-        let message_data = self.buffer.split_to(len);
+    fn encode_snapshot(&self, snapshot: &WorldSnapshot) -> Result<Bytes> {
+        encode_value(*self, snapshot)
+    }
 
-        let serializer = BinarySerializer::new(self.format);
-        let message = serializer.deserialize_message(&message_data)?;
+    fn decode_snapshot(&self, data: &[u8]) -> Result<WorldSnapshot> {
+        decode_value(*self, data)
+    }
 
-        Ok(Some(message))
+    fn encode_delta(&self, delta: &Delta) -> Result<Bytes> {
+        encode_value(*self, delta)
     }
 
-    pub fn clear(&mut self) {
-        self.buffer.clear();
+    fn decode_delta(&self, data: &[u8]) -> Result<Delta> {
+        decode_value(*self, data)
     }
 }
 
-trait Advance {
-    fn advance(&mut self, cnt: usize);
+#[derive(Clone)]
+enum Codec {
+    Format(BinaryFormat),
+    Custom(Arc<dyn MessageCodec>),
 }
 
-impl Advance for BytesMut {
-    fn advance(&mut self, cnt: usize) {
-        let _ = self.split_to(cnt);
+impl fmt::Debug for Codec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Codec::Format(format) => format.fmt(f),
+            Codec::Custom(codec) => write!(f, "Custom({})", codec.name()),
+        }
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Debug, Clone)]
+pub struct BinarySerializer {
+    codec: Codec,
+    sort_snapshots: bool,
+    intern_ids: bool,
+    compression: CompressionType,
+    limits: MessageLimits,
+    /// Entities per chunk of a parallel-encoded snapshot; 0 is off.
+    #[cfg(feature = "rayon")]
+    chunk_entities: usize,
+    #[cfg(feature = "rayon")]
+    pool: Option<Arc<rayon::ThreadPool>>,
+}
 
-    #[test]
-    fn test_json_serialization() {
-        let serializer = BinarySerializer::json();
-        let message = Message::ping(1);
+impl BinarySerializer {
+    pub fn new(format: BinaryFormat) -> Self {
+        Self {
+            codec: Codec::Format(format),
+            sort_snapshots: false,
+            intern_ids: false,
+            compression: CompressionType::None,
+            limits: MessageLimits::default(),
+            #[cfg(feature = "rayon")]
+            chunk_entities: 0,
+            #[cfg(feature = "rayon")]
+            pool: None,
+        }
+    }
 
-        let serialized = serializer.serialize_message(&message).unwrap();
-        let deserialized = serializer.deserialize_message(&serialized).unwrap();
+    /// Encode with a user-provided format. Components can't be serialized on
+    /// their own with a custom codec.
+    pub fn with_codec<C: MessageCodec + 'static>(codec: C) -> Self {
+        Self {
+            codec: Codec::Custom(Arc::new(codec)),
+            ..Self::new(BinaryFormat::MessagePack)
+        }
+    }
 
-        assert_eq!(message.header.msg_type, deserialized.header.msg_type);
+    /// Compress every encoded payload; both peers must use the same setting.
+    pub fn with_compression(mut self, compression: CompressionType) -> Self {
+        self.compression = compression;
+        self
     }
 
-    #[test]
-    fn test_messagepack_serialization() {
-        let serializer = BinarySerializer::messagepack();
-        let message = Message::ping(1);
+    pub fn set_compression(&mut self, compression: CompressionType) {
+        self.compression = compression;
+    }
 
-        let serialized = serializer.serialize_message(&message).unwrap();
-        let deserialized = serializer.deserialize_message(&serialized).unwrap();
+    pub fn get_compression(&self) -> CompressionType {
+        self.compression
+    }
 
-        assert_eq!(message.header.msg_type, deserialized.header.msg_type);
+    /// Reject received frames and messages over `limits`' byte limits.
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
     }
 
-    #[test]
-    fn test_bincode_serialization() {
-        let serializer = BinarySerializer::bincode();
+    pub fn limits(&self) -> &MessageLimits {
+        &self.limits
+    }
 
-        let snapshot = WorldSnapshot {
-            entities: vec![],
-            timestamp: 100.0,
-            version: "1.0.0".to_string(),
+    /// Sort snapshot entities and components before encoding (see [`WorldSnapshot::sort`]).
+    pub fn with_sorted_snapshots(mut self, enabled: bool) -> Self {
+        self.sort_snapshots = enabled;
+        self
+    }
+
+    /// Write component and field ids once per snapshot or delta, in a
+    /// dictionary ahead of the data, and refer to them by index elsewhere.
+    /// Worlds where many entities share the same components shrink
+    /// considerably. Both peers must use the same setting; custom codecs
+    /// ignore it.
+    pub fn with_interned_ids(mut self, enabled: bool) -> Self {
+        self.intern_ids = enabled;
+        self
+    }
+
+    /// Encode snapshots as chunks of `entities_per_chunk` entities in
+    /// parallel, and decode their chunks in parallel. `0` turns it off.
+    ///
+    /// The result is a sequence of [`FrameCodec`] frames, each a complete
+    /// encoded snapshot of one chunk with the same timestamp and version, so
+    /// compression and interning apply per chunk. Both peers must use the same
+    /// setting. Only [`Self::serialize_snapshot`] and
+    /// [`Self::deserialize_snapshot`] are affected; custom codecs ignore it.
+    #[cfg(feature = "rayon")]
+    pub fn with_parallel_snapshots(mut self, entities_per_chunk: usize) -> Self {
+        self.chunk_entities = entities_per_chunk;
+        self
+    }
+
+    /// Run parallel snapshot encoding on `pool` instead of rayon's global pool.
+    #[cfg(feature = "rayon")]
+    pub fn with_thread_pool(mut self, pool: Arc<rayon::ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+
+    /// Whether `other` encodes every message to the same bytes as `self`.
+    /// Custom codecs only match when they are the same instance.
+    pub fn same_encoding(&self, other: &Self) -> bool {
+        let same_codec = match (&self.codec, &other.codec) {
+            (Codec::Format(a), Codec::Format(b)) => a == b,
+            (Codec::Custom(a), Codec::Custom(b)) => Arc::ptr_eq(a, b),
+            _ => false,
         };
 
-        let serialized = serializer.serialize_snapshot(&snapshot).unwrap();
-        let deserialized = serializer.deserialize_snapshot(&serialized).unwrap();
+        #[cfg(feature = "rayon")]
+        let same_codec = same_codec && self.chunk_entities == other.chunk_entities;
 
-        assert_eq!(snapshot.timestamp, deserialized.timestamp);
+        same_codec
+            && self.sort_snapshots == other.sort_snapshots
+            && self.intern_ids == other.intern_ids
+            && self.compression == other.compression
     }
 
-    #[test]
-    fn test_streaming_serialization() {
-        let mut stream_serializer = StreamingSerializer::new(BinaryFormat::MessagePack);
-        let mut stream_deserializer = StreamingDeserializer::new(BinaryFormat::MessagePack);
+    pub fn json() -> Self {
+        Self::new(BinaryFormat::Json)
+    }
 
-        let msg1 = Message::ping(1);
-        let msg2 = Message::pong(1);
+    pub fn messagepack() -> Self {
+        Self::new(BinaryFormat::MessagePack)
+    }
 
-        stream_serializer.write_message(&msg1).unwrap();
-        stream_serializer.write_message(&msg2).unwrap();
+    pub fn bincode() -> Self {
+        Self::new(BinaryFormat::Bincode)
+    }
 
-        let data = stream_serializer.flush();
-        stream_deserializer.feed(&data);
+    pub fn serialize_message(&self, message: &Message) -> Result<Bytes> {
+        self.serialize_message_shared(message, None)
+    }
 
-        let decoded1 = stream_deserializer.try_read_message().unwrap().unwrap();
-        let decoded2 = stream_deserializer.try_read_message().unwrap().unwrap();
+    /// [`Self::serialize_message`], interning ids against a stream's dictionary.
+    pub(crate) fn serialize_message_shared(&self, message: &Message, shared: Option<&mut SharedDictionary>) -> Result<Bytes> {
+        if self.sort_snapshots && matches!(message.payload, MessagePayload::Snapshot(_)) {
+            let mut sorted = message.clone();
+            if let MessagePayload::Snapshot(payload) = &mut sorted.payload {
+                sort_entities(&mut payload.entities);
+            }
+            return self.encode_message(&sorted, shared);
+        }
 
-        assert_eq!(msg1.header.msg_type, decoded1.header.msg_type);
-        assert_eq!(msg2.header.msg_type, decoded2.header.msg_type);
+        self.encode_message(message, shared)
     }
 
-    #[test]
-    fn test_snapshot_serialization() {
-        let snapshot = WorldSnapshot {
-            entities: vec![
-                SerializedEntity {
-                    id: 1,
-                    components: vec![
-                        SerializedComponent {
-                            id: "Position".to_string(),
-                            data: ComponentData::from_json_value(serde_json::json!({"x": 10.0, "y": 20.0})),
-                        }
-                    ],
-                }
-            ],
-            timestamp: 123.456,
-            version: "1.0.0".to_string(),
-        };
+    /// [`Self::serialize_message`], appending the encoding to `buffer` instead
+    /// of allocating a new one, so a send loop can keep reusing one buffer:
+    /// `clear` it, or `split` the previous message off, before each call.
+    ///
+    /// Built-in formats write straight into `buffer`, which only allocates
+    /// when it has to grow. With compression, interned ids, sorted snapshots
+    /// or a custom codec the message is encoded as by `serialize_message` and
+    /// copied in, which saves nothing over calling it directly.
+    pub fn serialize_message_into(&self, message: &Message, buffer: &mut BytesMut) -> Result<()> {
+        #[cfg(feature = "std")]
+        if let Codec::Format(format) = self.codec {
+            let sorted = self.sort_snapshots && matches!(message.payload, MessagePayload::Snapshot(_));
+            if !sorted && !self.intern_ids && self.compression == CompressionType::None {
+                let start = Instant::now();
+                let len = buffer.len();
+                encode_value_into(format, message, buffer)?;
+                self.trace_serialized(message, buffer.len() - len, start);
+                return Ok(());
+            }
+        }
 
-        let serializer = BinarySerializer::messagepack();
-        let serialized = serializer.serialize_snapshot(&snapshot).unwrap();
-        let deserialized = serializer.deserialize_snapshot(&serialized).unwrap();
+        buffer.extend_from_slice(&self.serialize_message(message)?);
+        Ok(())
+    }
 
-        assert_eq!(snapshot.entities.len(), deserialized.entities.len());
-        assert_eq!(snapshot.timestamp, deserialized.timestamp);
-        assert_eq!(snapshot.version, deserialized.version);
+    fn encode_message(&self, message: &Message, shared: Option<&mut SharedDictionary>) -> Result<Bytes> {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+
+        let result = self.encode_ids(message, shared, |codec| codec.encode(message));
+
+        #[cfg(feature = "std")]
+        if let Ok(ref bytes) = result {
+            self.trace_serialized(message, bytes.len(), start);
+        }
+
+        result
+    }
+
+    #[cfg(feature = "std")]
+    fn trace_serialized(&self, message: &Message, len: usize, start: Instant) {
+        if debug::is_debug_enabled() {
+            debug::log_message("Serialized", message);
+        }
+
+        if debug::is_trace_enabled() {
+            debug::trace_serialization(self.format_name(), len, start.elapsed().as_micros());
+        }
+    }
+
+    pub fn deserialize_message(&self, data: &[u8]) -> Result<Message> {
+        self.deserialize_message_shared(data, None)
+    }
+
+    fn deserialize_message_shared(&self, data: &[u8], shared: Option<&mut SharedDictionary>) -> Result<Message> {
+        #[cfg(feature = "std")]
+        let start = Instant::now();
+
+        let result: Result<Message> = self.decode_ids("message", data, shared, |codec, data| codec.decode(data))
+            .or_else(|e| self.decode_unknown(data).ok_or(e))
+            .and_then(|message| self.limits.check_payload(&message.payload).map(|()| message));
+
+        #[cfg(feature = "std")]
+        if let Ok(ref message) = result {
+            if debug::is_debug_enabled() {
+                debug::log_message("Deserialized", message);
+            }
+
+            if debug::is_trace_enabled() {
+                debug::trace_deserialization(self.format_name(), data.len(), start.elapsed().as_micros());
+            }
+        }
+
+        result
+    }
+
+    /// Payloads from peers that predate the compact encoding can't have their
+    /// unknown variant skipped, so retry with the payload ignored and accept the
+    /// message if its header says the type is unknown. Only self-describing
+    /// formats can ignore a payload; for the rest the first error stands.
+    fn decode_unknown(&self, data: &[u8]) -> Option<Message> {
+        if !self.builtin_format().is_some_and(BinaryFormat::is_self_describing) {
+            return None;
+        }
+
+        #[derive(Deserialize)]
+        struct Envelope {
+            header: MessageHeader,
+            #[allow(dead_code)]
+            payload: IgnoredAny,
+        }
+
+        let envelope: Envelope = self.decode("message", data, |_, _| Err(unsupported("envelopes"))).ok()?;
+        (envelope.header.msg_type == MessageType::Unknown).then(|| Message {
+            header: envelope.header,
+            payload: MessagePayload::Unknown,
+        })
+    }
+
+    pub fn serialize_snapshot(&self, snapshot: &WorldSnapshot) -> Result<Bytes> {
+        if self.sort_snapshots {
+            let mut sorted = snapshot.clone();
+            sorted.sort();
+            return self.encode_snapshot(&sorted);
+        }
+
+        self.encode_snapshot(snapshot)
+    }
+
+    fn encode_snapshot(&self, snapshot: &WorldSnapshot) -> Result<Bytes> {
+        #[cfg(feature = "rayon")]
+        if matches!(self.codec, Codec::Format(_)) && self.chunk_entities > 0 {
+            return self.encode_chunked(snapshot);
+        }
+
+        self.encode_ids(snapshot, None, |codec| codec.encode_snapshot(snapshot))
+    }
+
+    #[cfg(feature = "rayon")]
+    fn encode_chunked(&self, snapshot: &WorldSnapshot) -> Result<Bytes> {
+        use rayon::prelude::*;
+
+        #[derive(Serialize)]
+        struct Chunk<'a> {
+            entities: &'a [SerializedEntity],
+            timestamp: f64,
+            version: &'a str,
+        }
+
+        let mut chunks: Vec<&[SerializedEntity]> = snapshot.entities.chunks(self.chunk_entities).collect();
+        if chunks.is_empty() {
+            chunks.push(&[]);
+        }
+
+        let encode_chunk = |entities: &&[SerializedEntity]| {
+            let (timestamp, version) = (snapshot.timestamp, snapshot.version.as_str());
+            self.encode_with(
+                |format| match self.intern_ids {
+                    true => crate::intern::encode_snapshot(format, entities, timestamp, version, None),
+                    false => encode_value(format, &Chunk { entities, timestamp, version }),
+                },
+                |_| Err(unsupported("snapshot chunks")),
+            )
+        };
+        let encode_all = || chunks.par_iter().map(encode_chunk).collect::<Result<Vec<Bytes>>>();
+        let frames = match &self.pool {
+            Some(pool) => pool.install(encode_all),
+            None => encode_all(),
+        }?;
+
+        let mut buffer = BytesMut::with_capacity(frames.iter().map(|f| FrameCodec::PREFIX_LEN + f.len()).sum());
+        for frame in &frames {
+            FrameCodec::encode(frame, &mut buffer)?;
+        }
+        Ok(buffer.freeze())
+    }
+
+    #[cfg(feature = "rayon")]
+    fn decode_chunked(&self, data: &[u8]) -> Result<WorldSnapshot> {
+        use rayon::prelude::*;
+
+        let mut frames = Vec::new();
+        let mut rest = data;
+        while !rest.is_empty() {
+            let Some((prefix, body)) = rest.split_first_chunk::<{ FrameCodec::PREFIX_LEN }>() else {
+                return Err(LinkError::Deserialization("Truncated snapshot chunk prefix".to_string()));
+            };
+            let len = FrameCodec::decode_prefix(*prefix);
+            if body.len() < len {
+                return Err(LinkError::Deserialization(format!(
+                    "Snapshot chunk of {} bytes is truncated to {}", len, body.len()
+                )));
+            }
+            let (frame, next) = body.split_at(len);
+            frames.push(frame);
+            rest = next;
+        }
+
+        let decode_all = || {
+            frames.par_iter()
+                .map(|frame| self.decode_ids("snapshot chunk", frame, None, |_, _| Err(unsupported("snapshot chunks"))))
+                .collect::<Result<Vec<WorldSnapshot>>>()
+        };
+        let chunks = match &self.pool {
+            Some(pool) => pool.install(decode_all),
+            None => decode_all(),
+        }?;
+
+        let mut chunks = chunks.into_iter();
+        let mut snapshot = chunks.next()
+            .ok_or_else(|| LinkError::Deserialization("Chunked snapshot has no chunks".to_string()))?;
+        for chunk in chunks {
+            snapshot.entities.extend(chunk.entities);
+        }
+        Ok(snapshot)
+    }
+
+    /// Like [`Self::deserialize_message`], but `ComponentData::Binary` payloads
+    /// reference `data` instead of being copied where the format allows it.
+    pub fn deserialize_message_bytes(&self, data: &Bytes) -> Result<Message> {
+        self.deserialize_message_bytes_shared(data, None)
+    }
+
+    /// [`Self::deserialize_message_bytes`], resolving ids against a stream's dictionary.
+    pub(crate) fn deserialize_message_bytes_shared(&self, data: &Bytes, shared: Option<&mut SharedDictionary>) -> Result<Message> {
+        #[cfg(feature = "std")]
+        let _source = BinarySourceGuard::new(data);
+        self.deserialize_message_shared(data, shared)
+    }
+
+    pub fn deserialize_snapshot(&self, data: &[u8]) -> Result<WorldSnapshot> {
+        let snapshot = self.decode_snapshot(data)?;
+        self.limits.check_entities(&snapshot.entities)?;
+        Ok(snapshot)
+    }
+
+    fn decode_snapshot(&self, data: &[u8]) -> Result<WorldSnapshot> {
+        #[cfg(feature = "rayon")]
+        if matches!(self.codec, Codec::Format(_)) && self.chunk_entities > 0 {
+            return self.decode_chunked(data);
+        }
+
+        self.decode_ids("snapshot", data, None, |codec, data| codec.decode_snapshot(data))
+    }
+
+    pub fn serialize_delta(&self, delta: &Delta) -> Result<Bytes> {
+        self.encode_ids(delta, None, |codec| codec.encode_delta(delta))
+    }
+
+    pub fn deserialize_delta(&self, data: &[u8]) -> Result<Delta> {
+        let delta = self.decode_ids("delta", data, None, |codec, data| codec.decode_delta(data))?;
+        self.limits.check_changes(&delta.changes)?;
+        Ok(delta)
+    }
+
+    pub fn serialize_component(&self, component: &SerializedComponent) -> Result<Bytes> {
+        self.encode(component, |_| Err(unsupported("components")))
+    }
+
+    pub fn deserialize_component(&self, data: &[u8]) -> Result<SerializedComponent> {
+        self.decode("component", data, |_, _| Err(unsupported("components")))
+    }
+
+    /// Encode `value` in the built-in format, or through `custom` for a custom
+    /// codec, then compress.
+    fn encode<T: Serialize>(
+        &self,
+        value: &T,
+        custom: impl FnOnce(&dyn MessageCodec) -> Result<Bytes>,
+    ) -> Result<Bytes> {
+        self.encode_with(|format| encode_value(format, value), custom)
+    }
+
+    /// Like [`Self::encode`], interning ids if enabled or given a `shared` dictionary.
+    fn encode_ids<T: Serialize + Interned>(
+        &self,
+        value: &T,
+        shared: Option<&mut SharedDictionary>,
+        custom: impl FnOnce(&dyn MessageCodec) -> Result<Bytes>,
+    ) -> Result<Bytes> {
+        if self.intern_ids || shared.is_some() {
+            self.encode_with(|format| value.encode_interned(format, shared), custom)
+        } else {
+            self.encode(value, custom)
+        }
+    }
+
+    fn encode_with(
+        &self,
+        encode: impl FnOnce(BinaryFormat) -> Result<Bytes>,
+        custom: impl FnOnce(&dyn MessageCodec) -> Result<Bytes>,
+    ) -> Result<Bytes> {
+        let encoded = match &self.codec {
+            Codec::Format(format) => encode(*format)?,
+            Codec::Custom(codec) => custom(codec.as_ref())?,
+        };
+
+        match self.compression {
+            CompressionType::None => Ok(encoded),
+            #[cfg(feature = "std")]
+            compression => Ok(Bytes::from(crate::compression::compress(&encoded, compression)?)),
+            #[cfg(not(feature = "std"))]
+            _ => Err(LinkError::Serialization("Compression requires the `std` feature".to_string())),
+        }
+    }
+
+    /// Decompress and decode `data`, attaching a [`DeserializationContext`]
+    /// naming `target` to any error.
+    fn decode<T: DeserializeOwned>(
+        &self,
+        target: &'static str,
+        data: &[u8],
+        custom: impl FnOnce(&dyn MessageCodec, &[u8]) -> Result<T>,
+    ) -> Result<T> {
+        self.decode_with(target, data, decode_value, custom)
+    }
+
+    /// Like [`Self::decode`], rehydrating interned ids if enabled or given a
+    /// `shared` dictionary.
+    fn decode_ids<T: DeserializeOwned + Interned>(
+        &self,
+        target: &'static str,
+        data: &[u8],
+        shared: Option<&mut SharedDictionary>,
+        custom: impl FnOnce(&dyn MessageCodec, &[u8]) -> Result<T>,
+    ) -> Result<T> {
+        if self.intern_ids || shared.is_some() {
+            self.decode_with(target, data, |format, data| T::decode_interned(format, data, shared), custom)
+        } else {
+            self.decode(target, data, custom)
+        }
+    }
+
+    fn decode_with<T>(
+        &self,
+        target: &'static str,
+        data: &[u8],
+        decode: impl FnOnce(BinaryFormat, &[u8]) -> Result<T>,
+        custom: impl FnOnce(&dyn MessageCodec, &[u8]) -> Result<T>,
+    ) -> Result<T> {
+        let fail = |source: LinkError, decoded: Option<&[u8]>| {
+            let position = match &source {
+                LinkError::Json(e) if e.line() > 0 => Some((e.line(), e.column())),
+                _ => None,
+            };
+            LinkError::Decode {
+                context: DeserializationContext {
+                    format: self.format_name().to_string(),
+                    target,
+                    length: data.len(),
+                    position,
+                    offset: decoded.zip(position).and_then(|(decoded, (line, column))| {
+                        line_column_offset(decoded, line, column)
+                    }),
+                },
+                source: Box::new(source),
+            }
+        };
+        let decode_uncompressed = |data: &[u8]| match &self.codec {
+            Codec::Format(format) => decode(*format, data),
+            Codec::Custom(codec) => custom(codec.as_ref(), data),
+        }.map_err(|source| fail(source, Some(data)));
+
+        if data.len() > self.limits.max_message_bytes {
+            return Err(LinkError::InvalidMessage(format!(
+                "Message of {} bytes exceeds the limit of {}", data.len(), self.limits.max_message_bytes
+            )));
+        }
+
+        match self.compression {
+            CompressionType::None => decode_uncompressed(data),
+            #[cfg(feature = "std")]
+            compression => crate::compression::decompress_bounded(
+                data,
+                compression,
+                self.limits.max_decompressed_bytes,
+            ).map_err(|source| fail(source, None)).and_then(|decompressed| {
+                let decompressed = Bytes::from(decompressed);
+                let _source = BinarySourceGuard::new(&decompressed);
+                decode_uncompressed(&decompressed)
+            }),
+            #[cfg(not(feature = "std"))]
+            _ => Err(fail(LinkError::Deserialization("Compression requires the `std` feature".to_string()), None)),
+        }
+    }
+
+    fn format_name(&self) -> &str {
+        match &self.codec {
+            Codec::Format(format) => format.name(),
+            Codec::Custom(codec) => codec.name(),
+        }
+    }
+
+    /// The built-in format in use. A serializer made with [`Self::with_codec`]
+    /// reports the MessagePack default it starts from; use [`Self::codec`] to
+    /// tell the two apart.
+    pub fn get_format(&self) -> BinaryFormat {
+        self.builtin_format().unwrap_or(BinaryFormat::MessagePack)
+    }
+
+    /// The codec messages, snapshots and deltas are encoded with: the
+    /// built-in format, or the one passed to [`Self::with_codec`].
+    pub fn codec(&self) -> &dyn MessageCodec {
+        match &self.codec {
+            Codec::Format(format) => format,
+            Codec::Custom(codec) => codec.as_ref(),
+        }
+    }
+
+    fn builtin_format(&self) -> Option<BinaryFormat> {
+        match self.codec {
+            Codec::Format(format) => Some(format),
+            Codec::Custom(_) => None,
+        }
+    }
+}
+
+/// Byte offset of a 1-based `line` and `column`, as serde_json reports them,
+/// in `data`.
+fn line_column_offset(data: &[u8], line: usize, column: usize) -> Option<usize> {
+    let line_start = if line == 1 {
+        0
+    } else {
+        data.iter().enumerate().filter(|(_, &b)| b == b'\n').nth(line - 2)?.0 + 1
+    };
+    Some((line_start + column.saturating_sub(1)).min(data.len()))
+}
+
+fn unsupported(what: &str) -> LinkError {
+    LinkError::Serialization(format!("Custom codecs don't encode {}", what))
+}
+
+pub(crate) fn encode_value<T: Serialize>(format: BinaryFormat, value: &T) -> Result<Bytes> {
+    match format {
+        BinaryFormat::Json => {
+            let json = serde_json::to_vec(value)?;
+            Ok(Bytes::from(json))
+        }
+        #[cfg(feature = "std")]
+        BinaryFormat::MessagePack => {
+            let msgpack = rmp_serde::to_vec(value)?;
+            Ok(Bytes::from(msgpack))
+        }
+        #[cfg(not(feature = "std"))]
+        BinaryFormat::MessagePack => Err(LinkError::Serialization(
+            "MessagePack requires the `std` feature".to_string(),
+        )),
+        BinaryFormat::Bincode => {
+            let bincode_data = bincode::serde::encode_to_vec(value, bincode::config::legacy())?;
+            Ok(Bytes::from(bincode_data))
+        }
+    }
+}
+
+/// [`encode_value`], appending to `buffer`. A failed encoding leaves
+/// `buffer` as it was.
+#[cfg(feature = "std")]
+fn encode_value_into<T: Serialize>(format: BinaryFormat, value: &T, buffer: &mut BytesMut) -> Result<()> {
+    let start = buffer.len();
+    let mut writer = (&mut *buffer).writer();
+    let result = match format {
+        BinaryFormat::Json => serde_json::to_writer(&mut writer, value).map_err(Into::into),
+        BinaryFormat::MessagePack => rmp_serde::encode::write(&mut writer, value).map_err(Into::into),
+        BinaryFormat::Bincode => bincode::serde::encode_into_std_write(value, &mut writer, bincode::config::legacy())
+            .map(drop)
+            .map_err(Into::into),
+    };
+    if result.is_err() {
+        buffer.truncate(start);
+    }
+    result
+}
+
+pub(crate) fn decode_value<T: DeserializeOwned>(format: BinaryFormat, data: &[u8]) -> Result<T> {
+    match format {
+        BinaryFormat::Json => {
+            let value = serde_json::from_slice(data)?;
+            Ok(value)
+        }
+        #[cfg(feature = "std")]
+        BinaryFormat::MessagePack => {
+            let value = rmp_serde::from_slice(data)?;
+            Ok(value)
+        }
+        #[cfg(not(feature = "std"))]
+        BinaryFormat::MessagePack => Err(LinkError::Deserialization(
+            "MessagePack requires the `std` feature".to_string(),
+        )),
+        BinaryFormat::Bincode => {
+            let (value, _) = bincode::serde::borrow_decode_from_slice(data, bincode::config::legacy())?;
+            Ok(value)
+        }
+    }
+}
+
+pub struct StreamingSerializer {
+    serializer: BinarySerializer,
+    frame_compression: CompressionType,
+    timestamps: Option<TimestampCodec>,
+    dictionary: Option<SharedDictionary>,
+    max_dictionary_len: usize,
+    buffer: BytesMut,
+}
+
+/// Default for [`StreamingSerializer::with_max_dictionary_len`].
+pub const DEFAULT_MAX_DICTIONARY_LEN: usize = 4096;
+
+impl StreamingSerializer {
+    pub fn new(format: BinaryFormat) -> Self {
+        Self::with_serializer(BinarySerializer::new(format))
+    }
+
+    /// Frame messages encoded by `serializer`, keeping its compression and sorting settings.
+    pub fn with_serializer(serializer: BinarySerializer) -> Self {
+        Self {
+            serializer,
+            frame_compression: CompressionType::None,
+            timestamps: None,
+            dictionary: None,
+            max_dictionary_len: DEFAULT_MAX_DICTIONARY_LEN,
+            buffer: BytesMut::with_capacity(8192),
+        }
+    }
+
+    /// Intern component and field ids against a dictionary shared by the
+    /// whole stream: each id is sent in the first frame that uses it, and
+    /// every later frame refers to it by index. Implies
+    /// [`BinarySerializer::with_interned_ids`] for every frame. The reading
+    /// side needs the same setting and must see every frame; see
+    /// [`Self::reset_dictionary`].
+    pub fn with_shared_dictionary(mut self, enabled: bool) -> Self {
+        self.dictionary = enabled.then(SharedDictionary::default);
+        self
+    }
+
+    /// Start the shared dictionary over. The next frame carries every id it
+    /// uses again and tells the reading side to drop its copy, e.g. after a
+    /// reader joined mid-stream or the set of components changed.
+    pub fn reset_dictionary(&mut self) {
+        if let Some(dictionary) = &mut self.dictionary {
+            dictionary.clear();
+        }
+    }
+
+    /// Reset the shared dictionary before a frame once it holds `max` ids,
+    /// so streams with ever-new ids don't grow it without bound. The reading
+    /// side needs a `max` at least as large.
+    pub fn with_max_dictionary_len(mut self, max: usize) -> Self {
+        self.max_dictionary_len = max;
+        self
+    }
+
+    /// Encode the timestamps of frames written with [`Self::write_delta`] as
+    /// varint delta-of-deltas against the previous delta frame instead of two
+    /// `f64`s. The reading side needs the same setting and must see every frame.
+    pub fn with_delta_timestamps(mut self, enabled: bool) -> Self {
+        self.timestamps = enabled.then(TimestampCodec::default);
+        self
+    }
+
+    /// Compress each frame body after encoding; the length prefix covers the
+    /// compressed bytes. The reading side needs the same setting.
+    pub fn with_frame_compression(mut self, compression: CompressionType) -> Self {
+        self.frame_compression = compression;
+        self
+    }
+
+    pub fn write_message(&mut self, message: &Message) -> Result<()> {
+        let mut prefix = BytesMut::new();
+        let base = put_dictionary_base(&mut self.dictionary, self.max_dictionary_len, &mut prefix);
+        let written = self.serializer.serialize_message_shared(message, self.dictionary.as_mut())
+            .and_then(|data| compress_frame(prefixed(prefix, data), self.frame_compression))
+            .and_then(|data| FrameCodec::encode(&data, &mut self.buffer));
+        self.keep_dictionary_if(written, base)
+    }
+
+    /// Frame a [`Delta`], read back with [`StreamingDeserializer::try_read_delta`].
+    pub fn write_delta(&mut self, delta: &Delta) -> Result<()> {
+        let mut prefix = BytesMut::new();
+        let base = put_dictionary_base(&mut self.dictionary, self.max_dictionary_len, &mut prefix);
+        let written = self.encode_delta_frame(delta, prefix)
+            .and_then(|data| FrameCodec::encode(&data, &mut self.buffer));
+        self.keep_dictionary_if(written, base)
+    }
+
+    fn encode_delta_frame(&mut self, delta: &Delta, mut prefix: BytesMut) -> Result<Bytes> {
+        let dictionary = self.dictionary.as_mut();
+        let data = match &mut self.timestamps {
+            Some(timestamps) => {
+                timestamps.encode(delta.timestamp, delta.base_timestamp, &mut prefix);
+                // Custom codecs only know whole deltas; their timestamps are
+                // ignored on the way back in.
+                self.serializer.encode_ids(&delta.changes, dictionary, |codec| codec.encode_delta(delta))?
+            }
+            None => self.serializer.encode_ids(delta, dictionary, |codec| codec.encode_delta(delta))?,
+        };
+        compress_frame(prefixed(prefix, data), self.frame_compression)
+    }
+
+    /// Ids are only shared once their frame is in the buffer: drop the ones a
+    /// failed frame added, which the reading side never sees.
+    fn keep_dictionary_if(&mut self, written: Result<()>, base: usize) -> Result<()> {
+        if written.is_err() {
+            if let Some(dictionary) = &mut self.dictionary {
+                dictionary.truncate(base);
+            }
+        }
+        written
+    }
+
+    pub fn flush(&mut self) -> Bytes {
+        self.buffer.split().freeze()
+    }
+
+    /// Drop buffered frames and start the timestamp chain and shared dictionary over.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        if let Some(timestamps) = &mut self.timestamps {
+            *timestamps = TimestampCodec::default();
+        }
+        self.reset_dictionary();
+    }
+}
+
+pub struct StreamingDeserializer {
+    serializer: BinarySerializer,
+    frame_compression: CompressionType,
+    timestamps: Option<TimestampCodec>,
+    dictionary: Option<SharedDictionary>,
+    max_dictionary_len: usize,
+    buffer: BytesMut,
+}
+
+impl StreamingDeserializer {
+    pub fn new(format: BinaryFormat) -> Self {
+        Self::with_serializer(BinarySerializer::new(format))
+    }
+
+    /// Decode frames with `serializer`, which must match the writing side's settings.
+    pub fn with_serializer(serializer: BinarySerializer) -> Self {
+        Self {
+            serializer,
+            frame_compression: CompressionType::None,
+            timestamps: None,
+            dictionary: None,
+            max_dictionary_len: DEFAULT_MAX_DICTIONARY_LEN,
+            buffer: BytesMut::with_capacity(8192),
+        }
+    }
+
+    /// Read frames written with [`StreamingSerializer::with_shared_dictionary`].
+    pub fn with_shared_dictionary(mut self, enabled: bool) -> Self {
+        self.dictionary = enabled.then(SharedDictionary::default);
+        self
+    }
+
+    /// Reject frames that build on a shared dictionary of `max` ids or more;
+    /// see [`StreamingSerializer::with_max_dictionary_len`].
+    pub fn with_max_dictionary_len(mut self, max: usize) -> Self {
+        self.max_dictionary_len = max;
+        self
+    }
+
+    /// Read delta frames written with [`StreamingSerializer::with_delta_timestamps`].
+    pub fn with_delta_timestamps(mut self, enabled: bool) -> Self {
+        self.timestamps = enabled.then(TimestampCodec::default);
+        self
+    }
+
+    /// Change the message compression expected in frames read from now on.
+    pub fn set_compression(&mut self, compression: CompressionType) {
+        self.serializer.set_compression(compression);
+    }
+
+    /// Decompress frame bodies written with [`StreamingSerializer::with_frame_compression`].
+    pub fn with_frame_compression(mut self, compression: CompressionType) -> Self {
+        self.frame_compression = compression;
+        self
+    }
+
+    pub fn feed(&mut self, data: &[u8]) {
+        self.buffer.extend_from_slice(data);
+    }
+
+    /// Split the next complete frame off the buffer, failing as soon as its
+    /// prefix claims more than the serializer's `max_message_bytes`.
+    fn next_frame(&mut self) -> Result<Option<Bytes>> {
+        if let Some(prefix) = self.buffer.first_chunk::<{ FrameCodec::PREFIX_LEN }>() {
+            FrameCodec::check_len(FrameCodec::decode_prefix(*prefix), self.serializer.limits.max_message_bytes)?;
+        }
+        Ok(FrameCodec::decode(&mut self.buffer))
+    }
+
+    pub fn try_read_message(&mut self) -> Result<Option<Message>> {
+        let Some(message_data) = self.next_frame()? else {
+            return Ok(None);
+        };
+        let mut message_data = decompress_frame(message_data, self.frame_compression, &self.serializer.limits)?;
+        let dictionary = get_dictionary_base(&mut self.dictionary, self.max_dictionary_len, &mut message_data)?;
+
+        let message = self.serializer.deserialize_message_bytes_shared(&message_data, dictionary)?;
+
+        Ok(Some(message))
+    }
+
+    pub fn try_read_delta(&mut self) -> Result<Option<Delta>> {
+        let Some(data) = self.next_frame()? else {
+            return Ok(None);
+        };
+        let mut data = decompress_frame(data, self.frame_compression, &self.serializer.limits)?;
+        let dictionary = get_dictionary_base(&mut self.dictionary, self.max_dictionary_len, &mut data)?;
+
+        let delta = match &mut self.timestamps {
+            Some(timestamps) => {
+                let (timestamp, base_timestamp) = timestamps.decode(&mut data)?;
+                Delta {
+                    changes: self.serializer.decode_ids("delta", &data, dictionary, |codec, data| {
+                        codec.decode_delta(data).map(|delta| delta.changes)
+                    })?,
+                    timestamp,
+                    base_timestamp,
+                }
+            }
+            None => self.serializer.decode_ids("delta", &data, dictionary, |codec, data| codec.decode_delta(data))?,
+        };
+        self.serializer.limits.check_changes(&delta.changes)?;
+
+        Ok(Some(delta))
+    }
+
+    /// Drop buffered bytes and start the timestamp chain and shared dictionary over.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+        if let Some(timestamps) = &mut self.timestamps {
+            *timestamps = TimestampCodec::default();
+        }
+        if let Some(dictionary) = &mut self.dictionary {
+            dictionary.clear();
+        }
+    }
+}
+
+/// With a shared dictionary, frames start with the number of shared ids they
+/// build on as a varint; 0 tells the reader to start its dictionary over.
+/// Returns that number, starting over first once the dictionary holds `max` ids.
+fn put_dictionary_base(dictionary: &mut Option<SharedDictionary>, max: usize, prefix: &mut BytesMut) -> usize {
+    let Some(dictionary) = dictionary.as_mut() else {
+        return 0;
+    };
+
+    if dictionary.len() >= max {
+        dictionary.clear();
+    }
+    put_varint(prefix, dictionary.len() as u64);
+    dictionary.len()
+}
+
+fn get_dictionary_base<'a>(
+    dictionary: &'a mut Option<SharedDictionary>,
+    max: usize,
+    data: &mut Bytes,
+) -> Result<Option<&'a mut SharedDictionary>> {
+    let Some(dictionary) = dictionary.as_mut() else {
+        return Ok(None);
+    };
+
+    match get_varint(data)? {
+        0 => dictionary.clear(),
+        _ if dictionary.len() >= max => {
+            return Err(LinkError::Deserialization(format!(
+                "Frame builds on {} shared ids, more than the limit of {}",
+                dictionary.len(),
+                max
+            )));
+        }
+        base if base != dictionary.len() as u64 => {
+            return Err(LinkError::Deserialization(format!(
+                "Frame builds on {} shared ids but {} were received; a frame was missed",
+                base,
+                dictionary.len()
+            )));
+        }
+        _ => {}
+    }
+    Ok(Some(dictionary))
+}
+
+fn prefixed(mut prefix: BytesMut, data: Bytes) -> Bytes {
+    if prefix.is_empty() {
+        return data;
+    }
+    prefix.put(data);
+    prefix.freeze()
+}
+
+/// Delta-of-delta coding of `f64` timestamps on their bit patterns, so values
+/// reconstruct exactly. A steady frame rate gives near-zero second differences,
+/// and a base equal to the previous frame's timestamp costs a single byte.
+#[derive(Debug, Clone, Copy, Default)]
+struct TimestampCodec {
+    previous: u64,
+    previous_step: u64,
+}
+
+impl TimestampCodec {
+    fn encode(&mut self, timestamp: f64, base_timestamp: f64, buffer: &mut BytesMut) {
+        let bits = timestamp.to_bits();
+        let step = bits.wrapping_sub(self.previous);
+
+        put_varint(buffer, zigzag(step.wrapping_sub(self.previous_step)));
+        put_varint(buffer, zigzag(base_timestamp.to_bits().wrapping_sub(self.previous)));
+
+        self.previous = bits;
+        self.previous_step = step;
+    }
+
+    fn decode(&mut self, data: &mut Bytes) -> Result<(f64, f64)> {
+        let step = self.previous_step.wrapping_add(unzigzag(get_varint(data)?));
+        let base = self.previous.wrapping_add(unzigzag(get_varint(data)?));
+        let bits = self.previous.wrapping_add(step);
+
+        self.previous = bits;
+        self.previous_step = step;
+        Ok((f64::from_bits(bits), f64::from_bits(base)))
+    }
+}
+
+fn zigzag(value: u64) -> u64 {
+    let value = value as i64;
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> u64 {
+    (value >> 1) ^ (value & 1).wrapping_neg()
+}
+
+fn put_varint(buffer: &mut BytesMut, mut value: u64) {
+    while value >= 0x80 {
+        buffer.put_u8(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buffer.put_u8(value as u8);
+}
+
+fn get_varint(data: &mut Bytes) -> Result<u64> {
+    let mut value = 0u64;
+    let mut shift = 0;
+    loop {
+        if !data.has_remaining() {
+            return Err(LinkError::Deserialization("Truncated varint".to_string()));
+        }
+        let byte = data.get_u8();
+        let bits = u64::from(byte & 0x7f);
+        // The tenth byte only has room for the top bit of a u64.
+        if shift == 63 && (bits > 1 || byte & 0x80 != 0) {
+            return Err(LinkError::Deserialization("Varint overflows 64 bits".to_string()));
+        }
+        value |= bits << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+        shift += 7;
+    }
+}
+
+fn compress_frame(data: Bytes, compression: CompressionType) -> Result<Bytes> {
+    match compression {
+        CompressionType::None => Ok(data),
+        #[cfg(feature = "std")]
+        compression => Ok(Bytes::from(crate::compression::compress(&data, compression)?)),
+        #[cfg(not(feature = "std"))]
+        _ => Err(LinkError::Serialization("Compression requires the `std` feature".to_string())),
+    }
+}
+
+/// Inflate a frame body, to no more than `limits.max_decompressed_bytes`.
+#[cfg_attr(not(feature = "std"), allow(unused_variables))]
+fn decompress_frame(data: Bytes, compression: CompressionType, limits: &MessageLimits) -> Result<Bytes> {
+    match compression {
+        CompressionType::None => Ok(data),
+        #[cfg(feature = "std")]
+        compression => Ok(Bytes::from(crate::compression::decompress_bounded(
+            &data,
+            compression,
+            limits.max_decompressed_bytes,
+        )?)),
+        #[cfg(not(feature = "std"))]
+        _ => Err(LinkError::Deserialization("Compression requires the `std` feature".to_string())),
+    }
+}
+
+/// Length-prefix framing shared by the streaming serializers and the stream
+/// transports.
+///
+/// A frame is the body's length as a `u32` in little-endian byte order, on
+/// every platform, followed by the body. Bodies over `u32::MAX` bytes can't
+/// be framed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FrameCodec;
+
+impl FrameCodec {
+    pub const PREFIX_LEN: usize = 4;
+
+    pub fn encode_prefix(len: usize) -> Result<[u8; 4]> {
+        u32::try_from(len)
+            .map(u32::to_le_bytes)
+            .map_err(|_| LinkError::Serialization(format!("Frame of {} bytes is too large", len)))
+    }
+
+    pub fn decode_prefix(prefix: [u8; 4]) -> usize {
+        u32::from_le_bytes(prefix) as usize
+    }
+
+    /// Append `data` as one frame to `buffer`.
+    pub fn encode(data: &[u8], buffer: &mut BytesMut) -> Result<()> {
+        buffer.put_slice(&Self::encode_prefix(data.len())?);
+        buffer.put_slice(data);
+        Ok(())
+    }
+
+    /// Split the first frame's body off `buffer`, or `None` if it hasn't fully arrived.
+    pub fn decode(buffer: &mut BytesMut) -> Option<Bytes> {
+        let prefix = buffer.get(..Self::PREFIX_LEN)?;
+        let len = Self::decode_prefix([prefix[0], prefix[1], prefix[2], prefix[3]]);
+
+        if buffer.len() < Self::PREFIX_LEN + len {
+            return None;
+        }
+
+        buffer.advance(Self::PREFIX_LEN);
+        Some(buffer.split_to(len).freeze())
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_to<W: Write>(writer: &mut W, data: &[u8]) -> Result<()> {
+        writer.write_all(&Self::encode_prefix(data.len())?)?;
+        writer.write_all(data)?;
+        Ok(())
+    }
+
+    /// Read one frame's body, blocking until it arrives. `None` if the reader
+    /// ends before the next frame starts. Frames over the default
+    /// [`MessageLimits::max_message_bytes`] are rejected.
+    #[cfg(feature = "std")]
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>> {
+        Self::read_from_bounded(reader, MessageLimits::default().max_message_bytes)
+    }
+
+    /// Like [`Self::read_from`], rejecting frames longer than `max_len` before
+    /// allocating their body.
+    #[cfg(feature = "std")]
+    pub fn read_from_bounded<R: Read>(reader: &mut R, max_len: usize) -> Result<Option<Vec<u8>>> {
+        let mut prefix = [0u8; 4];
+        match reader.read_exact(&mut prefix) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        let len = Self::decode_prefix(prefix);
+        Self::check_len(len, max_len)?;
+
+        let mut body = vec![0u8; len];
+        reader.read_exact(&mut body)?;
+        Ok(Some(body))
+    }
+
+    /// Fail if a frame body of `len` bytes is over `max_len`.
+    pub fn check_len(len: usize, max_len: usize) -> Result<()> {
+        if len > max_len {
+            return Err(LinkError::InvalidMessage(format!(
+                "Frame of {} bytes exceeds the limit of {}", len, max_len
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_frame_codec() {
+        let mut buffer = BytesMut::new();
+        FrameCodec::encode(b"abc", &mut buffer).unwrap();
+        FrameCodec::encode(b"", &mut buffer).unwrap();
+        assert_eq!(&buffer[..7], &[3, 0, 0, 0, b'a', b'b', b'c']);
+
+        let mut partial = BytesMut::from(&buffer[..6]);
+        assert!(FrameCodec::decode(&mut partial).is_none());
+        assert_eq!(partial.len(), 6);
+
+        assert_eq!(FrameCodec::decode(&mut buffer).unwrap(), Bytes::from_static(b"abc"));
+        assert_eq!(FrameCodec::decode(&mut buffer).unwrap(), Bytes::new());
+        assert!(buffer.is_empty());
+
+        let mut stream = Vec::new();
+        FrameCodec::write_to(&mut stream, b"xyz").unwrap();
+        let mut reader = stream.as_slice();
+        assert_eq!(FrameCodec::read_from(&mut reader).unwrap().unwrap(), b"xyz");
+        assert!(FrameCodec::read_from(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_frame_read_is_bounded() {
+        let mut stream = Vec::new();
+        FrameCodec::write_to(&mut stream, b"abcd").unwrap();
+        assert!(FrameCodec::read_from_bounded(&mut stream.as_slice(), 4).unwrap().is_some());
+        assert!(matches!(
+            FrameCodec::read_from_bounded(&mut stream.as_slice(), 3),
+            Err(LinkError::InvalidMessage(_))
+        ));
+
+        // A prefix claiming 4 GiB fails before anything is allocated.
+        let forged = u32::MAX.to_le_bytes();
+        assert!(FrameCodec::read_from(&mut forged.as_slice()).is_err());
+
+        let limits = MessageLimits::new().with_max_message_bytes(16);
+        let mut deserializer = StreamingDeserializer::with_serializer(BinarySerializer::messagepack().with_limits(limits));
+        deserializer.feed(&forged);
+        assert!(matches!(deserializer.try_read_message(), Err(LinkError::InvalidMessage(_))));
+    }
+
+    #[test]
+    fn test_decode_enforces_limits() {
+        let snapshot = WorldSnapshot {
+            entities: (0..3).map(|id| SerializedEntity { id, components: vec![] }).collect(),
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        let message = Message::snapshot(snapshot.entities.clone(), 1.0, SchemaVersion::new(1));
+
+        let serializer = BinarySerializer::messagepack().with_compression(CompressionType::Lz4);
+        let data = serializer.serialize_message(&message).unwrap();
+        let snapshot_data = serializer.serialize_snapshot(&snapshot).unwrap();
+        let too_small = |limits: MessageLimits| {
+            let serializer = serializer.clone().with_limits(limits);
+            assert!(serializer.deserialize_message(&data).is_err());
+            assert!(serializer.deserialize_snapshot(&snapshot_data).is_err());
+        };
+
+        too_small(MessageLimits::new().with_max_message_bytes(data.len().min(snapshot_data.len()) - 1));
+        too_small(MessageLimits::new().with_max_decompressed_bytes(8));
+        too_small(MessageLimits::new().with_max_entities(2));
+
+        // Channel messages are checked through to the payload they carry.
+        let wrapped = serializer.serialize_message(&Message::channel(1, message)).unwrap();
+        let limited = serializer.clone().with_limits(MessageLimits::new().with_max_entities(2));
+        assert!(matches!(limited.deserialize_message(&wrapped), Err(LinkError::InvalidMessage(_))));
+        assert!(serializer.deserialize_message(&wrapped).is_ok());
+    }
+
+    #[test]
+    fn test_json_serialization() {
+        let serializer = BinarySerializer::json();
+        let message = Message::ping(SchemaVersion::new(1));
+
+        let serialized = serializer.serialize_message(&message).unwrap();
+        let deserialized = serializer.deserialize_message(&serialized).unwrap();
+
+        assert_eq!(message.header.msg_type, deserialized.header.msg_type);
+    }
+
+    #[test]
+    fn test_reliable_delivery_classification() {
+        assert!(Message::snapshot(vec![], 1.0, SchemaVersion::new(1)).is_reliable());
+        assert!(Message::ack(7, SchemaVersion::new(1)).is_reliable());
+        assert!(MessageType::SchemaSync.requires_reliable_delivery());
+        assert!(!Message::delta(vec![], 0, SchemaVersion::new(1)).is_reliable());
+        assert!(!Message::ping(SchemaVersion::new(1)).is_reliable());
+        assert!(!Message::pong(SchemaVersion::new(1)).is_reliable());
+    }
+
+    #[test]
+    fn test_schema_version_is_a_bare_integer_on_the_wire() {
+        let message = Message::ping(SchemaVersion::new(7));
+        let json = serde_json::to_value(&message.header).unwrap();
+        assert_eq!(json["schema_version"], 7);
+
+        let mut version = SchemaVersion::INITIAL;
+        version.increment();
+        assert_eq!(version, SchemaVersion::new(2));
+        assert!(version.next() > version);
+        assert_eq!(version.to_string(), "2");
+    }
+
+    #[test]
+    fn test_messagepack_serialization() {
+        let serializer = BinarySerializer::messagepack();
+        let message = Message::ping(SchemaVersion::new(1));
+
+        let serialized = serializer.serialize_message(&message).unwrap();
+        let deserialized = serializer.deserialize_message(&serialized).unwrap();
+
+        assert_eq!(message.header.msg_type, deserialized.header.msg_type);
+    }
+
+    #[test]
+    fn test_decode_errors_carry_context() {
+        let json = b"{\n  \"header\": 5\n}";
+        let error = BinarySerializer::json().deserialize_message(json).unwrap_err();
+        let context = error.deserialization_context().unwrap();
+        assert_eq!(context.format, "JSON");
+        assert_eq!(context.target, "message");
+        assert_eq!(context.length, json.len());
+        assert_eq!(context.position.map(|(line, _)| line), Some(2));
+        assert_eq!(context.offset, Some(json.iter().position(|&b| b == b'5').unwrap()));
+        assert!(matches!(error.without_context(), LinkError::Json(_)));
+        assert!(error.to_string().starts_with(&format!("Failed to decode {}-byte JSON message at line 2", json.len())));
+
+        let serializer = BinarySerializer::messagepack().with_compression(CompressionType::Lz4);
+        let encoded = serializer.serialize_snapshot(&WorldSnapshot {
+            entities: Vec::new(),
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        }).unwrap();
+        let error = serializer.deserialize_snapshot(&encoded[..encoded.len() - 1]).unwrap_err();
+        assert!(matches!(
+            error,
+            LinkError::Decode { context: DeserializationContext { target: "snapshot", position: None, offset: None, .. }, .. }
+        ));
+        assert_eq!(error.deserialization_context().unwrap().length, encoded.len() - 1);
+    }
+
+    #[test]
+    fn test_compact_payload_tags() {
+        let version = SchemaVersion::new(1);
+        let messages = [
+            Message::ping(version),
+            Message::ack(7, version),
+            Message::request_snapshot_since(3, version),
+            Message::error(ErrorCode::Timeout, "late".to_string(), version),
+            Message::encrypted(Bytes::from_static(b"sealed"), version),
+        ];
+        for format in [BinaryFormat::MessagePack, BinaryFormat::Bincode] {
+            let serializer = BinarySerializer::new(format);
+            for message in &messages {
+                let decoded = serializer.deserialize_message(&serializer.serialize_message(message).unwrap()).unwrap();
+                assert_eq!(format!("{:?}", decoded.payload), format!("{:?}", message.payload));
+            }
+        }
+
+        // Binary formats tag with the `MessageType`, JSON keeps the name.
+        assert_eq!(rmp_serde::to_vec(&MessagePayload::Ping).unwrap(), [0x92, MessageType::Ping as u8, 0xc0]);
+        assert_eq!(serde_json::to_string(&MessagePayload::Ping).unwrap(), r#"{"type":"ping"}"#);
+
+        let future: MessagePayload = rmp_serde::from_slice(&rmp_serde::to_vec(&(200u8, "body")).unwrap()).unwrap();
+        assert!(matches!(future, MessagePayload::Unknown));
+    }
+
+    #[test]
+    fn test_unknown_payload_in_bincode() {
+        let header = Message::ping(SchemaVersion::new(1)).header;
+        let future = bincode::serde::encode_to_vec((&header, (200u8, "body")), bincode::config::legacy()).unwrap();
+
+        // Bincode can't skip a body it has no type for, so the message is
+        // rejected rather than misread.
+        let error = BinarySerializer::bincode().deserialize_message(&future).unwrap_err();
+        assert!(error.to_string().contains("200"), "{}", error);
+
+        let known = BinarySerializer::bincode().serialize_message(&Message::ping(SchemaVersion::new(1))).unwrap();
+        assert!(BinarySerializer::bincode().deserialize_message(&known).is_ok());
+    }
+
+    #[test]
+    fn test_nested_channel_payload_rejected() {
+        let header = Message::ping(SchemaVersion::new(1)).header;
+        let encode = |depth: usize| {
+            let mut bytes = bincode::serde::encode_to_vec(&header, bincode::config::legacy()).unwrap();
+            for _ in 0..depth {
+                bytes.extend([MessageType::Channel as u8, 0]);
+            }
+            bytes.push(MessageType::Ping as u8);
+            bytes
+        };
+
+        let serializer = BinarySerializer::bincode();
+        let decoded = serializer.deserialize_message(&encode(1)).unwrap();
+        assert!(matches!(decoded.payload, MessagePayload::Channel { ref payload, .. } if matches!(**payload, MessagePayload::Ping)));
+
+        // Deep nesting used to recurse until the stack overflowed.
+        for depth in [2, 200_000] {
+            let error = serializer.deserialize_message(&encode(depth)).unwrap_err();
+            assert!(error.to_string().contains("nested channel"), "{}", error);
+        }
+
+        let nested = Message::channel(1, Message::channel(0, Message::ping(SchemaVersion::new(1))));
+        for format in [BinaryFormat::Json, BinaryFormat::MessagePack] {
+            let serializer = BinarySerializer::new(format);
+            assert!(serializer.deserialize_message(&serializer.serialize_message(&nested).unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_bincode_serialization() {
+        let serializer = BinarySerializer::bincode();
+
+        let snapshot = WorldSnapshot {
+            entities: vec![],
+            timestamp: 100.0,
+            version: "1.0.0".to_string(),
+        };
+
+        let serialized = serializer.serialize_snapshot(&snapshot).unwrap();
+        let deserialized = serializer.deserialize_snapshot(&serialized).unwrap();
+
+        assert_eq!(snapshot.timestamp, deserialized.timestamp);
+    }
+
+    #[test]
+    fn test_bincode_error_source() {
+        use std::error::Error;
+
+        let error = BinarySerializer::bincode().deserialize_snapshot(&[1, 2, 3]).unwrap_err();
+        assert!(matches!(error.without_context(), LinkError::BincodeDecode(_)));
+
+        let mut source = error.source();
+        while let Some(inner) = source {
+            if inner.downcast_ref::<bincode::error::DecodeError>().is_some() {
+                return;
+            }
+            source = inner.source();
+        }
+        panic!("bincode's error is missing from the source chain of {}", error);
+    }
+
+    /// JSON behind a magic byte, standing in for a user-defined format.
+    struct TaggedJson;
+
+    impl TaggedJson {
+        fn wrap(data: Bytes) -> Result<Bytes> {
+            let mut tagged = BytesMut::from(&[0xA5][..]);
+            tagged.put(data);
+            Ok(tagged.freeze())
+        }
+
+        fn unwrap(data: &[u8]) -> Result<&[u8]> {
+            match data.split_first() {
+                Some((0xA5, rest)) => Ok(rest),
+                _ => Err(LinkError::Deserialization("Missing tag".to_string())),
+            }
+        }
+    }
+
+    impl MessageCodec for TaggedJson {
+        fn name(&self) -> &str {
+            "TaggedJson"
+        }
+
+        fn encode(&self, message: &Message) -> Result<Bytes> {
+            Self::wrap(BinaryFormat::Json.encode(message)?)
+        }
+
+        fn decode(&self, data: &[u8]) -> Result<Message> {
+            BinaryFormat::Json.decode(Self::unwrap(data)?)
+        }
+
+        fn encode_snapshot(&self, snapshot: &WorldSnapshot) -> Result<Bytes> {
+            Self::wrap(BinaryFormat::Json.encode_snapshot(snapshot)?)
+        }
+
+        fn decode_snapshot(&self, data: &[u8]) -> Result<WorldSnapshot> {
+            BinaryFormat::Json.decode_snapshot(Self::unwrap(data)?)
+        }
+
+        fn encode_delta(&self, delta: &Delta) -> Result<Bytes> {
+            Self::wrap(BinaryFormat::Json.encode_delta(delta)?)
+        }
+
+        fn decode_delta(&self, data: &[u8]) -> Result<Delta> {
+            BinaryFormat::Json.decode_delta(Self::unwrap(data)?)
+        }
+    }
+
+    #[test]
+    fn test_custom_codec() {
+        let serializer = BinarySerializer::with_codec(TaggedJson)
+            .with_compression(CompressionType::Lz4);
+        assert_eq!(serializer.get_format(), BinaryFormat::MessagePack);
+        assert_eq!(serializer.codec().name(), "TaggedJson");
+        assert_eq!(BinarySerializer::bincode().codec().name(), "Bincode");
+
+        let message = Message::ping(SchemaVersion::new(1));
+        let serialized = serializer.serialize_message(&message).unwrap();
+        let deserialized = serializer.deserialize_message(&serialized).unwrap();
+        assert_eq!(message.header.msg_type, deserialized.header.msg_type);
+
+        let snapshot = WorldSnapshot {
+            entities: vec![],
+            timestamp: 100.0,
+            version: "1.0.0".to_string(),
+        };
+        let serialized = serializer.serialize_snapshot(&snapshot).unwrap();
+        assert_eq!(serializer.deserialize_snapshot(&serialized).unwrap().timestamp, 100.0);
+
+        let uncompressed = BinarySerializer::with_codec(TaggedJson)
+            .serialize_snapshot(&snapshot)
+            .unwrap();
+        assert_eq!(uncompressed[0], 0xA5);
+
+        let component = SerializedComponent {
+            id: "Position".to_string(),
+            data: ComponentData::from_json_value(serde_json::json!({"x": 1.0})),
+        };
+        assert!(serializer.serialize_component(&component).is_err());
+
+        let mut stream_serializer = StreamingSerializer::with_serializer(serializer.clone())
+            .with_delta_timestamps(true);
+        let mut stream_deserializer = StreamingDeserializer::with_serializer(serializer)
+            .with_delta_timestamps(true);
+        let delta = Delta {
+            changes: vec![DeltaChange::EntityRemoved { entity_id: 3 }],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+        stream_serializer.write_delta(&delta).unwrap();
+        stream_deserializer.feed(&stream_serializer.flush());
+
+        let decoded = stream_deserializer.try_read_delta().unwrap().unwrap();
+        assert_eq!(decoded.timestamp, 2.0);
+        assert_eq!(decoded.base_timestamp, 1.0);
+        assert_eq!(decoded.changes.len(), 1);
+    }
+
+    #[test]
+    fn test_serialize_message_into() {
+        let entities = (0..20)
+            .map(|id| SerializedEntity {
+                id,
+                components: vec![SerializedComponent {
+                    id: "Position".to_string(),
+                    data: ComponentData::Structured([("x".to_string(), FieldValue::F64(id as f64))].into_iter().collect()),
+                }],
+            })
+            .collect();
+        let message = Message::snapshot(entities, 1.0, SchemaVersion::new(1));
+
+        for serializer in [
+            BinarySerializer::json(),
+            BinarySerializer::messagepack(),
+            BinarySerializer::bincode(),
+            BinarySerializer::messagepack().with_compression(CompressionType::Lz4),
+        ] {
+            let expected = serializer.serialize_message(&message).unwrap();
+            let mut buffer = BytesMut::from(&b"ab"[..]);
+            serializer.serialize_message_into(&message, &mut buffer).unwrap();
+            assert_eq!(&buffer[..2], b"ab");
+            assert_eq!(&buffer[2..], &expected[..]);
+
+            // A cleared buffer is written again without reallocating.
+            let start = buffer.as_ptr();
+            buffer.clear();
+            serializer.serialize_message_into(&message, &mut buffer).unwrap();
+            assert_eq!((buffer.as_ptr(), &buffer[..]), (start, &expected[..]));
+        }
+    }
+
+    #[test]
+    fn test_streaming_serialization() {
+        let mut stream_serializer = StreamingSerializer::new(BinaryFormat::MessagePack);
+        let mut stream_deserializer = StreamingDeserializer::new(BinaryFormat::MessagePack);
+
+        let msg1 = Message::ping(SchemaVersion::new(1));
+        let msg2 = Message::pong(SchemaVersion::new(1));
+
+        stream_serializer.write_message(&msg1).unwrap();
+        stream_serializer.write_message(&msg2).unwrap();
+
+        let data = stream_serializer.flush();
+        stream_deserializer.feed(&data);
+
+        let decoded1 = stream_deserializer.try_read_message().unwrap().unwrap();
+        let decoded2 = stream_deserializer.try_read_message().unwrap().unwrap();
+
+        assert_eq!(msg1.header.msg_type, decoded1.header.msg_type);
+        assert_eq!(msg2.header.msg_type, decoded2.header.msg_type);
+    }
+
+    #[test]
+    fn test_streaming_incremental_feed() {
+        let mut stream_serializer = StreamingSerializer::new(BinaryFormat::MessagePack);
+        let mut stream_deserializer = StreamingDeserializer::new(BinaryFormat::MessagePack);
+
+        let sent: Vec<u64> = (0..3).collect();
+        for &ack_id in &sent {
+            stream_serializer.write_message(&Message::ack(ack_id, SchemaVersion::new(1))).unwrap();
+        }
+        let data = stream_serializer.flush();
+
+        // One byte at a time, so frames arrive split inside the prefix and the body.
+        let mut received = Vec::new();
+        for byte in data.iter() {
+            stream_deserializer.feed(&[*byte]);
+            while let Some(message) = stream_deserializer.try_read_message().unwrap() {
+                match message.payload {
+                    MessagePayload::Ack { ack_id, .. } => received.push(ack_id),
+                    other => panic!("expected ack, got {:?}", other),
+                }
+            }
+        }
+
+        assert_eq!(received, sent);
+        assert!(stream_deserializer.try_read_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_streaming_delta_timestamps() {
+        let deltas: Vec<Delta> = (1..500u32)
+            .map(|frame| {
+                let mut timestamp = frame as f64 / 60.0;
+                if frame % 97 == 0 {
+                    timestamp += 0.123_456_789;
+                }
+                Delta {
+                    changes: vec![DeltaChange::EntityRemoved { entity_id: frame }],
+                    timestamp,
+                    base_timestamp: if frame % 50 == 0 { 0.0 } else { (frame - 1) as f64 / 60.0 },
+                }
+            })
+            .collect();
+
+        let mut plain = StreamingSerializer::new(BinaryFormat::MessagePack);
+        let mut compact = StreamingSerializer::new(BinaryFormat::MessagePack).with_delta_timestamps(true);
+        for delta in &deltas {
+            plain.write_delta(delta).unwrap();
+            compact.write_delta(delta).unwrap();
+        }
+        let plain = plain.flush();
+        let compact = compact.flush();
+        assert!(compact.len() < plain.len());
+
+        let mut reader = StreamingDeserializer::new(BinaryFormat::MessagePack).with_delta_timestamps(true);
+        reader.feed(&compact);
+        for delta in &deltas {
+            let decoded = reader.try_read_delta().unwrap().unwrap();
+            assert_eq!(decoded.timestamp.to_bits(), delta.timestamp.to_bits());
+            assert_eq!(decoded.base_timestamp.to_bits(), delta.base_timestamp.to_bits());
+            assert_eq!(decoded.changes.len(), 1);
+        }
+        assert!(reader.try_read_delta().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_streaming_lz4_frames() {
+        let messages: Vec<Message> = (0..4u32)
+            .map(|i| {
+                let entities = (0..32)
+                    .map(|id| SerializedEntity {
+                        id,
+                        components: vec![SerializedComponent {
+                            id: "Position".to_string(),
+                            data: ComponentData::from_json_value(serde_json::json!({"x": 0.0, "y": 0.0, "z": 0.0})),
+                        }],
+                    })
+                    .collect();
+                Message::snapshot(entities, i as f64, SchemaVersion::new(1))
+            })
+            .collect();
+
+        let mut plain = StreamingSerializer::new(BinaryFormat::MessagePack);
+        let mut compressed = StreamingSerializer::new(BinaryFormat::MessagePack)
+            .with_frame_compression(CompressionType::Lz4);
+        for message in &messages {
+            plain.write_message(message).unwrap();
+            compressed.write_message(message).unwrap();
+        }
+
+        let plain = plain.flush();
+        let compressed = compressed.flush();
+        assert!(compressed.len() < plain.len() / 2);
+
+        let mut deserializer = StreamingDeserializer::new(BinaryFormat::MessagePack)
+            .with_frame_compression(CompressionType::Lz4);
+        deserializer.feed(&compressed);
+
+        let serializer = BinarySerializer::messagepack();
+        for message in &messages {
+            let decoded = deserializer.try_read_message().unwrap().unwrap();
+            assert_eq!(
+                serializer.serialize_message(&decoded).unwrap(),
+                serializer.serialize_message(message).unwrap()
+            );
+        }
+        assert!(deserializer.try_read_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_non_finite_floats_roundtrip() {
+        let fields: HashMap<FieldId, FieldValue> = [
+            ("nan", FieldValue::F64(f64::NAN)),
+            ("inf", FieldValue::F64(f64::INFINITY)),
+            ("neg_inf", FieldValue::F32(f32::NEG_INFINITY)),
+            ("finite", FieldValue::F64(1.5)),
+            ("nested", FieldValue::Array(vec![FieldValue::F32(f32::NAN)])),
+        ]
+        .into_iter()
+        .map(|(k, v)| (k.to_string(), v))
+        .collect();
+        let data = ComponentData::Structured(fields);
+        let message = Message::snapshot(vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent { id: "Stats".to_string(), data: data.clone() }],
+        }], 1.0, SchemaVersion::new(1));
+
+        for serializer in [BinarySerializer::json(), BinarySerializer::messagepack()] {
+            let encoded = serializer.serialize_message(&message).unwrap();
+            match serializer.deserialize_message(&encoded).unwrap().payload {
+                MessagePayload::Snapshot(mut payload) => {
+                    assert!(payload.entities.remove(0).components.remove(0).data.same_as(&data));
+                }
+                other => panic!("expected snapshot, got {:?}", other),
+            }
+        }
+
+        let json = String::from_utf8(BinarySerializer::json().serialize_message(&message).unwrap().to_vec()).unwrap();
+        assert!(json.contains(r#"{"F64":"NaN"}"#));
+        assert!(json.contains(r#"{"F32":"-Infinity"}"#));
+
+        let from_null: FieldValue = serde_json::from_str(r#"{"F64":null}"#).unwrap();
+        assert!(from_null.same_as(&FieldValue::F64(f64::NAN)));
+        assert!(!FieldValue::F64(0.0).same_as(&FieldValue::F64(-0.0)));
+        assert_ne!(FieldValue::F64(f64::NAN), FieldValue::F64(f64::NAN));
+        assert_eq!(FieldValue::F64(0.0), FieldValue::F64(-0.0));
+    }
+
+    #[test]
+    fn test_varint_bounds() {
+        for value in [0, 1, 127, 128, u64::from(u32::MAX), u64::MAX] {
+            let mut buffer = BytesMut::new();
+            put_varint(&mut buffer, value);
+            assert_eq!(get_varint(&mut buffer.freeze()).unwrap(), value);
+        }
+
+        let varint = |bytes: &[u8]| get_varint(&mut Bytes::copy_from_slice(bytes));
+        let mut max = [0xff; 10];
+        max[9] = 0x01;
+        assert_eq!(varint(&max).unwrap(), u64::MAX);
+        max[9] = 0x02;
+        assert!(varint(&max).is_err());
+        assert!(varint(&[0xff; 11]).is_err());
+        assert!(varint(&[0x80, 0x80]).is_err());
+    }
+
+    #[test]
+    fn test_binary_component_zero_copy() {
+        let blob = Bytes::from(vec![7u8; 64 * 1024]);
+        let entities = vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Mesh".to_string(),
+                data: ComponentData::Binary(blob.clone()),
+            }],
+        }];
+        let message = Message::snapshot(entities, 1.0, SchemaVersion::new(1));
+
+        let binary_of = |message: Message| match message.payload {
+            MessagePayload::Snapshot(mut payload) => match payload.entities.remove(0).components.remove(0).data {
+                ComponentData::Binary(data) => data,
+                other => panic!("expected binary data, got {:?}", other),
+            },
+            other => panic!("expected snapshot, got {:?}", other),
+        };
+
+        let serializer = BinarySerializer::messagepack();
+        let encoded = serializer.serialize_message(&message).unwrap();
+        let decoded = binary_of(serializer.deserialize_message_bytes(&encoded).unwrap());
+        assert_eq!(decoded, blob);
+
+        let range = encoded.as_ptr() as usize..encoded.as_ptr() as usize + encoded.len();
+        assert!(range.contains(&(decoded.as_ptr() as usize)));
+
+        // A decode that unwinds still drops its source, so later plain decodes copy.
+        let unwound = std::panic::catch_unwind(|| {
+            let _source = BinarySourceGuard::new(&encoded);
+            panic!("decoder panicked");
+        });
+        assert!(unwound.is_err());
+        let copied = binary_of(serializer.deserialize_message(&encoded).unwrap());
+        assert!(!range.contains(&(copied.as_ptr() as usize)));
+
+        let json = BinarySerializer::json();
+        let encoded = json.serialize_message(&message).unwrap();
+        assert_eq!(binary_of(json.deserialize_message_bytes(&encoded).unwrap()), blob);
+    }
+
+    #[test]
+    fn test_delta_changes_for_entity() {
+        let update = |entity_id, component_id: &str| DeltaChange::ComponentUpdated {
+            entity_id,
+            component_id: component_id.to_string(),
+            data: ComponentData::Empty,
+        };
+        let delta = Delta {
+            changes: vec![
+                DeltaChange::EntityAdded { entity_id: 7 },
+                update(42, "Health"),
+                update(7, "Position"),
+                DeltaChange::FieldsUpdated { entity_id: 42, component_id: "Position".to_string(), fields: vec![] },
+                DeltaChange::ComponentRemoved { entity_id: 42, component_id: "Health".to_string() },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+
+        assert_eq!(delta.changes_for_entity(42).count(), 3);
+        assert_eq!(delta.changes_for_entity(7).count(), 2);
+        assert_eq!(delta.changed_components(42), ["Health", "Position"]);
+        assert_eq!(delta.changed_components(7), ["Position"]);
+        assert!(delta.changed_components(1).is_empty());
+    }
+
+    #[test]
+    fn test_changed_entities() {
+        let entity = |id: u32, x: f64| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({"x": x})),
+            }],
+        };
+        let snapshot = |entities: Vec<SerializedEntity>| WorldSnapshot::for_test(0.0, entities);
+
+        let before = snapshot(vec![entity(1, 0.0), entity(2, 0.0), entity(3, 0.0)]);
+        let mut tagged = entity(3, 0.0);
+        tagged.components.push(SerializedComponent { id: "Dead".to_string(), data: ComponentData::Empty });
+        let after = snapshot(vec![entity(4, 0.0), tagged, entity(2, 1.0)]);
+
+        let changed = before.changed_entities(&after);
+        assert_eq!(changed, ChangedEntities {
+            added: vec![4],
+            removed: vec![1],
+            modified: vec![2, 3],
+        });
+        assert!(after.changed_entities(&after).is_empty());
+    }
+
+    #[test]
+    fn test_validate_snapshot() {
+        let component = |id: &str| SerializedComponent {
+            id: id.to_string(),
+            data: ComponentData::Empty,
+        };
+        let mut snapshot = WorldSnapshot {
+            entities: vec![
+                SerializedEntity { id: 1, components: vec![component("Player"), component("Health")] },
+                SerializedEntity { id: 2, components: vec![component("Player")] },
+            ],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        assert!(snapshot.validate().is_ok());
+
+        snapshot.entities[0].components.push(component("Player"));
+        let error = snapshot.validate().unwrap_err().to_string();
+        assert!(error.contains("Duplicate component Player on entity 1"), "{}", error);
+
+        snapshot.entities[0].components.pop();
+        snapshot.entities.push(SerializedEntity { id: 2, components: vec![] });
+        assert!(matches!(snapshot.validate(), Err(LinkError::InvalidMessage(m)) if m == "Duplicate entity 2"));
+    }
+
+    #[test]
+    fn test_snapshot_serialization() {
+        let snapshot = WorldSnapshot {
+            entities: vec![
+                SerializedEntity {
+                    id: 1,
+                    components: vec![
+                        SerializedComponent {
+                            id: "Position".to_string(),
+                            data: ComponentData::from_json_value(serde_json::json!({"x": 10.0, "y": 20.0})),
+                        }
+                    ],
+                }
+            ],
+            timestamp: 123.456,
+            version: "1.0.0".to_string(),
+        };
+
+        let serializer = BinarySerializer::messagepack();
+        let serialized = serializer.serialize_snapshot(&snapshot).unwrap();
+        let deserialized = serializer.deserialize_snapshot(&serialized).unwrap();
+
+        assert_eq!(snapshot.entities.len(), deserialized.entities.len());
+        assert_eq!(snapshot.timestamp, deserialized.timestamp);
+        assert_eq!(snapshot.version, deserialized.version);
+    }
+
+    #[test]
+    fn test_snapshot_and_delta_equality() {
+        let snapshot = WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 1,
+                components: vec![SerializedComponent { id: "Player".to_string(), data: ComponentData::Empty }],
+            }],
+            timestamp: f64::NAN,
+            version: "1.0.0".to_string(),
+        };
+        assert_eq!(snapshot, snapshot.clone());
+
+        let mut round_trip = snapshot.clone();
+        round_trip.timestamp = 123.456;
+        let serializer = BinarySerializer::messagepack();
+        let serialized = serializer.serialize_snapshot(&round_trip).unwrap();
+        assert_eq!(serializer.deserialize_snapshot(&serialized).unwrap(), round_trip);
+
+        let mut jittered = snapshot.clone();
+        jittered.timestamp = 0.1 + 0.2;
+        let mut expected = snapshot.clone();
+        expected.timestamp = 0.3;
+        assert_ne!(jittered, expected);
+        assert!(jittered.approx_eq(&expected, 1e-9));
+        expected.entities[0].components[0].id = "Dead".to_string();
+        assert!(!jittered.approx_eq(&expected, 1e-9));
+
+        let delta = Delta {
+            changes: vec![DeltaChange::EntityRemoved { entity_id: 1 }],
+            timestamp: 0.1 + 0.2,
+            base_timestamp: 0.1,
+        };
+        let mut other = delta.clone();
+        other.timestamp = 0.3;
+        assert_ne!(delta, other);
+        assert!(delta.approx_eq(&other, 1e-9));
+        other.changes.push(DeltaChange::EntityAdded { entity_id: 2 });
+        assert!(!delta.approx_eq(&other, 1e-9));
+    }
+
+    #[test]
+    fn test_interned_ids() {
+        use std::collections::HashMap;
+
+        let fields: HashMap<FieldId, FieldValue> = ["x", "y", "z"].iter()
+            .map(|name| (name.to_string(), FieldValue::F32(1.5)))
+            .collect();
+        let snapshot = WorldSnapshot {
+            entities: (0..100)
+                .map(|id| SerializedEntity {
+                    id,
+                    components: vec![
+                        SerializedComponent { id: "Position".to_string(), data: ComponentData::Structured(fields.clone()) },
+                        SerializedComponent { id: "Health".to_string(), data: ComponentData::Binary(Bytes::from_static(&[7])) },
+                    ],
+                })
+                .collect(),
+            timestamp: 2.0,
+            version: "1.0.0".to_string(),
+        };
+        let delta = Delta {
+            changes: vec![
+                DeltaChange::ComponentRemoved { entity_id: 4, component_id: "Health".to_string() },
+                DeltaChange::FieldsUpdated {
+                    entity_id: 4,
+                    component_id: "Position".to_string(),
+                    fields: vec![FieldDelta::new("x".to_string(), None, FieldValue::F32(2.0))],
+                },
+            ],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+
+        for format in [BinaryFormat::Json, BinaryFormat::MessagePack, BinaryFormat::Bincode] {
+            let plain = BinarySerializer::new(format);
+            let interned = BinarySerializer::new(format).with_interned_ids(true);
+            assert!(!plain.same_encoding(&interned));
+
+            let bytes = interned.serialize_snapshot(&snapshot).unwrap();
+            assert!(bytes.len() < plain.serialize_snapshot(&snapshot).unwrap().len());
+            let decoded = interned.deserialize_snapshot(&bytes).unwrap();
+            assert_eq!(decoded.entities[99].components[0].id, "Position");
+            assert_eq!(decoded.entities[99].components[0].data, snapshot.entities[99].components[0].data);
+            assert_eq!(decoded.entities[99].components[1].data, snapshot.entities[99].components[1].data);
+
+            let message = Message::delta(delta.changes.clone(), 1, SchemaVersion::new(1));
+            let decoded = interned.deserialize_message(&interned.serialize_message(&message).unwrap()).unwrap();
+            let MessagePayload::Delta(payload) = decoded.payload else { panic!("expected a delta") };
+            assert_eq!(payload.changes[0].component_id().unwrap(), "Health");
+            assert!(matches!(
+                &payload.changes[1],
+                DeltaChange::FieldsUpdated { fields, .. } if fields[0].field_id == "x"
+            ));
+
+            let ping = Message::ping(SchemaVersion::new(1));
+            let decoded = interned.deserialize_message(&interned.serialize_message(&ping).unwrap()).unwrap();
+            assert!(matches!(decoded.payload, MessagePayload::Ping));
+        }
+    }
+
+    #[test]
+    fn test_shared_stream_dictionary() {
+        let delta = |x: f32| Delta {
+            changes: vec![DeltaChange::FieldsUpdated {
+                entity_id: 4,
+                component_id: "Position".to_string(),
+                fields: vec![FieldDelta::new("x".to_string(), None, FieldValue::F32(x))],
+            }],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+        for format in [BinaryFormat::Json, BinaryFormat::MessagePack, BinaryFormat::Bincode] {
+            let mut writer = StreamingSerializer::new(format).with_shared_dictionary(true);
+            let mut frames = Vec::new();
+            writer.write_delta(&delta(1.0)).unwrap();
+            frames.push(writer.flush());
+            writer.write_delta(&delta(2.0)).unwrap();
+            frames.push(writer.flush());
+            writer.write_message(&Message::delta(delta(3.0).changes, 1, SchemaVersion::new(1))).unwrap();
+            frames.push(writer.flush());
+            writer.reset_dictionary();
+            writer.write_delta(&delta(4.0)).unwrap();
+            frames.push(writer.flush());
+            assert!(frames[1].len() < frames[0].len());
+            assert_eq!(frames[3].len(), frames[0].len());
+
+            let mut reader = StreamingDeserializer::new(format).with_shared_dictionary(true);
+            frames.iter().for_each(|frame| reader.feed(frame));
+            assert_eq!(reader.try_read_delta().unwrap().unwrap().changes, delta(1.0).changes);
+            assert_eq!(reader.try_read_delta().unwrap().unwrap().changes, delta(2.0).changes);
+            let MessagePayload::Delta(payload) = reader.try_read_message().unwrap().unwrap().payload else {
+                panic!("expected a delta")
+            };
+            assert_eq!(payload.changes, delta(3.0).changes);
+            assert_eq!(reader.try_read_delta().unwrap().unwrap().changes, delta(4.0).changes);
+
+            // A reader that missed the frame introducing the ids can't follow.
+            let mut late = StreamingDeserializer::new(format).with_shared_dictionary(true);
+            late.feed(&frames[1]);
+            assert!(late.try_read_delta().is_err());
+        }
+    }
+
+    #[test]
+    fn test_shared_dictionary_cap_and_failed_frames() {
+        let delta = |component: &str| Delta {
+            changes: vec![DeltaChange::ComponentRemoved { entity_id: 1, component_id: component.to_string() }],
+            timestamp: 2.0,
+            base_timestamp: 1.0,
+        };
+        let shared = |writer: &StreamingSerializer| writer.dictionary.as_ref().unwrap().len();
+
+        // The dictionary starts over once full, and the reader follows.
+        let mut writer = StreamingSerializer::new(BinaryFormat::MessagePack)
+            .with_shared_dictionary(true)
+            .with_max_dictionary_len(2);
+        let mut reader = StreamingDeserializer::new(BinaryFormat::MessagePack)
+            .with_shared_dictionary(true)
+            .with_max_dictionary_len(2);
+        for component in ["A", "B", "C", "A"] {
+            writer.write_delta(&delta(component)).unwrap();
+            reader.feed(&writer.flush());
+            assert_eq!(reader.try_read_delta().unwrap().unwrap().changes, delta(component).changes);
+        }
+        assert_eq!(shared(&writer), 2);
+
+        // A reader with a smaller cap refuses to follow.
+        let mut writer = StreamingSerializer::new(BinaryFormat::MessagePack).with_shared_dictionary(true);
+        let mut reader = StreamingDeserializer::new(BinaryFormat::MessagePack)
+            .with_shared_dictionary(true)
+            .with_max_dictionary_len(1);
+        writer.write_delta(&delta("A")).unwrap();
+        writer.write_delta(&delta("B")).unwrap();
+        reader.feed(&writer.flush());
+        reader.try_read_delta().unwrap();
+        assert!(reader.try_read_delta().is_err());
+
+        // Ids of a frame that never made it into the buffer aren't shared.
+        let mut writer = StreamingSerializer::new(BinaryFormat::MessagePack)
+            .with_shared_dictionary(true)
+            .with_frame_compression(CompressionType::Zstd);
+        assert!(writer.write_delta(&delta("A")).is_err());
+        assert!(writer.write_message(&Message::delta(delta("B").changes, 1, SchemaVersion::new(1))).is_err());
+        assert_eq!(shared(&writer), 0);
+        assert!(writer.flush().is_empty());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_snapshots() {
+        let snapshot = WorldSnapshot {
+            entities: (0..1000)
+                .map(|id| SerializedEntity {
+                    id,
+                    components: vec![SerializedComponent {
+                        id: "Health".to_string(),
+                        data: ComponentData::Binary(Bytes::from(id.to_le_bytes().to_vec())),
+                    }],
+                })
+                .collect(),
+            timestamp: 5.0,
+            version: "1.0.0".to_string(),
+        };
+
+        let pool = Arc::new(rayon::ThreadPoolBuilder::new().num_threads(2).build().unwrap());
+        for serializer in [
+            BinarySerializer::bincode().with_parallel_snapshots(64),
+            BinarySerializer::messagepack()
+                .with_parallel_snapshots(100)
+                .with_interned_ids(true)
+                .with_compression(CompressionType::Lz4)
+                .with_thread_pool(pool.clone()),
+        ] {
+            assert!(!serializer.same_encoding(&BinarySerializer::new(serializer.get_format())));
+
+            let encoded = serializer.serialize_snapshot(&snapshot).unwrap();
+            assert_eq!(serializer.deserialize_snapshot(&encoded).unwrap(), snapshot);
+            assert!(serializer.deserialize_snapshot(&encoded[..encoded.len() - 1]).is_err());
+
+            let empty = WorldSnapshot { entities: Vec::new(), ..snapshot.clone() };
+            let encoded = serializer.serialize_snapshot(&empty).unwrap();
+            assert_eq!(serializer.deserialize_snapshot(&encoded).unwrap(), empty);
+        }
+    }
+
+    #[test]
+    fn test_sorted_snapshot_bytes_are_reproducible() {
+        use std::collections::HashMap;
+
+        let entity = |id: EntityId| {
+            let mut fields = HashMap::new();
+            for (i, name) in ["a", "b", "c", "d", "e", "f"].iter().enumerate() {
+                fields.insert(name.to_string(), FieldValue::U32(i as u32));
+            }
+
+            SerializedEntity {
+                id,
+                components: vec![
+                    SerializedComponent { id: "Velocity".to_string(), data: ComponentData::Structured(fields.clone()) },
+                    SerializedComponent { id: "Position".to_string(), data: ComponentData::Structured(fields) },
+                ],
+            }
+        };
+
+        let forward = WorldSnapshot {
+            entities: vec![entity(1), entity(2), entity(3)],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        let mut reversed = forward.clone();
+        reversed.entities.reverse();
+
+        let serializer = BinarySerializer::bincode().with_sorted_snapshots(true);
+        assert_eq!(
+            serializer.serialize_snapshot(&forward).unwrap(),
+            serializer.serialize_snapshot(&reversed).unwrap(),
+        );
+
+        assert_eq!(forward.content_hash(), reversed.content_hash());
+        let mut changed = forward.clone();
+        changed.entities[0].components[0].data = ComponentData::Binary(Bytes::from_static(&[1]));
+        assert_ne!(forward.content_hash(), changed.content_hash());
+
+        reversed.sort();
+        assert_eq!(reversed.entities[0].id, 1);
+        assert_eq!(reversed.entities[0].components[0].id, "Position");
+    }
+
+    #[test]
+    fn test_canonical_component_order() {
+        let component = |id: &str| SerializedComponent {
+            id: id.to_string(),
+            data: ComponentData::from_json_value(serde_json::json!({"id": id})),
+        };
+        let snapshot = |ids: [&str; 3]| WorldSnapshot::for_test(1.0, vec![
+            SerializedEntity { id: 2, components: ids.iter().map(|id| component(id)).collect() },
+            SerializedEntity { id: 1, components: vec![component("Health")] },
+        ]);
+
+        let mut built = snapshot(["Velocity", "Health", "Position"]);
+        let mut shuffled = snapshot(["Position", "Velocity", "Health"]);
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        assert_ne!(serializer.serialize_snapshot(&built).unwrap(), serializer.serialize_snapshot(&shuffled).unwrap());
+
+        built.sort_components();
+        shuffled.sort_components();
+        let encoded = serializer.serialize_snapshot(&built).unwrap();
+        assert_eq!(encoded, serializer.serialize_snapshot(&shuffled).unwrap());
+        assert_eq!(serializer.deserialize_snapshot(&encoded).unwrap(), shuffled);
+        assert_eq!(built.entities.iter().map(|e| e.id).collect::<Vec<_>>(), vec![2, 1]);
+        assert_eq!(built.entities[0].components[0].id, "Health");
     }
 }
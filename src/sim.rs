@@ -0,0 +1,284 @@
+use crate::clock::{self, Clock};
+use crate::error::Result;
+use crate::protocol::{CompressionType, Message};
+use crate::transport::Transport;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Network conditions applied by [`SimTransport`].
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub latency: Duration,
+    /// Extra delay drawn uniformly from `0..=jitter` per message.
+    pub jitter: Duration,
+    pub loss_probability: f64,
+    /// Chance that a message is held back an extra `latency + jitter`, letting
+    /// the messages behind it overtake.
+    pub reorder_probability: f64,
+    pub seed: u64,
+}
+
+impl Default for SimConfig {
+    fn default() -> Self {
+        Self {
+            latency: Duration::ZERO,
+            jitter: Duration::ZERO,
+            loss_probability: 0.0,
+            reorder_probability: 0.0,
+            seed: 0,
+        }
+    }
+}
+
+impl SimConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_latency(mut self, latency: Duration) -> Self {
+        self.latency = latency;
+        self
+    }
+
+    pub fn with_jitter(mut self, jitter: Duration) -> Self {
+        self.jitter = jitter;
+        self
+    }
+
+    pub fn with_loss(mut self, probability: f64) -> Self {
+        self.loss_probability = probability;
+        self
+    }
+
+    pub fn with_reordering(mut self, probability: f64) -> Self {
+        self.reorder_probability = probability;
+        self
+    }
+
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+}
+
+/// Wraps a transport and makes its incoming messages behave like a bad network.
+///
+/// Every message received from the inner transport is dropped or given a
+/// simulated arrival time; `receive` only returns messages whose arrival time
+/// has passed on the clock. The same seed always produces the same drops and
+/// delays, so tests using a `MockClock` are reproducible. Sends go straight
+/// to the inner transport.
+pub struct SimTransport<T: Transport> {
+    inner: T,
+    config: SimConfig,
+    clock: Arc<dyn Clock>,
+    rng: SplitMix64,
+    in_flight: Vec<InFlight>,
+    next_sequence: u64,
+    dropped: u64,
+}
+
+struct InFlight {
+    arrival: Instant,
+    sequence: u64,
+    message: Message,
+}
+
+impl<T: Transport> SimTransport<T> {
+    pub fn new(inner: T, config: SimConfig) -> Self {
+        Self::with_clock(inner, config, clock::system_clock())
+    }
+
+    pub fn with_clock(inner: T, config: SimConfig, clock: Arc<dyn Clock>) -> Self {
+        Self {
+            inner,
+            rng: SplitMix64(config.seed),
+            config,
+            clock,
+            in_flight: Vec::new(),
+            next_sequence: 0,
+            dropped: 0,
+        }
+    }
+
+    pub fn get_inner(&self) -> &T {
+        &self.inner
+    }
+
+    pub fn get_inner_mut(&mut self) -> &mut T {
+        &mut self.inner
+    }
+
+    /// Messages received from the inner transport that haven't arrived yet.
+    pub fn in_flight_count(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped
+    }
+
+    fn pull(&mut self) -> Result<()> {
+        while let Some(message) = self.inner.receive()? {
+            if self.rng.next_f64() < self.config.loss_probability {
+                self.dropped += 1;
+                continue;
+            }
+
+            let mut delay = self.config.latency + self.config.jitter.mul_f64(self.rng.next_f64());
+            if self.rng.next_f64() < self.config.reorder_probability {
+                delay += self.config.latency + self.config.jitter;
+            }
+
+            self.in_flight.push(InFlight {
+                arrival: self.clock.now() + delay,
+                sequence: self.next_sequence,
+                message,
+            });
+            self.next_sequence += 1;
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: Transport> Transport for SimTransport<T> {
+    fn send(&mut self, message: &Message) -> Result<()> {
+        self.inner.send(message)
+    }
+
+    fn receive(&mut self) -> Result<Option<Message>> {
+        self.pull()?;
+
+        let now = self.clock.now();
+        let next = self.in_flight.iter()
+            .enumerate()
+            .filter(|(_, m)| m.arrival <= now)
+            .min_by_key(|(_, m)| (m.arrival, m.sequence))
+            .map(|(i, _)| i);
+
+        Ok(next.map(|i| self.in_flight.remove(i).message))
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.inner.flush()
+    }
+
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        self.inner.supports_compression(compression)
+    }
+
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.inner.set_compression(compression)
+    }
+
+    fn close(&mut self) -> Result<()> {
+        self.in_flight.clear();
+        self.inner.close()
+    }
+
+    fn is_connected(&self) -> bool {
+        self.inner.is_connected()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.inner.peer_addr()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+/// Small deterministic generator; quality only needs to be good enough for
+/// picking drops and delays.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::clock::MockClock;
+    use crate::protocol::{MessagePayload, SchemaVersion};
+    use crate::serialization::BinaryFormat;
+    use crate::transport::MemoryTransport;
+
+    fn sim(config: SimConfig, clock: &MockClock, count: u64) -> SimTransport<MemoryTransport> {
+        let format = BinaryFormat::MessagePack;
+        let mut sender = MemoryTransport::new(format);
+        for ack_id in 0..count {
+            sender.send(&Message::ack(ack_id, SchemaVersion::new(1))).unwrap();
+        }
+
+        let mut inner = MemoryTransport::new(format);
+        sender.connect_to(&mut inner);
+        SimTransport::with_clock(inner, config, Arc::new(clock.clone()))
+    }
+
+    fn drain(transport: &mut SimTransport<MemoryTransport>) -> Vec<u64> {
+        std::iter::from_fn(|| transport.receive().unwrap())
+            .map(|m| match m.payload {
+                MessagePayload::Ack { ack_id, .. } => ack_id,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_latency() {
+        let clock = MockClock::new();
+        let mut transport = sim(SimConfig::new().with_latency(Duration::from_millis(50)), &clock, 3);
+
+        assert!(drain(&mut transport).is_empty());
+        assert_eq!(transport.in_flight_count(), 3);
+
+        clock.advance(Duration::from_millis(50));
+        assert_eq!(drain(&mut transport), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_loss() {
+        let clock = MockClock::new();
+        let mut transport = sim(SimConfig::new().with_loss(1.0), &clock, 3);
+
+        assert!(drain(&mut transport).is_empty());
+        assert_eq!(transport.dropped_count(), 3);
+    }
+
+    #[test]
+    fn test_seeded_jitter_is_reproducible() {
+        let config = SimConfig::new()
+            .with_latency(Duration::from_millis(20))
+            .with_jitter(Duration::from_millis(40))
+            .with_reordering(0.3)
+            .with_seed(7);
+
+        let run = || {
+            let clock = MockClock::new();
+            let mut transport = sim(config.clone(), &clock, 20);
+            assert!(drain(&mut transport).is_empty());
+            clock.advance(Duration::from_millis(200));
+            drain(&mut transport)
+        };
+
+        let order = run();
+        assert_eq!(order.len(), 20);
+        assert_ne!(order, (0..20).collect::<Vec<_>>());
+        assert_eq!(order, run());
+    }
+}
@@ -1,11 +1,21 @@
 use crate::error::{LinkError, Result};
 use crate::protocol::*;
-use crate::serialization::{WorldSnapshot, Delta};
+use crate::serialization::{BinaryFormat, BinarySerializer, WorldSnapshot, Delta};
 use crate::transport::Transport;
-use crate::compression::DeltaCompressor;
-use crate::rate_limit::{RateLimiter, RateLimitConfig};
-use crate::schema::{SchemaRegistry, SchemaVersion};
+use crate::compression::{BandwidthStats, DeltaCompressor};
+use crate::rate_limit::{RateLimiter, RateLimitConfig, RateLimitPolicy, SlidingWindow};
+use crate::schema::{SchemaRegistry, SchemaValidator, SchemaVersion};
+use crate::clock::{self, Clock};
+use crate::debug;
+use ahash::{AHashMap, AHashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
+use serde::Serialize;
+use bytes::{Bytes, BytesMut};
+
+/// Unanswered pings kept for matching against pongs; older ones are dropped.
+const MAX_PENDING_PINGS: usize = 8;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SyncMode {
@@ -14,16 +24,58 @@ pub enum SyncMode {
     Manual,
 }
 
+/// Lifecycle of a `SyncManager`'s link to its peer.
+///
+/// A manager starts in `Connecting` (or `Disconnected` if the transport is not
+/// connected) and moves to `Connected` after the first successful exchange.
+/// `negotiate_schemas` inserts `SchemaNegotiating` until the peer's schemas
+/// arrive. A dead transport moves to `Reconnecting` while auto-reconnect
+/// attempts remain, then to `Disconnected`; `close` ends in `Closed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConnectionState {
+    Disconnected,
+    Connecting,
+    SchemaNegotiating,
+    Connected,
+    Reconnecting,
+    Closed,
+}
+
 #[derive(Debug, Clone)]
 pub struct SyncConfig {
     pub mode: SyncMode,
     pub sync_interval: Duration,
+    pub adaptive_interval: Option<(Duration, Duration)>,
     pub enable_rate_limiting: bool,
     pub rate_limit_config: RateLimitConfig,
     pub enable_field_compression: bool,
+    pub skip_unchanged: bool,
+    pub canonical_components: bool,
+    pub canonical_json: bool,
+    pub keyframe_history: usize,
+    pub validate_incoming: bool,
+    pub strict_validation: bool,
+    pub supported_compression: Vec<CompressionType>,
+    pub full_snapshot_threshold: f64,
+    pub full_snapshot_hysteresis: f64,
+    pub exact_snapshot_size: bool,
+    pub changed_entity_threshold: Option<f64>,
+    pub max_delta_chain: Option<u64>,
+    pub ack_baselines: bool,
+    pub compact_field_deltas: bool,
+    pub compress_components: bool,
+    pub reduce_precision: bool,
+    pub coerce_numeric_fields: bool,
+    pub limits: MessageLimits,
     pub auto_reconnect: bool,
     pub max_reconnect_attempts: u32,
     pub reconnect_delay: Duration,
+    pub keepalive_interval: Option<Duration>,
+    pub keepalive_timeout: Duration,
+    pub stats_window: Option<Duration>,
+    pub bandwidth_stats: bool,
+    pub size_warning: Option<u64>,
+    pub duplicate_window: Option<usize>,
 }
 
 impl Default for SyncConfig {
@@ -31,12 +83,37 @@ impl Default for SyncConfig {
         Self {
             mode: SyncMode::Delta,
             sync_interval: Duration::from_millis(100),
+            adaptive_interval: None,
             enable_rate_limiting: true,
             rate_limit_config: RateLimitConfig::default(),
             enable_field_compression: true,
+            skip_unchanged: false,
+            canonical_components: false,
+            canonical_json: false,
+            keyframe_history: 8,
+            validate_incoming: false,
+            strict_validation: false,
+            supported_compression: vec![CompressionType::Lz4, CompressionType::Deflate, CompressionType::None],
+            full_snapshot_threshold: 0.8,
+            full_snapshot_hysteresis: 0.25,
+            exact_snapshot_size: false,
+            changed_entity_threshold: None,
+            max_delta_chain: None,
+            ack_baselines: false,
+            compact_field_deltas: false,
+            compress_components: false,
+            reduce_precision: false,
+            coerce_numeric_fields: false,
+            limits: MessageLimits::default(),
             auto_reconnect: false,
             max_reconnect_attempts: 3,
             reconnect_delay: Duration::from_secs(1),
+            keepalive_interval: None,
+            keepalive_timeout: Duration::from_secs(5),
+            stats_window: None,
+            bandwidth_stats: false,
+            size_warning: None,
+            duplicate_window: None,
         }
     }
 }
@@ -56,6 +133,18 @@ impl SyncConfig {
         self
     }
 
+    /// Derive the sync interval from the measured round trip instead of using
+    /// `sync_interval`, so a slow link gets fewer, larger deltas.
+    ///
+    /// Each pong updates a smoothed RTT the way TCP does: the first sample is
+    /// taken as is, then `srtt = 7/8 * srtt + 1/8 * sample`. The interval is
+    /// `srtt` clamped to `min..=max`, i.e. roughly one sync per round trip.
+    /// Until the first pong, `sync_interval` applies.
+    pub fn with_adaptive_interval(mut self, min: Duration, max: Duration) -> Self {
+        self.adaptive_interval = Some((min, max.max(min)));
+        self
+    }
+
     pub fn with_rate_limiting(mut self, enabled: bool) -> Self {
         self.enable_rate_limiting = enabled;
         self
@@ -71,11 +160,327 @@ impl SyncConfig {
         self
     }
 
+    /// Skip `send` entirely when the snapshot's content hash matches the last
+    /// one sent. Off by default.
+    pub fn with_skip_unchanged(mut self, enabled: bool) -> Self {
+        self.skip_unchanged = enabled;
+        self
+    }
+
+    /// Sort each outgoing entity's components by id (see
+    /// [`WorldSnapshot::sort_components`]), so the same state always encodes
+    /// the same way whatever order the ECS listed components in.
+    pub fn with_canonical_components(mut self, enabled: bool) -> Self {
+        self.canonical_components = enabled;
+        self
+    }
+
+    /// Compare JSON components by content when diffing, so JSON that only
+    /// differs in key order or whitespace isn't sent again. See
+    /// [`DeltaCompressor::with_canonical_json`].
+    pub fn with_canonical_json(mut self, enabled: bool) -> Self {
+        self.canonical_json = enabled;
+        self
+    }
+
+    /// Number of sent snapshots kept to answer `RequestSnapshot { since }` with a delta.
+    pub fn with_keyframe_history(mut self, size: usize) -> Self {
+        self.keyframe_history = size;
+        self
+    }
+
+    /// Check components in incoming snapshots and deltas against the schema
+    /// registry, rejecting messages that violate a registered schema.
+    pub fn with_validate_incoming(mut self, enabled: bool) -> Self {
+        self.validate_incoming = enabled;
+        self
+    }
+
+    /// Have `validate_incoming` also reject fields the schema doesn't declare;
+    /// see [`SchemaValidator::with_strict`].
+    pub fn with_strict_validation(mut self, enabled: bool) -> Self {
+        self.strict_validation = enabled;
+        self
+    }
+
+    /// Compression offered during `negotiate_schemas`; entries the transport
+    /// can't handle are dropped.
+    pub fn with_supported_compression(mut self, compression: Vec<CompressionType>) -> Self {
+        self.supported_compression = compression;
+        self
+    }
+
+    /// In delta mode, send a full snapshot instead when the delta would be larger
+    /// than `ratio` times the snapshot's size. Use `f64::INFINITY` to always send deltas.
+    ///
+    /// The snapshot's size is measured entity by entity, only until it clearly
+    /// outweighs the delta, and without the serializer's message compression.
+    /// See [`Self::with_exact_snapshot_size`] to encode it in full instead.
+    pub fn with_full_snapshot_threshold(mut self, ratio: f64) -> Self {
+        self.full_snapshot_threshold = ratio;
+        self
+    }
+
+    /// Once `full_snapshot_threshold` has switched to full snapshots, go back
+    /// to deltas only when they are under `1 - margin` times the threshold, so
+    /// a world hovering around it doesn't alternate between the two every sync.
+    pub fn with_full_snapshot_hysteresis(mut self, margin: f64) -> Self {
+        self.full_snapshot_hysteresis = margin;
+        self
+    }
+
+    /// Compare the encoded delta against the encoded snapshot for
+    /// `full_snapshot_threshold`, rather than estimating. Exact, but every
+    /// delta sent costs a full snapshot encode as well.
+    pub fn with_exact_snapshot_size(mut self, enabled: bool) -> Self {
+        self.exact_snapshot_size = enabled;
+        self
+    }
+
+    /// In delta mode, send a full snapshot instead of a delta when more than
+    /// `ratio` of the entities were added, removed or modified since the
+    /// delta's baseline (the last sync, or with `ack_baselines` the last one
+    /// acknowledged), counted against the larger of the two worlds. Read off
+    /// the delta itself, so it costs no extra diff, and is checked before the
+    /// size comparison of `full_snapshot_threshold`, which still applies below
+    /// it. The compressor's history is reset with each such snapshot.
+    pub fn with_changed_entity_threshold(mut self, ratio: f64) -> Self {
+        self.changed_entity_threshold = Some(ratio);
+        self
+    }
+
+    /// In delta mode, send a full snapshot after `length` consecutive deltas,
+    /// bounding how long a chain the receiver has to apply. The compressor's
+    /// history is reset with each such keyframe.
+    pub fn with_max_delta_chain(mut self, length: u64) -> Self {
+        self.max_delta_chain = Some(length);
+        self
+    }
+
+    /// Diff each delta against the newest snapshot the peer acknowledged with
+    /// [`SyncManager::acknowledge`] instead of the last one sent, so a lost delta
+    /// is repaired by the next one. Meant for unreliable transports.
+    ///
+    /// Baselines come from the keyframe history, which holds `keyframe_history`
+    /// full snapshots in memory. Until the first ack, or once the acked snapshot
+    /// has been evicted, deltas re-add the whole world.
+    pub fn with_ack_baselines(mut self, enabled: bool) -> Self {
+        self.ack_baselines = enabled;
+        self
+    }
+
+    /// Send field updates to components with a registered schema as
+    /// `IndexedFieldsUpdated`. The peer needs the same schema versions
+    /// registered to read them.
+    pub fn with_compact_field_deltas(mut self, enabled: bool) -> Self {
+        self.compact_field_deltas = enabled;
+        self
+    }
+
+    /// Compress the data of components whose registered schema carries a
+    /// compression hint, each on its own, in outgoing snapshots and deltas.
+    /// Incoming compressed components are always decompressed.
+    pub fn with_component_compression(mut self, enabled: bool) -> Self {
+        self.compress_components = enabled;
+        self
+    }
+
+    /// Send `F64` values of components whose registered schema is marked low
+    /// precision as `F32` in outgoing snapshots and deltas. Snapshots are
+    /// reduced before they are diffed, so changes below `f32` precision send
+    /// nothing. Lossy; see [`SchemaRegistry::reduce_precision_entities`].
+    pub fn with_precision_reduction(mut self, enabled: bool) -> Self {
+        self.reduce_precision = enabled;
+        self
+    }
+
+    /// Coerce numeric fields in incoming snapshots and deltas to the types in
+    /// the schema registry, before `validate_incoming` checks them. Avoids
+    /// phantom deltas from values that came back wider through JSON.
+    pub fn with_numeric_coercion(mut self, enabled: bool) -> Self {
+        self.coerce_numeric_fields = enabled;
+        self
+    }
+
+    /// Bounds on incoming snapshots and deltas; messages beyond them are
+    /// rejected with `LinkError::InvalidMessage`.
+    pub fn with_limits(mut self, limits: MessageLimits) -> Self {
+        self.limits = limits;
+        self
+    }
+
     pub fn with_auto_reconnect(mut self, enabled: bool, max_attempts: u32) -> Self {
         self.auto_reconnect = enabled;
         self.max_reconnect_attempts = max_attempts;
         self
     }
+
+    /// Ping the peer after `interval` without traffic either way, and give up
+    /// on the connection if nothing comes back within `timeout`. See
+    /// [`SyncManager::maintain`].
+    pub fn with_keepalive(mut self, interval: Duration, timeout: Duration) -> Self {
+        self.keepalive_interval = Some(interval);
+        self.keepalive_timeout = timeout;
+        self
+    }
+
+    /// Also report what was sent over the trailing `window`, as
+    /// [`SyncStats::window`], next to the lifetime totals.
+    pub fn with_stats_window(mut self, window: Duration) -> Self {
+        self.stats_window = Some(window);
+        self
+    }
+
+    /// Count the encoded bytes of each delta sent per component; see
+    /// [`SyncManager::bandwidth_by_component`].
+    pub fn with_bandwidth_stats(mut self, enabled: bool) -> Self {
+        self.bandwidth_stats = enabled;
+        self
+    }
+
+    /// Raise a [`SyncEvent::Warning`] for each outgoing snapshot or delta that
+    /// encodes larger than `bytes`. The message is still sent.
+    pub fn with_size_warning(mut self, bytes: u64) -> Self {
+        self.size_warning = Some(bytes);
+        self
+    }
+
+    /// Remember the header ids of the last `window` incoming messages and drop
+    /// repeats as [`SyncEvent::Duplicate`], so a retransmitted delta isn't
+    /// applied twice.
+    pub fn with_duplicate_suppression(mut self, window: usize) -> Self {
+        self.duplicate_window = Some(window);
+        self
+    }
+
+    /// Start a [`SyncConfigBuilder`] from the defaults.
+    pub fn builder() -> SyncConfigBuilder {
+        SyncConfigBuilder::default()
+    }
+
+    /// Check that the settings make sense together, failing with
+    /// `LinkError::InvalidConfig` naming the first combination that doesn't.
+    pub fn validate(&self) -> Result<()> {
+        let invalid = |reason: &str| Err(LinkError::InvalidConfig(reason.to_string()));
+
+        if self.auto_reconnect && self.max_reconnect_attempts == 0 {
+            return invalid("auto_reconnect is on but max_reconnect_attempts is 0");
+        }
+        if self.adaptive_interval.is_some_and(|(min, max)| min.is_zero() || min > max) {
+            return invalid("adaptive_interval needs 0 < min <= max");
+        }
+        if self.keepalive_interval.is_some_and(|interval| interval.is_zero()) {
+            return invalid("keepalive interval is 0");
+        }
+        if self.keepalive_interval.is_some() && self.keepalive_timeout.is_zero() {
+            return invalid("keepalive timeout is 0");
+        }
+        if self.full_snapshot_threshold.is_nan() || self.full_snapshot_threshold < 0.0 {
+            return invalid("full_snapshot_threshold must be a non-negative ratio");
+        }
+        if !(0.0..=1.0).contains(&self.full_snapshot_hysteresis) {
+            return invalid("full_snapshot_hysteresis must be between 0 and 1");
+        }
+        if self.changed_entity_threshold.is_some_and(|ratio| ratio.is_nan() || ratio < 0.0) {
+            return invalid("changed_entity_threshold must be a non-negative ratio");
+        }
+        if self.max_delta_chain == Some(0) {
+            return invalid("max_delta_chain of 0 sends only snapshots; use SyncMode::Full");
+        }
+        if self.ack_baselines && self.keyframe_history < 2 {
+            return invalid("ack_baselines needs a keyframe_history of at least 2");
+        }
+        if self.enable_rate_limiting {
+            let limits = &self.rate_limit_config;
+            if limits.max_messages_per_second == 0 || limits.burst_size == 0 || limits.window_duration.is_zero() {
+                return invalid("rate limiting is on but lets no message through");
+            }
+            if limits.policy == RateLimitPolicy::Queue(0) {
+                return invalid("RateLimitPolicy::Queue needs room for at least one message");
+            }
+        }
+        if self.duplicate_window == Some(0) {
+            return invalid("duplicate_window is 0");
+        }
+        if self.stats_window.is_some_and(|window| window.is_zero()) {
+            return invalid("stats_window is 0");
+        }
+        if self.supported_compression.is_empty() {
+            return invalid("supported_compression is empty; include CompressionType::None");
+        }
+
+        Ok(())
+    }
+}
+
+/// Builds a [`SyncConfig`] and validates it.
+///
+/// Setters mirror `SyncConfig`'s `with_*` methods; [`Self::build`] runs
+/// [`SyncConfig::validate`] so inconsistent combinations fail up front.
+#[derive(Debug, Clone, Default)]
+pub struct SyncConfigBuilder {
+    config: SyncConfig,
+}
+
+macro_rules! builder_setters {
+    ($($name:ident($($arg:ident: $ty:ty),*);)*) => {
+        $(
+            #[doc = concat!("See [`SyncConfig::", stringify!($name), "`].")]
+            pub fn $name(mut self, $($arg: $ty),*) -> Self {
+                self.config = self.config.$name($($arg),*);
+                self
+            }
+        )*
+    };
+}
+
+impl SyncConfigBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start from `config` instead of the defaults.
+    pub fn from_config(config: SyncConfig) -> Self {
+        Self { config }
+    }
+
+    builder_setters! {
+        with_mode(mode: SyncMode);
+        with_sync_interval(interval: Duration);
+        with_adaptive_interval(min: Duration, max: Duration);
+        with_rate_limiting(enabled: bool);
+        with_rate_limit_config(config: RateLimitConfig);
+        with_field_compression(enabled: bool);
+        with_skip_unchanged(enabled: bool);
+        with_canonical_components(enabled: bool);
+        with_canonical_json(enabled: bool);
+        with_keyframe_history(size: usize);
+        with_validate_incoming(enabled: bool);
+        with_strict_validation(enabled: bool);
+        with_supported_compression(compression: Vec<CompressionType>);
+        with_full_snapshot_threshold(ratio: f64);
+        with_full_snapshot_hysteresis(margin: f64);
+        with_exact_snapshot_size(enabled: bool);
+        with_changed_entity_threshold(ratio: f64);
+        with_max_delta_chain(length: u64);
+        with_ack_baselines(enabled: bool);
+        with_compact_field_deltas(enabled: bool);
+        with_component_compression(enabled: bool);
+        with_precision_reduction(enabled: bool);
+        with_numeric_coercion(enabled: bool);
+        with_limits(limits: MessageLimits);
+        with_auto_reconnect(enabled: bool, max_attempts: u32);
+        with_keepalive(interval: Duration, timeout: Duration);
+        with_stats_window(window: Duration);
+        with_bandwidth_stats(enabled: bool);
+        with_size_warning(bytes: u64);
+        with_duplicate_suppression(window: usize);
+    }
+
+    pub fn build(self) -> Result<SyncConfig> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
 }
 
 pub struct SyncManager<T: Transport> {
@@ -84,114 +489,328 @@ pub struct SyncManager<T: Transport> {
     delta_compressor: DeltaCompressor,
     rate_limiter: Option<RateLimiter>,
     schema_registry: SchemaRegistry,
+    clock: Arc<dyn Clock>,
     last_sync: Option<Instant>,
+    last_sync_millis: Option<u64>,
     sync_count: u64,
+    skipped_syncs: u64,
+    auto_full_snapshots: u64,
+    snapshot_fallback: bool,
+    keyframe_due: bool,
+    change_ratio_snapshots: u64,
+    duplicates_dropped: u64,
+    messages_sent: u64,
+    bytes_sent: u64,
+    deltas_sent: u64,
+    delta_chain: u64,
+    /// Header ids and send times of pings not yet answered, oldest first.
+    pending_pings: VecDeque<(u64, Instant)>,
+    last_traffic: Instant,
+    last_received: Option<Instant>,
+    rtt: Option<Duration>,
+    smoothed_rtt: Option<Duration>,
+    last_sent_hash: Option<u64>,
     error_count: u64,
     reconnect_attempts: u32,
     schema_version: SchemaVersion,
+    peer_schema_version: Option<SchemaVersion>,
+    state: ConnectionState,
+    pending_events: VecDeque<SyncEvent>,
+    compression: CompressionType,
+    acked_baseline: Option<f64>,
+    field_masks: AHashMap<ComponentId, AHashSet<FieldId>>,
+    rate_limited: VecDeque<(Message, Bytes)>,
+    encode_buffer: BytesMut,
+    rate_limit_dropped: u64,
+    recent_sends: SlidingWindow,
+    recent_ids: RecentIds,
 }
 
 impl<T: Transport> SyncManager<T> {
+    /// Create a manager, failing with `LinkError::InvalidConfig` if `config`
+    /// doesn't pass [`SyncConfig::validate`].
+    pub fn try_new(transport: T, config: SyncConfig) -> Result<Self> {
+        Self::try_with_clock(transport, config, clock::system_clock())
+    }
+
+    /// Like [`Self::try_new`], with the sync interval and rate limiter reading
+    /// time from `clock`.
+    pub fn try_with_clock(transport: T, config: SyncConfig, clock: Arc<dyn Clock>) -> Result<Self> {
+        config.validate()?;
+        Ok(Self::build(transport, config, clock))
+    }
+
+    #[deprecated(note = "does not validate the config; use `SyncManager::try_new`")]
     pub fn new(transport: T, config: SyncConfig) -> Self {
-        let delta_compressor = DeltaCompressor::with_field_compression(config.enable_field_compression);
+        Self::build(transport, config, clock::system_clock())
+    }
+
+    /// Create a manager whose sync interval and rate limiter read time from `clock`.
+    #[deprecated(note = "does not validate the config; use `SyncManager::try_with_clock`")]
+    pub fn with_clock(transport: T, config: SyncConfig, clock: Arc<dyn Clock>) -> Self {
+        Self::build(transport, config, clock)
+    }
+
+    fn build(transport: T, config: SyncConfig, clock: Arc<dyn Clock>) -> Self {
+        let mut delta_compressor = DeltaCompressor::with_field_compression(config.enable_field_compression)
+            .with_history_size(config.keyframe_history)
+            .with_canonical_json(config.canonical_json);
+        if config.bandwidth_stats {
+            let format = transport.serializer().map_or(BinaryFormat::MessagePack, BinarySerializer::get_format);
+            delta_compressor = delta_compressor.with_bandwidth_stats(format);
+        }
         let rate_limiter = if config.enable_rate_limiting {
-            Some(RateLimiter::new(config.rate_limit_config.clone()))
+            Some(RateLimiter::with_clock(config.rate_limit_config.clone(), Arc::clone(&clock)))
         } else {
             None
         };
 
+        let state = if transport.is_connected() {
+            ConnectionState::Connecting
+        } else {
+            ConnectionState::Disconnected
+        };
+        let now = clock.now();
+
         Self {
             transport,
             config,
             delta_compressor,
             rate_limiter,
             schema_registry: SchemaRegistry::new(),
+            clock,
             last_sync: None,
+            last_sync_millis: None,
             sync_count: 0,
+            skipped_syncs: 0,
+            auto_full_snapshots: 0,
+            snapshot_fallback: false,
+            keyframe_due: false,
+            change_ratio_snapshots: 0,
+            duplicates_dropped: 0,
+            messages_sent: 0,
+            bytes_sent: 0,
+            deltas_sent: 0,
+            delta_chain: 0,
+            pending_pings: VecDeque::new(),
+            last_traffic: now,
+            last_received: None,
+            rtt: None,
+            smoothed_rtt: None,
+            last_sent_hash: None,
             error_count: 0,
             reconnect_attempts: 0,
-            schema_version: 1,
+            schema_version: SchemaVersion::INITIAL,
+            peer_schema_version: None,
+            state,
+            pending_events: VecDeque::new(),
+            compression: CompressionType::None,
+            acked_baseline: None,
+            field_masks: AHashMap::new(),
+            rate_limited: VecDeque::new(),
+            encode_buffer: BytesMut::new(),
+            rate_limit_dropped: 0,
+            recent_sends: SlidingWindow::default(),
+            recent_ids: RecentIds::default(),
         }
     }
 
     pub fn send_snapshot(&mut self, snapshot: WorldSnapshot) -> Result<()> {
-        if !self.transport.is_connected() {
-            if self.config.auto_reconnect && self.reconnect_attempts < self.config.max_reconnect_attempts {
-                self.reconnect_attempts += 1;
-                return Err(LinkError::ConnectionClosed);
-            } else {
-                return Err(LinkError::ConnectionClosed);
-            }
-        }
+        self.ensure_connected()?;
+        let snapshot = self.prepare_outgoing(snapshot)?;
+        self.send_prepared_snapshot(snapshot)
+    }
 
+    /// `send_snapshot` for a snapshot that has been through `prepare_outgoing`.
+    fn send_prepared_snapshot(&mut self, snapshot: WorldSnapshot) -> Result<()> {
+        let start = Instant::now();
         let schema_version = self.schema_version;
         let message = Message::snapshot(
-            snapshot.entities,
+            self.wire_entities(&snapshot.entities)?,
             snapshot.timestamp,
             schema_version,
         );
 
-        let estimated_size = 1024u64;
-        if let Some(limiter) = &mut self.rate_limiter {
-            limiter.check_and_record(estimated_size)?;
+        let data = self.encode(&message)?;
+        let trace = SendTrace { full_size: data.len(), micros: start.elapsed().as_micros() };
+        if self.dispatch(message, data, Some(&snapshot))? {
+            trace.sent(trace.full_size);
         }
+        self.delta_compressor.record(snapshot);
 
-        self.transport.send(&message)?;
-
-        self.last_sync = Some(Instant::now());
+        self.mark_synced();
         self.sync_count += 1;
         self.reconnect_attempts = 0;
+        self.mark_active();
 
         Ok(())
     }
 
     pub fn send_delta(&mut self, snapshot: WorldSnapshot) -> Result<()> {
-        if !self.transport.is_connected() {
-            if self.config.auto_reconnect && self.reconnect_attempts < self.config.max_reconnect_attempts {
-                self.reconnect_attempts += 1;
-                return Err(LinkError::ConnectionClosed);
-            } else {
-                return Err(LinkError::ConnectionClosed);
-            }
+        self.ensure_connected()?;
+        let snapshot = self.prepare_outgoing(snapshot)?;
+        self.send_prepared_delta(snapshot)
+    }
+
+    /// `send_delta` for a snapshot that has been through `prepare_outgoing`.
+    fn send_prepared_delta(&mut self, snapshot: WorldSnapshot) -> Result<()> {
+        let Some(outgoing) = self.delta_or_snapshot_message(snapshot)? else {
+            return Ok(());
+        };
+
+        let size = outgoing.data.len();
+        if self.dispatch(outgoing.message, outgoing.data, Some(&outgoing.baseline))? {
+            outgoing.trace.sent(size);
         }
+        self.record_baseline(outgoing.baseline, outgoing.keyframe);
 
-        let delta = self.delta_compressor.create_delta(snapshot);
+        self.mark_synced();
+        self.sync_count += 1;
+        self.reconnect_attempts = 0;
+        self.mark_active();
 
-        if delta.changes.is_empty() {
+        Ok(())
+    }
+
+    /// Send several snapshots in a single transport write using the configured
+    /// mode. In delta mode each snapshot is diffed against the previous one, and
+    /// snapshots without changes are left out of the batch.
+    pub fn send_batch(&mut self, snapshots: Vec<WorldSnapshot>) -> Result<()> {
+        if self.config.mode == SyncMode::Manual {
             return Ok(());
         }
 
-        let base_timestamp = (delta.base_timestamp * 1000.0) as u64;
-        let schema_version = self.schema_version;
-        let message = Message::delta(delta.changes, base_timestamp, schema_version);
+        self.ensure_connected()?;
 
-        let estimated_size = 1024u64;
-        if let Some(limiter) = &mut self.rate_limiter {
-            limiter.check_and_record(estimated_size)?;
-        }
+        // Each snapshot is the next one's baseline before the batch goes out,
+        // so if it doesn't, the peer can only catch up from a keyframe.
+        let synced = match self.batch_messages(snapshots) {
+            Ok(synced) => synced,
+            Err(e) => {
+                self.keyframe_due = true;
+                return Err(e);
+            }
+        };
 
-        self.transport.send(&message)?;
+        if synced == 0 {
+            return Ok(());
+        }
 
-        self.last_sync = Some(Instant::now());
-        self.sync_count += 1;
+        self.mark_synced();
+        self.sync_count += synced;
         self.reconnect_attempts = 0;
+        self.mark_active();
 
         Ok(())
     }
 
+    /// Build, admit and send the messages of `send_batch`, returning how many
+    /// snapshots were synced.
+    fn batch_messages(&mut self, snapshots: Vec<WorldSnapshot>) -> Result<u64> {
+        let schema_version = self.schema_version;
+        let mut messages = Vec::with_capacity(snapshots.len());
+        let mut encoded = Vec::with_capacity(snapshots.len());
+        let mut traces = Vec::with_capacity(snapshots.len());
+        let mut synced = 0;
+
+        for snapshot in snapshots {
+            let snapshot = self.prepare_outgoing(snapshot)?;
+            let outgoing = if self.config.mode == SyncMode::Full {
+                let start = Instant::now();
+                let message = Message::snapshot(self.wire_entities(&snapshot.entities)?, snapshot.timestamp, schema_version);
+                let data = self.encode(&message)?;
+                let trace = SendTrace { full_size: data.len(), micros: start.elapsed().as_micros() };
+                Outgoing { message, data, baseline: snapshot, keyframe: false, trace }
+            } else {
+                match self.delta_or_snapshot_message(snapshot)? {
+                    Some(outgoing) => outgoing,
+                    None => continue,
+                }
+            };
+
+            synced += 1;
+            let admitted = self.admit(outgoing.message, outgoing.data, Some(&outgoing.baseline))?;
+            self.record_baseline(outgoing.baseline, outgoing.keyframe);
+            if let Some((message, data)) = admitted {
+                messages.push(message);
+                encoded.push(data);
+                traces.push(outgoing.trace);
+            }
+        }
+
+        if !messages.is_empty() {
+            self.transport.send_batch_encoded(&messages, &encoded)?;
+            self.transport.flush()?;
+            for (trace, data) in traces.iter().zip(&encoded) {
+                trace.sent(data.len());
+            }
+            let batch_size = encoded.iter().map(|data| data.len() as u64).sum();
+            self.record_sent(&messages, batch_size);
+        }
+
+        Ok(synced)
+    }
+
     pub fn send(&mut self, snapshot: WorldSnapshot) -> Result<()> {
+        if self.config.mode == SyncMode::Manual {
+            return Ok(());
+        }
+
+        // Prepared before hashing so changes to masked fields count as unchanged.
+        let snapshot = self.prepare_outgoing(snapshot)?;
+
+        let hash = if self.config.skip_unchanged {
+            let hash = snapshot.content_hash();
+            if self.last_sent_hash == Some(hash) {
+                self.skipped_syncs += 1;
+                self.mark_synced();
+                return Ok(());
+            }
+            Some(hash)
+        } else {
+            None
+        };
+
+        self.ensure_connected()?;
         match self.config.mode {
-            SyncMode::Full => self.send_snapshot(snapshot),
-            SyncMode::Delta => self.send_delta(snapshot),
-            SyncMode::Manual => Ok(()),
+            SyncMode::Full => self.send_prepared_snapshot(snapshot)?,
+            SyncMode::Delta => self.send_prepared_delta(snapshot)?,
+            SyncMode::Manual => {}
+        }
+
+        self.last_sent_hash = hash;
+
+        Ok(())
+    }
+
+    /// Send `current_snapshot` using the configured mode if `should_sync()` says
+    /// a sync is due. Returns whether a sync happened. Runs [`Self::maintain`]
+    /// first when a keepalive is configured, and sends whatever the rate limit
+    /// held back as far as it now allows.
+    pub fn tick(&mut self, current_snapshot: WorldSnapshot) -> Result<bool> {
+        if self.config.keepalive_interval.is_some() {
+            self.maintain()?;
+        }
+        self.flush_rate_limited()?;
+
+        if !self.should_sync() {
+            return Ok(false);
         }
+
+        let sync_count = self.sync_count;
+        self.send(current_snapshot)?;
+
+        Ok(self.sync_count > sync_count)
     }
 
+    /// Returns queued `StateChanged` events before reading from the transport.
     pub fn receive(&mut self) -> Result<Option<SyncEvent>> {
-        if !self.transport.is_connected() {
-            return Err(LinkError::ConnectionClosed);
+        if let Some(event) = self.pending_events.pop_front() {
+            return Ok(Some(event));
         }
 
+        self.ensure_connected()?;
+
         match self.transport.receive()? {
             Some(message) => {
                 let event = self.process_message(message)?;
@@ -201,7 +820,99 @@ impl<T: Transport> SyncManager<T> {
         }
     }
 
+    /// Keepalive bookkeeping for `SyncConfig::with_keepalive`; a no-op without it.
+    ///
+    /// Sends a ping once `keepalive_interval` has passed without anything sent
+    /// or received. If nothing at all arrives within `keepalive_timeout` of
+    /// that ping, the connection is considered dead: the transport is closed,
+    /// the state goes to `Disconnected` and `SyncEvent::ConnectionTimeout` is
+    /// queued for [`Self::receive`].
+    pub fn maintain(&mut self) -> Result<()> {
+        let Some(interval) = self.config.keepalive_interval else {
+            return Ok(());
+        };
+        if self.state == ConnectionState::Closed || !self.transport.is_connected() {
+            return Ok(());
+        }
+
+        let now = self.clock.now();
+        let timeout = self.config.keepalive_timeout;
+        if let Some(&(_, sent_at)) = self.pending_pings.front() {
+            if now.duration_since(sent_at) < timeout {
+                return Ok(());
+            }
+
+            let answered = self.last_received.is_some_and(|received| received >= sent_at);
+            if !answered {
+                self.pending_pings.clear();
+                self.pending_events.push_back(SyncEvent::ConnectionTimeout);
+                self.set_state(ConnectionState::Disconnected);
+                return self.transport.close();
+            }
+
+            // Other traffic proved the peer alive but the pong was lost; stop
+            // waiting for it so the next idle interval pings again.
+            self.pending_pings.retain(|&(_, sent_at)| now.duration_since(sent_at) < timeout);
+        }
+
+        if now.duration_since(self.last_traffic) >= interval {
+            self.ping()?;
+        }
+
+        Ok(())
+    }
+
     fn process_message(&mut self, message: Message) -> Result<SyncEvent> {
+        let now = self.clock.now();
+        self.last_received = Some(now);
+        self.last_traffic = now;
+
+        let Some(window) = self.config.duplicate_window else {
+            return self.apply_message(message);
+        };
+        let id = message.header.id;
+        if self.recent_ids.contains(id) {
+            self.duplicates_dropped += 1;
+            return Ok(SyncEvent::Duplicate { id });
+        }
+        // Only remember messages that went through, so a retransmit can
+        // still repair one that failed.
+        let event = self.apply_message(message)?;
+        self.recent_ids.insert(id, window);
+        Ok(event)
+    }
+
+    fn apply_message(&mut self, mut message: Message) -> Result<SyncEvent> {
+        if !matches!(message.payload, MessagePayload::SchemaSync(_)) {
+            self.mark_active();
+        }
+
+        self.record_peer_schema_version(message.header.schema_version);
+
+        if let Err(e) = self.config.limits.check_payload(&message.payload) {
+            self.error_count += 1;
+            return Err(e);
+        }
+
+        if let Err(e) = decompress_payload(&mut message.payload, self.config.limits.max_decompressed_bytes) {
+            self.error_count += 1;
+            return Err(e);
+        }
+
+        if self.config.coerce_numeric_fields {
+            if let Err(e) = self.normalize_payload(&mut message.payload) {
+                self.error_count += 1;
+                return Err(e);
+            }
+        }
+
+        if self.config.validate_incoming {
+            if let Err(e) = self.validate_payload(&message.payload) {
+                self.error_count += 1;
+                return Err(e);
+            }
+        }
+
         match message.payload {
             MessagePayload::Snapshot(payload) => {
                 let snapshot = WorldSnapshot {
@@ -216,52 +927,469 @@ impl<T: Transport> SyncManager<T> {
             }
             MessagePayload::Delta(payload) => {
                 let delta = Delta {
-                    changes: payload.changes,
-                    timestamp: message.header.timestamp as f64 / 1000.0,
+                    changes: self.schema_registry.expand_changes(payload.changes)?,
+                    timestamp: payload.metadata.world_time
+                        .unwrap_or(message.header.timestamp as f64 / 1000.0),
                     base_timestamp: payload.base_timestamp as f64 / 1000.0,
                 };
 
                 Ok(SyncEvent::Delta(delta))
             }
-            MessagePayload::RequestSnapshot => {
-                Ok(SyncEvent::SnapshotRequested)
+            MessagePayload::RequestSnapshot { since } => {
+                let answered = self.answer_snapshot_request(since)?;
+                Ok(SyncEvent::SnapshotRequested { since, answered })
             }
-            MessagePayload::Ack { ack_id } => {
+            MessagePayload::Ack { ack_id, timestamp } => {
+                if let (true, Some(timestamp)) = (self.config.ack_baselines, timestamp) {
+                    self.record_ack(timestamp);
+                }
                 Ok(SyncEvent::Ack(ack_id))
             }
             MessagePayload::Ping => {
-                let pong = Message::pong(self.schema_version);
+                let pong = Message::pong_for(message.header.id, self.schema_version);
                 self.transport.send(&pong)?;
                 Ok(SyncEvent::Ping)
             }
-            MessagePayload::Pong => {
+            MessagePayload::Pong { ping_id } => {
+                // Pongs that don't say which ping they answer take the oldest.
+                let index = match ping_id {
+                    Some(ping_id) => self.pending_pings.iter().position(|&(id, _)| id == ping_id),
+                    None => (!self.pending_pings.is_empty()).then_some(0),
+                };
+                if let Some((_, sent_at)) = index.and_then(|index| self.pending_pings.remove(index)) {
+                    let rtt = self.clock.now().duration_since(sent_at);
+                    self.rtt = Some(rtt);
+                    self.smoothed_rtt = Some(match self.smoothed_rtt {
+                        Some(smoothed) => smoothed * 7 / 8 + rtt / 8,
+                        None => rtt,
+                    });
+                }
                 Ok(SyncEvent::Pong)
             }
             MessagePayload::SchemaSync(payload) => {
+                if payload.protocol_version != PROTOCOL_VERSION {
+                    return Err(self.reject_protocol(payload.protocol_version));
+                }
+
+                // A late handshake doesn't bring back a connection that was dropped.
+                if matches!(self.state, ConnectionState::Closed | ConnectionState::Disconnected) {
+                    return Ok(SyncEvent::SchemaSync(payload.schemas));
+                }
+
+                if !payload.is_reply {
+                    self.send_schema_sync(true)?;
+                }
+
+                // Our reply above still went out uncompressed, which is what the peer expects.
+                let compression = crate::compression::negotiate(&self.offered_compression(), &payload.compression);
+                self.transport.set_compression(compression)?;
+                self.compression = compression;
+
+                if matches!(
+                    self.state,
+                    ConnectionState::Connecting | ConnectionState::SchemaNegotiating | ConnectionState::Reconnecting
+                ) {
+                    self.set_state(ConnectionState::Connected);
+                }
                 Ok(SyncEvent::SchemaSync(payload.schemas))
             }
             MessagePayload::Error { code, message: error_message } => {
                 self.error_count += 1;
-                Ok(SyncEvent::Error { code, message: error_message })
+                Ok(SyncEvent::Error {
+                    code: ErrorCode::try_from(code).ok(),
+                    raw_code: code,
+                    message: error_message,
+                })
+            }
+            MessagePayload::Encrypted { .. } => {
+                self.error_count += 1;
+                Err(LinkError::InvalidMessage(
+                    "Encrypted message reached the sync layer; wrap the transport in a SecureTransport".to_string()
+                ))
             }
+            MessagePayload::Channel { .. } => {
+                self.error_count += 1;
+                Err(LinkError::InvalidMessage(
+                    "Channel message reached the sync layer; wrap the transport in a MultiplexTransport".to_string()
+                ))
+            }
+            MessagePayload::Unknown => Ok(SyncEvent::Unknown(message.header.msg_type)),
         }
     }
 
-    pub fn request_snapshot(&mut self) -> Result<()> {
-        let message = Message::request_snapshot(self.schema_version);
-        self.transport.send(&message)?;
-        Ok(())
+    fn normalize_payload(&self, payload: &mut MessagePayload) -> Result<()> {
+        match payload {
+            MessagePayload::Snapshot(payload) => self.schema_registry.normalize_entities(&mut payload.entities),
+            MessagePayload::Delta(payload) => self.schema_registry.normalize_changes(&mut payload.changes),
+            _ => Ok(()),
+        }
     }
 
-    pub fn send_ack(&mut self, message_id: u64) -> Result<()> {
-        let message = Message::ack(message_id, self.schema_version);
-        self.transport.send(&message)?;
+    fn validate_payload(&self, payload: &MessagePayload) -> Result<()> {
+        let validator = SchemaValidator::borrowed(&self.schema_registry)
+            .with_strict(self.config.strict_validation);
+
+        match payload {
+            MessagePayload::Snapshot(payload) => validator.validate_entities(&payload.entities),
+            MessagePayload::Delta(payload) => validator.validate_changes(&payload.changes),
+            _ => Ok(()),
+        }
+    }
+
+    /// Send our registered schemas and supported compression, and hold
+    /// `SchemaNegotiating` until the peer answers. Both sides then switch to the
+    /// negotiated compression, so avoid sending anything else in between.
+    pub fn negotiate_schemas(&mut self) -> Result<()> {
+        self.ensure_connected()?;
+        self.send_schema_sync(false)?;
+        self.set_state(ConnectionState::SchemaNegotiating);
+
         Ok(())
     }
 
-    pub fn ping(&mut self) -> Result<()> {
+    /// Compression in use after the last schema handshake.
+    pub fn compression(&self) -> CompressionType {
+        self.compression
+    }
+
+    fn offered_compression(&self) -> Vec<CompressionType> {
+        self.config.supported_compression.iter()
+            .copied()
+            .filter(|c| self.transport.supports_compression(*c))
+            .collect()
+    }
+
+    fn send_schema_sync(&mut self, is_reply: bool) -> Result<()> {
+        let schemas = self.schema_registry.get_all()?
+            .iter()
+            .map(ComponentSchemaInfo::from)
+            .collect();
+
+        let mut message = Message::schema_sync(schemas, self.schema_version);
+        if let MessagePayload::SchemaSync(payload) = &mut message.payload {
+            payload.compression = self.offered_compression();
+            payload.is_reply = is_reply;
+        }
+
+        self.transport.send(&message)
+    }
+
+    /// Tell the peer its protocol version is incompatible and drop the connection.
+    fn reject_protocol(&mut self, remote: u32) -> LinkError {
+        self.error_count += 1;
+
+        let error = LinkError::ProtocolMismatch { local: PROTOCOL_VERSION, remote };
+        let notice = Message::error(ErrorCode::ProtocolMismatch, error.to_string(), self.schema_version);
+        // Best effort: the peer may not be able to read it anyway.
+        let _ = self.transport.send(&notice);
+        let _ = self.transport.close();
+        self.set_state(ConnectionState::Disconnected);
+
+        error
+    }
+
+    pub fn connection_state(&self) -> ConnectionState {
+        self.state
+    }
+
+    fn set_state(&mut self, state: ConnectionState) {
+        if self.state != state {
+            self.pending_events.push_back(SyncEvent::StateChanged { from: self.state, to: state });
+            self.state = state;
+        }
+    }
+
+    /// Send `message`, encoded as `data`, or hold it back per the configured
+    /// `RateLimitPolicy`. Either way it is on its way once this returns `Ok`,
+    /// and the caller can record `current` as the new baseline. Returns whether
+    /// it was sent right away.
+    fn dispatch(&mut self, message: Message, data: Bytes, current: Option<&WorldSnapshot>) -> Result<bool> {
+        let Some((message, data)) = self.admit(message, data, current)? else {
+            return Ok(false);
+        };
+        self.transport.send_encoded(&message, &data)?;
+        self.record_sent(std::slice::from_ref(&message), data.len() as u64);
+        Ok(true)
+    }
+
+    /// Run an outgoing message past the rate limiter. Returns it if it can go
+    /// out now, or `None` if the configured `RateLimitPolicy` held it back.
+    /// `current` is the world state the message brings the peer to, used when
+    /// a held delta has to become a full snapshot; it defaults to the latest
+    /// recorded snapshot.
+    fn admit(&mut self, message: Message, data: Bytes, current: Option<&WorldSnapshot>) -> Result<Option<(Message, Bytes)>> {
+        let size = data.len() as u64;
+        self.check_size(&message, size);
+
+        let policy = match &mut self.rate_limiter {
+            None => return Ok(Some((message, data))),
+            Some(limiter) if limiter.get_config().policy == RateLimitPolicy::Reject => {
+                limiter.check_and_record(size)?;
+                return Ok(Some((message, data)));
+            }
+            Some(limiter) => limiter.get_config().policy,
+        };
+
+        // Held messages go first; a new one only skips the line when there is none.
+        self.flush_rate_limited()?;
+        if let Some(limiter) = self.rate_limiter.as_mut().filter(|_| self.rate_limited.is_empty()) {
+            match limiter.check_and_record(size) {
+                Ok(()) => return Ok(Some((message, data))),
+                Err(e) if limiter.time_until_available(size).is_none() => return Err(e),
+                Err(_) => {}
+            }
+        }
+
+        match policy {
+            RateLimitPolicy::Queue(max) if self.rate_limited.len() >= max => {
+                return Err(LinkError::RateLimitExceeded(
+                    format!("Rate limit backlog full: {} messages", max)
+                ));
+            }
+            RateLimitPolicy::DropOldest if !self.rate_limited.is_empty() => {
+                self.rate_limit_dropped += self.rate_limited.len() as u64;
+                self.rate_limited.clear();
+
+                // The delta builds on what was just dropped, so send the whole state instead.
+                let latest = current.or_else(|| self.delta_compressor.history().last());
+                if let (MessagePayload::Delta(_), Some(latest)) = (&message.payload, latest) {
+                    let snapshot = Message::snapshot(self.wire_entities(&latest.entities)?, latest.timestamp, self.schema_version);
+                    let data = self.encode(&snapshot)?;
+                    self.rate_limited.push_back((snapshot, data));
+                    return Ok(None);
+                }
+            }
+            _ => {}
+        }
+
+        self.rate_limited.push_back((message, data));
+        Ok(None)
+    }
+
+    /// Send messages held back by `RateLimitPolicy::Queue` or `DropOldest`, in
+    /// order, as far as the rate limit allows. Returns how many went out.
+    pub fn flush_rate_limited(&mut self) -> Result<usize> {
+        let mut sent = 0;
+
+        while let Some((_, data)) = self.rate_limited.front() {
+            let size = data.len() as u64;
+            let Some(limiter) = &mut self.rate_limiter else {
+                break;
+            };
+            if limiter.time_until_available(size) != Some(Duration::ZERO) {
+                break;
+            }
+            limiter.check_and_record(size)?;
+
+            let Some((message, data)) = self.rate_limited.pop_front() else {
+                break;
+            };
+            if let Err(e) = self.transport.send_encoded(&message, &data) {
+                self.rate_limited.push_front((message, data));
+                return Err(e);
+            }
+            self.record_sent(std::slice::from_ref(&message), size);
+            sent += 1;
+        }
+
+        Ok(sent)
+    }
+
+    fn record_sent(&mut self, messages: &[Message], bytes: u64) {
+        let now = self.clock.now();
+        self.last_traffic = now;
+        self.messages_sent += messages.len() as u64;
+        self.bytes_sent += bytes;
+
+        if let Some(window) = self.config.stats_window {
+            self.recent_sends.prune(now, window);
+            // One record per message; the batch's bytes go on the first.
+            for i in 0..messages.len() {
+                self.recent_sends.record(now, if i == 0 { bytes } else { 0 });
+            }
+        }
+
+        for message in messages {
+            match &message.payload {
+                MessagePayload::Delta(payload) => {
+                    self.deltas_sent += 1;
+                    self.delta_chain += 1;
+                    self.delta_compressor.account_changes(&payload.changes);
+                }
+                MessagePayload::Snapshot(_) => self.delta_chain = 0,
+                _ => {}
+            }
+        }
+    }
+
+    /// Never send `fields` of `component_id`: they are stripped from every
+    /// outgoing snapshot before it is diffed or recorded, so they can't leak
+    /// through deltas either. Only structured and JSON object data can be
+    /// masked. An empty `fields` removes the mask.
+    pub fn set_field_mask(&mut self, component_id: ComponentId, fields: impl IntoIterator<Item = FieldId>) {
+        let fields: AHashSet<FieldId> = fields.into_iter().collect();
+        if fields.is_empty() {
+            self.field_masks.remove(&component_id);
+        } else {
+            self.field_masks.insert(component_id, fields);
+        }
+    }
+
+    fn mask_fields(&self, snapshot: &mut WorldSnapshot) {
+        if self.field_masks.is_empty() {
+            return;
+        }
+
+        for component in snapshot.entities.iter_mut().flat_map(|e| e.components.iter_mut()) {
+            if let Some(fields) = self.field_masks.get(&component.id) {
+                mask_component(&mut component.data, fields);
+            }
+        }
+    }
+
+    /// Mask a snapshot about to be sent; debug builds also reject malformed
+    /// ones (see [`WorldSnapshot::validate`]).
+    fn prepare_outgoing(&self, mut snapshot: WorldSnapshot) -> Result<WorldSnapshot> {
+        if cfg!(debug_assertions) {
+            snapshot.validate()?;
+        }
+        self.mask_fields(&mut snapshot);
+        // Reduced before diffing, so changes lost to `f32` don't make deltas
+        // and baselines hold what the peer actually has.
+        if self.config.reduce_precision {
+            self.schema_registry.reduce_precision_entities(&mut snapshot.entities);
+        }
+        if self.config.canonical_components {
+            snapshot.sort_components();
+        }
+        Ok(snapshot)
+    }
+
+    fn mark_synced(&mut self) {
+        self.last_sync = Some(self.clock.now());
+        self.last_sync_millis = Some(self.clock.epoch_millis());
+    }
+
+    /// Raise a [`SyncEvent::Warning`] if `message` is over the configured size warning.
+    fn check_size(&mut self, message: &Message, size: u64) {
+        if self.config.size_warning.is_none_or(|threshold| size <= threshold) {
+            return;
+        }
+
+        let kind = match message.payload {
+            MessagePayload::Delta(_) => WarningKind::LargeDelta,
+            _ => WarningKind::LargeSnapshot,
+        };
+        self.pending_events.push_back(SyncEvent::Warning { kind, size });
+    }
+
+    /// A successful exchange completes `Connecting` and `Reconnecting`.
+    fn mark_active(&mut self) {
+        if matches!(self.state, ConnectionState::Connecting | ConnectionState::Reconnecting) {
+            self.set_state(ConnectionState::Connected);
+        }
+    }
+
+    fn ensure_connected(&mut self) -> Result<()> {
+        if self.transport.is_connected() {
+            if self.state == ConnectionState::Disconnected {
+                self.set_state(ConnectionState::Connecting);
+            }
+            return Ok(());
+        }
+
+        if self.state != ConnectionState::Closed {
+            if self.config.auto_reconnect && self.reconnect_attempts < self.config.max_reconnect_attempts {
+                self.reconnect_attempts += 1;
+                self.set_state(ConnectionState::Reconnecting);
+            } else {
+                self.set_state(ConnectionState::Disconnected);
+            }
+        }
+
+        Err(LinkError::ConnectionClosed)
+    }
+
+    pub fn request_snapshot(&mut self) -> Result<()> {
+        let message = Message::request_snapshot(self.schema_version);
+        self.transport.send(&message)?;
+        Ok(())
+    }
+
+    /// Ask the peer for the changes since the snapshot taken at `timestamp`.
+    /// The peer answers with a full snapshot if that baseline is no longer cached.
+    pub fn request_snapshot_since(&mut self, timestamp: f64) -> Result<()> {
+        let message = Message::request_snapshot_since(wire_timestamp(timestamp), self.schema_version);
+        self.transport.send(&message)?;
+        Ok(())
+    }
+
+    /// Reply from the keyframe ring: a delta when the requested baseline is still
+    /// cached, otherwise the latest full snapshot. Returns false if nothing has
+    /// been sent yet, leaving the request to the application.
+    fn answer_snapshot_request(&mut self, since: Option<u64>) -> Result<bool> {
+        let latest = match self.delta_compressor.history().last() {
+            Some(latest) => latest,
+            None => return Ok(false),
+        };
+
+        let base = since.and_then(|since| {
+            self.delta_compressor.history()
+                .find(|s| wire_timestamp(s.timestamp) == since)
+        });
+
+        let message = match base {
+            Some(base) => {
+                let delta = self.delta_compressor.peek_delta_from(base.timestamp, latest);
+                let changes = self.wire_changes(delta.changes)?;
+                Message::delta_at(changes, wire_timestamp(delta.base_timestamp), delta.timestamp, self.schema_version)
+            }
+            None => Message::snapshot(self.wire_entities(&latest.entities)?, latest.timestamp, self.schema_version),
+        };
+
+        let data = self.encode(&message)?;
+        self.dispatch(message, data, None)?;
+        Ok(true)
+    }
+
+    /// Tell the peer the world state at `timestamp` (a snapshot's timestamp,
+    /// or the timestamp of an applied delta) is in place, so a sender using
+    /// ack baselines can diff against it.
+    pub fn acknowledge(&mut self, timestamp: f64) -> Result<()> {
+        let message = Message::ack_baseline(wire_timestamp(timestamp), self.schema_version);
+        self.transport.send(&message)?;
+        Ok(())
+    }
+
+    fn record_ack(&mut self, acked_at: u64) {
+        let acked = self.delta_compressor.history()
+            .map(|s| s.timestamp)
+            .find(|&timestamp| wire_timestamp(timestamp) == acked_at);
+
+        if let Some(timestamp) = acked {
+            if self.acked_baseline.is_none_or(|current| timestamp > current) {
+                self.acked_baseline = Some(timestamp);
+            }
+        }
+    }
+
+    pub fn send_ack(&mut self, message_id: u64) -> Result<()> {
+        let message = Message::ack(message_id, self.schema_version);
+        self.transport.send(&message)?;
+        Ok(())
+    }
+
+    /// Send a ping. The round trip is measured when the pong comes back; see
+    /// `SyncStats::rtt`. Pings can overlap: each pong is matched to its ping,
+    /// and the last eight are kept waiting.
+    pub fn ping(&mut self) -> Result<()> {
         let message = Message::ping(self.schema_version);
         self.transport.send(&message)?;
+        if self.pending_pings.len() == MAX_PENDING_PINGS {
+            self.pending_pings.pop_front();
+        }
+        self.pending_pings.push_back((message.header.id, self.clock.now()));
+        self.last_traffic = self.clock.now();
         Ok(())
     }
 
@@ -271,24 +1399,103 @@ impl<T: Transport> SyncManager<T> {
         }
 
         if let Some(last_sync) = self.last_sync {
-            last_sync.elapsed() >= self.config.sync_interval
+            self.clock.now().duration_since(last_sync) >= self.effective_sync_interval()
         } else {
             true
         }
     }
 
+    /// The interval `should_sync` waits for: `sync_interval`, or the adaptive
+    /// one described at [`SyncConfig::with_adaptive_interval`].
+    pub fn effective_sync_interval(&self) -> Duration {
+        match (self.config.adaptive_interval, self.smoothed_rtt) {
+            (Some((min, max)), Some(smoothed)) => smoothed.clamp(min, max),
+            _ => self.config.sync_interval,
+        }
+    }
+
+    /// [`SyncStats::metrics_text`] for the current stats, ready to serve on a
+    /// Prometheus scrape endpoint.
+    pub fn metrics_text(&self) -> String {
+        self.get_stats().metrics_text()
+    }
+
     pub fn get_stats(&self) -> SyncStats {
         let rate_limiter_stats = self.rate_limiter.as_ref().map(|l| l.get_stats());
+        let window = self.config.stats_window.map(|window| {
+            let now = self.clock.now();
+            let messages_sent = self.recent_sends.count(now, window);
+            let bytes_sent = self.recent_sends.bytes(now, window);
+            // A zero window only gets past `SyncConfig::validate` through the unchecked constructors.
+            let rate = |count: u64| if window.is_zero() { 0.0 } else { count as f64 / window.as_secs_f64() };
+            WindowStats {
+                window,
+                messages_sent,
+                bytes_sent,
+                messages_per_second: rate(messages_sent),
+                bytes_per_second: rate(bytes_sent),
+            }
+        });
 
         SyncStats {
             sync_count: self.sync_count,
+            skipped_syncs: self.skipped_syncs,
+            auto_full_snapshots: self.auto_full_snapshots,
+            change_ratio_snapshots: self.change_ratio_snapshots,
+            duplicates_dropped: self.duplicates_dropped,
+            messages_sent: self.messages_sent,
+            bytes_sent: self.bytes_sent,
+            deltas_sent: self.deltas_sent,
+            delta_chain: self.delta_chain,
+            rtt: self.rtt,
+            smoothed_rtt: self.smoothed_rtt,
             error_count: self.error_count,
             last_sync: self.last_sync,
+            last_sync_millis: self.last_sync_millis,
             rate_limiter_stats,
+            rate_limited_pending: self.rate_limited.len(),
+            rate_limited_dropped: self.rate_limit_dropped,
             reconnect_attempts: self.reconnect_attempts,
+            window,
+        }
+    }
+
+    /// Zero the counters in [`SyncStats`], including the rate limiter's
+    /// totals and the stats window. Connection state, round trip estimates
+    /// and the delta chain are kept, since syncing depends on them.
+    pub fn reset_stats(&mut self) {
+        self.sync_count = 0;
+        self.skipped_syncs = 0;
+        self.auto_full_snapshots = 0;
+        self.change_ratio_snapshots = 0;
+        self.duplicates_dropped = 0;
+        self.messages_sent = 0;
+        self.bytes_sent = 0;
+        self.deltas_sent = 0;
+        self.error_count = 0;
+        self.rate_limit_dropped = 0;
+        self.recent_sends.clear();
+        self.delta_compressor.reset_bandwidth_stats();
+        if let Some(rate_limiter) = &mut self.rate_limiter {
+            rate_limiter.reset_stats();
         }
     }
 
+    /// Encoded bytes of the deltas sent per component since the last
+    /// [`Self::reset_stats`]. Empty unless [`SyncConfig::with_bandwidth_stats`] is on.
+    pub fn bandwidth_by_component(&self) -> HashMap<ComponentId, u64> {
+        self.delta_compressor.bandwidth_by_component()
+    }
+
+    /// See [`Self::bandwidth_by_component`].
+    pub fn get_bandwidth_stats(&self) -> Option<&BandwidthStats> {
+        self.delta_compressor.get_bandwidth_stats()
+    }
+
+    pub fn get_transport(&self) -> &T {
+        &self.transport
+    }
+
     pub fn get_schema_registry(&self) -> &SchemaRegistry {
         &self.schema_registry
     }
@@ -297,16 +1504,38 @@ impl<T: Transport> SyncManager<T> {
         &mut self.schema_registry
     }
 
+    /// Queues [`SyncEvent::SchemaVersionMismatch`] if the peer is known to be
+    /// on a different version.
     pub fn set_schema_version(&mut self, version: SchemaVersion) {
+        let changed = self.schema_version != version;
         self.schema_version = version;
+        if let Some(theirs) = self.peer_schema_version.filter(|&theirs| changed && theirs != version) {
+            self.pending_events.push_back(SyncEvent::SchemaVersionMismatch { ours: version, theirs });
+        }
     }
 
     pub fn get_schema_version(&self) -> SchemaVersion {
         self.schema_version
     }
 
+    /// The schema version in the header of the last message received, `None`
+    /// before anything arrived.
+    pub fn peer_schema_version(&self) -> Option<SchemaVersion> {
+        self.peer_schema_version
+    }
+
+    /// Queue [`SyncEvent::SchemaVersionMismatch`] when the peer's version
+    /// changes to something other than ours, so it's raised once per change
+    /// rather than on every message.
+    fn record_peer_schema_version(&mut self, theirs: SchemaVersion) {
+        if self.peer_schema_version.replace(theirs) != Some(theirs) && theirs != self.schema_version {
+            self.pending_events.push_back(SyncEvent::SchemaVersionMismatch { ours: self.schema_version, theirs });
+        }
+    }
+
     pub fn reset_delta_compressor(&mut self) {
         self.delta_compressor.reset();
+        self.last_sent_hash = None;
     }
 
     pub fn is_connected(&self) -> bool {
@@ -314,46 +1543,471 @@ impl<T: Transport> SyncManager<T> {
     }
 
     pub fn close(&mut self) -> Result<()> {
-        self.transport.close()
+        self.transport.close()?;
+        self.set_state(ConnectionState::Closed);
+        Ok(())
+    }
+
+    fn wire_changes(&self, changes: Vec<DeltaChange>) -> Result<Vec<DeltaChange>> {
+        let mut changes = if self.config.compact_field_deltas {
+            self.schema_registry.compact_changes(changes)
+        } else {
+            changes
+        };
+        if self.config.compress_components {
+            self.schema_registry.compress_changes(&mut changes)?;
+        }
+        Ok(changes)
+    }
+
+    fn wire_entities(&self, entities: &[SerializedEntity]) -> Result<Vec<SerializedEntity>> {
+        let mut entities = entities.to_vec();
+        if self.config.compress_components {
+            self.schema_registry.compress_entities(&mut entities)?;
+        }
+        Ok(entities)
+    }
+
+    /// Encode `message` as the transport puts it on the wire, so the size used
+    /// for rate limiting and for choosing between a delta and a full snapshot
+    /// is exact, and the bytes can go out as they are through `send_encoded`.
+    /// Transports without a serializer of their own are measured as MessagePack.
+    ///
+    /// Messages are encoded into a buffer kept across calls, so a new
+    /// allocation is only needed when it runs out of room.
+    fn encode(&mut self, message: &Message) -> Result<Bytes> {
+        match self.transport.serializer() {
+            Some(serializer) => serializer.serialize_message_into(message, &mut self.encode_buffer)?,
+            None => BinarySerializer::new(BinaryFormat::MessagePack).serialize_message_into(message, &mut self.encode_buffer)?,
+        }
+        Ok(self.encode_buffer.split().freeze())
+    }
+
+    /// A full snapshot of `snapshot` that starts the compressor's history over
+    /// once it is sent.
+    fn keyframe_message(&mut self, snapshot: WorldSnapshot) -> Result<Outgoing> {
+        let start = Instant::now();
+        let message = Message::snapshot(self.wire_entities(&snapshot.entities)?, snapshot.timestamp, self.schema_version);
+        let data = self.encode(&message)?;
+        let trace = SendTrace { full_size: data.len(), micros: start.elapsed().as_micros() };
+        Ok(Outgoing { message, data, baseline: snapshot, keyframe: true, trace })
+    }
+
+    /// Make `baseline`, now on its way to the peer, the one the next delta is
+    /// diffed against.
+    fn record_baseline(&mut self, baseline: WorldSnapshot, keyframe: bool) {
+        if keyframe {
+            // Older baselines are pointless once the keyframe is out.
+            self.delta_compressor.reset();
+            self.acked_baseline = None;
+            self.keyframe_due = false;
+        }
+        self.delta_compressor.record(baseline);
+    }
+
+    /// The snapshot deltas are diffed against: the last one sent, or with
+    /// `ack_baselines` the last one the peer acknowledged.
+    fn delta_baseline(&self) -> Option<&WorldSnapshot> {
+        match (self.config.ack_baselines, self.acked_baseline) {
+            (false, _) => self.delta_compressor.get_previous_snapshot(),
+            (true, Some(base)) => self.delta_compressor.history().find(|s| s.timestamp == base),
+            (true, None) => None,
+        }
+    }
+
+    /// Share of entities `delta` touches, out of those in `snapshot` or its
+    /// baseline; 0 without a baseline.
+    fn changed_ratio(&self, snapshot: &WorldSnapshot, delta: &Delta) -> f64 {
+        let Some(base) = self.delta_baseline() else {
+            return 0.0;
+        };
+        let total = base.entities.len().max(snapshot.entities.len());
+        if total == 0 {
+            return 0.0;
+        }
+
+        let changed: HashSet<EntityId> = delta.changes.iter().map(DeltaChange::entity_id).collect();
+        changed.len() as f64 / total as f64
+    }
+
+    /// Diff `snapshot` against the last one sent and build whichever of the
+    /// delta and the full snapshot is smaller (per `full_snapshot_threshold`,
+    /// `full_snapshot_hysteresis` and `exact_snapshot_size`), along with the
+    /// snapshot to record as the new baseline once the message is sent. `None`
+    /// if nothing changed.
+    ///
+    /// An empty world with no baseline yet still goes out, as an empty
+    /// snapshot, so the peer knows the session started and what it starts from.
+    fn delta_or_snapshot_message(&mut self, snapshot: WorldSnapshot) -> Result<Option<Outgoing>> {
+        if self.keyframe_due || self.config.max_delta_chain.is_some_and(|max| self.delta_chain >= max) {
+            return self.keyframe_message(snapshot).map(Some);
+        }
+
+        let start = Instant::now();
+        let delta = match (self.config.ack_baselines, self.acked_baseline) {
+            (false, _) => self.delta_compressor.peek_delta(&snapshot),
+            (true, Some(base)) => self.delta_compressor.peek_delta_from(base, &snapshot),
+            (true, None) => self.delta_compressor.peek_initial_delta(&snapshot),
+        };
+
+        if self.config.changed_entity_threshold.is_some_and(|ratio| self.changed_ratio(&snapshot, &delta) > ratio) {
+            self.change_ratio_snapshots += 1;
+            return self.keyframe_message(snapshot).map(Some);
+        }
+
+        if delta.changes.is_empty() {
+            if self.delta_compressor.get_previous_snapshot().is_some() {
+                self.delta_compressor.record(snapshot);
+                return Ok(None);
+            }
+
+            let message = Message::snapshot(Vec::new(), snapshot.timestamp, self.schema_version);
+            let data = self.encode(&message)?;
+            let trace = SendTrace { full_size: data.len(), micros: start.elapsed().as_micros() };
+            return Ok(Some(Outgoing { message, data, baseline: snapshot, keyframe: false, trace }));
+        }
+
+        let schema_version = self.schema_version;
+        let delta_message = Message::delta_at(
+            self.wire_changes(delta.changes)?,
+            wire_timestamp(delta.base_timestamp),
+            delta.timestamp,
+            schema_version,
+        );
+        let delta_data = self.encode(&delta_message)?;
+
+        // Once on full snapshots, stay there until deltas are clearly smaller again.
+        let mut threshold = self.config.full_snapshot_threshold;
+        if self.snapshot_fallback {
+            threshold *= 1.0 - self.config.full_snapshot_hysteresis;
+        }
+
+        let full = if self.config.exact_snapshot_size {
+            let full = self.snapshot_outgoing(&snapshot)?;
+            self.snapshot_fallback = delta_data.len() as f64 > full.1.len() as f64 * threshold;
+            Some(full)
+        } else {
+            self.snapshot_fallback = self.outweighs_snapshot(delta_data.len(), &snapshot, threshold)?;
+            None
+        };
+
+        if self.snapshot_fallback {
+            self.auto_full_snapshots += 1;
+            let (message, data) = match full {
+                Some(full) => full,
+                None => self.snapshot_outgoing(&snapshot)?,
+            };
+            let trace = SendTrace { full_size: data.len(), micros: start.elapsed().as_micros() };
+            return Ok(Some(Outgoing { message, data, baseline: snapshot, keyframe: false, trace }));
+        }
+
+        // The full size is only worked out for the trace when nothing else needs it.
+        let full_size = match full {
+            Some((_, data)) => data.len(),
+            None if debug::is_trace_enabled() => self.snapshot_outgoing(&snapshot)?.1.len(),
+            None => delta_data.len(),
+        };
+        let trace = SendTrace { full_size, micros: start.elapsed().as_micros() };
+        Ok(Some(Outgoing { message: delta_message, data: delta_data, baseline: snapshot, keyframe: false, trace }))
     }
 
-    fn estimate_message_size(&self, _message: &Message) -> u64 {
-        1024
+    /// Whether `delta_len` bytes exceed `threshold` times the encoded size of
+    /// `snapshot` as a full snapshot message. Its entities are encoded one at a
+    /// time on top of the empty message, stopping as soon as they outweigh the
+    /// delta, so a small delta costs a few entities' worth of encoding rather
+    /// than the whole snapshot.
+    fn outweighs_snapshot(&mut self, delta_len: usize, snapshot: &WorldSnapshot, threshold: f64) -> Result<bool> {
+        let format = self.transport.serializer().map_or(BinaryFormat::MessagePack, BinarySerializer::get_format);
+        let outweighs = |len: usize| delta_len as f64 > len as f64 * threshold;
+
+        let mut len = self.encode(&Message::snapshot(Vec::new(), snapshot.timestamp, self.schema_version))?.len();
+        for entity in &snapshot.entities {
+            if !outweighs(len) {
+                return Ok(false);
+            }
+            let entity = &self.wire_entities(core::slice::from_ref(entity))?[0];
+            len += crate::serialization::encode_value(format, entity)?.len();
+        }
+        Ok(outweighs(len))
+    }
+
+    /// `snapshot` as a full snapshot message, encoded.
+    fn snapshot_outgoing(&mut self, snapshot: &WorldSnapshot) -> Result<(Message, Bytes)> {
+        let message = Message::snapshot(self.wire_entities(&snapshot.entities)?, snapshot.timestamp, self.schema_version);
+        let data = self.encode(&message)?;
+        Ok((message, data))
     }
 }
 
-#[derive(Debug, Clone)]
+/// A message ready to go out, and the snapshot the compressor records as its
+/// baseline once it has.
+struct Outgoing {
+    message: Message,
+    data: Bytes,
+    baseline: WorldSnapshot,
+    /// Starts the compressor's history over.
+    keyframe: bool,
+    trace: SendTrace,
+}
+
+/// What building an outgoing message cost, traced once it is sent.
+struct SendTrace {
+    /// Encoded size of the full snapshot, whichever message goes out.
+    full_size: usize,
+    /// Time spent diffing and encoding.
+    micros: u128,
+}
+
+impl SendTrace {
+    /// Trace the `size` bytes that went out against the full snapshot.
+    /// Messages the rate limiter holds back aren't traced.
+    fn sent(&self, size: usize) {
+        debug::trace_compression(self.full_size, size, self.micros);
+    }
+}
+
+/// Restore components compressed by the sender's schema hints, inflating no
+/// more than `max_len` bytes for the whole message.
+fn decompress_payload(payload: &mut MessagePayload, max_len: usize) -> Result<()> {
+    match payload {
+        MessagePayload::Snapshot(payload) => crate::compression::decompress_entities(&mut payload.entities, max_len),
+        MessagePayload::Delta(payload) => crate::compression::decompress_changes(&mut payload.changes, max_len),
+        _ => Ok(()),
+    }
+}
+
+/// Remove `fields` from structured or JSON object data. Binary data is opaque
+/// and passes through untouched.
+fn mask_component(data: &mut ComponentData, fields: &AHashSet<FieldId>) {
+    match data {
+        ComponentData::Structured(values) => values.retain(|field_id, _| !fields.contains(field_id)),
+        ComponentData::Json(json) => {
+            let Ok(serde_json::Value::Object(mut object)) = serde_json::from_str(json) else {
+                return;
+            };
+            let len = object.len();
+            object.retain(|key, _| !fields.contains(key));
+            if object.len() != len {
+                *json = serde_json::Value::Object(object).to_string();
+            }
+        }
+        ComponentData::Binary(_) | ComponentData::Empty | ComponentData::Compressed { .. } => {}
+    }
+}
+
+pub(crate) fn wire_timestamp(timestamp: f64) -> u64 {
+    (timestamp * 1000.0) as u64
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct SyncStats {
     pub sync_count: u64,
+    pub skipped_syncs: u64,
+    /// Delta-mode syncs sent as a full snapshot because the delta was too large.
+    pub auto_full_snapshots: u64,
+    /// Delta-mode syncs sent as a full snapshot because too many entities
+    /// changed, see [`SyncConfig::with_changed_entity_threshold`].
+    pub change_ratio_snapshots: u64,
+    /// Incoming messages dropped as repeats, see [`SyncConfig::with_duplicate_suppression`].
+    pub duplicates_dropped: u64,
+    /// Snapshot and delta messages sent.
+    pub messages_sent: u64,
+    /// Encoded size of those messages as the transport's serializer writes
+    /// them, compression included and framing not. Transports that don't
+    /// expose a serializer are counted at their MessagePack size.
+    pub bytes_sent: u64,
+    pub deltas_sent: u64,
+    /// Deltas sent since the last full snapshot.
+    pub delta_chain: u64,
+    /// Round trip of the last answered [`SyncManager::ping`].
+    pub rtt: Option<Duration>,
+    /// Moving average of the round trips, see [`SyncConfig::with_adaptive_interval`].
+    pub smoothed_rtt: Option<Duration>,
     pub error_count: u64,
+    #[serde(skip)]
     pub last_sync: Option<Instant>,
+    /// Wall-clock time of the last sync in milliseconds since the Unix epoch,
+    /// like `MessageHeader::timestamp`. Use `last_sync` for interval math.
+    pub last_sync_millis: Option<u64>,
     pub rate_limiter_stats: Option<crate::rate_limit::RateLimitStats>,
+    /// Messages held back by the rate limit policy, waiting to be sent.
+    pub rate_limited_pending: usize,
+    /// Held messages superseded under `RateLimitPolicy::DropOldest`.
+    pub rate_limited_dropped: u64,
     pub reconnect_attempts: u32,
+    /// Sends over the trailing window, see [`SyncConfig::with_stats_window`].
+    pub window: Option<WindowStats>,
+}
+
+/// What was sent over a trailing window.
+#[derive(Debug, Clone, Serialize)]
+pub struct WindowStats {
+    pub window: Duration,
+    pub messages_sent: u64,
+    pub bytes_sent: u64,
+    pub messages_per_second: f64,
+    pub bytes_per_second: f64,
+}
+
+impl SyncStats {
+    /// Render the stats in the Prometheus text exposition format.
+    pub fn metrics_text(&self) -> String {
+        let rate_limiter = self.rate_limiter_stats.as_ref();
+        let mut text = String::new();
+
+        let counters = [
+            ("tx2_messages_sent_total", "Snapshot and delta messages sent.", self.messages_sent),
+            ("tx2_bytes_sent_total", "Encoded bytes of messages sent.", self.bytes_sent),
+            ("tx2_deltas_total", "Delta messages sent.", self.deltas_sent),
+            ("tx2_full_snapshot_fallbacks_total", "Deltas replaced by a full snapshot.", self.auto_full_snapshots),
+            ("tx2_change_ratio_snapshots_total", "Deltas replaced by a full snapshot because too many entities changed.", self.change_ratio_snapshots),
+            ("tx2_duplicates_dropped_total", "Incoming messages dropped as duplicates.", self.duplicates_dropped),
+            ("tx2_skipped_syncs_total", "Syncs skipped because nothing changed.", self.skipped_syncs),
+            ("tx2_errors_total", "Error messages received and rejected incoming messages.", self.error_count),
+            ("tx2_rate_limited_total", "Messages rejected by the rate limiter.", rate_limiter.map_or(0, |s| s.total_rejected)),
+        ];
+        for (name, help, value) in counters {
+            write_metric(&mut text, name, help, "counter", value as f64);
+        }
+
+        if let Some(stats) = rate_limiter {
+            write_metric(&mut text, "tx2_messages_in_window", "Messages sent in the current rate limit window.", "gauge", stats.messages_in_window as f64);
+            write_metric(&mut text, "tx2_bytes_in_window", "Bytes sent in the current rate limit window.", "gauge", stats.bytes_in_window as f64);
+        }
+        if let Some(window) = &self.window {
+            write_metric(&mut text, "tx2_messages_per_second", "Messages sent per second over the stats window.", "gauge", window.messages_per_second);
+            write_metric(&mut text, "tx2_bytes_per_second", "Bytes sent per second over the stats window.", "gauge", window.bytes_per_second);
+        }
+        if let Some(rtt) = self.rtt {
+            write_metric(&mut text, "tx2_rtt_seconds", "Round trip time of the last ping.", "gauge", rtt.as_secs_f64());
+        }
+        write_metric(&mut text, "tx2_reconnect_attempts", "Reconnect attempts since the last successful sync.", "gauge", self.reconnect_attempts as f64);
+
+        text
+    }
+}
+
+fn write_metric(text: &mut String, name: &str, help: &str, kind: &str, value: f64) {
+    use std::fmt::Write;
+
+    let _ = writeln!(text, "# HELP {} {}", name, help);
+    let _ = writeln!(text, "# TYPE {} {}", name, kind);
+    let _ = writeln!(text, "{} {}", name, value);
 }
 
 #[derive(Debug)]
 pub enum SyncEvent {
     Snapshot(WorldSnapshot),
     Delta(Delta),
-    SnapshotRequested,
+    /// `answered` is false when no snapshot has been sent yet, so the
+    /// application has to respond itself.
+    SnapshotRequested { since: Option<u64>, answered: bool },
     Ack(u64),
     Ping,
     Pong,
     SchemaSync(Vec<ComponentSchemaInfo>),
-    Error { code: u32, message: String },
+    /// `code` is `None` for codes this build doesn't know; `raw_code` always
+    /// carries the value from the wire.
+    Error { code: Option<ErrorCode>, raw_code: u32, message: String },
+    StateChanged { from: ConnectionState, to: ConnectionState },
+    /// A keepalive ping went unanswered; see [`SyncManager::maintain`].
+    ConnectionTimeout,
+    /// A message whose payload this build doesn't understand, most likely from
+    /// a newer peer. It was skipped; the header's type is passed along.
+    Unknown(MessageType),
+    /// An outgoing message crossed [`SyncConfig::with_size_warning`]; `size`
+    /// is its encoded length in bytes.
+    Warning { kind: WarningKind, size: u64 },
+    /// The peer's messages carry a different schema version than ours; see
+    /// [`SyncManager::peer_schema_version`]. Raised when either side changes
+    /// version, so the application can sync schemas or migrate.
+    SchemaVersionMismatch { ours: SchemaVersion, theirs: SchemaVersion },
+    /// A message with a recently seen header `id` arrived again and was
+    /// dropped; see [`SyncConfig::with_duplicate_suppression`].
+    Duplicate { id: u64 },
+}
+
+/// Header ids of recently processed messages, oldest first.
+#[derive(Debug, Default)]
+struct RecentIds {
+    order: VecDeque<u64>,
+    seen: HashSet<u64>,
+}
+
+impl RecentIds {
+    fn contains(&self, id: u64) -> bool {
+        self.seen.contains(&id)
+    }
+
+    fn insert(&mut self, id: u64, capacity: usize) {
+        if !self.seen.insert(id) {
+            return;
+        }
+        self.order.push_back(id);
+        while self.order.len() > capacity {
+            if let Some(evicted) = self.order.pop_front() {
+                self.seen.remove(&evicted);
+            }
+        }
+    }
+}
+
+/// What a [`SyncEvent::Warning`] is about.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WarningKind {
+    LargeSnapshot,
+    LargeDelta,
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::protocol::SerializedEntity;
     use crate::transport::MemoryTransport;
     use crate::serialization::BinaryFormat;
 
+    #[test]
+    fn test_config_builder_validation() {
+        let config = SyncConfig::builder()
+            .with_ack_baselines(true)
+            .with_keepalive(Duration::from_secs(1), Duration::from_millis(500))
+            .build()
+            .unwrap();
+        assert!(config.ack_baselines);
+        assert!(SyncConfig::default().validate().is_ok());
+
+        let invalid = |builder: SyncConfigBuilder| match builder.build() {
+            Err(LinkError::InvalidConfig(reason)) => reason,
+            other => panic!("expected an invalid config, got {:?}", other),
+        };
+        assert!(invalid(SyncConfig::builder().with_auto_reconnect(true, 0)).contains("max_reconnect_attempts"));
+        assert!(invalid(SyncConfig::builder().with_ack_baselines(true).with_keyframe_history(1)).contains("keyframe_history"));
+        assert!(invalid(SyncConfig::builder().with_full_snapshot_threshold(f64::NAN)).contains("full_snapshot_threshold"));
+        assert!(invalid(SyncConfig::builder().with_full_snapshot_hysteresis(1.5)).contains("full_snapshot_hysteresis"));
+        assert!(invalid(SyncConfigBuilder::from_config(SyncConfig::new().with_max_delta_chain(0))).contains("max_delta_chain"));
+    }
+
+    #[test]
+    #[allow(deprecated)]
+    fn test_sync_manager_validates_config() {
+        let transport = || MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new().with_stats_window(Duration::ZERO);
+        assert!(matches!(
+            SyncManager::try_new(transport(), config.clone()),
+            Err(LinkError::InvalidConfig(_))
+        ));
+
+        // The unchecked constructor still reports rates instead of NaN.
+        let manager = SyncManager::new(transport(), config);
+        let window = manager.get_stats().window.unwrap();
+        assert_eq!((window.messages_per_second, window.bytes_per_second), (0.0, 0.0));
+    }
+
     #[test]
     fn test_sync_manager_snapshot() {
         let transport = MemoryTransport::new(BinaryFormat::MessagePack);
         let config = SyncConfig::new().with_mode(SyncMode::Full);
-        let mut manager = SyncManager::new(transport, config);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
 
         let snapshot = WorldSnapshot {
             entities: vec![],
@@ -366,17 +2020,41 @@ mod tests {
     }
 
     #[test]
-    fn test_sync_manager_delta() {
-        use crate::protocol::{SerializedEntity, SerializedComponent, ComponentData};
+    fn test_last_sync_millis() {
+        use crate::clock::MockClock;
 
+        let clock = MockClock::new();
         let transport = MemoryTransport::new(BinaryFormat::MessagePack);
-        let config = SyncConfig::new().with_mode(SyncMode::Delta);
-        let mut manager = SyncManager::new(transport, config);
+        let config = SyncConfig::new().with_mode(SyncMode::Full);
+        let mut manager = SyncManager::try_with_clock(transport, config, Arc::new(clock.clone())).unwrap();
+        let snapshot = WorldSnapshot { entities: vec![], timestamp: 100.0, version: "1.0.0".to_string() };
 
-        let snapshot1 = WorldSnapshot {
-            entities: vec![],
-            timestamp: 100.0,
-            version: "1.0.0".to_string(),
+        assert!(manager.get_stats().last_sync_millis.is_none());
+
+        let start = clock.epoch_millis();
+        clock.advance(Duration::from_millis(250));
+        manager.send_snapshot(snapshot).unwrap();
+
+        let stats = manager.get_stats();
+        assert_eq!(stats.last_sync_millis, Some(start + 250));
+
+        let exported = serde_json::to_value(&stats).unwrap();
+        assert_eq!(exported["last_sync_millis"], start + 250);
+        assert!(exported.get("last_sync").is_none());
+    }
+
+    #[test]
+    fn test_sync_manager_delta() {
+        use crate::protocol::{SerializedEntity, SerializedComponent, ComponentData};
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new().with_mode(SyncMode::Delta);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+
+        let snapshot1 = WorldSnapshot {
+            entities: vec![],
+            timestamp: 100.0,
+            version: "1.0.0".to_string(),
         };
 
         assert!(manager.send_delta(snapshot1).is_ok());
@@ -398,7 +2076,1515 @@ mod tests {
         };
 
         assert!(manager.send_delta(snapshot2).is_ok());
-        assert_eq!(manager.get_stats().sync_count, 1);
+        assert_eq!(manager.get_stats().sync_count, 2);
+    }
+
+    #[test]
+    fn test_canonical_components() {
+        use crate::protocol::{SerializedComponent, ComponentData};
+        use crate::serialization::BinarySerializer;
+
+        let config = SyncConfig::new().with_mode(SyncMode::Full).with_canonical_components(true);
+        let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        let components = ["Velocity", "Position"].map(|id| SerializedComponent { id: id.to_string(), data: ComponentData::Empty });
+        manager.send(WorldSnapshot {
+            entities: vec![SerializedEntity { id: 1, components: components.to_vec() }],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        }).unwrap();
+
+        let sent = &manager.get_transport().get_send_buffer()[0];
+        match BinarySerializer::new(BinaryFormat::MessagePack).deserialize_message(sent).unwrap().payload {
+            MessagePayload::Snapshot(payload) => assert_eq!(payload.entities[0].components[0].id, "Position"),
+            other => panic!("expected snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_empty_initial_snapshot() {
+        use crate::protocol::{SerializedComponent, ComponentData};
+        use crate::serialization::BinarySerializer;
+
+        let world = |ids: std::ops::Range<u32>, timestamp: f64| WorldSnapshot::for_test(timestamp, ids.map(|id| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({"x": id})),
+            }],
+        }).collect());
+
+        let config = SyncConfig::new().with_mode(SyncMode::Delta);
+        let mut server = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        server.send(world(0..0, 1.0)).unwrap();
+        server.send(world(0..0, 2.0)).unwrap();
+        server.send(world(0..3, 3.0)).unwrap();
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let sent = server.get_transport().get_send_buffer();
+        assert_eq!(sent.len(), 2);
+
+        let mut client = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), SyncConfig::new()).unwrap();
+        let mut state = None;
+        for data in sent {
+            match client.process_message(serializer.deserialize_message(data).unwrap()).unwrap() {
+                SyncEvent::Snapshot(snapshot) => state = Some(snapshot),
+                SyncEvent::Delta(delta) => state.as_mut().expect("baseline before delta").apply_delta(&delta).unwrap(),
+                other => panic!("unexpected event {:?}", other),
+            }
+        }
+
+        let mut state = state.unwrap();
+        state.entities.sort_by_key(|e| e.id);
+        assert_eq!(state.entities, world(0..3, 3.0).entities);
+    }
+
+    #[test]
+    fn test_delta_mode_falls_back_to_full_snapshot() {
+        use crate::protocol::{SerializedComponent, ComponentData};
+        use crate::serialization::BinarySerializer;
+
+        let snapshot = |moved: std::ops::Range<u32>, timestamp: f64| WorldSnapshot::for_test(timestamp, (0..20).map(|id| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({
+                    "x": if moved.contains(&id) { timestamp } else { 0.0 },
+                    "y": 0.0,
+                })),
+            }],
+        }).collect());
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let last_sent = |manager: &SyncManager<MemoryTransport>| {
+            let sent = manager.get_transport().get_send_buffer();
+            serializer.deserialize_message(sent.last().unwrap()).unwrap().header.msg_type
+        };
+
+        // Measuring the snapshot only as far as needed decides as encoding it all would.
+        for exact in [false, true] {
+            let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+            let config = SyncConfig::new().with_mode(SyncMode::Delta).with_exact_snapshot_size(exact);
+            let mut manager = SyncManager::try_new(transport, config).unwrap();
+
+            // Without a baseline the delta re-adds every entity and component.
+            manager.send_delta(snapshot(0..0, 1.0)).unwrap();
+            assert_eq!(last_sent(&manager), MessageType::Snapshot);
+            assert_eq!(manager.get_stats().auto_full_snapshots, 1);
+
+            manager.send_delta(snapshot(0..1, 2.0)).unwrap();
+            assert_eq!(last_sent(&manager), MessageType::Delta);
+
+            manager.send_delta(snapshot(0..20, 3.0)).unwrap();
+            assert_eq!(last_sent(&manager), MessageType::Snapshot);
+            assert_eq!(manager.get_stats().auto_full_snapshots, 2);
+        }
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_full_snapshot_threshold(f64::INFINITY);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+
+        manager.send_delta(snapshot(0..0, 1.0)).unwrap();
+        assert_eq!(last_sent(&manager), MessageType::Delta);
+        assert_eq!(manager.get_stats().auto_full_snapshots, 0);
+    }
+
+    #[test]
+    fn test_full_snapshot_fallback_hysteresis() {
+        use crate::protocol::{SerializedComponent, ComponentData};
+        use crate::serialization::BinarySerializer;
+
+        let snapshot = |moved: std::ops::Range<u32>, x: f64, timestamp: f64| WorldSnapshot::for_test(timestamp, (0..20).map(|id| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({
+                    "x": if moved.contains(&id) { x } else { 0.0 },
+                    "y": 0.0,
+                })),
+            }],
+        }).collect());
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let sent_types = |margin: f64| {
+            let config = SyncConfig::new()
+                .with_mode(SyncMode::Delta)
+                .with_rate_limiting(false)
+                .with_full_snapshot_hysteresis(margin);
+            let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+
+            // The second delta is over half the snapshot's size: under the
+            // threshold, but not by the margin. The third changes one entity.
+            for (moved, timestamp) in [(0..0, 1.0), (0..8, 2.0), (0..9, 3.0)] {
+                manager.send_delta(snapshot(moved, 2.0, timestamp)).unwrap();
+            }
+            manager.get_transport().get_send_buffer().iter()
+                .map(|data| serializer.deserialize_message(data).unwrap().header.msg_type)
+                .collect::<Vec<_>>()
+        };
+
+        use MessageType::{Delta, Snapshot};
+        assert_eq!(sent_types(0.0), vec![Snapshot, Delta, Delta]);
+        assert_eq!(sent_types(0.5), vec![Snapshot, Snapshot, Delta]);
+    }
+
+    #[test]
+    fn test_failed_send_keeps_baseline() {
+        use crate::clock::MockClock;
+        use crate::protocol::{SerializedComponent, ComponentData};
+        use crate::serialization::BinarySerializer;
+
+        let clock = MockClock::new();
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_full_snapshot_threshold(f64::INFINITY)
+            .with_rate_limit_config(RateLimitConfig::new().with_max_messages(1));
+        let mut manager = SyncManager::try_with_clock(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            config,
+            Arc::new(clock.clone()),
+        ).unwrap();
+
+        let snapshot = |x: f64, timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({ "x": x })),
+            }],
+        }]);
+
+        manager.send_delta(snapshot(1.0, 1.0)).unwrap();
+        assert!(matches!(manager.send_delta(snapshot(2.0, 2.0)), Err(LinkError::RateLimitExceeded(_))));
+
+        // The rejected state is still news to the peer.
+        clock.advance(Duration::from_millis(1100));
+        manager.send_delta(snapshot(2.0, 3.0)).unwrap();
+        let sent = manager.get_transport().get_send_buffer();
+        assert_eq!(sent.len(), 2);
+
+        // Sizes come from the bytes that actually went out.
+        let stats = manager.get_stats();
+        assert_eq!(stats.bytes_sent, sent.iter().map(|data| data.len() as u64).sum::<u64>());
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        match serializer.deserialize_message(&sent[1]).unwrap().payload {
+            MessagePayload::Delta(delta) => assert_eq!(delta.changes.len(), 1),
+            other => panic!("expected a delta, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_send_traces_encoded_sizes() {
+        use crate::protocol::{SerializedComponent, ComponentData};
+
+        let snapshot = |x: f64, timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({ "x": x, "name": "player".repeat(16) })),
+            }],
+        }]);
+        // The sizes the last compression trace of a send reports.
+        let traced_sizes = |lines: Vec<String>| -> (u64, u64) {
+            let line = lines.iter().rev().find_map(|line| line.split_once("Delta compression:")).unwrap().1;
+            let mut sizes = line.split(|c: char| !c.is_ascii_digit()).filter_map(|n| n.parse().ok());
+            (sizes.next().unwrap(), sizes.next().unwrap())
+        };
+
+        for mode in [SyncMode::Full, SyncMode::Delta] {
+            let config = SyncConfig::new().with_mode(mode).with_full_snapshot_threshold(f64::INFINITY);
+            let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+
+            let mut full_sizes = Vec::new();
+            for (x, timestamp) in [(1.0, 1.0), (2.0, 2.0)] {
+                let before = manager.get_stats().bytes_sent;
+                let lines = crate::debug::capture_traces(|| manager.send(snapshot(x, timestamp)).unwrap());
+                let (full, sent) = traced_sizes(lines);
+                assert_eq!(sent, manager.get_stats().bytes_sent - before);
+                full_sizes.push(full);
+                if mode == SyncMode::Full {
+                    assert_eq!(full, sent);
+                } else if timestamp > 1.0 {
+                    assert!(sent < full);
+                }
+            }
+            assert_eq!(full_sizes[0], full_sizes[1]);
+        }
+    }
+
+    #[test]
+    fn test_bandwidth_stats() {
+        use crate::protocol::{SerializedComponent, ComponentData};
+
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_full_snapshot_threshold(f64::INFINITY)
+            .with_bandwidth_stats(true);
+        let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+
+        let snapshot = |x: f64, timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![
+                SerializedComponent {
+                    id: "Position".to_string(),
+                    data: ComponentData::from_json_value(serde_json::json!({ "x": x })),
+                },
+                SerializedComponent {
+                    id: "Velocity".to_string(),
+                    data: ComponentData::from_json_value(serde_json::json!({ "dx": 1.0 })),
+                },
+            ],
+        }]);
+
+        // The first delta adds the whole entity.
+        manager.send_delta(snapshot(1.0, 1.0)).unwrap();
+        let bandwidth = manager.bandwidth_by_component();
+        assert!(bandwidth["Position"] > 0);
+        assert!(bandwidth["Velocity"] > 0);
+
+        manager.reset_stats();
+        assert!(manager.bandwidth_by_component().is_empty());
+
+        manager.send_delta(snapshot(2.0, 2.0)).unwrap();
+        let bandwidth = manager.bandwidth_by_component();
+        assert!(bandwidth["Position"] > 0);
+        assert!(!bandwidth.contains_key("Velocity"));
+        assert_eq!(manager.get_bandwidth_stats().unwrap().total_bytes(), bandwidth["Position"]);
+
+        let mut plain = SyncManager::try_new(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            SyncConfig::new().with_mode(SyncMode::Delta),
+        ).unwrap();
+        plain.send_delta(snapshot(1.0, 1.0)).unwrap();
+        plain.send_delta(snapshot(2.0, 2.0)).unwrap();
+        assert!(plain.get_bandwidth_stats().is_none());
+    }
+
+    #[test]
+    fn test_max_delta_chain_forces_keyframe() {
+        use crate::serialization::BinarySerializer;
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_rate_limiting(false)
+            .with_full_snapshot_threshold(f64::INFINITY)
+            .with_max_delta_chain(2);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+
+        let snapshot = |timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity { id: timestamp as u32, components: vec![] }]);
+
+        let mut chains = Vec::new();
+        for tick in 1..=6 {
+            manager.send_delta(snapshot(tick as f64)).unwrap();
+            chains.push(manager.get_stats().delta_chain);
+        }
+        assert_eq!(chains, vec![1, 2, 0, 1, 2, 0]);
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let types: Vec<_> = manager.get_transport().get_send_buffer().iter()
+            .map(|data| serializer.deserialize_message(data).unwrap().header.msg_type)
+            .collect();
+        assert_eq!(types, vec![
+            MessageType::Delta, MessageType::Delta, MessageType::Snapshot,
+            MessageType::Delta, MessageType::Delta, MessageType::Snapshot,
+        ]);
+
+        // After a keyframe only that snapshot is kept as a baseline.
+        assert_eq!(manager.delta_compressor.history().count(), 1);
+    }
+
+    #[test]
+    fn test_rejected_keyframe_keeps_history() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_full_snapshot_threshold(f64::INFINITY)
+            .with_max_delta_chain(1)
+            .with_rate_limit_config(RateLimitConfig::new().with_max_messages(1));
+        let mut manager = SyncManager::try_with_clock(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            config,
+            Arc::new(clock.clone()),
+        ).unwrap();
+
+        let snapshot = |timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity { id: timestamp as u32, components: vec![] }]);
+
+        manager.send_delta(snapshot(1.0)).unwrap();
+        assert!(manager.send_delta(snapshot(2.0)).is_err());
+        assert_eq!(manager.delta_compressor.history().count(), 1);
+        assert_eq!(manager.delta_compressor.get_previous_snapshot().unwrap().timestamp, 1.0);
+
+        clock.advance(Duration::from_millis(1100));
+        manager.send_delta(snapshot(3.0)).unwrap();
+        assert_eq!(manager.delta_compressor.get_previous_snapshot().unwrap().timestamp, 3.0);
+        assert_eq!(manager.delta_compressor.history().count(), 1);
+    }
+
+    #[test]
+    fn test_changed_entity_threshold() {
+        use crate::protocol::{SerializedComponent, ComponentData, FieldValue};
+        use crate::serialization::BinarySerializer;
+
+        let snapshot = |moved: usize, timestamp: f64| WorldSnapshot::for_test(timestamp, (0..10u32).map(|id| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::Structured([(
+                    "x".to_string(),
+                    FieldValue::F64(if (id as usize) < moved { 1.0 } else { 0.0 }),
+                )].into_iter().collect()),
+            }],
+        }).collect());
+
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_rate_limiting(false)
+            .with_full_snapshot_threshold(f64::INFINITY)
+            .with_changed_entity_threshold(0.5);
+        let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        for (tick, moved) in [0, 4, 10, 8].into_iter().enumerate() {
+            manager.send_delta(snapshot(moved, tick as f64 + 1.0)).unwrap();
+        }
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let types: Vec<_> = manager.get_transport().get_send_buffer().iter()
+            .map(|data| serializer.deserialize_message(data).unwrap().header.msg_type)
+            .collect();
+        assert_eq!(types, vec![MessageType::Delta, MessageType::Delta, MessageType::Snapshot, MessageType::Delta]);
+        assert_eq!(manager.get_stats().change_ratio_snapshots, 1);
+        assert_eq!(manager.get_stats().auto_full_snapshots, 0);
+
+        // With acked baselines the ratio counts from the acknowledged snapshot,
+        // not from the last one sent.
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_rate_limiting(false)
+            .with_full_snapshot_threshold(f64::INFINITY)
+            .with_ack_baselines(true)
+            .with_changed_entity_threshold(0.5);
+        let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        manager.send_delta(snapshot(0, 1.0)).unwrap();
+        manager.process_message(Message::ack_baseline(1000, SchemaVersion::new(1))).unwrap();
+        manager.send_delta(snapshot(3, 2.0)).unwrap();
+        manager.send_delta(snapshot(6, 3.0)).unwrap();
+
+        let types: Vec<_> = manager.get_transport().get_send_buffer().iter()
+            .map(|data| serializer.deserialize_message(data).unwrap().header.msg_type)
+            .collect();
+        assert_eq!(types, vec![MessageType::Delta, MessageType::Delta, MessageType::Snapshot]);
+        assert_eq!(manager.get_stats().change_ratio_snapshots, 1);
+    }
+
+    #[test]
+    fn test_should_sync_with_mock_clock() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Full)
+            .with_sync_interval(Duration::from_millis(100));
+        let mut manager = SyncManager::try_with_clock(transport, config, Arc::new(clock.clone())).unwrap();
+
+        assert!(manager.should_sync());
+
+        let snapshot = WorldSnapshot {
+            entities: vec![],
+            timestamp: 100.0,
+            version: "1.0.0".to_string(),
+        };
+        manager.send_snapshot(snapshot).unwrap();
+        assert!(!manager.should_sync());
+
+        clock.advance(Duration::from_millis(99));
+        assert!(!manager.should_sync());
+
+        clock.advance(Duration::from_millis(1));
+        assert!(manager.should_sync());
+    }
+
+    #[test]
+    fn test_stats_window_and_reset() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Full)
+            .with_stats_window(Duration::from_secs(10));
+        let mut manager = SyncManager::try_with_clock(transport, config, Arc::new(clock.clone())).unwrap();
+        let snapshot = |timestamp| WorldSnapshot::for_test(timestamp, vec![]);
+
+        manager.send_snapshot(snapshot(1.0)).unwrap();
+        clock.advance(Duration::from_secs(6));
+        manager.send_snapshot(snapshot(2.0)).unwrap();
+
+        let window = manager.get_stats().window.unwrap();
+        assert_eq!(window.messages_sent, 2);
+        assert_eq!(window.messages_per_second, 0.2);
+
+        // The first send leaves the window, the lifetime total keeps it.
+        clock.advance(Duration::from_secs(5));
+        let stats = manager.get_stats();
+        assert_eq!((stats.messages_sent, stats.window.unwrap().messages_sent), (2, 1));
+
+        manager.reset_stats();
+        let stats = manager.get_stats();
+        assert_eq!((stats.sync_count, stats.messages_sent, stats.bytes_sent), (0, 0, 0));
+        assert_eq!(stats.window.unwrap().messages_sent, 0);
+        assert_eq!(stats.rate_limiter_stats.unwrap().total_messages, 0);
+    }
+
+    #[test]
+    fn test_size_warning() {
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Full)
+            .with_size_warning(200);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+        let snapshot = |entities: u32| WorldSnapshot::for_test(entities as f64, (0..entities).map(|id| SerializedEntity { id, components: vec![] }).collect());
+        let warnings = |manager: &mut SyncManager<MemoryTransport>| -> Vec<_> {
+            std::iter::from_fn(|| manager.pending_events.pop_front())
+                .filter_map(|event| match event {
+                    SyncEvent::Warning { kind, size } => Some((kind, size)),
+                    _ => None,
+                })
+                .collect()
+        };
+
+        manager.send_snapshot(snapshot(1)).unwrap();
+        assert!(warnings(&mut manager).is_empty());
+
+        manager.send_snapshot(snapshot(100)).unwrap();
+        let raised = warnings(&mut manager);
+        assert!(matches!(&raised[..], [(WarningKind::LargeSnapshot, size)] if *size > 200));
+        assert_eq!(manager.get_transport().get_send_buffer().len(), 2);
+    }
+
+    #[test]
+    fn test_metrics_text() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new().with_mode(SyncMode::Delta);
+        let mut manager = SyncManager::try_with_clock(transport, config, Arc::new(clock.clone())).unwrap();
+
+        let snapshot = |timestamp: f64, ids: &[u32]| WorldSnapshot::for_test(timestamp, ids.iter().map(|&id| SerializedEntity { id, components: vec![] }).collect());
+        let ids: Vec<u32> = (0..20).collect();
+        manager.send_snapshot(snapshot(1.0, &ids)).unwrap();
+        manager.send_delta(snapshot(2.0, &ids[1..])).unwrap();
+
+        manager.ping().unwrap();
+        clock.advance(Duration::from_millis(40));
+        manager.process_message(Message::pong(SchemaVersion::new(1))).unwrap();
+
+        let stats = manager.get_stats();
+        assert_eq!(stats.messages_sent, 2);
+        assert_eq!(stats.deltas_sent, 1);
+        assert!(stats.bytes_sent > 0);
+        assert_eq!(stats.rtt, Some(Duration::from_millis(40)));
+
+        let text = manager.metrics_text();
+        assert!(text.contains("# TYPE tx2_messages_sent_total counter\ntx2_messages_sent_total 2\n"));
+        assert!(text.contains("tx2_deltas_total 1\n"));
+        assert!(text.contains(&format!("tx2_bytes_sent_total {}\n", stats.bytes_sent)));
+        assert!(text.contains("tx2_rate_limited_total 0\n"));
+        assert!(text.contains("# TYPE tx2_rtt_seconds gauge\ntx2_rtt_seconds 0.04\n"));
+        assert!(text.contains("tx2_messages_in_window 2\n"));
+    }
+
+    #[test]
+    fn test_bytes_sent_counts_encoded_lengths() {
+        let mut transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        transport.set_compression(CompressionType::Lz4).unwrap();
+        let config = SyncConfig::new().with_mode(SyncMode::Delta).with_rate_limiting(false);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+
+        let snapshot = |timestamp: f64, ids: &[u32]| WorldSnapshot::for_test(timestamp, ids.iter().map(|&id| SerializedEntity { id, components: vec![] }).collect());
+        let ids: Vec<u32> = (0..50).collect();
+        manager.send_snapshot(snapshot(1.0, &ids)).unwrap();
+        manager.send_delta(snapshot(2.0, &ids[1..])).unwrap();
+        manager.send_batch(vec![snapshot(3.0, &ids[2..]), snapshot(4.0, &ids[3..])]).unwrap();
+
+        let written: usize = manager.get_transport().get_send_buffer().iter().map(|data| data.len()).sum();
+        assert_eq!(manager.get_stats().bytes_sent, written as u64);
+    }
+
+    #[test]
+    fn test_overlapping_pings() {
+        use crate::clock::MockClock;
+        use crate::serialization::BinarySerializer;
+
+        let clock = MockClock::new();
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let mut manager = SyncManager::try_with_clock(transport, SyncConfig::new(), Arc::new(clock.clone())).unwrap();
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let sent = |manager: &SyncManager<MemoryTransport>, index: usize| {
+            serializer.deserialize_message(&manager.get_transport().get_send_buffer()[index]).unwrap()
+        };
+
+        manager.ping().unwrap();
+        clock.advance(Duration::from_millis(10));
+        manager.ping().unwrap();
+        let (first, second) = (sent(&manager, 0).header.id, sent(&manager, 1).header.id);
+
+        // Each pong is timed against its own ping, whatever the order.
+        clock.advance(Duration::from_millis(30));
+        manager.process_message(Message::pong_for(second, SchemaVersion::new(1))).unwrap();
+        assert_eq!(manager.get_stats().rtt, Some(Duration::from_millis(30)));
+
+        clock.advance(Duration::from_millis(5));
+        manager.process_message(Message::pong_for(first, SchemaVersion::new(1))).unwrap();
+        assert_eq!(manager.get_stats().rtt, Some(Duration::from_millis(45)));
+
+        manager.process_message(Message::pong_for(first, SchemaVersion::new(1))).unwrap();
+        assert_eq!(manager.get_stats().rtt, Some(Duration::from_millis(45)));
+
+        // Pings are answered with their own id.
+        let ping = Message::ping(SchemaVersion::new(1));
+        manager.process_message(ping.clone()).unwrap();
+        assert!(matches!(sent(&manager, 2).payload, MessagePayload::Pong { ping_id: Some(id) } if id == ping.header.id));
+    }
+
+    #[test]
+    fn test_tick_respects_sync_interval() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Full)
+            .with_sync_interval(Duration::from_millis(50));
+        let mut manager = SyncManager::try_with_clock(transport, config, Arc::new(clock.clone())).unwrap();
+
+        let snapshot = WorldSnapshot {
+            entities: vec![],
+            timestamp: 100.0,
+            version: "1.0.0".to_string(),
+        };
+
+        assert!(manager.tick(snapshot.clone()).unwrap());
+        assert!(!manager.tick(snapshot.clone()).unwrap());
+
+        clock.advance(Duration::from_millis(50));
+        assert!(manager.tick(snapshot.clone()).unwrap());
+        assert_eq!(manager.get_stats().sync_count, 2);
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let mut manual = SyncManager::try_new(transport, SyncConfig::new().with_mode(SyncMode::Manual)).unwrap();
+        assert!(!manual.tick(snapshot).unwrap());
+    }
+
+    #[test]
+    fn test_adaptive_sync_interval() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_sync_interval(Duration::from_millis(50))
+            .with_adaptive_interval(Duration::from_millis(20), Duration::from_millis(200));
+        let mut manager = SyncManager::try_with_clock(transport, config, Arc::new(clock.clone())).unwrap();
+        assert_eq!(manager.effective_sync_interval(), Duration::from_millis(50));
+
+        let mut pong_after = |millis| {
+            manager.ping().unwrap();
+            clock.advance(Duration::from_millis(millis));
+            manager.process_message(Message::pong(SchemaVersion::new(1))).unwrap();
+            manager.effective_sync_interval()
+        };
+        assert_eq!(pong_after(80), Duration::from_millis(80));
+        assert_eq!(pong_after(160), Duration::from_millis(90));
+        assert_eq!(pong_after(2), Duration::from_millis(79));
+
+        // A fast link bottoms out at `min`.
+        while pong_after(1) > Duration::from_millis(20) {}
+        assert!(manager.get_stats().smoothed_rtt.unwrap() <= Duration::from_millis(20));
+        assert_eq!(manager.effective_sync_interval(), Duration::from_millis(20));
+
+        manager.last_sync = Some(clock.now());
+        clock.advance(Duration::from_millis(20));
+        assert!(manager.should_sync());
+    }
+
+    #[test]
+    fn test_keepalive() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_keepalive(Duration::from_secs(1), Duration::from_millis(500));
+        let mut manager = SyncManager::try_with_clock(transport, config, Arc::new(clock.clone())).unwrap();
+        let pings = |manager: &SyncManager<MemoryTransport>| manager.get_transport().get_send_buffer().len();
+
+        manager.maintain().unwrap();
+        assert_eq!(pings(&manager), 0);
+
+        clock.advance(Duration::from_secs(1));
+        manager.maintain().unwrap();
+        assert_eq!(pings(&manager), 1);
+
+        // Answered in time: the next ping waits for another idle interval.
+        clock.advance(Duration::from_millis(400));
+        manager.maintain().unwrap();
+        manager.process_message(Message::pong(SchemaVersion::new(1))).unwrap();
+        clock.advance(Duration::from_millis(900));
+        manager.maintain().unwrap();
+        assert_eq!(pings(&manager), 1);
+        assert_eq!(manager.connection_state(), ConnectionState::Connected);
+
+        clock.advance(Duration::from_millis(100));
+        manager.maintain().unwrap();
+        assert_eq!(pings(&manager), 2);
+
+        clock.advance(Duration::from_millis(500));
+        manager.maintain().unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::Disconnected);
+        assert!(!manager.is_connected());
+
+        let events: Vec<_> = std::iter::from_fn(|| manager.pending_events.pop_front()).collect();
+        assert!(matches!(
+            &events[..],
+            [
+                SyncEvent::StateChanged { to: ConnectionState::Connected, .. },
+                SyncEvent::ConnectionTimeout,
+                SyncEvent::StateChanged { from: ConnectionState::Connected, to: ConnectionState::Disconnected },
+            ]
+        ));
+    }
+
+    #[test]
+    fn test_keepalive_survives_lost_pong() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_keepalive(Duration::from_secs(1), Duration::from_millis(500));
+        let mut manager = SyncManager::try_with_clock(transport, config, Arc::new(clock.clone())).unwrap();
+        let pings = |manager: &SyncManager<MemoryTransport>| manager.get_transport().get_send_buffer().len();
+
+        clock.advance(Duration::from_secs(1));
+        manager.maintain().unwrap();
+        assert_eq!(pings(&manager), 1);
+
+        // Other traffic arrives, but the pong is lost.
+        clock.advance(Duration::from_millis(200));
+        manager.process_message(Message::ack(1, SchemaVersion::new(1))).unwrap();
+        clock.advance(Duration::from_millis(300));
+        manager.maintain().unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::Connected);
+
+        // Keepalive is still armed: the next idle interval pings again...
+        clock.advance(Duration::from_millis(700));
+        manager.maintain().unwrap();
+        assert_eq!(pings(&manager), 2);
+
+        // ...and silence after that ping still times the connection out.
+        clock.advance(Duration::from_millis(500));
+        manager.maintain().unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::Disconnected);
+    }
+
+    #[test]
+    fn test_send_skips_unchanged_snapshots() {
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new().with_mode(SyncMode::Full).with_skip_unchanged(true);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+
+        let snapshot = |timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity { id: 1, components: vec![] }]);
+
+        manager.send(snapshot(1.0)).unwrap();
+        manager.send(snapshot(2.0)).unwrap();
+        manager.send(snapshot(3.0)).unwrap();
+
+        let stats = manager.get_stats();
+        assert_eq!(stats.sync_count, 1);
+        assert_eq!(stats.skipped_syncs, 2);
+
+        manager.reset_delta_compressor();
+        manager.send(snapshot(4.0)).unwrap();
+        assert_eq!(manager.get_stats().sync_count, 2);
+    }
+
+    #[test]
+    fn test_field_masks() {
+        use crate::protocol::{ComponentData, SerializedComponent};
+        use crate::serialization::BinarySerializer;
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new().with_mode(SyncMode::Delta).with_rate_limiting(false);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+        manager.set_field_mask("Enemy".to_string(), ["ai_target".to_string()]);
+        manager.set_field_mask("Loot".to_string(), ["table".to_string()]);
+
+        let snapshot = |timestamp: f64, target: u32| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![
+                SerializedComponent {
+                    id: "Enemy".to_string(),
+                    data: ComponentData::Structured([
+                        ("hp".to_string(), FieldValue::U32(10)),
+                        ("ai_target".to_string(), FieldValue::U32(target)),
+                    ].into_iter().collect()),
+                },
+                SerializedComponent {
+                    id: "Loot".to_string(),
+                    data: ComponentData::from_json_value(serde_json::json!({"gold": 5, "table": target})),
+                },
+            ],
+        }]);
+
+        manager.send_snapshot(snapshot(1.0, 7)).unwrap();
+        let sent = manager.get_transport().get_send_buffer()[0].clone();
+        let message = BinarySerializer::new(BinaryFormat::MessagePack).deserialize_message(&sent).unwrap();
+        let MessagePayload::Snapshot(payload) = message.payload else {
+            panic!("expected a snapshot");
+        };
+        let components = &payload.entities[0].components;
+        assert_eq!(
+            components[0].data,
+            ComponentData::Structured([("hp".to_string(), FieldValue::U32(10))].into_iter().collect())
+        );
+        assert_eq!(components[1].data.to_json_value(), Some(serde_json::json!({"gold": 5})));
+
+        // Only masked fields changed, so there is nothing to send.
+        manager.send_delta(snapshot(2.0, 8)).unwrap();
+        assert_eq!(manager.get_transport().get_send_buffer().len(), 1);
+
+        manager.set_field_mask("Enemy".to_string(), []);
+        manager.send_delta(snapshot(3.0, 8)).unwrap();
+        assert_eq!(manager.get_transport().get_send_buffer().len(), 2);
+    }
+
+    #[test]
+    fn test_request_snapshot_since() {
+        use crate::protocol::{ComponentData, SerializedComponent};
+        use crate::serialization::BinarySerializer;
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_rate_limiting(false)
+            .with_keyframe_history(2);
+        let mut server = SyncManager::try_new(transport, config).unwrap();
+
+        let snapshot = |timestamp: f64, x: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({"x": x})),
+            }],
+        }]);
+
+        let request = Message::request_snapshot_since(2000, SchemaVersion::new(1));
+        assert!(matches!(
+            server.process_message(request.clone()).unwrap(),
+            SyncEvent::SnapshotRequested { since: Some(2000), answered: false }
+        ));
+
+        server.send(snapshot(1.0, 0.0)).unwrap();
+        server.send(snapshot(2.0, 1.0)).unwrap();
+        server.send(snapshot(3.0, 2.0)).unwrap();
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let last_sent = |server: &SyncManager<MemoryTransport>| {
+            let sent = server.get_transport().get_send_buffer();
+            serializer.deserialize_message(sent.last().unwrap()).unwrap()
+        };
+
+        assert!(matches!(
+            server.process_message(request).unwrap(),
+            SyncEvent::SnapshotRequested { since: Some(2000), answered: true }
+        ));
+        match last_sent(&server).payload {
+            MessagePayload::Delta(payload) => {
+                assert_eq!(payload.base_timestamp, 2000);
+                assert_eq!(payload.changes.len(), 1);
+            }
+            other => panic!("expected delta, got {:?}", other),
+        }
+
+        // 1.0 has been evicted from the two-entry keyframe ring.
+        server.process_message(Message::request_snapshot_since(1000, SchemaVersion::new(1))).unwrap();
+        match last_sent(&server).payload {
+            MessagePayload::Snapshot(payload) => assert_eq!(payload.metadata.world_time, 3.0),
+            other => panic!("expected snapshot, got {:?}", other),
+        }
+
+        // Answers count towards the stats like any other send.
+        let sent = server.get_transport().get_send_buffer();
+        let stats = server.get_stats();
+        assert_eq!(stats.messages_sent, sent.len() as u64);
+        assert_eq!(stats.bytes_sent, sent.iter().map(|data| data.len() as u64).sum::<u64>());
+    }
+
+    #[test]
+    fn test_unknown_payload_is_skipped() {
+        use crate::serialization::BinarySerializer;
+        use serde::Serialize;
+
+        // A message as a newer peer would encode it, with a type and payload
+        // this build has never heard of.
+        #[derive(Serialize)]
+        #[allow(dead_code)]
+        enum FutureType { Snapshot, Delta, RequestSnapshot, Ack, Ping, Pong, SchemaSync, Error, Teleport }
+
+        #[derive(Serialize)]
+        #[serde(tag = "type", rename_all = "snake_case")]
+        enum FuturePayload { Teleport { entity_id: u32, x: f64 } }
+
+        #[derive(Serialize)]
+        struct FutureHeader { msg_type: FutureType, timestamp: u64, id: u64, sequence: u64, schema_version: u32 }
+
+        #[derive(Serialize)]
+        struct FutureMessage { header: FutureHeader, payload: FuturePayload }
+
+        let future = FutureMessage {
+            header: FutureHeader { msg_type: FutureType::Teleport, timestamp: 0, id: 1, sequence: 1, schema_version: 1 },
+            payload: FuturePayload::Teleport { entity_id: 1, x: 2.0 },
+        };
+
+        for format in [BinaryFormat::MessagePack, BinaryFormat::Json] {
+            let data = match format {
+                BinaryFormat::Json => serde_json::to_vec(&future).unwrap(),
+                _ => rmp_serde::to_vec(&future).unwrap(),
+            };
+
+            let message = BinarySerializer::new(format).deserialize_message(&data).unwrap();
+            assert_eq!(message.header.msg_type, MessageType::Unknown);
+            assert!(matches!(message.payload, MessagePayload::Unknown));
+
+            let transport = MemoryTransport::new(format);
+            let mut manager = SyncManager::try_new(transport, SyncConfig::new()).unwrap();
+            assert!(matches!(
+                manager.process_message(message).unwrap(),
+                SyncEvent::Unknown(MessageType::Unknown)
+            ));
+            assert_eq!(manager.get_stats().error_count, 0);
+        }
+    }
+
+    #[test]
+    fn test_ack_baselines() {
+        use crate::protocol::{SerializedComponent, ComponentData};
+        use crate::serialization::BinarySerializer;
+
+        let snapshot = |timestamp: f64| WorldSnapshot::for_test(timestamp, (0..10).map(|id| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::from_json_value(serde_json::json!({
+                    "x": if id == 0 { timestamp } else { 0.0 },
+                })),
+            }],
+        }).collect());
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_ack_baselines(true);
+        let mut server = SyncManager::try_new(transport, config).unwrap();
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let last_base = |server: &SyncManager<MemoryTransport>| {
+            let sent = server.get_transport().get_send_buffer();
+            match serializer.deserialize_message(sent.last().unwrap()).unwrap().payload {
+                MessagePayload::Delta(payload) => Some(payload.base_timestamp),
+                _ => None,
+            }
+        };
+
+        // Nothing acked yet: everything is resent.
+        server.send_delta(snapshot(1.0)).unwrap();
+        server.send_delta(snapshot(2.0)).unwrap();
+        assert_eq!(last_base(&server), None);
+
+        // A message ack that happens to look like a timestamp isn't a baseline ack.
+        server.process_message(Message::ack(2000, SchemaVersion::new(1))).unwrap();
+        server.send_delta(snapshot(2.5)).unwrap();
+        assert_eq!(last_base(&server), None);
+
+        server.process_message(Message::ack_baseline(2000, SchemaVersion::new(1))).unwrap();
+        server.send_delta(snapshot(3.0)).unwrap();
+        assert_eq!(last_base(&server), Some(2000));
+
+        // The delta for 3.0 was lost, so 4.0 is still diffed against 2.0.
+        server.send_delta(snapshot(4.0)).unwrap();
+        assert_eq!(last_base(&server), Some(2000));
+
+        // Stale and unknown acks don't move the baseline back.
+        server.process_message(Message::ack_baseline(4000, SchemaVersion::new(1))).unwrap();
+        server.process_message(Message::ack_baseline(1000, SchemaVersion::new(1))).unwrap();
+        server.process_message(Message::ack_baseline(9000, SchemaVersion::new(1))).unwrap();
+        server.send_delta(snapshot(5.0)).unwrap();
+        assert_eq!(last_base(&server), Some(4000));
+    }
+
+    #[test]
+    fn test_compact_field_deltas_roundtrip() {
+        use crate::protocol::{SerializedComponent, ComponentData, FieldValue};
+        use crate::schema::{ComponentSchema, FieldSchema};
+        use crate::serialization::BinarySerializer;
+
+        let schema = ComponentSchema::new("Transform".to_string(), SchemaVersion::new(1))
+            .with_field(FieldSchema::new("x".to_string(), FieldType::F32))
+            .with_field(FieldSchema::new("y".to_string(), FieldType::F32));
+
+        let snapshot = |x: f32, timestamp: f64| WorldSnapshot::for_test(timestamp, (0..10).map(|id| SerializedEntity {
+            id,
+            components: vec![SerializedComponent {
+                id: "Transform".to_string(),
+                data: ComponentData::Structured([
+                    ("x".to_string(), FieldValue::F32(if id == 0 { x } else { 0.0 })),
+                    ("y".to_string(), FieldValue::F32(0.0)),
+                ].into_iter().collect()),
+            }],
+        }).collect());
+
+        let config = SyncConfig::new().with_mode(SyncMode::Delta).with_compact_field_deltas(true);
+        let mut server = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        server.get_schema_registry().register(schema.clone()).unwrap();
+
+        server.send_delta(snapshot(0.0, 1.0)).unwrap();
+        server.send_delta(snapshot(1.0, 2.0)).unwrap();
+
+        let sent = server.get_transport().get_send_buffer().last().unwrap().clone();
+        let message = BinarySerializer::new(BinaryFormat::MessagePack).deserialize_message(&sent).unwrap();
+        match &message.payload {
+            MessagePayload::Delta(payload) => {
+                assert!(matches!(payload.changes[..], [DeltaChange::IndexedFieldsUpdated { .. }]));
+            }
+            other => panic!("expected delta, got {:?}", other),
+        }
+
+        let mut client = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), SyncConfig::new()).unwrap();
+        client.get_schema_registry().register(schema).unwrap();
+        match client.process_message(message).unwrap() {
+            SyncEvent::Delta(delta) => match &delta.changes[..] {
+                [DeltaChange::FieldsUpdated { fields, .. }] => assert_eq!(fields[0].field_id, "x"),
+                other => panic!("expected field update, got {:?}", other),
+            },
+            other => panic!("expected delta, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_component_compression_hints() {
+        use crate::protocol::{SerializedComponent, ComponentData, CompressionType, FieldValue};
+        use crate::schema::ComponentSchema;
+        use crate::serialization::BinarySerializer;
+
+        let mesh = ComponentData::Binary(vec![3u8; 4096].into());
+        let health = ComponentData::Structured([("hp".to_string(), FieldValue::U32(100))].into_iter().collect());
+        let snapshot = WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 1,
+                components: vec![
+                    SerializedComponent { id: "Mesh".to_string(), data: mesh.clone() },
+                    SerializedComponent { id: "Health".to_string(), data: health.clone() },
+                ],
+            }],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+
+        let config = SyncConfig::new().with_mode(SyncMode::Full).with_component_compression(true);
+        let mut server = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        server.get_schema_registry().register(
+            ComponentSchema::new("Mesh".to_string(), SchemaVersion::new(1)).with_compression_hint(CompressionType::Lz4)
+        ).unwrap();
+        server.send(snapshot).unwrap();
+
+        let sent = server.get_transport().get_send_buffer().last().unwrap().clone();
+        assert!(sent.len() < 1024);
+        let message = BinarySerializer::new(BinaryFormat::MessagePack).deserialize_message(&sent).unwrap();
+        match &message.payload {
+            MessagePayload::Snapshot(payload) => {
+                let components = &payload.entities[0].components;
+                assert!(matches!(components[0].data, ComponentData::Compressed { compression: CompressionType::Lz4, .. }));
+                assert_eq!(components[1].data, health);
+            }
+            other => panic!("expected snapshot, got {:?}", other),
+        }
+
+        // The receiver needs no hints to restore the data.
+        let mut client = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), SyncConfig::new()).unwrap();
+        match client.process_message(message).unwrap() {
+            SyncEvent::Snapshot(snapshot) => {
+                assert_eq!(snapshot.entities[0].components[0].data, mesh);
+                assert_eq!(snapshot.entities[0].components[1].data, health);
+            }
+            other => panic!("expected snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_component_compression_bounds_and_field_deltas() {
+        use crate::protocol::{SerializedComponent, ComponentData, CompressionType, FieldValue, MessageLimits};
+        use crate::schema::ComponentSchema;
+        use crate::serialization::BinarySerializer;
+
+        let snapshot = |hp: u32, timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Stats".to_string(),
+                data: ComponentData::Structured(
+                    (0..64).map(|i| (format!("stat_{}", i), FieldValue::U32(0)))
+                        .chain([("hp".to_string(), FieldValue::U32(hp))])
+                        .collect(),
+                ),
+            }],
+        }]);
+
+        let config = SyncConfig::new().with_mode(SyncMode::Delta).with_component_compression(true);
+        let mut server = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        server.get_schema_registry().register(
+            ComponentSchema::new("Stats".to_string(), SchemaVersion::new(1)).with_compression_hint(CompressionType::Deflate)
+        ).unwrap();
+
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        server.send_delta(snapshot(100, 1.0)).unwrap();
+        let first = serializer.deserialize_message(server.get_transport().get_send_buffer().last().unwrap()).unwrap();
+
+        // Field-level deltas carry only the changed field and are never compressed.
+        server.send_delta(snapshot(90, 2.0)).unwrap();
+        let second = serializer.deserialize_message(server.get_transport().get_send_buffer().last().unwrap()).unwrap();
+        match &second.payload {
+            MessagePayload::Delta(payload) => {
+                assert!(matches!(&payload.changes[..], [DeltaChange::FieldsUpdated { fields, .. }] if fields.len() == 1));
+            }
+            other => panic!("expected delta, got {:?}", other),
+        }
+
+        // The receiver refuses to inflate components past its limit.
+        let limits = MessageLimits::new().with_max_decompressed_bytes(64);
+        let mut client = SyncManager::try_new(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            SyncConfig::new().with_limits(limits),
+        ).unwrap();
+        assert!(client.process_message(first.clone()).is_err());
+
+        let mut client = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), SyncConfig::new()).unwrap();
+        assert!(matches!(client.process_message(first).unwrap(), SyncEvent::Delta(_) | SyncEvent::Snapshot(_)));
+    }
+
+    #[test]
+    fn test_precision_reduction() {
+        use crate::protocol::{SerializedComponent, ComponentData, FieldType, FieldValue};
+        use crate::schema::{ComponentSchema, FieldSchema};
+        use crate::serialization::BinarySerializer;
+
+        let data = |x: FieldValue| ComponentData::Structured([("x".to_string(), x)].into_iter().collect());
+        let snapshot = WorldSnapshot {
+            entities: vec![SerializedEntity {
+                id: 1,
+                components: vec![
+                    SerializedComponent { id: "Position".to_string(), data: data(FieldValue::F64(0.1)) },
+                    SerializedComponent { id: "Mass".to_string(), data: data(FieldValue::F64(0.1)) },
+                ],
+            }],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        let register = |registry: &SchemaRegistry| {
+            let field = || FieldSchema::new("x".to_string(), FieldType::F64);
+            registry.register(
+                ComponentSchema::new("Position".to_string(), SchemaVersion::new(1)).with_field(field()).with_low_precision()
+            ).unwrap();
+            registry.register(ComponentSchema::new("Mass".to_string(), SchemaVersion::new(1)).with_field(field())).unwrap();
+        };
+
+        let config = SyncConfig::new().with_mode(SyncMode::Full).with_precision_reduction(true);
+        let mut server = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        register(server.get_schema_registry());
+        server.send(snapshot).unwrap();
+
+        let sent = server.get_transport().get_send_buffer().last().unwrap().clone();
+        let message = BinarySerializer::new(BinaryFormat::MessagePack).deserialize_message(&sent).unwrap();
+        let mut client = SyncManager::try_new(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            SyncConfig::new().with_validate_incoming(true),
+        ).unwrap();
+        register(client.get_schema_registry());
+        match client.process_message(message).unwrap() {
+            SyncEvent::Snapshot(snapshot) => {
+                let components = &snapshot.entities[0].components;
+                assert_eq!(components[0].data, data(FieldValue::F32(0.1)));
+                assert_eq!(components[1].data, data(FieldValue::F64(0.1)));
+            }
+            other => panic!("expected snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_precision_reduction_before_diffing() {
+        use crate::protocol::{SerializedComponent, ComponentData, ComponentSchemaInfo, FieldType, FieldValue};
+        use crate::schema::{ComponentSchema, FieldSchema};
+
+        let snapshot = |x: f64, timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity {
+            id: 1,
+            components: vec![SerializedComponent {
+                id: "Position".to_string(),
+                data: ComponentData::Structured([("x".to_string(), FieldValue::F64(x))].into_iter().collect()),
+            }],
+        }]);
+        let schema = ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
+            .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
+            .with_low_precision();
+        assert!(ComponentSchemaInfo::from(&schema).low_precision);
+
+        let config = SyncConfig::new().with_mode(SyncMode::Delta).with_precision_reduction(true);
+        let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        manager.get_schema_registry().register(schema).unwrap();
+
+        manager.send_delta(snapshot(0.1, 1.0)).unwrap();
+        let sent = manager.get_stats().messages_sent;
+
+        // A change lost to `f32` precision sends nothing.
+        manager.send_delta(snapshot(0.1 + 1e-12, 2.0)).unwrap();
+        assert_eq!(manager.get_stats().messages_sent, sent);
+
+        // Out of range values saturate instead of becoming infinite.
+        manager.send_delta(snapshot(1e300, 3.0)).unwrap();
+        let x = |snapshot: &WorldSnapshot| match &snapshot.entities[0].components[0].data {
+            ComponentData::Structured(fields) => fields["x"].clone(),
+            other => panic!("expected structured data, got {:?}", other),
+        };
+        let baseline = manager.delta_compressor.get_previous_snapshot().unwrap();
+        assert_eq!(x(baseline), FieldValue::F32(f32::MAX));
+    }
+
+    #[test]
+    fn test_schema_version_mismatch() {
+        let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), SyncConfig::new()).unwrap();
+        assert_eq!(manager.peer_schema_version(), None);
+
+        manager.process_message(Message::pong(SchemaVersion::INITIAL)).unwrap();
+        manager.process_message(Message::pong(SchemaVersion::new(2))).unwrap();
+        manager.process_message(Message::pong(SchemaVersion::new(2))).unwrap();
+        assert_eq!(manager.peer_schema_version(), Some(SchemaVersion::new(2)));
+
+        manager.set_schema_version(SchemaVersion::new(2));
+        manager.set_schema_version(SchemaVersion::new(3));
+        let mismatches: Vec<_> = std::iter::from_fn(|| manager.pending_events.pop_front())
+            .filter_map(|event| match event {
+                SyncEvent::SchemaVersionMismatch { ours, theirs } => Some((ours.get(), theirs.get())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(mismatches, vec![(1, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn test_duplicate_suppression() {
+        let config = SyncConfig::new().with_duplicate_suppression(2);
+        let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        let [first, second, third] = [1u64, 2, 3].map(|sequence| {
+            let mut message = Message::pong(SchemaVersion::INITIAL);
+            message.header = MessageHeader::with_timestamp(MessageType::Pong, SchemaVersion::INITIAL, 1000, sequence);
+            message
+        });
+
+        assert!(matches!(manager.process_message(first.clone()).unwrap(), SyncEvent::Pong));
+        assert!(matches!(
+            manager.process_message(first.clone()).unwrap(),
+            SyncEvent::Duplicate { id } if id == first.header.id
+        ));
+        manager.process_message(second).unwrap();
+        manager.process_message(third).unwrap();
+        // Evicted from the window of two, so it goes through again.
+        assert!(matches!(manager.process_message(first).unwrap(), SyncEvent::Pong));
+        assert_eq!(manager.get_stats().duplicates_dropped, 1);
+
+        assert!(SyncConfig::builder().with_duplicate_suppression(0).build().is_err());
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let mut manager = SyncManager::try_new(transport, SyncConfig::new()).unwrap();
+
+        let known = Message::error(ErrorCode::SchemaMismatch, "bad schema".to_string(), SchemaVersion::new(1));
+        assert!(matches!(
+            manager.process_message(known).unwrap(),
+            SyncEvent::Error { code: Some(ErrorCode::SchemaMismatch), raw_code: 1, .. }
+        ));
+
+        let mut unknown = Message::error(ErrorCode::Internal, "from the future".to_string(), SchemaVersion::new(1));
+        unknown.payload = MessagePayload::Error { code: 9000, message: "from the future".to_string() };
+        assert!(matches!(
+            manager.process_message(unknown).unwrap(),
+            SyncEvent::Error { code: None, raw_code: 9000, .. }
+        ));
+
+        assert_eq!(u32::from(ErrorCode::RateLimited), 2);
+        assert_eq!(ErrorCode::try_from(4), Ok(ErrorCode::Unauthorized));
+        assert_eq!(ErrorCode::try_from(42), Err(42));
+        assert_eq!(manager.get_stats().error_count, 2);
+    }
+
+    #[test]
+    fn test_numeric_coercion_before_validation() {
+        use crate::protocol::{ComponentData, FieldType, SerializedComponent};
+        use crate::schema::{ComponentSchema, FieldSchema};
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new()
+            .with_numeric_coercion(true)
+            .with_validate_incoming(true);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+        manager.get_schema_registry().register(
+            ComponentSchema::new("Health".to_string(), SchemaVersion::new(1))
+                .with_field(FieldSchema::new("current".to_string(), FieldType::U32))
+        ).unwrap();
+
+        let health = |value: FieldValue| Message::snapshot(
+            vec![SerializedEntity {
+                id: 1,
+                components: vec![SerializedComponent {
+                    id: "Health".to_string(),
+                    data: ComponentData::Structured([("current".to_string(), value)].into_iter().collect()),
+                }],
+            }],
+            1.0,
+            SchemaVersion::new(1),
+        );
+
+        let SyncEvent::Snapshot(snapshot) = manager.process_message(health(FieldValue::I64(5))).unwrap() else {
+            panic!("expected a snapshot");
+        };
+        assert_eq!(
+            snapshot.entities[0].components[0].data,
+            ComponentData::Structured([("current".to_string(), FieldValue::U32(5))].into_iter().collect())
+        );
+
+        assert!(manager.process_message(health(FieldValue::I64(-5))).is_err());
+        assert_eq!(manager.get_stats().error_count, 1);
+    }
+
+    #[test]
+    fn test_validate_incoming() {
+        use crate::protocol::{ComponentData, FieldType, SerializedComponent};
+        use crate::schema::{ComponentSchema, FieldSchema};
+
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new().with_validate_incoming(true);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+        manager.get_schema_registry().register(
+            ComponentSchema::new("Position".to_string(), SchemaVersion::new(1))
+                .with_field(FieldSchema::new("x".to_string(), FieldType::F64))
+        ).unwrap();
+
+        let position = |x: FieldValue| SerializedComponent {
+            id: "Position".to_string(),
+            data: ComponentData::Structured([("x".to_string(), x)].into_iter().collect()),
+        };
+
+        let valid = Message::snapshot(
+            vec![SerializedEntity { id: 1, components: vec![position(FieldValue::F64(1.0))] }],
+            1.0,
+            SchemaVersion::new(1),
+        );
+        assert!(matches!(manager.process_message(valid).unwrap(), SyncEvent::Snapshot(_)));
+
+        let invalid = Message::delta(
+            vec![DeltaChange::ComponentUpdated {
+                entity_id: 1,
+                component_id: "Position".to_string(),
+                data: position(FieldValue::String("east".to_string())).data,
+            }],
+            1000,
+            SchemaVersion::new(1),
+        );
+        assert!(matches!(manager.process_message(invalid), Err(LinkError::InvalidMessage(_))));
+        assert_eq!(manager.get_stats().error_count, 1);
+
+        // Fields the schema doesn't know only fail strict validation.
+        let mut extra = position(FieldValue::F64(1.0));
+        if let ComponentData::Structured(fields) = &mut extra.data {
+            fields.insert("y".to_string(), FieldValue::F64(2.0));
+        }
+        let extra = Message::snapshot(vec![SerializedEntity { id: 1, components: vec![extra] }], 2.0, SchemaVersion::new(1));
+        assert!(manager.process_message(extra.clone()).is_ok());
+
+        let config = SyncConfig::new().with_validate_incoming(true).with_strict_validation(true);
+        let mut strict = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config).unwrap();
+        strict.get_schema_registry().register(manager.get_schema_registry().get("Position").unwrap()).unwrap();
+        assert!(matches!(strict.process_message(extra), Err(LinkError::InvalidMessage(_))));
+    }
+
+    #[test]
+    fn test_message_limits() {
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let limits = MessageLimits::new()
+            .with_max_entities(2)
+            .with_max_changes_per_delta(3);
+        let mut manager = SyncManager::try_new(transport, SyncConfig::new().with_limits(limits)).unwrap();
+
+        let entities = |count: u32| (0..count)
+            .map(|id| SerializedEntity { id, components: vec![] })
+            .collect::<Vec<_>>();
+        assert!(manager.process_message(Message::snapshot(entities(2), 1.0, SchemaVersion::new(1))).is_ok());
+        assert!(matches!(
+            manager.process_message(Message::snapshot(entities(3), 1.0, SchemaVersion::new(1))),
+            Err(LinkError::InvalidMessage(_))
+        ));
+
+        let removals = |count: u32| (0..count)
+            .map(|entity_id| DeltaChange::EntityRemoved { entity_id })
+            .collect::<Vec<_>>();
+        assert!(manager.process_message(Message::delta(removals(3), 1000, SchemaVersion::new(1))).is_ok());
+        assert!(manager.process_message(Message::delta(removals(4), 1000, SchemaVersion::new(1))).is_err());
+        assert_eq!(manager.get_stats().error_count, 2);
+    }
+
+    #[test]
+    fn test_send_batch() {
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new().with_mode(SyncMode::Delta).with_rate_limiting(false);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+
+        let snapshot = |timestamp: f64, ids: &[u32]| WorldSnapshot::for_test(timestamp, ids.iter().map(|&id| SerializedEntity { id, components: vec![] }).collect());
+
+        manager.send_batch(vec![
+            snapshot(1.0, &[1]),
+            snapshot(2.0, &[1]),
+            snapshot(3.0, &[1, 2]),
+        ]).unwrap();
+
+        assert_eq!(manager.get_stats().sync_count, 2);
+        assert_eq!(manager.get_transport().get_send_buffer().len(), 2);
+    }
+
+    #[test]
+    fn test_schema_sync_does_not_reopen_connection() {
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let mut manager = SyncManager::try_new(transport, SyncConfig::new()).unwrap();
+        manager.close().unwrap();
+
+        let event = manager.process_message(Message::schema_sync(vec![], SchemaVersion::new(1))).unwrap();
+        assert!(matches!(event, SyncEvent::SchemaSync(_)));
+        assert_eq!(manager.connection_state(), ConnectionState::Closed);
+        assert!(manager.get_transport().get_send_buffer().is_empty());
+    }
+
+    #[test]
+    fn test_connection_state_transitions() {
+        let transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        let config = SyncConfig::new().with_auto_reconnect(true, 1);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::Connecting);
+
+        manager.negotiate_schemas().unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::SchemaNegotiating);
+
+        manager.process_message(Message::ping(SchemaVersion::new(1))).unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::SchemaNegotiating);
+
+        manager.process_message(Message::schema_sync(vec![], SchemaVersion::new(1))).unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::Connected);
+
+        manager.close().unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::Closed);
+
+        let events: Vec<_> = std::iter::from_fn(|| manager.pending_events.pop_front())
+            .map(|event| match event {
+                SyncEvent::StateChanged { to, .. } => to,
+                other => panic!("unexpected event {:?}", other),
+            })
+            .collect();
+        assert_eq!(events, vec![
+            ConnectionState::SchemaNegotiating,
+            ConnectionState::Connected,
+            ConnectionState::Closed,
+        ]);
+
+        let mut transport = MemoryTransport::new(BinaryFormat::MessagePack);
+        transport.close().unwrap();
+        let mut manager = SyncManager::try_new(transport, SyncConfig::new().with_auto_reconnect(true, 1)).unwrap();
+        assert_eq!(manager.connection_state(), ConnectionState::Disconnected);
+
+        let snapshot = WorldSnapshot {
+            entities: vec![],
+            timestamp: 1.0,
+            version: "1.0.0".to_string(),
+        };
+        assert!(manager.send_snapshot(snapshot.clone()).is_err());
+        assert_eq!(manager.connection_state(), ConnectionState::Reconnecting);
+        assert!(manager.send_snapshot(snapshot).is_err());
+        assert_eq!(manager.connection_state(), ConnectionState::Disconnected);
+        assert!(matches!(
+            manager.receive().unwrap(),
+            Some(SyncEvent::StateChanged { from: ConnectionState::Disconnected, to: ConnectionState::Reconnecting })
+        ));
+    }
+
+    #[test]
+    fn test_compression_negotiation() {
+        use crate::serialization::BinarySerializer;
+
+        let take_sent = |manager: &SyncManager<MemoryTransport>, compression: CompressionType| {
+            let serializer = BinarySerializer::messagepack().with_compression(compression);
+            let sent = manager.get_transport().get_send_buffer();
+            serializer.deserialize_message(sent.last().unwrap())
+        };
+
+        let config = SyncConfig::new().with_rate_limiting(false);
+        let mut server = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), config.clone()).unwrap();
+        let mut client = SyncManager::try_new(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            config.with_supported_compression(vec![CompressionType::Deflate, CompressionType::Lz4]),
+        ).unwrap();
+
+        server.negotiate_schemas().unwrap();
+        let offer = take_sent(&server, CompressionType::None).unwrap();
+        client.process_message(offer).unwrap();
+        assert_eq!(client.compression(), CompressionType::Lz4);
+
+        let reply = take_sent(&client, CompressionType::None).unwrap();
+        assert!(matches!(&reply.payload, MessagePayload::SchemaSync(p) if p.is_reply));
+        server.process_message(reply).unwrap();
+        assert_eq!(server.compression(), CompressionType::Lz4);
+        assert_eq!(server.connection_state(), ConnectionState::Connected);
+
+        client.ping().unwrap();
+        assert!(take_sent(&client, CompressionType::None).is_err());
+        assert!(take_sent(&client, CompressionType::Lz4).is_ok());
+
+        let mut plain = SyncManager::try_new(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            SyncConfig::new().with_supported_compression(vec![CompressionType::None]),
+        ).unwrap();
+        plain.negotiate_schemas().unwrap();
+        let offer = take_sent(&plain, CompressionType::None).unwrap();
+        server.process_message(offer).unwrap();
+        assert_eq!(server.compression(), CompressionType::None);
+    }
+
+    #[test]
+    fn test_protocol_version_mismatch() {
+        let mut manager = SyncManager::try_new(MemoryTransport::new(BinaryFormat::MessagePack), SyncConfig::new()).unwrap();
+        manager.negotiate_schemas().unwrap();
+
+        let mut offer = Message::schema_sync(vec![], SchemaVersion::new(1));
+        if let MessagePayload::SchemaSync(payload) = &mut offer.payload {
+            payload.protocol_version = PROTOCOL_VERSION + 1;
+        }
+
+        assert!(matches!(
+            manager.process_message(offer),
+            Err(LinkError::ProtocolMismatch { local: PROTOCOL_VERSION, remote }) if remote == PROTOCOL_VERSION + 1
+        ));
+        assert_eq!(manager.connection_state(), ConnectionState::Disconnected);
+        assert!(!manager.is_connected());
+        assert_eq!(manager.get_stats().error_count, 1);
     }
 
     #[test]
@@ -410,7 +3596,7 @@ mod tests {
             .with_rate_limiting(true)
             .with_rate_limit_config(rate_config);
 
-        let mut manager = SyncManager::new(transport, config);
+        let mut manager = SyncManager::try_new(transport, config).unwrap();
 
         let snapshot = WorldSnapshot {
             entities: vec![],
@@ -422,4 +3608,106 @@ mod tests {
         assert!(manager.send_snapshot(snapshot.clone()).is_ok());
         assert!(manager.send_snapshot(snapshot).is_err());
     }
+
+    #[test]
+    fn test_rate_limit_policies() {
+        use crate::clock::MockClock;
+
+        let clock = MockClock::new();
+        let rate_config = RateLimitConfig::new()
+            .with_max_messages(1)
+            .with_policy(RateLimitPolicy::Queue(2));
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Full)
+            .with_rate_limit_config(rate_config);
+        let mut manager = SyncManager::try_with_clock(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            config,
+            Arc::new(clock.clone()),
+        ).unwrap();
+
+        let snapshot = |timestamp: f64| WorldSnapshot::for_test(timestamp, vec![SerializedEntity { id: timestamp as u32, components: vec![] }]);
+
+        manager.send_snapshot(snapshot(1.0)).unwrap();
+        manager.send_snapshot(snapshot(2.0)).unwrap();
+        manager.send_snapshot(snapshot(3.0)).unwrap();
+        assert!(matches!(manager.send_snapshot(snapshot(4.0)), Err(LinkError::RateLimitExceeded(_))));
+        assert_eq!(manager.get_transport().get_send_buffer().len(), 1);
+        assert_eq!(manager.get_stats().rate_limited_pending, 2);
+
+        // Drains one per window, in order.
+        clock.advance(Duration::from_millis(1100));
+        assert_eq!(manager.flush_rate_limited().unwrap(), 1);
+        clock.advance(Duration::from_millis(1100));
+        assert_eq!(manager.flush_rate_limited().unwrap(), 1);
+        let mut peer = MemoryTransport::new(BinaryFormat::MessagePack);
+        manager.transport.connect_to(&mut peer);
+        let times: Vec<_> = std::iter::from_fn(|| peer.receive().unwrap())
+            .map(|m| match m.payload {
+                MessagePayload::Snapshot(p) => p.metadata.world_time,
+                other => panic!("expected a snapshot, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(times, vec![1.0, 2.0, 3.0]);
+
+        // DropOldest keeps only the latest state; a superseded delta becomes a snapshot.
+        let rate_config = RateLimitConfig::new()
+            .with_max_messages(1)
+            .with_policy(RateLimitPolicy::DropOldest);
+        let config = SyncConfig::new()
+            .with_mode(SyncMode::Delta)
+            .with_full_snapshot_threshold(f64::INFINITY)
+            .with_rate_limit_config(rate_config);
+        let mut manager = SyncManager::try_with_clock(
+            MemoryTransport::new(BinaryFormat::MessagePack),
+            config,
+            Arc::new(clock.clone()),
+        ).unwrap();
+
+        manager.send_delta(snapshot(1.0)).unwrap();
+        manager.send_delta(snapshot(2.0)).unwrap();
+        manager.send_delta(snapshot(3.0)).unwrap();
+        let stats = manager.get_stats();
+        assert_eq!((stats.rate_limited_pending, stats.rate_limited_dropped), (1, 1));
+
+        clock.advance(Duration::from_millis(1100));
+        manager.flush_rate_limited().unwrap();
+        let mut peer = MemoryTransport::new(BinaryFormat::MessagePack);
+        manager.transport.connect_to(&mut peer);
+        peer.receive().unwrap();
+        match peer.receive().unwrap().unwrap().payload {
+            MessagePayload::Snapshot(p) => assert_eq!(p.entities[0].id, 3),
+            other => panic!("expected a snapshot, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_multiplexed_snapshots_and_deltas_take_their_channels() {
+        use crate::multiplex::{MultiplexTransport, FAST_CHANNEL, RELIABLE_CHANNEL};
+
+        let snapshot = |timestamp: f64, entities: u32| WorldSnapshot::for_test(timestamp, (0..entities).map(|id| SerializedEntity { id, components: vec![] }).collect());
+
+        let transport = MultiplexTransport::new(MemoryTransport::new(BinaryFormat::MessagePack));
+        let config = SyncConfig::new().with_mode(SyncMode::Delta).with_rate_limiting(false);
+        let mut server = SyncManager::try_new(transport, config).unwrap();
+        server.send_snapshot(snapshot(1.0, 20)).unwrap();
+        server.send_delta(snapshot(2.0, 21)).unwrap();
+
+        let mut inner = MemoryTransport::new(BinaryFormat::MessagePack);
+        server.transport.get_inner_mut().connect_to(&mut inner);
+        let serializer = BinarySerializer::new(BinaryFormat::MessagePack);
+        let routed: Vec<_> = inner.get_receive_buffer().iter()
+            .map(|data| match serializer.deserialize_message(data).unwrap().payload {
+                MessagePayload::Channel { channel, payload } => (channel, payload.message_type()),
+                other => panic!("expected a channel message, got {:?}", other),
+            })
+            .collect();
+        assert_eq!(routed, vec![(RELIABLE_CHANNEL, MessageType::Snapshot), (FAST_CHANNEL, MessageType::Delta)]);
+
+        let mut client = SyncManager::try_new(MultiplexTransport::new(inner), SyncConfig::new()).unwrap();
+        let events: Vec<_> = std::iter::from_fn(|| client.receive().unwrap())
+            .filter(|event| !matches!(event, SyncEvent::StateChanged { .. }))
+            .collect();
+        assert!(matches!(events[..], [SyncEvent::Snapshot(_), SyncEvent::Delta(_)]));
+    }
 }
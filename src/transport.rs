@@ -1,7 +1,12 @@
+use crate::debug;
 use crate::error::{LinkError, Result};
-use crate::protocol::Message;
-use crate::serialization::{BinarySerializer, BinaryFormat};
-use bytes::Bytes;
+use crate::protocol::{CompressionType, Message};
+use crate::serialization::{BinarySerializer, BinaryFormat, FrameCodec, StreamingDeserializer};
+use bytes::{BufMut, Bytes, BytesMut};
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
 
 #[cfg(feature = "async")]
 use async_trait::async_trait;
@@ -11,6 +16,78 @@ pub trait Transport {
     fn receive(&mut self) -> Result<Option<Message>>;
     fn close(&mut self) -> Result<()>;
     fn is_connected(&self) -> bool;
+
+    /// Send several messages at once. Stream transports override this to frame
+    /// them into a single write; the default sends them one by one.
+    fn send_batch(&mut self, messages: &[Message]) -> Result<()> {
+        for message in messages {
+            self.send(message)?;
+        }
+        Ok(())
+    }
+
+    /// Push any buffered frames out to the peer. Unbuffered transports have
+    /// nothing to do.
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether `set_compression` accepts `compression`.
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        compression == CompressionType::None
+    }
+
+    /// Compress messages sent and expect compressed messages received from now on.
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        if compression == CompressionType::None {
+            Ok(())
+        } else {
+            Err(LinkError::Transport(format!("{:?} compression is not supported by this transport", compression)))
+        }
+    }
+
+    /// Wait at most `timeout` for a message, failing with `LinkError::Timeout`.
+    ///
+    /// The default implementation just calls `receive`, which is correct for
+    /// transports whose `receive` never blocks (such as `MemoryTransport`).
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        let _ = timeout;
+        self.receive()
+    }
+
+    /// The serializer `send` encodes with, for callers that encode one message
+    /// for several transports and pass the bytes to `send_encoded`. `None` for
+    /// transports that don't put that serializer's output on the wire as is.
+    fn serializer(&self) -> Option<&BinarySerializer> {
+        None
+    }
+
+    /// Send `message`, already encoded as `data` by a serializer with the
+    /// same encoding as `self.serializer()`. The default ignores `data`.
+    fn send_encoded(&mut self, message: &Message, data: &Bytes) -> Result<()> {
+        let _ = data;
+        self.send(message)
+    }
+
+    /// [`Self::send_batch`] for messages already encoded as `data`, one entry
+    /// per message, as for `send_encoded`. The default sends them one by one.
+    fn send_batch_encoded(&mut self, messages: &[Message], data: &[Bytes]) -> Result<()> {
+        for (message, data) in messages.iter().zip(data) {
+            self.send_encoded(message, data)?;
+        }
+        Ok(())
+    }
+
+    /// Address of the remote end, for logging and diagnostics. `None` for
+    /// transports without a socket, and once a socket transport is closed.
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// Address of this end of the connection; see [`Self::peer_addr`].
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
 }
 
 #[cfg(feature = "async")]
@@ -20,6 +97,22 @@ pub trait AsyncTransport: Send + Sync {
     async fn receive(&mut self) -> Result<Option<Message>>;
     async fn close(&mut self) -> Result<()>;
     fn is_connected(&self) -> bool;
+
+    /// Push any buffered frames out to the peer. Unbuffered transports have
+    /// nothing to do.
+    async fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// See [`Transport::peer_addr`].
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        None
+    }
+
+    /// See [`Transport::local_addr`].
+    fn local_addr(&self) -> Option<SocketAddr> {
+        None
+    }
 }
 
 pub struct MemoryTransport {
@@ -27,18 +120,39 @@ pub struct MemoryTransport {
     send_buffer: Vec<Bytes>,
     receive_buffer: Vec<Bytes>,
     connected: bool,
+    max_pending: Option<usize>,
 }
 
 impl MemoryTransport {
     pub fn new(format: BinaryFormat) -> Self {
+        Self::with_serializer(BinarySerializer::new(format))
+    }
+
+    /// Create a transport that encodes with `serializer`, e.g. one built with
+    /// [`BinarySerializer::with_codec`].
+    pub fn with_serializer(serializer: BinarySerializer) -> Self {
         Self {
-            serializer: BinarySerializer::new(format),
+            serializer,
             send_buffer: Vec::new(),
             receive_buffer: Vec::new(),
             connected: true,
+            max_pending: None,
+        }
+    }
+
+    /// Create a transport whose `send` fails with `LinkError::Backpressure` once
+    /// `max_messages` messages are waiting in the send buffer.
+    pub fn with_capacity(format: BinaryFormat, max_messages: usize) -> Self {
+        Self {
+            max_pending: Some(max_messages),
+            ..Self::new(format)
         }
     }
 
+    pub fn pending_count(&self) -> usize {
+        self.send_buffer.len()
+    }
+
     pub fn create_pair(format: BinaryFormat) -> (Self, Self) {
         let t1 = Self::new(format);
         let t2 = Self::new(format);
@@ -65,11 +179,36 @@ impl Transport for MemoryTransport {
             return Err(LinkError::ConnectionClosed);
         }
 
+        if let Some(max) = self.max_pending {
+            if self.send_buffer.len() >= max {
+                return Err(LinkError::Backpressure(self.send_buffer.len()));
+            }
+        }
+
         let data = self.serializer.serialize_message(message)?;
         self.send_buffer.push(data);
         Ok(())
     }
 
+    fn serializer(&self) -> Option<&BinarySerializer> {
+        Some(&self.serializer)
+    }
+
+    fn send_encoded(&mut self, _message: &Message, data: &Bytes) -> Result<()> {
+        if !self.connected {
+            return Err(LinkError::ConnectionClosed);
+        }
+
+        if let Some(max) = self.max_pending {
+            if self.send_buffer.len() >= max {
+                return Err(LinkError::Backpressure(self.send_buffer.len()));
+            }
+        }
+
+        self.send_buffer.push(data.clone());
+        Ok(())
+    }
+
     fn receive(&mut self) -> Result<Option<Message>> {
         if !self.connected {
             return Err(LinkError::ConnectionClosed);
@@ -80,10 +219,19 @@ impl Transport for MemoryTransport {
         }
 
         let data = self.receive_buffer.remove(0);
-        let message = self.serializer.deserialize_message(&data)?;
+        let message = self.serializer.deserialize_message_bytes(&data)?;
         Ok(Some(message))
     }
 
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        crate::compression::is_supported(compression)
+    }
+
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.serializer.set_compression(compression);
+        Ok(())
+    }
+
     fn close(&mut self) -> Result<()> {
         self.connected = false;
         self.send_buffer.clear();
@@ -96,16 +244,79 @@ impl Transport for MemoryTransport {
     }
 }
 
+/// Append `message` to `buffer` as one frame, encoding it in place so a
+/// reused buffer needs no allocation per message.
+fn encode_frame(serializer: &BinarySerializer, message: &Message, buffer: &mut BytesMut) -> Result<()> {
+    let start = buffer.len();
+    buffer.put_slice(&[0; FrameCodec::PREFIX_LEN]);
+
+    let body = start + FrameCodec::PREFIX_LEN;
+    let prefix = serializer.serialize_message_into(message, buffer)
+        .and_then(|()| FrameCodec::encode_prefix(buffer.len() - body));
+    match prefix {
+        Ok(prefix) => {
+            buffer[start..body].copy_from_slice(&prefix);
+            Ok(())
+        }
+        Err(e) => {
+            buffer.truncate(start);
+            Err(e)
+        }
+    }
+}
+
+/// Frames stdin's reader thread has read, ending with `Ok(None)` at EOF or the
+/// error that stopped it.
+type IncomingFrames = Receiver<Result<Option<Vec<u8>>>>;
+
+/// Stdin can't be read with a timeout, so frames are read on a background
+/// thread, started by the first receive, and handed over through a channel.
+/// A receive that times out leaves the thread mid-frame rather than the
+/// stream, so nothing is lost.
 pub struct StdioTransport {
     serializer: BinarySerializer,
+    buffer: BytesMut,
     connected: bool,
+    incoming: Option<IncomingFrames>,
+}
+
+/// Read frames of at most `max_len` bytes from `reader` on a new thread until
+/// it hits EOF or an error.
+fn spawn_frame_reader<R: Read + Send + 'static>(mut reader: R, max_len: usize) -> IncomingFrames {
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || loop {
+        let frame = FrameCodec::read_from_bounded(&mut reader, max_len);
+        let done = !matches!(frame, Ok(Some(_)));
+        if sender.send(frame).is_err() || done {
+            break;
+        }
+    });
+    receiver
 }
 
 impl StdioTransport {
     pub fn new(format: BinaryFormat) -> Self {
+        Self::with_serializer(BinarySerializer::new(format))
+    }
+
+    pub fn with_serializer(serializer: BinarySerializer) -> Self {
         Self {
-            serializer: BinarySerializer::new(format),
+            serializer,
+            buffer: BytesMut::new(),
             connected: true,
+            incoming: None,
+        }
+    }
+
+    fn incoming(&mut self) -> &IncomingFrames {
+        let max_len = self.serializer.limits().max_message_bytes;
+        self.incoming.get_or_insert_with(|| spawn_frame_reader(std::io::stdin(), max_len))
+    }
+
+    fn decode(&self, frame: Result<Option<Vec<u8>>>) -> Result<Option<Message>> {
+        match frame? {
+            Some(buffer) => self.serializer.deserialize_message(&buffer).map(Some),
+            None => Ok(None),
         }
     }
 }
@@ -116,14 +327,61 @@ impl Transport for StdioTransport {
             return Err(LinkError::ConnectionClosed);
         }
 
-        use std::io::Write;
+        self.buffer.clear();
+        encode_frame(&self.serializer, message, &mut self.buffer)?;
 
-        let data = self.serializer.serialize_message(message)?;
-        let len = data.len() as u32;
+        let mut stdout = std::io::stdout();
+        stdout.write_all(&self.buffer)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn serializer(&self) -> Option<&BinarySerializer> {
+        Some(&self.serializer)
+    }
+
+    fn send_encoded(&mut self, _message: &Message, data: &Bytes) -> Result<()> {
+        if !self.connected {
+            return Err(LinkError::ConnectionClosed);
+        }
+
+        let mut stdout = std::io::stdout();
+        FrameCodec::write_to(&mut stdout, data)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn send_batch(&mut self, messages: &[Message]) -> Result<()> {
+        if !self.connected {
+            return Err(LinkError::ConnectionClosed);
+        }
+
+        self.buffer.clear();
+        for message in messages {
+            encode_frame(&self.serializer, message, &mut self.buffer)?;
+        }
+
+        let mut stdout = std::io::stdout();
+        stdout.write_all(&self.buffer)?;
+        stdout.flush()?;
+
+        Ok(())
+    }
+
+    fn send_batch_encoded(&mut self, _messages: &[Message], data: &[Bytes]) -> Result<()> {
+        if !self.connected {
+            return Err(LinkError::ConnectionClosed);
+        }
+
+        self.buffer.clear();
+        for data in data {
+            FrameCodec::encode(data, &mut self.buffer)?;
+        }
 
         let mut stdout = std::io::stdout();
-        stdout.write_all(&len.to_le_bytes())?;
-        stdout.write_all(&data)?;
+        stdout.write_all(&self.buffer)?;
         stdout.flush()?;
 
         Ok(())
@@ -134,26 +392,39 @@ impl Transport for StdioTransport {
             return Err(LinkError::ConnectionClosed);
         }
 
-        use std::io::Read;
+        // Once the reader thread has stopped, stdin is at EOF.
+        let frame = self.incoming().recv().unwrap_or(Ok(None));
+        self.decode(frame)
+    }
 
-        let mut stdin = std::io::stdin();
-        let mut len_bytes = [0u8; 4];
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        if !self.connected {
+            return Err(LinkError::ConnectionClosed);
+        }
 
-        match stdin.read_exact(&mut len_bytes) {
-            Ok(_) => {},
-            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
-                return Ok(None);
-            }
-            Err(e) => return Err(e.into()),
+        match self.incoming().recv_timeout(timeout) {
+            Ok(frame) => self.decode(frame),
+            Err(RecvTimeoutError::Timeout) => Err(LinkError::Timeout),
+            Err(RecvTimeoutError::Disconnected) => Ok(None),
         }
+    }
 
-        let len = u32::from_le_bytes(len_bytes) as usize;
-        let mut buffer = vec![0u8; len];
+    fn flush(&mut self) -> Result<()> {
+        if !self.connected {
+            return Err(LinkError::ConnectionClosed);
+        }
 
-        stdin.read_exact(&mut buffer)?;
+        std::io::stdout().flush()?;
+        Ok(())
+    }
 
-        let message = self.serializer.deserialize_message(&buffer)?;
-        Ok(Some(message))
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        crate::compression::is_supported(compression)
+    }
+
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.serializer.set_compression(compression);
+        Ok(())
     }
 
     fn close(&mut self) -> Result<()> {
@@ -166,6 +437,221 @@ impl Transport for StdioTransport {
     }
 }
 
+impl Drop for StdioTransport {
+    fn drop(&mut self) {
+        if self.connected {
+            if let Err(e) = self.flush() {
+                debug::trace_transport_drop_error("stdio", &e);
+            }
+        }
+    }
+}
+
+pub struct TcpTransport {
+    serializer: BinarySerializer,
+    deserializer: StreamingDeserializer,
+    buffer: BytesMut,
+    stream: Option<TcpStream>,
+}
+
+impl TcpTransport {
+    pub fn new(format: BinaryFormat, stream: TcpStream) -> Self {
+        Self::with_serializer(BinarySerializer::new(format), stream)
+    }
+
+    pub fn with_serializer(serializer: BinarySerializer, stream: TcpStream) -> Self {
+        Self {
+            deserializer: StreamingDeserializer::with_serializer(serializer.clone()),
+            serializer,
+            buffer: BytesMut::new(),
+            stream: Some(stream),
+        }
+    }
+
+    pub fn connect<A: ToSocketAddrs>(addr: A, format: BinaryFormat) -> Result<Self> {
+        let stream = TcpStream::connect(addr)?;
+        stream.set_nodelay(true)?;
+        Ok(Self::new(format, stream))
+    }
+
+    /// Read whatever is available into the frame buffer. Returns `false` on a
+    /// read timeout.
+    fn fill(&mut self, read_timeout: Option<Duration>) -> Result<bool> {
+        let stream = self.stream.as_mut()
+            .ok_or(LinkError::ConnectionClosed)?;
+
+        stream.set_read_timeout(read_timeout)?;
+
+        let mut chunk = [0u8; 8192];
+        match stream.read(&mut chunk) {
+            Ok(0) => {
+                self.stream = None;
+                Err(LinkError::ConnectionClosed)
+            }
+            Ok(n) => {
+                if debug::is_trace_enabled() {
+                    debug::trace_transport_receive(n, &self.peer_label());
+                }
+                self.deserializer.feed(&chunk[..n]);
+                Ok(true)
+            }
+            Err(e) if matches!(e.kind(), std::io::ErrorKind::WouldBlock | std::io::ErrorKind::TimedOut) => {
+                Ok(false)
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::Interrupted => Ok(true),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Write one frame, tracing it against the peer address.
+    fn write_frame(&mut self, data: &[u8]) -> Result<()> {
+        if debug::is_trace_enabled() {
+            debug::trace_transport_send(FrameCodec::PREFIX_LEN + data.len(), &self.peer_label());
+        }
+
+        let stream = self.stream.as_mut()
+            .ok_or(LinkError::ConnectionClosed)?;
+        FrameCodec::write_to(stream, data)
+    }
+
+    /// Write the frames encoded into `self.buffer` in one go.
+    fn write_buffer(&mut self) -> Result<()> {
+        let stream = self.stream.as_mut()
+            .ok_or(LinkError::ConnectionClosed)?;
+        stream.write_all(&self.buffer)?;
+
+        if debug::is_trace_enabled() {
+            debug::trace_transport_send(self.buffer.len(), &self.peer_label());
+        }
+        Ok(())
+    }
+
+    fn peer_label(&self) -> String {
+        self.peer_addr().map_or_else(|| "closed tcp connection".to_string(), |addr| addr.to_string())
+    }
+}
+
+impl Transport for TcpTransport {
+    fn send(&mut self, message: &Message) -> Result<()> {
+        if self.stream.is_none() {
+            return Err(LinkError::ConnectionClosed);
+        }
+
+        self.buffer.clear();
+        encode_frame(&self.serializer, message, &mut self.buffer)?;
+        self.write_buffer()
+    }
+
+    fn serializer(&self) -> Option<&BinarySerializer> {
+        Some(&self.serializer)
+    }
+
+    fn send_encoded(&mut self, _message: &Message, data: &Bytes) -> Result<()> {
+        self.write_frame(data)
+    }
+
+    fn send_batch(&mut self, messages: &[Message]) -> Result<()> {
+        if self.stream.is_none() {
+            return Err(LinkError::ConnectionClosed);
+        }
+
+        self.buffer.clear();
+        for message in messages {
+            encode_frame(&self.serializer, message, &mut self.buffer)?;
+        }
+        self.write_buffer()
+    }
+
+    fn send_batch_encoded(&mut self, _messages: &[Message], data: &[Bytes]) -> Result<()> {
+        if self.stream.is_none() {
+            return Err(LinkError::ConnectionClosed);
+        }
+
+        self.buffer.clear();
+        for data in data {
+            FrameCodec::encode(data, &mut self.buffer)?;
+        }
+        self.write_buffer()
+    }
+
+    fn receive(&mut self) -> Result<Option<Message>> {
+        loop {
+            if let Some(message) = self.deserializer.try_read_message()? {
+                return Ok(Some(message));
+            }
+
+            self.fill(None)?;
+        }
+    }
+
+    fn receive_timeout(&mut self, timeout: Duration) -> Result<Option<Message>> {
+        let deadline = Instant::now() + timeout;
+
+        loop {
+            if let Some(message) = self.deserializer.try_read_message()? {
+                return Ok(Some(message));
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() || !self.fill(Some(remaining))? {
+                return Err(LinkError::Timeout);
+            }
+        }
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        let stream = self.stream.as_mut()
+            .ok_or(LinkError::ConnectionClosed)?;
+
+        stream.flush()?;
+        Ok(())
+    }
+
+    fn supports_compression(&self, compression: CompressionType) -> bool {
+        crate::compression::is_supported(compression)
+    }
+
+    fn set_compression(&mut self, compression: CompressionType) -> Result<()> {
+        self.serializer.set_compression(compression);
+        self.deserializer.set_compression(compression);
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(stream) = self.stream.take() {
+            stream.shutdown(std::net::Shutdown::Both)?;
+        }
+        self.deserializer.clear();
+        Ok(())
+    }
+
+    fn is_connected(&self) -> bool {
+        self.stream.is_some()
+    }
+
+    fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.as_ref()?.peer_addr().ok()
+    }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.stream.as_ref()?.local_addr().ok()
+    }
+}
+
+/// Flushes and shuts the socket down so the peer sees a clean end of stream
+/// even when `close` was never called.
+impl Drop for TcpTransport {
+    fn drop(&mut self) {
+        if self.stream.is_none() {
+            return;
+        }
+
+        if let Err(e) = self.flush().and_then(|_| self.close()) {
+            debug::trace_transport_drop_error("tcp", &e);
+        }
+    }
+}
+
 #[cfg(feature = "websocket")]
 pub mod websocket {
     use super::*;
@@ -233,9 +719,25 @@ pub mod websocket {
             Ok(())
         }
 
+        async fn flush(&mut self) -> Result<()> {
+            let stream = self.stream.as_mut()
+                .ok_or(LinkError::ConnectionClosed)?;
+
+            stream.flush().await
+                .map_err(|e| LinkError::Transport(e.to_string()))
+        }
+
         fn is_connected(&self) -> bool {
             self.stream.is_some()
         }
+
+        fn peer_addr(&self) -> Option<SocketAddr> {
+            self.stream.as_ref()?.get_ref().peer_addr().ok()
+        }
+
+        fn local_addr(&self) -> Option<SocketAddr> {
+            self.stream.as_ref()?.get_ref().local_addr().ok()
+        }
     }
 }
 
@@ -263,14 +765,14 @@ impl std::error::Error for TransportError {}
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::protocol::MessageType;
+    use crate::protocol::{MessageType, SchemaVersion};
 
     #[test]
     fn test_memory_transport() {
         let mut transport1 = MemoryTransport::new(BinaryFormat::MessagePack);
         let mut transport2 = MemoryTransport::new(BinaryFormat::MessagePack);
 
-        let message = Message::ping(1);
+        let message = Message::ping(SchemaVersion::new(1));
         transport1.send(&message).unwrap();
 
         transport1.connect_to(&mut transport2);
@@ -279,6 +781,163 @@ mod tests {
         assert_eq!(message.header.msg_type, received.header.msg_type);
     }
 
+    #[test]
+    fn test_memory_transport_backpressure() {
+        let mut sender = MemoryTransport::with_capacity(BinaryFormat::MessagePack, 2);
+        let mut receiver = MemoryTransport::new(BinaryFormat::MessagePack);
+
+        sender.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        sender.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        assert_eq!(sender.pending_count(), 2);
+        assert!(matches!(sender.send(&Message::ping(SchemaVersion::new(1))), Err(LinkError::Backpressure(2))));
+
+        sender.connect_to(&mut receiver);
+        assert_eq!(sender.pending_count(), 0);
+        assert!(sender.send(&Message::ping(SchemaVersion::new(1))).is_ok());
+    }
+
+    fn tcp_pair() -> (TcpTransport, TcpTransport) {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = TcpTransport::connect(addr, BinaryFormat::MessagePack).unwrap();
+        let (server_stream, _) = listener.accept().unwrap();
+
+        (client, TcpTransport::new(BinaryFormat::MessagePack, server_stream))
+    }
+
+    #[test]
+    fn test_tcp_transport() {
+        let (mut client, mut server) = tcp_pair();
+
+        client.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        client.send(&Message::pong(SchemaVersion::new(1))).unwrap();
+
+        let first = server.receive().unwrap().unwrap();
+        let second = server.receive_timeout(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(first.header.msg_type, MessageType::Ping);
+        assert_eq!(second.header.msg_type, MessageType::Pong);
+
+        assert_eq!(client.peer_addr(), server.local_addr());
+        assert_eq!(server.peer_addr(), client.local_addr());
+        assert!(client.peer_addr().is_some());
+        assert_eq!(MemoryTransport::new(BinaryFormat::MessagePack).peer_addr(), None);
+
+        client.close().unwrap();
+        assert_eq!((client.peer_addr(), client.local_addr()), (None, None));
+    }
+
+    #[test]
+    fn test_tcp_send_batch() {
+        let (mut client, mut server) = tcp_pair();
+
+        client.send_batch(&[Message::ping(SchemaVersion::new(1)), Message::pong(SchemaVersion::new(1)), Message::ping(SchemaVersion::new(1))]).unwrap();
+        client.flush().unwrap();
+
+        let types: Vec<_> = (0..3)
+            .map(|_| server.receive_timeout(Duration::from_secs(1)).unwrap().unwrap().header.msg_type)
+            .collect();
+        assert_eq!(types, vec![MessageType::Ping, MessageType::Pong, MessageType::Ping]);
+    }
+
+    #[test]
+    fn test_tcp_flush() {
+        let (mut client, mut server) = tcp_pair();
+
+        client.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        client.flush().unwrap();
+        assert!(server.receive_timeout(Duration::from_secs(1)).unwrap().is_some());
+
+        client.close().unwrap();
+        assert!(matches!(client.flush(), Err(LinkError::ConnectionClosed)));
+    }
+
+    #[test]
+    fn test_tcp_receive_timeout() {
+        let (mut client, mut server) = tcp_pair();
+
+        let start = Instant::now();
+        assert!(matches!(
+            server.receive_timeout(Duration::from_millis(50)),
+            Err(LinkError::Timeout)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        client.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        assert!(server.receive_timeout(Duration::from_secs(1)).unwrap().is_some());
+
+        client.close().unwrap();
+        assert!(matches!(
+            server.receive_timeout(Duration::from_secs(1)),
+            Err(LinkError::ConnectionClosed)
+        ));
+        assert!(!server.is_connected());
+    }
+
+    /// Bytes fed through a channel, blocking until they arrive, like stdin.
+    struct ChannelReader {
+        chunks: Receiver<Vec<u8>>,
+        pending: Vec<u8>,
+    }
+
+    impl Read for ChannelReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if self.pending.is_empty() {
+                match self.chunks.recv() {
+                    Ok(chunk) => self.pending = chunk,
+                    Err(_) => return Ok(0),
+                }
+            }
+            let len = buf.len().min(self.pending.len());
+            buf[..len].copy_from_slice(&self.pending[..len]);
+            self.pending.drain(..len);
+            Ok(len)
+        }
+    }
+
+    #[test]
+    fn test_stdio_receive_timeout() {
+        let (sender, chunks) = mpsc::channel();
+        let mut transport = StdioTransport::new(BinaryFormat::MessagePack);
+        transport.incoming = Some(spawn_frame_reader(ChannelReader { chunks, pending: Vec::new() }, 1 << 20));
+
+        let mut frame = BytesMut::new();
+        encode_frame(&transport.serializer, &Message::ping(SchemaVersion::new(1)), &mut frame).unwrap();
+        let (first, rest) = frame.split_at(3);
+
+        // A timeout part way through a frame doesn't lose what was read.
+        sender.send(first.to_vec()).unwrap();
+        let start = Instant::now();
+        assert!(matches!(
+            transport.receive_timeout(Duration::from_millis(50)),
+            Err(LinkError::Timeout)
+        ));
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        sender.send(rest.to_vec()).unwrap();
+        let message = transport.receive_timeout(Duration::from_secs(1)).unwrap().unwrap();
+        assert_eq!(message.header.msg_type, MessageType::Ping);
+
+        drop(sender);
+        assert!(transport.receive_timeout(Duration::from_secs(1)).unwrap().is_none());
+        assert!(transport.receive().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_tcp_drop_closes_connection() {
+        let (mut client, mut server) = tcp_pair();
+
+        client.send(&Message::ping(SchemaVersion::new(1))).unwrap();
+        drop(client);
+
+        assert!(server.receive_timeout(Duration::from_secs(1)).unwrap().is_some());
+        assert!(matches!(
+            server.receive_timeout(Duration::from_secs(1)),
+            Err(LinkError::ConnectionClosed)
+        ));
+        assert!(!server.is_connected());
+    }
+
     #[test]
     fn test_transport_close() {
         let mut transport = MemoryTransport::new(BinaryFormat::Json);
@@ -289,7 +948,7 @@ mod tests {
 
         assert!(!transport.is_connected());
 
-        let message = Message::ping(1);
+        let message = Message::ping(SchemaVersion::new(1));
         assert!(transport.send(&message).is_err());
     }
 }
@@ -0,0 +1,301 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Expr, ExprLit, Fields, Lit, LitInt, LitStr};
+
+/// Derive `tx2_link::LinkComponent` for a struct with named fields.
+///
+/// Struct attributes:
+/// - `#[link_component(id = "Name")]` overrides the component id (defaults to the struct name)
+/// - `#[link_component(version = N)]` sets the schema version (defaults to 1)
+///
+/// Field attributes:
+/// - `#[link_component(skip)]` excludes a field from replication; it is restored with `Default::default()`
+///
+/// A struct without replicated fields becomes a marker component (`ComponentData::Empty`).
+#[proc_macro_derive(LinkComponent, attributes(link_component))]
+pub fn derive_link_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+/// Derive `tx2_link::component::ReplicatedField` for an enum, replicated as
+/// `FieldValue::Enum`.
+///
+/// Variants are either units or carry a single unnamed field that is itself a
+/// `ReplicatedField`, sent as the payload. Discriminants follow Rust's rules:
+/// an explicit `Variant = N` or one more than the previous variant. Components
+/// deriving `LinkComponent` restrict enum fields to the known discriminants.
+#[proc_macro_derive(ReplicatedField)]
+pub fn derive_replicated_field(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    match expand_enum(input) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_enum(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let variants = match &input.data {
+        Data::Enum(data) => &data.variants,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "ReplicatedField can only be derived for enums",
+            ))
+        }
+    };
+
+    let mut discriminants = Vec::new();
+    let mut to_arms = Vec::new();
+    let mut from_arms = Vec::new();
+    let mut next: u32 = 0;
+
+    for variant in variants {
+        let ident = &variant.ident;
+
+        let discriminant = match &variant.discriminant {
+            Some((_, Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }))) => lit.base10_parse()?,
+            Some((_, expr)) => {
+                return Err(syn::Error::new_spanned(expr, "enum discriminants must be integer literals"))
+            }
+            None => next,
+        };
+        next = discriminant.wrapping_add(1);
+        discriminants.push(discriminant);
+
+        match &variant.fields {
+            Fields::Unit => {
+                to_arms.push(quote! {
+                    Self::#ident => ::tx2_link::protocol::FieldValue::Enum {
+                        discriminant: #discriminant,
+                        payload: None,
+                    }
+                });
+                from_arms.push(quote! {
+                    #discriminant => Some(Self::#ident)
+                });
+            }
+            Fields::Unnamed(fields) if fields.unnamed.len() == 1 => {
+                let ty = &fields.unnamed[0].ty;
+                to_arms.push(quote! {
+                    Self::#ident(value) => ::tx2_link::protocol::FieldValue::Enum {
+                        discriminant: #discriminant,
+                        payload: Some(Box::new(
+                            <#ty as ::tx2_link::component::ReplicatedField>::to_field_value(value),
+                        )),
+                    }
+                });
+                from_arms.push(quote! {
+                    #discriminant => payload
+                        .as_deref()
+                        .and_then(<#ty as ::tx2_link::component::ReplicatedField>::from_field_value)
+                        .map(Self::#ident)
+                });
+            }
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    variant,
+                    "ReplicatedField variants must be units or have a single unnamed field",
+                ))
+            }
+        }
+    }
+
+    Ok(quote! {
+        impl #impl_generics ::tx2_link::component::ReplicatedField for #name #ty_generics #where_clause {
+            const FIELD_TYPE: ::tx2_link::protocol::FieldType = ::tx2_link::protocol::FieldType::Enum;
+            const DISCRIMINANTS: Option<&'static [u32]> = Some(&[#(#discriminants),*]);
+
+            fn to_field_value(&self) -> ::tx2_link::protocol::FieldValue {
+                match self {
+                    #(#to_arms,)*
+                }
+            }
+
+            fn from_field_value(value: &::tx2_link::protocol::FieldValue) -> Option<Self> {
+                let ::tx2_link::protocol::FieldValue::Enum { discriminant, payload } = value else {
+                    return None;
+                };
+
+                #[allow(unreachable_patterns)]
+                match *discriminant {
+                    #(#from_arms,)*
+                    _ => None,
+                }
+            }
+        }
+    })
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let mut component_id = name.to_string();
+    let mut version: u32 = 1;
+
+    for attr in &input.attrs {
+        if !attr.path().is_ident("link_component") {
+            continue;
+        }
+
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("id") {
+                let lit: LitStr = meta.value()?.parse()?;
+                component_id = lit.value();
+                Ok(())
+            } else if meta.path.is_ident("version") {
+                let lit: LitInt = meta.value()?.parse()?;
+                version = lit.base10_parse()?;
+                Ok(())
+            } else {
+                Err(meta.error("unsupported link_component attribute"))
+            }
+        })?;
+    }
+
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    name,
+                    "LinkComponent can only be derived for structs with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                name,
+                "LinkComponent can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut to_fields = Vec::new();
+    let mut from_fields = Vec::new();
+    let mut schema_fields = Vec::new();
+
+    for field in fields {
+        let ident = field.ident.as_ref().expect("named field");
+        let ty = &field.ty;
+        let key = ident.to_string();
+
+        let mut skip = false;
+        for attr in &field.attrs {
+            if !attr.path().is_ident("link_component") {
+                continue;
+            }
+
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("unsupported link_component field attribute"))
+                }
+            })?;
+        }
+
+        if skip {
+            from_fields.push(quote! {
+                #ident: ::core::default::Default::default()
+            });
+            continue;
+        }
+
+        to_fields.push(quote! {
+            fields.insert(
+                #key.to_string(),
+                <#ty as ::tx2_link::component::ReplicatedField>::to_field_value(&self.#ident),
+            );
+        });
+
+        from_fields.push(quote! {
+            #ident: <#ty as ::tx2_link::component::ReplicatedField>::from_field_value(
+                fields.get(#key).unwrap_or(&::tx2_link::protocol::FieldValue::Null),
+            )
+            .ok_or_else(|| ::tx2_link::LinkError::Deserialization(format!(
+                "Field '{}' missing or has wrong type in component '{}'",
+                #key,
+                #component_id,
+            )))?
+        });
+
+        schema_fields.push(quote! {
+            {
+                let field = ::tx2_link::FieldSchema::new(
+                    #key.to_string(),
+                    <#ty as ::tx2_link::component::ReplicatedField>::FIELD_TYPE,
+                );
+                let field = match <#ty as ::tx2_link::component::ReplicatedField>::DISCRIMINANTS {
+                    Some(discriminants) => field.with_allowed_discriminants(discriminants.iter().copied()),
+                    None => field,
+                };
+                if <#ty as ::tx2_link::component::ReplicatedField>::OPTIONAL {
+                    field.optional()
+                } else {
+                    field
+                }
+            }
+        });
+    }
+
+    // Structs whose fields are all skipped replicate as marker components.
+    let to_component_data = if to_fields.is_empty() {
+        quote! {
+            ::tx2_link::protocol::ComponentData::Empty
+        }
+    } else {
+        quote! {
+            let mut fields = ::std::collections::HashMap::new();
+            #(#to_fields)*
+            ::tx2_link::protocol::ComponentData::Structured(fields)
+        }
+    };
+
+    Ok(quote! {
+        impl #impl_generics ::tx2_link::component::LinkComponent for #name #ty_generics #where_clause {
+            fn component_id() -> ::tx2_link::ComponentId {
+                #component_id.to_string()
+            }
+
+            fn schema() -> ::tx2_link::ComponentSchema {
+                ::tx2_link::ComponentSchema::new(#component_id.to_string(), ::tx2_link::SchemaVersion::new(#version))
+                    #(.with_field(#schema_fields))*
+            }
+
+            fn to_component_data(&self) -> ::tx2_link::protocol::ComponentData {
+                #to_component_data
+            }
+
+            fn from_component_data(data: &::tx2_link::protocol::ComponentData) -> ::tx2_link::Result<Self> {
+                #[allow(unused_variables)]
+                let empty = ::std::collections::HashMap::new();
+                let fields = match data {
+                    ::tx2_link::protocol::ComponentData::Structured(fields) => fields,
+                    ::tx2_link::protocol::ComponentData::Empty => &empty,
+                    _ => {
+                        return Err(::tx2_link::LinkError::Deserialization(format!(
+                            "Component '{}' expects structured data",
+                            #component_id,
+                        )))
+                    }
+                };
+
+                Ok(Self {
+                    #(#from_fields),*
+                })
+            }
+        }
+    })
+}